@@ -12,7 +12,7 @@ impl Future for Delay {
     type Output = &'static str;
 
     // 这里的实现是有问题的，详情看 https://tokio.rs/tokio/tutorial/async#a-few-loose-ends
-    // mini_tokio.rs 中有对应正确的实现
+    // mini_tokio/main.rs 中有对应正确的实现，那里的计时器由执行器驱动的时间轮实现(mini_tokio/time.rs)，不再需要为每个 Delay 生成线程
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         if Instant::now() >= self.when {
             // 时间到了，Future 可以结束
@@ -9,6 +9,8 @@ use tokio_stream::{Stream, StreamExt};
 
 /// 还记得在深入async 中构建的 Delay Future 吗？现在让我们来更进一步，
 /// 将它转换成一个 stream，每 10 毫秒生成一个值，总共生成 3 次:
+///
+/// 这里的 Delay 仍然为每个计时器生成一个线程，mini_tokio/time.rs 中的时间轮展示了运行时是如何避免这一点的
 struct Delay {
     when: Instant,
     // 用于说明是否已经生成一个线程
@@ -0,0 +1,126 @@
+//! 比较单线程和多线程的调度器，负载参考 tokio 的 `benches/rt_multi_threaded.rs`：
+//!
+//! * spawn_many：一个任务生成大量很短的任务再等待它们完成，主要是生成、调度和窃取的开销
+//! * ping_pong：很多对任务通过 channel 来回传递消息，每条消息都会唤醒对端，主要是唤醒和 LIFO 槽的效果
+//! * chained_spawn：每个任务生成下一个任务，形成一条很长的链，多线程模式下很难并行
+//!
+//! 每个负载运行若干次，取中位数。
+//!
+//! 在只有 1 个 CPU 的机器上(`bench 4`，release)，多线程模式没有并行的收益，只剩下线程之间同步和切换的开销：
+//!
+//! ```text
+//! runtime                  spawn_many      ping_pong  chained_spawn
+//! current_thread              67.04ms        55.99ms        33.47ms
+//! multi_thread(4)            143.37ms        48.63ms        29.02ms
+//! ```
+//!
+//! ping_pong 中被唤醒的任务直接进入 LIFO 槽，不经过队列，所以多线程模式下也不比单线程慢
+
+use crate::runtime::{self, MiniTokio};
+
+use futures::channel::{mpsc, oneshot};
+use futures::{SinkExt, StreamExt};
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 5;
+
+const SPAWN_MANY_TASKS: usize = 100_000;
+
+const PING_PONG_PAIRS: usize = 1_000;
+const PING_PONG_ROUNDS: usize = 100;
+
+const CHAIN_LENGTH: usize = 100_000;
+
+/// `workers` 默认是 CPU 的个数
+pub fn run(workers: Option<usize>) {
+    let workers = workers.unwrap_or_else(|| thread::available_parallelism().map_or(4, |n| n.get()));
+    let runtimes = [
+        ("current_thread".to_string(), MiniTokio::new().unwrap()),
+        (
+            format!("multi_thread({})", workers),
+            MiniTokio::new_multi_thread(workers).unwrap(),
+        ),
+    ];
+
+    println!(
+        "{:<20} {:>14} {:>14} {:>14}",
+        "runtime", "spawn_many", "ping_pong", "chained_spawn"
+    );
+    for (name, mut mini_tokio) in runtimes {
+        let spawn_many = measure(&mut mini_tokio, spawn_many);
+        let ping_pong = measure(&mut mini_tokio, ping_pong);
+        let chained_spawn = measure(&mut mini_tokio, chained_spawn);
+        println!(
+            "{:<20} {:>14.2?} {:>14.2?} {:>14.2?}",
+            name, spawn_many, ping_pong, chained_spawn
+        );
+    }
+}
+
+fn measure<F>(mini_tokio: &mut MiniTokio, workload: fn() -> F) -> Duration
+where
+    F: std::future::Future<Output = ()>,
+{
+    let mut elapsed: Vec<_> = (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            mini_tokio.block_on(workload());
+            start.elapsed()
+        })
+        .collect();
+    elapsed.sort();
+    elapsed[ROUNDS / 2]
+}
+
+async fn spawn_many() {
+    // 在任务中生成，多线程模式下新任务先进入这个 worker 的本地队列，再被其它 worker 偷走
+    runtime::spawn(async {
+        let handles: Vec<_> = (0..SPAWN_MANY_TASKS)
+            .map(|i| runtime::spawn(async move { black_box((0..100).fold(i, |acc, x| acc ^ x)) }))
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    })
+    .await
+    .unwrap();
+}
+
+async fn ping_pong() {
+    let handles: Vec<_> = (0..PING_PONG_PAIRS)
+        .map(|_| {
+            runtime::spawn(async {
+                let (mut ping_tx, mut ping_rx) = mpsc::channel::<usize>(1);
+                let (mut pong_tx, mut pong_rx) = mpsc::channel::<usize>(1);
+                runtime::spawn(async move {
+                    while let Some(n) = ping_rx.next().await {
+                        pong_tx.send(n + 1).await.unwrap();
+                    }
+                });
+                for i in 0..PING_PONG_ROUNDS {
+                    ping_tx.send(i).await.unwrap();
+                    assert_eq!(pong_rx.next().await, Some(i + 1));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+async fn chained_spawn() {
+    fn link(n: usize, done: oneshot::Sender<()>) {
+        if n == 0 {
+            let _ = done.send(());
+        } else {
+            runtime::spawn(async move { link(n - 1, done) });
+        }
+    }
+
+    let (tx, rx) = oneshot::channel();
+    runtime::spawn(async move { link(CHAIN_LENGTH, tx) });
+    rx.await.unwrap();
+}
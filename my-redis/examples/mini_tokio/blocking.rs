@@ -0,0 +1,332 @@
+//! `spawn_blocking` 使用的线程池，结构和 tokio 的 `runtime/blocking/pool` 一样。
+//!
+//! 执行器的线程只能运行很快就会让出的 future，CPU 密集或者会阻塞的同步代码放到这里的线程中运行：
+//!
+//! * 线程按需创建：提交任务时没有空闲的线程，而且线程数还没有达到 `max_threads`，就创建一个新的线程，否则排队等待
+//! * 空闲超过 `keep_alive` 的线程退出，负载下降之后线程数会慢慢减少
+//! * 任务中的 panic 被捕获，通过 `JoinHandle` 返回 `JoinError::Panic`，线程继续运行下一个任务
+//!
+//! 运行时被 drop 时还在排队的任务被取消，已经开始运行的任务会等它运行完
+
+use crate::task::{JoinError, JoinHandle, JoinState};
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// tokio 的默认值
+const MAX_THREADS: usize = 512;
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+pub(crate) struct BlockingPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    /// 通知空闲的线程有新任务，或者运行时关闭了
+    condvar: Condvar,
+    /// 线程退出时通知 `shutdown`
+    exited: Condvar,
+}
+
+struct State {
+    queue: VecDeque<Box<dyn FnOnce() + Send>>,
+    num_threads: usize,
+    num_idle: usize,
+    /// 发给空闲线程的通知数，区分被通知和等待超时
+    num_notify: usize,
+    max_threads: usize,
+    keep_alive: Duration,
+    next_id: usize,
+    shutdown: bool,
+}
+
+/// 运行之前被 drop 时通知 `JoinHandle` 任务被取消了
+struct BlockingTask<F, R> {
+    func: Option<F>,
+    state: Arc<JoinState<R>>,
+}
+
+impl BlockingPool {
+    pub(crate) fn new() -> BlockingPool {
+        BlockingPool {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    queue: VecDeque::new(),
+                    num_threads: 0,
+                    num_idle: 0,
+                    num_notify: 0,
+                    max_threads: MAX_THREADS,
+                    keep_alive: KEEP_ALIVE,
+                    next_id: 0,
+                    shutdown: false,
+                }),
+                condvar: Condvar::new(),
+                exited: Condvar::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn set_max_threads(&self, max_threads: usize) {
+        assert!(max_threads > 0, "`max_threads` must be greater than 0");
+        self.inner.state.lock().unwrap().max_threads = max_threads;
+    }
+
+    pub(crate) fn set_keep_alive(&self, keep_alive: Duration) {
+        self.inner.state.lock().unwrap().keep_alive = keep_alive;
+    }
+
+    /// 当前的线程数，包括空闲的
+    pub(crate) fn num_threads(&self) -> usize {
+        self.inner.state.lock().unwrap().num_threads
+    }
+
+    pub(crate) fn spawn<F, R>(&self, func: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let state = JoinState::new();
+        let task = BlockingTask {
+            func: Some(func),
+            state: state.clone(),
+        };
+        self.push(Box::new(move || task.run()));
+        JoinHandle::blocking(state)
+    }
+
+    fn push(&self, task: Box<dyn FnOnce() + Send>) {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.shutdown {
+            // 在这里 drop 任务，`JoinHandle` 得到 `JoinError::Cancelled`
+            return;
+        }
+        state.queue.push_back(task);
+
+        if state.num_idle > 0 {
+            state.num_idle -= 1;
+            state.num_notify += 1;
+            self.inner.condvar.notify_one();
+            return;
+        }
+        if state.num_threads == state.max_threads {
+            // 等某个线程运行完手上的任务
+            return;
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.num_threads += 1;
+        let inner = self.inner.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("mini-tokio-blocking-{}", id))
+            .spawn(move || inner.run());
+        if spawned.is_err() {
+            // 已经有线程时任务留在队列中等它们来取；一个线程也没有时任务只能被取消
+            state.num_threads -= 1;
+            if state.num_threads == 0 {
+                state.queue.clear();
+            }
+        }
+    }
+
+    /// 取消排队的任务，等待正在运行的任务完成，所有线程退出
+    pub(crate) fn shutdown(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.shutdown = true;
+        let queue = std::mem::take(&mut state.queue);
+        self.inner.condvar.notify_all();
+        while state.num_threads > 0 {
+            state = self.inner.exited.wait(state).unwrap();
+        }
+        drop(state);
+        drop(queue);
+    }
+}
+
+impl Inner {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        'outer: loop {
+            while let Some(task) = state.queue.pop_front() {
+                drop(state);
+                task();
+                state = self.state.lock().unwrap();
+            }
+            if state.shutdown {
+                break;
+            }
+
+            state.num_idle += 1;
+            loop {
+                let keep_alive = state.keep_alive;
+                let (guard, res) = self.condvar.wait_timeout(state, keep_alive).unwrap();
+                state = guard;
+                // 被通知时提交任务的线程已经把 `num_idle` 减掉了
+                if state.num_notify > 0 {
+                    state.num_notify -= 1;
+                    continue 'outer;
+                }
+                if state.shutdown || res.timed_out() {
+                    state.num_idle -= 1;
+                    break 'outer;
+                }
+            }
+        }
+        state.num_threads -= 1;
+        self.exited.notify_all();
+    }
+}
+
+impl<F, R> BlockingTask<F, R>
+where
+    F: FnOnce() -> R,
+{
+    fn run(mut self) {
+        let func = self.func.take().unwrap();
+        // 和 `Harness` 一样，panic 之后不会再访问 `func` 捕获的状态
+        let output = panic::catch_unwind(AssertUnwindSafe(func)).map_err(JoinError::Panic);
+        self.state.complete(output);
+    }
+}
+
+impl<F, R> Drop for BlockingTask<F, R> {
+    fn drop(&mut self) {
+        if self.func.is_some() {
+            self.state.complete(Err(JoinError::Cancelled));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    fn queued(pool: &BlockingPool) -> usize {
+        pool.inner.state.lock().unwrap().queue.len()
+    }
+
+    /// 等到 `f` 返回 true，最多等 5 秒
+    fn wait_until(mut f: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn threads_grow_up_to_max() {
+        let pool = BlockingPool::new();
+        pool.set_max_threads(2);
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let released = released.clone();
+                pool.spawn(move || {
+                    released.lock().unwrap().recv().unwrap();
+                    i
+                })
+            })
+            .collect();
+        // 达到上限之后新的任务排队等待
+        assert_eq!(pool.num_threads(), 2);
+        wait_until(|| queued(&pool) == 2);
+
+        for _ in 0..4 {
+            release.send(()).unwrap();
+        }
+        let results: Vec<_> = handles.into_iter().map(|h| block_on(h).unwrap()).collect();
+        assert_eq!(results, [0, 1, 2, 3]);
+        assert_eq!(pool.num_threads(), 2);
+        pool.shutdown();
+    }
+
+    #[test]
+    fn idle_thread_is_reused() {
+        let pool = BlockingPool::new();
+        for i in 0..10 {
+            assert_eq!(block_on(pool.spawn(move || i)).unwrap(), i);
+            // 等线程空闲下来再提交下一个任务
+            wait_until(|| pool.inner.state.lock().unwrap().num_idle == 1);
+        }
+        let state = pool.inner.state.lock().unwrap();
+        assert_eq!((state.num_threads, state.next_id), (1, 1));
+        drop(state);
+        pool.shutdown();
+    }
+
+    #[test]
+    fn idle_threads_exit_after_keep_alive() {
+        let pool = BlockingPool::new();
+        pool.set_keep_alive(Duration::from_millis(50));
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| pool.spawn(|| thread::sleep(Duration::from_millis(20))))
+            .collect();
+        assert_eq!(pool.num_threads(), 3);
+        for handle in handles {
+            block_on(handle).unwrap();
+        }
+        let idle_since = Instant::now();
+
+        wait_until(|| pool.num_threads() == 0);
+        assert!(idle_since.elapsed() >= Duration::from_millis(50));
+
+        // 线程都退出之后按需重新创建
+        assert_eq!(block_on(pool.spawn(|| 42)).unwrap(), 42);
+        assert_eq!(pool.num_threads(), 1);
+        pool.shutdown();
+    }
+
+    #[test]
+    fn panic_does_not_kill_thread() {
+        let pool = BlockingPool::new();
+        let err = block_on(pool.spawn(|| panic!("boom"))).unwrap_err();
+        assert!(err.is_panic());
+        wait_until(|| pool.inner.state.lock().unwrap().num_idle == 1);
+        assert_eq!(block_on(pool.spawn(|| 1)).unwrap(), 1);
+        assert_eq!(pool.num_threads(), 1);
+        pool.shutdown();
+    }
+
+    #[test]
+    fn shutdown_cancels_queued_and_waits_for_running() {
+        let pool = BlockingPool::new();
+        pool.set_max_threads(1);
+        let (started_tx, started) = mpsc::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let running = {
+            let finished = finished.clone();
+            pool.spawn(move || {
+                started_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                finished.store(true, Ordering::SeqCst);
+                "done"
+            })
+        };
+        let queued_task = pool.spawn(|| "never");
+        started.recv().unwrap();
+
+        // 正在运行的任务完成之后 shutdown 才返回，线程都退出了
+        pool.shutdown();
+        assert!(finished.load(Ordering::SeqCst));
+        assert_eq!(pool.num_threads(), 0);
+        assert_eq!(block_on(running).unwrap(), "done");
+        assert!(block_on(queued_task).unwrap_err().is_cancelled());
+
+        // 关闭之后提交的任务直接被取消，不会创建线程
+        assert!(block_on(pool.spawn(|| 1)).unwrap_err().is_cancelled());
+        assert_eq!(pool.num_threads(), 0);
+    }
+}
@@ -0,0 +1,558 @@
+//! mini-tokio 的 IO 驱动(reactor)，基于 epoll，系统调用的写法来自 `asynchronous/examples/s06_epoll_example.rs`。
+//!
+//! * 非阻塞的 fd 通过 `Handle::register` 以边缘触发(EPOLLET)的方式注册一次，之后不需要再修改关注的事件
+//! * 每个 fd 对应一个 `ScheduledIo`，保存就绪状态以及等待读、等待写的 waker。epoll 返回事件时设置就绪状态并唤醒 waker
+//! * 任务读写时先检查就绪状态，读写返回 `WouldBlock` 之后才清除就绪状态并等待下一次事件，这是边缘触发的要求
+//! * 执行器没有任务可以运行时调用 `park` 阻塞在 `epoll_wait` 中，其它线程唤醒任务时通过 eventfd 把它叫醒
+//!
+//! 就绪状态中带有一个计数(tick)，每个事件加一。清除时只有计数没变才清除，
+//! 避免把读写返回 `WouldBlock` 之后、清除之前刚到达的事件也清除掉，和 tokio 的做法一样
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+macro_rules! syscall {
+    ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
+        let res = unsafe { libc::$fn($($arg, )*) };
+        if res == -1 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(res)
+        }
+    }};
+}
+pub(crate) use syscall;
+
+/// 一次 `epoll_wait` 最多返回的事件数
+const MAX_EVENTS: usize = 1024;
+
+/// eventfd 使用的 token，注册的 fd 从 1 开始
+const WAKE_TOKEN: u64 = 0;
+
+const READABLE: usize = 0b01;
+const WRITABLE: usize = 0b10;
+const READY_MASK: usize = 0xffff;
+const TICK_SHIFT: u32 = 16;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Handle>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interest {
+    Readable,
+    Writable,
+}
+
+impl Interest {
+    fn mask(self) -> usize {
+        match self {
+            Interest::Readable => READABLE,
+            Interest::Writable => WRITABLE,
+        }
+    }
+}
+
+/// IO 驱动的句柄，可以在多个线程之间共享
+#[derive(Clone)]
+pub struct Handle {
+    driver: Arc<Driver>,
+}
+
+struct Driver {
+    epoll_fd: RawFd,
+    /// 用来唤醒阻塞在 `epoll_wait` 中的线程
+    wake_fd: RawFd,
+    /// 执行器是否正阻塞(或者即将阻塞)在 `epoll_wait` 中，只有这时 `unpark` 才需要写 eventfd
+    parked: AtomicBool,
+    ios: Mutex<HashMap<u64, Arc<ScheduledIo>>>,
+    next_token: AtomicU64,
+    events: Mutex<Vec<libc::epoll_event>>,
+}
+
+/// 一个 fd 的就绪状态以及等待它的任务
+#[derive(Default)]
+struct ScheduledIo {
+    /// 高位是 tick，低 16 位是 `READABLE | WRITABLE`
+    readiness: AtomicUsize,
+    waiters: Mutex<Waiters>,
+}
+
+#[derive(Default)]
+struct Waiters {
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+/// `poll_ready` 观察到的就绪状态，传给 `clear_readiness`
+#[derive(Clone, Copy)]
+struct ReadyEvent {
+    tick: usize,
+    ready: usize,
+}
+
+/// `enter` 返回的守卫，drop 时恢复之前的句柄
+pub struct EnterGuard {
+    prev: Option<Handle>,
+}
+
+impl Handle {
+    pub fn new() -> io::Result<Handle> {
+        let epoll_fd = syscall!(epoll_create1(libc::EPOLL_CLOEXEC))?;
+        let wake_fd = match syscall!(eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK)) {
+            Ok(fd) => fd,
+            Err(err) => {
+                let _ = syscall!(close(epoll_fd));
+                return Err(err);
+            }
+        };
+        let driver = Driver {
+            epoll_fd,
+            wake_fd,
+            parked: AtomicBool::new(false),
+            ios: Mutex::new(HashMap::new()),
+            next_token: AtomicU64::new(WAKE_TOKEN + 1),
+            events: Mutex::new(Vec::with_capacity(MAX_EVENTS)),
+        };
+        // 先构造出 driver，失败时由它的 Drop 关闭两个 fd
+        add_interest(epoll_fd, wake_fd, WAKE_TOKEN)?;
+        Ok(Handle {
+            driver: Arc::new(driver),
+        })
+    }
+
+    /// 当前线程所在的运行时的 IO 驱动
+    ///
+    /// # Panics
+    ///
+    /// 不在 mini-tokio 的运行时中调用时 panic
+    pub fn current() -> Handle {
+        CURRENT
+            .with(|current| current.borrow().clone())
+            .expect("must be called from the context of a mini-tokio runtime")
+    }
+
+    pub fn enter(&self) -> EnterGuard {
+        let prev = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+        EnterGuard { prev }
+    }
+
+    /// 注册一个非阻塞的 fd，返回的 `Registration` 被 drop 时取消注册
+    pub fn register(&self, fd: RawFd) -> io::Result<Registration> {
+        let token = self.driver.next_token.fetch_add(1, Ordering::Relaxed);
+        let io = Arc::new(ScheduledIo::default());
+        self.driver.ios.lock().unwrap().insert(token, io.clone());
+        if let Err(err) = add_interest(self.driver.epoll_fd, fd, token) {
+            self.driver.ios.lock().unwrap().remove(&token);
+            return Err(err);
+        }
+        Ok(Registration {
+            handle: self.clone(),
+            fd,
+            token,
+            io,
+        })
+    }
+
+    /// 阻塞在 `epoll_wait` 中最多 `timeout`(None 表示一直等)，然后唤醒就绪的 fd 上等待的任务。
+    ///
+    /// `is_idle` 在标记为 parked 之后调用，返回 false 时(例如调度队列中又有了任务)不阻塞，
+    /// 这样在检查和阻塞之间到来的 `unpark` 也不会丢失
+    pub fn park(
+        &self,
+        timeout: Option<Duration>,
+        is_idle: impl FnOnce() -> bool,
+    ) -> io::Result<()> {
+        self.driver.parked.store(true, Ordering::SeqCst);
+        let timeout = if is_idle() {
+            timeout
+        } else {
+            Some(Duration::ZERO)
+        };
+        let res = self.poll(timeout);
+        self.driver.parked.store(false, Ordering::SeqCst);
+        res
+    }
+
+    /// 不阻塞地处理已经到达的事件
+    pub fn poll_events(&self) -> io::Result<()> {
+        self.poll(Some(Duration::ZERO))
+    }
+
+    /// 叫醒阻塞在 `park` 中的线程，没有线程阻塞时什么也不做
+    pub fn unpark(&self) {
+        if self.driver.parked.swap(false, Ordering::SeqCst) {
+            let one: u64 = 1;
+            let _ = syscall!(write(
+                self.driver.wake_fd,
+                &one as *const u64 as *const libc::c_void,
+                8
+            ));
+        }
+    }
+
+    fn poll(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // 向上取整到毫秒，否则不到 1ms 的等待会变成 0，执行器空转
+        let timeout = match timeout {
+            Some(timeout) => timeout
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+
+        // 同一时间只有一个线程在 epoll_wait 中，拿不到锁说明别的线程正在处理事件
+        let mut events = match self.driver.events.try_lock() {
+            Ok(events) => events,
+            Err(_) => return Ok(()),
+        };
+        events.clear();
+        let res = match syscall!(epoll_wait(
+            self.driver.epoll_fd,
+            events.as_mut_ptr(),
+            MAX_EVENTS as libc::c_int,
+            timeout,
+        )) {
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => 0,
+            Err(err) => return Err(err),
+        };
+        // 和 s06_epoll_example.rs 一样，内核已经写入了 res 个事件
+        unsafe { events.set_len(res as usize) };
+
+        for event in events.iter() {
+            let token = event.u64;
+            if token == WAKE_TOKEN {
+                let mut buf = 0u64;
+                let _ = syscall!(read(
+                    self.driver.wake_fd,
+                    &mut buf as *mut u64 as *mut libc::c_void,
+                    8
+                ));
+                continue;
+            }
+
+            let flags = event.events as libc::c_int;
+            let mut ready = 0;
+            if flags & (libc::EPOLLIN | libc::EPOLLRDHUP | libc::EPOLLHUP | libc::EPOLLERR) != 0 {
+                ready |= READABLE;
+            }
+            if flags & (libc::EPOLLOUT | libc::EPOLLHUP | libc::EPOLLERR) != 0 {
+                ready |= WRITABLE;
+            }
+
+            let io = self.driver.ios.lock().unwrap().get(&token).cloned();
+            if let Some(io) = io {
+                io.set_readiness(ready);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        let _ = syscall!(close(self.wake_fd));
+        let _ = syscall!(close(self.epoll_fd));
+    }
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.prev.take());
+    }
+}
+
+impl ScheduledIo {
+    /// epoll 返回事件时调用：先更新就绪状态再唤醒，和 `poll_ready` 中先保存 waker 再检查的顺序配合，不会丢失唤醒
+    fn set_readiness(&self, ready: usize) {
+        let _ = self
+            .readiness
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                let tick = (curr >> TICK_SHIFT).wrapping_add(1);
+                Some((tick << TICK_SHIFT) | (curr & READY_MASK) | ready)
+            });
+
+        let mut waiters = self.waiters.lock().unwrap();
+        let reader = if ready & READABLE != 0 {
+            waiters.reader.take()
+        } else {
+            None
+        };
+        let writer = if ready & WRITABLE != 0 {
+            waiters.writer.take()
+        } else {
+            None
+        };
+        drop(waiters);
+
+        for waker in reader.into_iter().chain(writer) {
+            waker.wake();
+        }
+    }
+
+    fn poll_ready(&self, cx: &mut Context<'_>, interest: Interest) -> Poll<ReadyEvent> {
+        let mask = interest.mask();
+        let curr = self.readiness.load(Ordering::Acquire);
+        if curr & mask != 0 {
+            return Poll::Ready(ReadyEvent::new(curr, mask));
+        }
+
+        let mut waiters = self.waiters.lock().unwrap();
+        let slot = match interest {
+            Interest::Readable => &mut waiters.reader,
+            Interest::Writable => &mut waiters.writer,
+        };
+        match slot {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *slot = Some(cx.waker().clone()),
+        }
+
+        // 保存 waker 之后再检查一次，期间到达的事件可能没有看到新的 waker
+        let curr = self.readiness.load(Ordering::Acquire);
+        if curr & mask != 0 {
+            Poll::Ready(ReadyEvent::new(curr, mask))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn clear_readiness(&self, event: ReadyEvent) {
+        let _ = self
+            .readiness
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |curr| {
+                // 期间又来了新的事件，保留就绪状态
+                if curr >> TICK_SHIFT != event.tick {
+                    return None;
+                }
+                Some(curr & !event.ready)
+            });
+    }
+}
+
+impl ReadyEvent {
+    fn new(curr: usize, mask: usize) -> ReadyEvent {
+        ReadyEvent {
+            tick: curr >> TICK_SHIFT,
+            ready: curr & mask,
+        }
+    }
+}
+
+/// 注册到 IO 驱动中的 fd，fd 本身仍然由调用方持有和关闭
+pub struct Registration {
+    handle: Handle,
+    fd: RawFd,
+    token: u64,
+    io: Arc<ScheduledIo>,
+}
+
+impl Registration {
+    /// 在 fd 就绪时执行 `f`，`f` 返回 `WouldBlock` 时清除就绪状态，等待下一次事件
+    pub fn poll_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        mut f: impl FnMut() -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            let event = match self.io.poll_ready(cx, interest) {
+                Poll::Ready(event) => event,
+                Poll::Pending => return Poll::Pending,
+            };
+            match f() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    self.io.clear_readiness(event);
+                }
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    /// 等待 fd 可写，例如非阻塞的 connect 完成
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.io.poll_ready(cx, Interest::Writable).map(|_| ())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = syscall!(epoll_ctl(
+            self.handle.driver.epoll_fd,
+            libc::EPOLL_CTL_DEL,
+            self.fd,
+            std::ptr::null_mut()
+        ));
+        self.handle.driver.ios.lock().unwrap().remove(&self.token);
+    }
+}
+
+/// 边缘触发，同时关注读写，之后不需要再修改
+fn add_interest(epoll_fd: RawFd, fd: RawFd, token: u64) -> io::Result<()> {
+    let mut event = libc::epoll_event {
+        events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET) as u32,
+        u64: token,
+    };
+    syscall!(epoll_ctl(epoll_fd, libc::EPOLL_CTL_ADD, fd, &mut event))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::{self, ArcWake};
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    /// 记录被唤醒的次数
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl ArcWake for CountWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl CountWaker {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn ready(io: &ScheduledIo) -> usize {
+        io.readiness.load(Ordering::Acquire) & READY_MASK
+    }
+
+    #[test]
+    fn clear_keeps_newer_events() {
+        let io = ScheduledIo::default();
+        let counter = Arc::new(CountWaker::default());
+        let waker = task::waker(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        io.set_readiness(READABLE);
+        let Poll::Ready(first) = io.poll_ready(&mut cx, Interest::Readable) else {
+            panic!("should be readable");
+        };
+        // 读返回 WouldBlock 之后、清除之前又来了一个事件，这次清除不生效
+        io.set_readiness(READABLE);
+        io.clear_readiness(first);
+        assert_eq!(ready(&io), READABLE);
+
+        let Poll::Ready(second) = io.poll_ready(&mut cx, Interest::Readable) else {
+            panic!("should be readable");
+        };
+        io.clear_readiness(second);
+        assert_eq!(ready(&io), 0);
+        assert!(io.poll_ready(&mut cx, Interest::Readable).is_pending());
+
+        // 等待期间到来的事件唤醒保存的 waker
+        io.set_readiness(READABLE);
+        assert_eq!(counter.count(), 1);
+        assert!(io.poll_ready(&mut cx, Interest::Readable).is_ready());
+    }
+
+    #[test]
+    fn clear_only_observed_interest() {
+        let io = ScheduledIo::default();
+        let reader = Arc::new(CountWaker::default());
+        let writer = Arc::new(CountWaker::default());
+        let reader_waker = task::waker(reader.clone());
+        let writer_waker = task::waker(writer.clone());
+
+        assert!(io
+            .poll_ready(&mut Context::from_waker(&reader_waker), Interest::Readable)
+            .is_pending());
+        assert!(io
+            .poll_ready(&mut Context::from_waker(&writer_waker), Interest::Writable)
+            .is_pending());
+
+        // 只唤醒关注这个事件的 waker
+        io.set_readiness(WRITABLE);
+        assert_eq!((reader.count(), writer.count()), (0, 1));
+        io.set_readiness(READABLE);
+        assert_eq!((reader.count(), writer.count()), (1, 1));
+        assert_eq!(ready(&io), READABLE | WRITABLE);
+
+        // 清除可读不影响可写
+        let Poll::Ready(event) =
+            io.poll_ready(&mut Context::from_waker(&reader_waker), Interest::Readable)
+        else {
+            panic!("should be readable");
+        };
+        io.clear_readiness(event);
+        assert_eq!(ready(&io), WRITABLE);
+    }
+
+    #[test]
+    fn readiness_cleared_on_would_block() {
+        let handle = Handle::new().unwrap();
+        let (mut peer, socket) = UnixStream::pair().unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let registration = handle.register(socket.as_raw_fd()).unwrap();
+
+        let counter = Arc::new(CountWaker::default());
+        let waker = task::waker(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        // 每次最多读 4 个字节
+        let read = || {
+            let mut buf = [0; 4];
+            let n = (&socket).read(&mut buf)?;
+            Ok(buf[..n].to_vec())
+        };
+
+        // 还没有数据，读返回 WouldBlock 之后等待
+        assert!(registration
+            .poll_io(&mut cx, Interest::Readable, read)
+            .is_pending());
+        handle.poll_events().unwrap();
+        assert_eq!(ready(&registration.io) & READABLE, 0);
+        assert_eq!(counter.count(), 0);
+
+        peer.write_all(b"hello world").unwrap();
+        handle.poll_events().unwrap();
+        assert_eq!(counter.count(), 1);
+        assert_eq!(ready(&registration.io) & READABLE, READABLE);
+
+        // 一次没读完时就绪状态保留，不需要新的事件就能读完剩下的数据
+        let mut received = vec![];
+        while let Poll::Ready(n) = registration.poll_io(&mut cx, Interest::Readable, read) {
+            received.extend(n.unwrap());
+        }
+        assert_eq!(received, b"hello world");
+        // 读到 WouldBlock 之后才清除，这时没有新的事件不会被唤醒
+        assert_eq!(ready(&registration.io) & READABLE, 0);
+        handle.poll_events().unwrap();
+        assert_eq!(counter.count(), 1);
+
+        // 边缘触发：新的数据带来新的事件
+        peer.write_all(b"again").unwrap();
+        handle.poll_events().unwrap();
+        assert_eq!(counter.count(), 2);
+        let Poll::Ready(n) = registration.poll_io(&mut cx, Interest::Readable, read) else {
+            panic!("should be readable");
+        };
+        assert_eq!(n.unwrap(), b"agai");
+
+        // 对端关闭之后可读，读到 EOF
+        drop(peer);
+        handle.poll_events().unwrap();
+        let mut rest = vec![];
+        loop {
+            match registration.poll_io(&mut cx, Interest::Readable, read) {
+                Poll::Ready(Ok(data)) if data.is_empty() => break,
+                Poll::Ready(Ok(data)) => rest.extend(data),
+                res => panic!("unexpected {:?}", res.map(|res| res.map(|_| ()))),
+            }
+        }
+        assert_eq!(rest, b"n");
+    }
+}
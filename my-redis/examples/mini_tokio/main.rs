@@ -0,0 +1,322 @@
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use rand::Rng;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod bench;
+mod blocking;
+mod io;
+mod multi_thread;
+mod net;
+mod runtime;
+mod task;
+mod time;
+
+use runtime::MiniTokio;
+
+// 我们的 mini-tokio 只应该在 Future 准备好可以进一步运行后，才去 poll 它，
+// 例如该 Future 之前阻塞等待的资源已经准备好并可以被使用了，就可以对其进行 poll。
+// 再比如，如果一个 Future 任务在阻塞等待从 TCP socket 中读取数据，那我们只想在 socket 中有数据可以读取后才去 poll 它
+// mini-tokio 只应该当任务的延迟时间到了后，才去 poll 它。
+// 为了实现这个功能，我们需要 通知 -> 运行 机制：当任务可以进一步被推进运行时，它会主动通知执行器，然后执行器再来 poll。
+// 完整代码见 https://github.com/tokio-rs/website/blob/master/tutorial-code/mini-tokio/src/main.rs
+//
+// 教程中的 `Delay` 为每个计时器生成一个线程，这里换成了由执行器驱动的分层时间轮，见 time.rs。
+// 没有任务可以运行时执行器阻塞在 epoll_wait 中，socket 就绪后唤醒对应的任务，见 io.rs 和 net.rs。
+// `cargo run --release --example mini_tokio`
+// `cargo run --release --example mini_tokio echo` 在 6142 端口运行 echo 服务端，可以用 `nc 127.0.0.1 6142` 测试
+// `cargo run --release --example mini_tokio bench [workers]` 比较单线程和多线程的调度器，见 bench.rs
+// `cargo run --release --example mini_tokio blocking_client` 用 mini-tokio 重写 tokio_blocking_client.rs
+fn main() {
+    let mut mini_tokio = MiniTokio::new().unwrap();
+
+    if std::env::args().nth(1).as_deref() == Some("echo") {
+        mini_tokio.spawn(async {
+            let listener = net::TcpListener::bind("127.0.0.1:6142").await.unwrap();
+            loop {
+                let (socket, addr) = listener.accept().await.unwrap();
+                println!("accepted {}", addr);
+                runtime::spawn(echo(socket));
+            }
+        });
+        mini_tokio.run();
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        bench::run(std::env::args().nth(2).map(|n| n.parse().unwrap()));
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("blocking_client") {
+        blocking_client();
+        return;
+    }
+
+    mini_tokio.spawn(async {
+        time::sleep(Duration::from_millis(10)).await;
+        println!("done");
+    });
+
+    // interval 的第一次 tick 立即完成
+    mini_tokio.spawn(async {
+        let start = Instant::now();
+        let mut interval = time::interval(Duration::from_millis(20));
+        for _ in 0..3 {
+            interval.tick().await;
+            println!("tick at {}ms", start.elapsed().as_millis());
+        }
+    });
+
+    // 超时之后内部的 sleep 被 drop，它的计时器也从时间轮中删除
+    mini_tokio.spawn(async {
+        let res = time::timeout(
+            Duration::from_millis(30),
+            time::sleep(Duration::from_secs(3600)),
+        )
+        .await;
+        println!("timeout: {:?}", res);
+        let res = time::timeout(Duration::from_millis(30), async { 42 }).await;
+        println!("timeout: {:?}", res);
+    });
+
+    mini_tokio.run();
+    println!("timers left: {}", mini_tokio.timer().len());
+
+    // `block_on` 运行一个根 future 并返回它的输出，任务的输出通过 `JoinHandle` 取得
+    let sum = mini_tokio.block_on(async {
+        let handles: Vec<_> = (1..=10u64)
+            .map(|i| {
+                runtime::spawn(async move {
+                    time::sleep(Duration::from_millis(i)).await;
+                    i * i
+                })
+            })
+            .collect();
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.await.unwrap();
+        }
+        sum
+    });
+    println!("sum of squares: {}", sum);
+
+    // 任务中的 panic 不会影响执行器和其它任务
+    let handle = mini_tokio.spawn(async { panic!("boom") });
+    let err = mini_tokio.block_on(handle).unwrap_err();
+    assert!(err.is_panic());
+    println!("{}", err);
+
+    // abort 之后任务的 future 被 drop，其中的计时器也随之取消
+    let res = mini_tokio.block_on(async {
+        let handle = runtime::spawn(time::sleep(Duration::from_secs(3600)));
+        time::sleep(Duration::from_millis(1)).await;
+        handle.abort();
+        handle.await
+    });
+    assert!(res.unwrap_err().is_cancelled());
+    println!("aborted, timers left: {}", mini_tokio.timer().len());
+
+    // 阻塞的代码交给 spawn_blocking，执行器在这期间仍然在运行计时器。线程池最多 2 个线程，4 个任务分两批运行
+    let mut blocking_runtime = MiniTokio::new()
+        .unwrap()
+        .max_blocking_threads(2)
+        .thread_keep_alive(Duration::from_millis(50));
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let sum = blocking_runtime.block_on({
+        let ticks = ticks.clone();
+        async move {
+            let ticker = runtime::spawn(async move {
+                let mut interval = time::interval(Duration::from_millis(10));
+                loop {
+                    interval.tick().await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            let handles: Vec<_> = (1..=4u64)
+                .map(|i| {
+                    runtime::spawn_blocking(move || {
+                        std::thread::sleep(Duration::from_millis(100));
+                        i
+                    })
+                })
+                .collect();
+            let mut sum = 0;
+            for handle in handles {
+                sum += handle.await.unwrap();
+            }
+            ticker.abort();
+            sum
+        }
+    });
+    println!(
+        "blocking sum {} in {}ms on {} threads, {} ticks meanwhile",
+        sum,
+        start.elapsed().as_millis(),
+        blocking_runtime.blocking_threads(),
+        ticks.load(Ordering::SeqCst)
+    );
+    std::thread::sleep(Duration::from_millis(100));
+    println!(
+        "blocking threads after keep-alive: {}",
+        blocking_runtime.blocking_threads()
+    );
+
+    // echo 服务端和客户端都在同一个执行器中，每个连接一个任务
+    echo_test(&mut mini_tokio, "current_thread");
+    // 多线程的运行时中，任务在 4 个 worker 之间被偷来偷去
+    echo_test(
+        &mut MiniTokio::new_multi_thread(4).unwrap(),
+        "multi_thread(4)",
+    );
+
+    // 10 万个计时器仍然只有执行器一个线程
+    const TIMERS: usize = 100_000;
+    let lateness = Arc::new(Mutex::new(Vec::with_capacity(TIMERS)));
+    let start = Instant::now();
+    let mut rng = rand::thread_rng();
+    for _ in 0..TIMERS {
+        let deadline = start + Duration::from_millis(rng.gen_range(1..=1000));
+        let lateness = lateness.clone();
+        mini_tokio.spawn(async move {
+            time::sleep_until(deadline).await;
+            let late = Instant::now() - deadline;
+            lateness.lock().unwrap().push(late);
+        });
+    }
+    mini_tokio.run();
+
+    let mut lateness = lateness.lock().unwrap();
+    lateness.sort();
+    println!(
+        "{} timers in {:?}, lateness p50 {:?} p99 {:?} max {:?}",
+        lateness.len(),
+        start.elapsed(),
+        lateness[lateness.len() / 2],
+        lateness[lateness.len() * 99 / 100],
+        lateness[lateness.len() - 1],
+    );
+
+    // Output(时间每次运行都不一样):
+    // tick at 0ms
+    // done
+    // tick at 20ms
+    // timeout: Err(Elapsed)
+    // timeout: Ok(42)
+    // tick at 40ms
+    // timers left: 0
+    // sum of squares: 385
+    // thread 'main' panicked at examples/mini_tokio/main.rs:105:43:
+    // boom
+    // note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+    // task panicked with message "boom"
+    // aborted, timers left: 0
+    // blocking sum 10 in 201ms on 2 threads, 21 ticks meanwhile
+    // blocking threads after keep-alive: 0
+    // current_thread: echoed 100 messages in 130ms
+    // multi_thread(4): echoed 100 messages in 120ms
+    // 100000 timers in 1.006s, lateness p50 970µs p99 25ms max 45ms
+    //
+    // 计时器的精度是 1ms，所以一般会晚 1ms 以内；p99 和 max 来自开头的几十毫秒，
+    // 那时执行器还在第一次 poll 这 10 万个任务。之后到期的计时器 p99 在 2ms 以内
+}
+
+/// tokio_blocking_client.rs 中耗时的工作在主线程中运行，后台任务在 tokio 的 worker 线程中运行。
+/// 这里用的是单线程的运行时，耗时的工作交给 `spawn_blocking`，`block_on` 等它完成的同时运行后台任务，输出和原来的例子一样
+fn blocking_client() {
+    let mut mini_tokio = MiniTokio::new().unwrap();
+
+    let mut handles = Vec::with_capacity(10);
+    for i in 0..10 {
+        handles.push(mini_tokio.spawn(my_bg_task(i)));
+    }
+
+    // 在后台任务运行的同时做一些耗费时间的事情
+    let work = mini_tokio.spawn_blocking(|| {
+        std::thread::sleep(Duration::from_millis(750));
+        println!("Finished time-consuming task.");
+    });
+    mini_tokio.block_on(work).unwrap();
+
+    // 等待这些后台任务的完成
+    for handle in handles {
+        mini_tokio.block_on(handle).unwrap();
+    }
+}
+
+async fn my_bg_task(i: u64) {
+    let millis = 1000 - 50 * i;
+    println!("Task {} sleeping for {} ms.", i, millis);
+
+    time::sleep(Duration::from_millis(millis)).await;
+
+    println!("Task {} stopping.", i);
+}
+
+fn echo_test(mini_tokio: &mut MiniTokio, name: &str) {
+    const CLIENTS: usize = 100;
+    let echoed = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    mini_tokio.spawn({
+        let echoed = echoed.clone();
+        async move {
+            let listener = net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            for i in 0..CLIENTS {
+                runtime::spawn(client(addr, i, echoed.clone()));
+            }
+            for _ in 0..CLIENTS {
+                let (socket, _) = listener.accept().await.unwrap();
+                runtime::spawn(echo(socket));
+            }
+        }
+    });
+    mini_tokio.run();
+    println!(
+        "{}: echoed {} messages in {:?}",
+        name,
+        echoed.load(Ordering::SeqCst),
+        start.elapsed()
+    );
+}
+
+/// 把读到的数据原样写回，直到对端关闭连接
+async fn echo(mut socket: net::TcpStream) {
+    let mut buf = vec![0; 1024];
+    loop {
+        match socket.read(&mut buf).await {
+            Ok(0) => return,
+            Ok(n) => {
+                if socket.write_all(&buf[..n]).await.is_err() {
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+/// 发送一条比 socket 缓冲区大的消息，边写边读，检查读回的内容
+async fn client(addr: SocketAddr, i: usize, echoed: Arc<AtomicUsize>) {
+    let socket = net::TcpStream::connect(addr).await.unwrap();
+    let message: Vec<u8> = format!("hello {}\n", i)
+        .bytes()
+        .cycle()
+        .take(256 * 1024)
+        .collect();
+
+    let (mut reader, mut writer) = socket.split();
+    let write = async {
+        writer.write_all(&message).await.unwrap();
+        writer.close().await.unwrap();
+    };
+    let read = async {
+        let mut received = vec![];
+        reader.read_to_end(&mut received).await.unwrap();
+        received
+    };
+    let ((), received) = futures::join!(write, read);
+    assert!(received == message, "client {} got a different message", i);
+    echoed.fetch_add(1, Ordering::SeqCst);
+}
@@ -0,0 +1,428 @@
+//! 多线程的调度器，结构和 tokio 的 `runtime/scheduler/multi_thread` 一样，队列用的是 crossbeam 的 deque。
+//!
+//! * 每个 worker 有一个本地队列，worker 中生成的任务放在这里，不需要和其它线程竞争
+//! * 不在 worker 中生成或者唤醒的任务(例如在 `block_on` 的根 future 中)放进全局的注入队列(injector)
+//! * worker 的本地队列和全局队列都空了之后，从随机的一个 worker 开始偷一半的任务(work stealing)
+//! * 在 worker 中被唤醒的任务放进 LIFO 槽，下一个就运行它。消息传递的场景下，接收方很快就能处理刚刚发出的消息，
+//!   数据也还在缓存中。同一个 LIFO 槽连续使用 `MAX_LIFO_POLLS` 次之后放回队尾，避免两个任务互相唤醒，一直霸占 worker
+//!
+//! 没有任务可以运行的 worker 先尝试成为驱动者(driver)：处理计时器并阻塞在 `epoll_wait` 中，同一时间只有一个；
+//! 其它空闲的 worker 阻塞在条件变量上。本地队列中有了可以偷的任务时唤醒一个空闲的 worker，
+//! 没有空闲的 worker 时叫醒阻塞在 `epoll_wait` 中的驱动者
+
+use crate::io;
+use crate::runtime::{Shared, EVENT_INTERVAL};
+use crate::task::Task;
+
+use crossbeam::deque::{Injector, Steal, Stealer, Worker};
+use rand::Rng;
+use std::cell::RefCell;
+use std::ptr;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+/// 每运行多少个任务先检查一次全局队列，避免本地队列一直不空时全局队列中的任务饿死。tokio 中默认是 31
+const GLOBAL_QUEUE_INTERVAL: usize = 31;
+
+/// LIFO 槽最多连续使用的次数，和 tokio 一样
+const MAX_LIFO_POLLS: usize = 3;
+
+thread_local! {
+    /// 当前线程是 worker 时，它的本地队列
+    static WORKER: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+pub(crate) struct Pool {
+    injector: Injector<Arc<Task>>,
+    /// 每个 worker 的本地队列的窃取端
+    stealers: Vec<Stealer<Arc<Task>>>,
+    /// 持有它的 worker 负责计时器和 IO 事件
+    driver: Mutex<()>,
+    /// 阻塞在条件变量上的 worker 数
+    sleepers: AtomicUsize,
+    /// 发出了但还没有被消耗的通知数
+    notified: Mutex<usize>,
+    condvar: Condvar,
+    shutdown: AtomicBool,
+}
+
+struct Local {
+    /// 用来判断当前线程是不是这个运行时的 worker
+    pool: *const Pool,
+    index: usize,
+    queue: Worker<Arc<Task>>,
+    lifo: Option<Arc<Task>>,
+    lifo_polls: usize,
+}
+
+impl Pool {
+    /// 返回的本地队列交给各个 worker 线程
+    pub(crate) fn new(workers: usize) -> (Pool, Vec<Worker<Arc<Task>>>) {
+        let queues: Vec<_> = (0..workers).map(|_| Worker::new_fifo()).collect();
+        let pool = Pool {
+            injector: Injector::new(),
+            stealers: queues.iter().map(Worker::stealer).collect(),
+            driver: Mutex::new(()),
+            sleepers: AtomicUsize::new(0),
+            notified: Mutex::new(0),
+            condvar: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        };
+        (pool, queues)
+    }
+
+    pub(crate) fn push(&self, task: Arc<Task>, lifo: bool, io: &io::Handle) {
+        let remote = WORKER.with(|worker| {
+            let mut worker = worker.borrow_mut();
+            let local = match worker.as_mut() {
+                Some(local) if ptr::eq(local.pool, self) => local,
+                _ => return Some(task),
+            };
+            if !lifo {
+                local.queue.push(task);
+            } else if let Some(prev) = local.lifo.replace(task) {
+                local.queue.push(prev);
+            } else {
+                // LIFO 槽不能被偷，不需要唤醒其它 worker
+                return None;
+            }
+            self.notify_one(io);
+            None
+        });
+        if let Some(task) = remote {
+            self.injector.push(task);
+            self.notify_one(io);
+        }
+    }
+
+    pub(crate) fn shutdown(&self, io: &io::Handle) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        let _notified = self.notified.lock().unwrap();
+        self.condvar.notify_all();
+        io.unpark();
+    }
+
+    /// worker 都退出之后取出队列中剩下的任务。worker 的本地队列也要清空，`stealers` 和它共享同一块缓冲区
+    pub(crate) fn drain(&self) {
+        while steal(|| self.injector.steal()).is_some() {}
+        for stealer in &self.stealers {
+            while steal(|| stealer.steal()).is_some() {}
+        }
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// 有没有可以偷的任务
+    fn has_work(&self) -> bool {
+        !self.injector.is_empty() || self.stealers.iter().any(|stealer| !stealer.is_empty())
+    }
+
+    /// 唤醒一个空闲的 worker，没有空闲的 worker 时叫醒驱动者
+    fn notify_one(&self, io: &io::Handle) {
+        // 和 `sleep` 中的 fence 配对：要么这里看到 sleeper，要么 sleeper 看到刚放进队列的任务
+        atomic::fence(Ordering::SeqCst);
+        let sleepers = self.sleepers.load(Ordering::SeqCst);
+        if sleepers == 0 {
+            io.unpark();
+            return;
+        }
+        let mut notified = self.notified.lock().unwrap();
+        if *notified < sleepers {
+            *notified += 1;
+            self.condvar.notify_one();
+        }
+    }
+
+    /// 阻塞在条件变量上，直到被通知、有了可以偷的任务或者运行时关闭
+    fn sleep(&self) {
+        let mut notified = self.notified.lock().unwrap();
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+        while *notified == 0 && !self.has_work() && !self.is_shutdown() {
+            notified = self.condvar.wait(notified).unwrap();
+        }
+        if *notified > 0 {
+            *notified -= 1;
+        }
+        self.sleepers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn next_task(&self, tick: usize) -> Option<Arc<Task>> {
+        WORKER.with(|worker| {
+            let mut worker = worker.borrow_mut();
+            let local = worker.as_mut().expect("not a worker thread");
+
+            if tick.is_multiple_of(GLOBAL_QUEUE_INTERVAL) {
+                if let Some(task) = steal(|| self.injector.steal()) {
+                    return Some(task);
+                }
+            }
+
+            if let Some(task) = local.lifo.take() {
+                if local.lifo_polls < MAX_LIFO_POLLS {
+                    local.lifo_polls += 1;
+                    return Some(task);
+                }
+                local.queue.push(task);
+            }
+            local.lifo_polls = 0;
+
+            local
+                .queue
+                .pop()
+                .or_else(|| steal(|| self.injector.steal_batch_and_pop(&local.queue)))
+                .or_else(|| self.steal_from_others(local))
+        })
+    }
+
+    /// 从随机的一个 worker 开始，依次尝试偷其它 worker 一半的任务
+    fn steal_from_others(&self, local: &Local) -> Option<Arc<Task>> {
+        let workers = self.stealers.len();
+        let start = rand::thread_rng().gen_range(0..workers);
+        (0..workers)
+            .map(|i| (start + i) % workers)
+            .filter(|&i| i != local.index)
+            .find_map(|i| steal(|| self.stealers[i].steal_batch_and_pop(&local.queue)))
+    }
+
+    /// 没有任务可以运行时调用
+    fn park(&self, shared: &Shared) {
+        let _driver = match self.driver.try_lock() {
+            Ok(driver) => driver,
+            Err(_) => return self.sleep(),
+        };
+
+        shared.timer.process();
+        let timeout = shared
+            .timer
+            .next_deadline()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        shared
+            .io
+            .park(timeout, || {
+                !self.has_work() && !self.is_shutdown() && !lifo_is_occupied()
+            })
+            .expect("epoll_wait failed");
+        shared.timer.process();
+    }
+
+    /// worker 一直有任务可以运行时，隔一段时间处理一次计时器和 IO 事件，不阻塞
+    fn maintenance(&self, shared: &Shared) {
+        if let Ok(_driver) = self.driver.try_lock() {
+            shared.timer.process();
+            shared.io.poll_events().expect("epoll_wait failed");
+        }
+    }
+}
+
+/// worker 线程的主循环，运行时被 drop 时返回
+pub(crate) fn run_worker(shared: Arc<Shared>, index: usize, queue: Worker<Arc<Task>>) {
+    let _enter = shared.enter();
+    let pool = shared.pool();
+    WORKER.with(|worker| {
+        *worker.borrow_mut() = Some(Local {
+            pool,
+            index,
+            queue,
+            lifo: None,
+            lifo_polls: 0,
+        })
+    });
+
+    let mut tick: usize = 0;
+    while !pool.is_shutdown() {
+        tick += 1;
+        if tick.is_multiple_of(EVENT_INTERVAL) {
+            pool.maintenance(&shared);
+        }
+        match pool.next_task(tick) {
+            Some(task) => task.poll(),
+            None => pool.park(&shared),
+        }
+    }
+
+    // 本地队列中剩下的任务不会再运行了
+    WORKER.with(|worker| worker.borrow_mut().take());
+}
+
+fn lifo_is_occupied() -> bool {
+    WORKER.with(|worker| {
+        worker
+            .borrow()
+            .as_ref()
+            .is_some_and(|local| local.lifo.is_some())
+    })
+}
+
+/// `Steal::Retry` 表示和其它线程冲突了，重试
+fn steal<T>(mut f: impl FnMut() -> Steal<T>) -> Option<T> {
+    loop {
+        match f() {
+            Steal::Success(task) => return Some(task),
+            Steal::Empty => return None,
+            Steal::Retry => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::{self, MiniTokio};
+    use crate::time;
+
+    use std::collections::HashSet;
+    use std::future::{poll_fn, Future};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    /// 测试卡住(丢失了唤醒)时失败，而不是一直挂起
+    fn block_on<F: Future>(runtime: &mut MiniTokio, future: F) -> F::Output {
+        runtime
+            .block_on(async { time::timeout(Duration::from_secs(10), future).await })
+            .expect("runtime stalled")
+    }
+
+    /// 让出一次，任务被放回队尾
+    async fn yield_now() {
+        let mut yielded = false;
+        poll_fn(|cx| {
+            if yielded {
+                return Poll::Ready(());
+            }
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[test]
+    fn idle_workers_steal_from_busy_worker() {
+        let mut runtime = MiniTokio::new_multi_thread(4).unwrap();
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+
+        // 所有子任务都在同一个 worker 中生成，放在它的本地队列里；它们同步地阻塞一会儿，只有被偷走才能并行运行
+        let spawner = {
+            let threads = threads.clone();
+            runtime.spawn(async move {
+                let handles: Vec<_> = (0..64)
+                    .map(|i| {
+                        let threads = threads.clone();
+                        runtime::spawn(async move {
+                            thread::sleep(Duration::from_millis(2));
+                            threads.lock().unwrap().insert(thread::current().id());
+                            i
+                        })
+                    })
+                    .collect();
+                let mut sum = 0;
+                for handle in handles {
+                    sum += handle.await.unwrap();
+                }
+                sum
+            })
+        };
+        assert_eq!(
+            block_on(&mut runtime, spawner).unwrap(),
+            (0..64).sum::<i32>()
+        );
+        assert!(threads.lock().unwrap().len() > 1);
+    }
+
+    #[test]
+    fn every_task_runs_once_under_contention() {
+        const PARENTS: usize = 64;
+        const CHILDREN: usize = 64;
+        const YIELDS: usize = 4;
+
+        let mut runtime = MiniTokio::new_multi_thread(8).unwrap();
+        let polls = Arc::new(AtomicUsize::new(0));
+
+        // 父任务从 worker 之外生成(进入全局队列)，子任务在各个 worker 中生成(进入本地队列)，
+        // 子任务反复让出，worker 之间不停地互相偷任务
+        let sum = block_on(&mut runtime, {
+            let polls = polls.clone();
+            async move {
+                let parents: Vec<_> = (0..PARENTS)
+                    .map(|parent| {
+                        let polls = polls.clone();
+                        runtime::spawn(async move {
+                            let children: Vec<_> = (0..CHILDREN)
+                                .map(|child| {
+                                    let polls = polls.clone();
+                                    runtime::spawn(async move {
+                                        for _ in 0..YIELDS {
+                                            polls.fetch_add(1, Ordering::SeqCst);
+                                            yield_now().await;
+                                        }
+                                        parent * CHILDREN + child
+                                    })
+                                })
+                                .collect();
+                            let mut sum = 0;
+                            for child in children {
+                                sum += child.await.unwrap();
+                            }
+                            sum
+                        })
+                    })
+                    .collect();
+                let mut sum = 0;
+                for parent in parents {
+                    sum += parent.await.unwrap();
+                }
+                sum
+            }
+        });
+        assert_eq!(sum, (0..PARENTS * CHILDREN).sum::<usize>());
+        assert_eq!(polls.load(Ordering::SeqCst), PARENTS * CHILDREN * YIELDS);
+    }
+
+    #[test]
+    fn concurrent_wakes_never_poll_twice() {
+        const TASKS: usize = 32;
+        const ROUNDS: usize = 200;
+
+        let mut runtime = MiniTokio::new_multi_thread(4).unwrap();
+        let wakers: Arc<Mutex<Vec<Option<Waker>>>> = Arc::new(Mutex::new(vec![None; TASKS]));
+
+        // 每个任务在 poll 中唤醒所有其它任务，同一个任务会同时在多个 worker 上被唤醒，
+        // 状态保证它不会同时被两个线程 poll，也不会在队列中出现两次
+        let handles: Vec<_> = (0..TASKS)
+            .map(|index| {
+                let wakers = wakers.clone();
+                let polling = AtomicBool::new(false);
+                let mut rounds = 0;
+                runtime.spawn(poll_fn(move |cx| {
+                    assert!(!polling.swap(true, Ordering::SeqCst), "polled concurrently");
+                    rounds += 1;
+                    let others: Vec<_> = {
+                        let mut wakers = wakers.lock().unwrap();
+                        wakers[index] = Some(cx.waker().clone());
+                        wakers.iter().flatten().cloned().collect()
+                    };
+                    for waker in others {
+                        waker.wake();
+                    }
+                    polling.store(false, Ordering::SeqCst);
+                    if rounds == ROUNDS {
+                        Poll::Ready(rounds)
+                    } else {
+                        // 自己也被唤醒了，不会因为其它任务先完成而卡住
+                        Poll::Pending
+                    }
+                }))
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(block_on(&mut runtime, handle).unwrap(), ROUNDS);
+        }
+    }
+}
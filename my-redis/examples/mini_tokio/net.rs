@@ -0,0 +1,165 @@
+//! 基于 IO 驱动的 `TcpListener` 和 `TcpStream`，读写实现了 futures 的 `AsyncRead`/`AsyncWrite`。
+//!
+//! socket 本身还是标准库的类型，只是设置成了非阻塞，读写返回 `WouldBlock` 时任务让出，等 epoll 通知之后再重试
+
+use crate::io::{syscall, Handle, Interest, Registration};
+
+use futures::io::{AsyncRead, AsyncWrite};
+use std::future::poll_fn;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{self, Shutdown, SocketAddr, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pub struct TcpListener {
+    // 先于 socket 被 drop，从 epoll 中删除时 fd 还没有关闭
+    registration: Registration,
+    inner: net::TcpListener,
+}
+
+pub struct TcpStream {
+    registration: Registration,
+    inner: net::TcpStream,
+}
+
+impl TcpListener {
+    /// 必须在 mini-tokio 的运行时中调用
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<TcpListener> {
+        let inner = net::TcpListener::bind(addr)?;
+        inner.set_nonblocking(true)?;
+        let registration = Handle::current().register(inner.as_raw_fd())?;
+        Ok(TcpListener {
+            registration,
+            inner,
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Interest::Readable, || self.inner.accept())
+        })
+        .await?;
+        Ok((TcpStream::from_std(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl TcpStream {
+    /// 非阻塞的 connect 立即返回 `EINPROGRESS`，等 socket 可写时连接就完成了，结果通过 `SO_ERROR` 获取
+    pub async fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = syscall!(socket(
+            domain,
+            libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0
+        ))?;
+        // 之后出错返回时由标准库的类型关闭 fd
+        let inner = unsafe { net::TcpStream::from_raw_fd(fd) };
+
+        let (storage, len) = socket_addr(&addr);
+        match syscall!(connect(
+            fd,
+            &storage as *const libc::sockaddr_storage as *const libc::sockaddr,
+            len
+        )) {
+            Ok(_) => {}
+            Err(err) if err.raw_os_error() == Some(libc::EINPROGRESS) => {}
+            Err(err) => return Err(err),
+        }
+
+        let stream = TcpStream::from_std(inner)?;
+        poll_fn(|cx| stream.registration.poll_write_ready(cx)).await;
+        if let Some(err) = stream.inner.take_error()? {
+            return Err(err);
+        }
+        Ok(stream)
+    }
+
+    fn from_std(inner: net::TcpStream) -> io::Result<TcpStream> {
+        inner.set_nonblocking(true)?;
+        let registration = Handle::current().register(inner.as_raw_fd())?;
+        Ok(TcpStream {
+            registration,
+            inner,
+        })
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.registration
+            .poll_io(cx, Interest::Readable, || (&this.inner).read(buf))
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.registration
+            .poll_io(cx, Interest::Writable, || (&this.inner).write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // 没有用户态的缓冲区
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.inner.shutdown(Shutdown::Write))
+    }
+}
+
+/// 把 `SocketAddr` 转换成 connect 需要的 `sockaddr_in`/`sockaddr_in6`
+fn socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: addr.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe {
+                (&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in).write(sin)
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                },
+                sin6_scope_id: addr.scope_id(),
+            };
+            unsafe {
+                (&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6).write(sin6)
+            };
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
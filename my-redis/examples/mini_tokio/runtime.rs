@@ -0,0 +1,456 @@
+//! 执行器：调度队列，以及驱动任务、计时器和 IO 的主循环 `block_on`。
+//!
+//! 单线程模式下所有任务都在调用 `block_on` 的线程中运行；多线程模式下任务在 worker 线程中运行，
+//! 调用 `block_on` 的线程只 poll 根 future，见 multi_thread.rs
+
+use crate::blocking::BlockingPool;
+use crate::multi_thread::{self, Pool};
+use crate::task::{JoinHandle, OwnedTasks, Task};
+use crate::{io, time};
+
+use crossbeam::channel;
+use futures::task::{self, ArcWake};
+use std::cell::RefCell;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 执行器每运行多少个任务检查一次计时器和 IO 事件，tokio 中对应的是 `event_interval`，默认 61
+pub(crate) const EVENT_INTERVAL: usize = 61;
+
+thread_local! {
+    /// 任务中的 `spawn` 通过它找到所在的运行时
+    static CURRENT: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+}
+
+pub struct MiniTokio {
+    shared: Arc<Shared>,
+    /// 单线程模式下的调度队列，多线程模式下是 None
+    scheduled: Option<channel::Receiver<Arc<Task>>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+/// 任务和执行器共享的状态
+pub(crate) struct Shared {
+    scheduler: Scheduler,
+    /// 还没有完成的任务数，为 0 时 `run` 返回
+    tasks: AtomicUsize,
+    /// `run` 等待所有任务完成时的 waker
+    idle: Mutex<Option<Waker>>,
+    /// 还没有完成的任务，运行时被 drop 时 drop 它们的 future
+    pub(crate) owned: OwnedTasks,
+    /// 所有任务共享的时间轮
+    pub(crate) timer: time::Handle,
+    /// 所有任务共享的 IO 驱动
+    pub(crate) io: io::Handle,
+    /// `spawn_blocking` 的线程池
+    blocking: BlockingPool,
+}
+
+enum Scheduler {
+    CurrentThread(channel::Sender<Arc<Task>>),
+    MultiThread(Box<Pool>),
+}
+
+impl Shared {
+    fn new(scheduler: Scheduler) -> std::io::Result<Shared> {
+        Ok(Shared {
+            scheduler,
+            tasks: AtomicUsize::new(0),
+            idle: Mutex::new(None),
+            owned: OwnedTasks::new(),
+            timer: time::Handle::new(),
+            io: io::Handle::new()?,
+            blocking: BlockingPool::new(),
+        })
+    }
+
+    /// 把任务放进调度队列。`lifo` 表示任务是被唤醒的(而不是新生成的或者主动让出的)，
+    /// 多线程的调度器会把它放在当前 worker 的 LIFO 槽中
+    pub(crate) fn schedule(&self, task: Arc<Task>, lifo: bool) {
+        match &self.scheduler {
+            // 任务可能在别的线程中被唤醒，这时执行器可能正阻塞在 `epoll_wait` 中，需要把它叫醒
+            Scheduler::CurrentThread(sender) => {
+                let _ = sender.send(task);
+                self.io.unpark();
+            }
+            Scheduler::MultiThread(pool) => pool.push(task, lifo, &self.io),
+        }
+    }
+
+    pub(crate) fn task_spawned(&self) {
+        self.tasks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// 最后一个任务完成时唤醒 `run`
+    pub(crate) fn task_done(&self) {
+        if self.tasks.fetch_sub(1, Ordering::SeqCst) == 1 {
+            if let Some(waker) = self.idle.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+
+    pub(crate) fn pool(&self) -> &Pool {
+        match &self.scheduler {
+            Scheduler::MultiThread(pool) => pool,
+            Scheduler::CurrentThread(_) => unreachable!("not a multi-thread runtime"),
+        }
+    }
+
+    /// 设置当前线程的运行时、计时器和 IO 驱动，任务中的 `time::sleep`、`net::TcpStream` 和 `spawn` 等通过它们找到运行时
+    pub(crate) fn enter(self: &Arc<Self>) -> EnterGuard {
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            assert!(
+                current.is_none(),
+                "cannot start a mini-tokio runtime from within a runtime"
+            );
+            *current = Some(self.clone());
+        });
+        EnterGuard {
+            _timer: self.timer.enter(),
+            _io: self.io.enter(),
+        }
+    }
+}
+
+/// `Shared::enter` 返回的守卫，drop 时清除当前线程的运行时
+pub(crate) struct EnterGuard {
+    _timer: time::EnterGuard,
+    _io: io::EnterGuard,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = None);
+    }
+}
+
+/// `block_on` 的根 future 的 waker。根 future 不在调度队列中，被唤醒时只做个标记，执行器在每一轮开始时检查
+struct RootWaker {
+    woken: AtomicBool,
+    /// 单线程模式下执行器阻塞在 `epoll_wait` 中，多线程模式下调用 `block_on` 的线程阻塞在 `thread::park` 中
+    io: Option<io::Handle>,
+    thread: thread::Thread,
+}
+
+impl ArcWake for RootWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.woken.store(true, Ordering::SeqCst);
+        match &arc_self.io {
+            Some(io) => io.unpark(),
+            None => arc_self.thread.unpark(),
+        }
+    }
+}
+
+impl MiniTokio {
+    /// 单线程的运行时
+    pub fn new() -> std::io::Result<MiniTokio> {
+        let (sender, scheduled) = channel::unbounded();
+        Ok(MiniTokio {
+            shared: Arc::new(Shared::new(Scheduler::CurrentThread(sender))?),
+            scheduled: Some(scheduled),
+            workers: vec![],
+        })
+    }
+
+    /// 有 `workers` 个 worker 线程的运行时，任务在 worker 之间通过工作窃取(work stealing)分配
+    pub fn new_multi_thread(workers: usize) -> std::io::Result<MiniTokio> {
+        assert!(workers > 0, "`workers` must be greater than 0");
+        let (pool, queues) = Pool::new(workers);
+        let shared = Arc::new(Shared::new(Scheduler::MultiThread(Box::new(pool)))?);
+        let mut runtime = MiniTokio {
+            shared,
+            scheduled: None,
+            workers: Vec::with_capacity(workers),
+        };
+        for (index, queue) in queues.into_iter().enumerate() {
+            let shared = runtime.shared.clone();
+            // 出错返回时 `runtime` 被 drop，已经启动的 worker 会被停止
+            let worker = thread::Builder::new()
+                .name(format!("mini-tokio-worker-{}", index))
+                .spawn(move || multi_thread::run_worker(shared, index, queue))?;
+            runtime.workers.push(worker);
+        }
+        Ok(runtime)
+    }
+
+    /// 在下面函数中，通过参数传入的 future 被 `Task` 包裹起来，然后会被推入到调度队列中，当 `run` 或 `block_on` 被调用时，该 future 将被执行
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        Task::spawn(future, &self.shared)
+    }
+
+    /// 在阻塞线程池中运行 `func`，返回的 `JoinHandle` 可以在任务中 `.await`，也可以交给 `block_on`
+    pub fn spawn_blocking<F, R>(&self, func: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.shared.blocking.spawn(func)
+    }
+
+    /// 阻塞线程池最多的线程数，默认 512。达到上限之后新的任务排队等待
+    pub fn max_blocking_threads(self, max_threads: usize) -> MiniTokio {
+        self.shared.blocking.set_max_threads(max_threads);
+        self
+    }
+
+    /// 阻塞线程池中的线程空闲多久之后退出，默认 10 秒
+    pub fn thread_keep_alive(self, keep_alive: Duration) -> MiniTokio {
+        self.shared.blocking.set_keep_alive(keep_alive);
+        self
+    }
+
+    /// 阻塞线程池当前的线程数
+    pub fn blocking_threads(&self) -> usize {
+        self.shared.blocking.num_threads()
+    }
+
+    pub fn timer(&self) -> &time::Handle {
+        &self.shared.timer
+    }
+
+    /// 运行直到所有任务完成
+    pub fn run(&mut self) {
+        let shared = self.shared.clone();
+        self.block_on(poll_fn(move |cx| {
+            // 先保存 waker 再检查，最后一个任务在两者之间完成也不会丢失唤醒
+            *shared.idle.lock().unwrap() = Some(cx.waker().clone());
+            if shared.tasks.load(Ordering::SeqCst) == 0 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+
+    /// 在当前线程中运行 `future` 直到它完成，单线程模式下同时运行调度队列中的任务。
+    /// `future` 不需要是 `Send` 的，它不会被放进调度队列；它完成时还没有完成的任务留在队列中，下次 `run` 或 `block_on` 时继续运行
+    ///
+    /// # Panics
+    ///
+    /// 在 mini-tokio 的运行时中调用时 panic，阻塞执行器的线程会让其它任务都无法运行
+    pub fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let _enter = self.shared.enter();
+
+        let mut future = pin!(future);
+        let root = Arc::new(RootWaker {
+            woken: AtomicBool::new(true),
+            io: self.scheduled.as_ref().map(|_| self.shared.io.clone()),
+            thread: thread::current(),
+        });
+        let waker = task::waker(root.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let scheduled = match &self.scheduled {
+            Some(scheduled) => scheduled,
+            // 多线程模式下任务、计时器和 IO 都由 worker 驱动，这里只需要等根 future 被唤醒
+            None => loop {
+                if root.woken.swap(false, Ordering::SeqCst) {
+                    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        return output;
+                    }
+                } else {
+                    thread::park();
+                }
+            },
+        };
+
+        loop {
+            if root.woken.swap(false, Ordering::SeqCst) {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+
+            // 到期的计时器唤醒对应的任务，把它们放进调度队列
+            self.shared.timer.process();
+
+            // 先运行队列中已有的任务，但每运行一批就检查一次计时器和 IO，避免它们被大量就绪的任务拖延
+            let mut polled = 0;
+            while polled < EVENT_INTERVAL {
+                match scheduled.try_recv() {
+                    Ok(task) => task.poll(),
+                    Err(_) => break,
+                }
+                polled += 1;
+            }
+            if polled == EVENT_INTERVAL {
+                self.shared.io.poll_events().expect("epoll_wait failed");
+            }
+            if polled > 0 || root.woken.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            // 调度队列为空时阻塞在 epoll_wait 中，直到有 IO 事件、别的线程唤醒了任务，或者下一个计时器到期
+            let timeout = self
+                .shared
+                .timer
+                .next_deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            self.shared
+                .io
+                .park(timeout, || {
+                    scheduled.is_empty() && !root.woken.load(Ordering::SeqCst)
+                })
+                .expect("epoll_wait failed");
+        }
+    }
+}
+
+impl Drop for MiniTokio {
+    /// 停止 worker 线程，还没有完成的任务不会再运行；然后关闭阻塞线程池，等待已经开始的阻塞任务完成。
+    ///
+    /// 最后 drop 所有任务的 future，它们的 `JoinHandle` 得到 `JoinError::Cancelled`，计时器和 IO 的 waker 也随之释放；
+    /// 再清空调度队列，否则队列中的任务和 `Shared` 互相引用，都不会被释放
+    fn drop(&mut self) {
+        if self.scheduled.is_none() {
+            self.shared.pool().shutdown(&self.shared.io);
+            for worker in self.workers.drain(..) {
+                let _ = worker.join();
+            }
+        }
+        // 阻塞任务完成时可能唤醒任务，所以先等它们完成再清空队列
+        self.shared.blocking.shutdown();
+        self.shared.owned.close();
+        match &self.scheduled {
+            Some(scheduled) => while scheduled.try_recv().is_ok() {},
+            None => self.shared.pool().drain(),
+        }
+    }
+}
+
+/// 在当前的运行时中生成一个新的任务，和 `tokio::spawn` 一样只能在任务中调用
+///
+/// # Panics
+///
+/// 不在 mini-tokio 的运行时中调用时 panic
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    Task::spawn(future, &current())
+}
+
+/// 在当前运行时的阻塞线程池中运行 `func`，见 `MiniTokio::spawn_blocking`
+///
+/// # Panics
+///
+/// 不在 mini-tokio 的运行时中调用时 panic
+pub fn spawn_blocking<F, R>(func: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    current().blocking.spawn(func)
+}
+
+fn current() -> Arc<Shared> {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .expect("must be called from the context of a mini-tokio runtime")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::TcpListener;
+    use std::future::pending;
+
+    /// future 被 drop 时计数
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// 生成分别等待计时器、IO 和另一个任务的任务，一共 4 个 `DropCounter`
+    fn spawn_pending(runtime: &mut MiniTokio, dropped: &Arc<AtomicUsize>) -> Vec<JoinHandle<()>> {
+        let counter = || DropCounter(dropped.clone());
+        let mut handles = vec![];
+
+        let guard = counter();
+        handles.push(runtime.spawn(async move {
+            let _guard = guard;
+            time::sleep(Duration::from_secs(3600)).await;
+        }));
+        let guard = counter();
+        handles.push(runtime.spawn(async move {
+            let _guard = guard;
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let _ = listener.accept().await;
+        }));
+        let (guard, inner_guard) = (counter(), counter());
+        handles.push(runtime.spawn(async move {
+            let _guard = guard;
+            let inner = spawn(async move {
+                let _guard = inner_guard;
+                pending::<()>().await
+            });
+            let _ = inner.await;
+        }));
+
+        // 等它们都开始等待
+        runtime.block_on(async { time::sleep(Duration::from_millis(20)).await });
+        handles
+    }
+
+    fn assert_released(
+        runtime: MiniTokio,
+        dropped: &AtomicUsize,
+        guards: usize,
+        handles: Vec<JoinHandle<()>>,
+    ) {
+        let shared = Arc::downgrade(&runtime.shared);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+        drop(runtime);
+        assert_eq!(dropped.load(Ordering::SeqCst), guards);
+
+        let results: Vec<_> = handles
+            .into_iter()
+            .map(futures::executor::block_on)
+            .collect();
+        for result in &results {
+            assert!(result.as_ref().unwrap_err().is_cancelled());
+        }
+        // `JoinHandle` 都 drop 之后不再有任务引用运行时
+        drop(results);
+        assert!(shared.upgrade().is_none());
+    }
+
+    #[test]
+    fn drop_current_thread_cancels_pending_tasks() {
+        let mut runtime = MiniTokio::new().unwrap();
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut handles = spawn_pending(&mut runtime, &dropped);
+        assert_eq!(runtime.timer().len(), 1);
+
+        // 还在调度队列中、从来没有运行过的任务
+        let guard = DropCounter(dropped.clone());
+        handles.push(runtime.spawn(async move {
+            let _guard = guard;
+        }));
+        assert_released(runtime, &dropped, 5, handles);
+    }
+
+    #[test]
+    fn drop_multi_thread_cancels_pending_tasks() {
+        let mut runtime = MiniTokio::new_multi_thread(4).unwrap();
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let handles = spawn_pending(&mut runtime, &dropped);
+        assert_released(runtime, &dropped, 4, handles);
+    }
+}
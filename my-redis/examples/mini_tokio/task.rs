@@ -0,0 +1,375 @@
+//! 任务以及 `spawn` 返回的 `JoinHandle`。
+//!
+//! 调度队列中的任务都是 `Output = ()` 的 future：用户的 future 被包在 `Harness` 中，
+//! 完成时把输出保存到和 `JoinHandle` 共享的 `JoinState` 中，再唤醒等待它的任务。
+//! `Harness` 用 `catch_unwind` 调用用户的 future，panic 只会结束这一个任务，`JoinHandle` 得到 `JoinError::Panic`。
+//! 输出在 `Harness` 被 drop 时才交出去，这时用户的 future 已经先被 drop 了
+//!
+//! 任务的状态保证同一时间只有一个线程 poll 它，也不会在队列中出现两次：
+//! 正在运行时被唤醒只标记为 `NOTIFIED`，poll 返回之后再由运行它的线程放回队列，这在多线程的调度器中是必须的。
+//!
+//! `abort` 只是做个标记并调度任务，执行器下一次处理这个任务时直接 drop 它的 future，和 tokio 一样：
+//! future 只会在执行器的线程中被 drop，不会和正在进行的 poll 冲突。`Harness` 没有输出就被 drop 时通知 `JoinHandle` 任务被取消了
+//!
+//! 任务通过 `Shared` 引用运行时，调度队列、时间轮和 IO 驱动中的 waker 又引用着任务，这是一个循环引用。
+//! 所以运行时用 `OwnedTasks` 记下所有还没有完成的任务，和 tokio 一样，被 drop 时 drop 它们的 future 并清空调度队列
+
+use crate::runtime::Shared;
+
+use futures::task::{self, ArcWake};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// 不在队列中，也没有在运行，等待被唤醒
+const IDLE: u8 = 0;
+/// 在某个调度队列中
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+/// 运行期间被唤醒了，poll 返回之后要重新调度
+const NOTIFIED: u8 = 3;
+const COMPLETE: u8 = 4;
+
+pub(crate) struct Task {
+    // `Mutex` 是为了让 `Task` 实现 `Sync` 特征，它能保证同一时间只有一个线程可以访问 `Future`。
+    // 事实上 `Mutex` 并没有在 Tokio 中被使用，这里我们只是为了简化： Tokio 的真实代码实在太长了 :D
+    //
+    // 任务完成或者被取消后 future 被置为 None，之后再被唤醒也不会重复 poll
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    state: AtomicU8,
+    /// `JoinHandle::abort` 设置，执行器看到之后 drop future
+    aborted: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Task {
+    fn schedule(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                // 已经在队列中、已经被标记过或者已经完成
+                _ => return,
+            };
+            match self
+                .state
+                .compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(actual) => state = actual,
+            }
+        }
+        if state == IDLE {
+            // 被唤醒的任务很可能要用到唤醒它的任务刚刚准备好的数据，多线程的调度器优先在当前线程运行它
+            self.shared.schedule(self.clone(), true);
+        }
+    }
+
+    // 使用给定的 future 来生成新的任务
+    //
+    // 新的任务会被推到调度队列中，接着执行器就可以获取该任务，然后执行
+    pub(crate) fn spawn<F>(future: F, shared: &Arc<Shared>) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let state = JoinState::new();
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(Harness {
+                future: ManuallyDrop::new(future),
+                output: None,
+                state: state.clone(),
+            }))),
+            state: AtomicU8::new(SCHEDULED),
+            aborted: AtomicBool::new(false),
+            shared: shared.clone(),
+        });
+        // 运行时已经关闭时直接 drop future，`JoinHandle` 得到 `JoinError::Cancelled`
+        if shared.owned.bind(&task) {
+            shared.task_spawned();
+            shared.schedule(task.clone(), false);
+        } else {
+            task.shutdown();
+        }
+        JoinHandle {
+            task: Some(task),
+            state,
+        }
+    }
+
+    // 注意不是 future 的 poll，是自己实现的 poll
+    pub(crate) fn poll(self: Arc<Self>) {
+        // 基于 Task 实例创建一个 waker, 它使用了之前的 `ArcWake`
+        let waker = task::waker(self.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        // 只有从队列中取出任务的线程会走到这里，状态保证了不会有其他线程在竞争锁
+        self.state.store(RUNNING, Ordering::Release);
+        let mut slot = self.future.try_lock().unwrap();
+        if let Some(future) = slot.as_mut() {
+            // 被取消的任务不再 poll，直接 drop
+            if self.aborted.load(Ordering::Acquire) || future.as_mut().poll(&mut cx).is_ready() {
+                *slot = None;
+                self.state.store(COMPLETE, Ordering::Release);
+                self.shared.owned.remove(&self);
+                self.shared.task_done();
+                return;
+            }
+        }
+        drop(slot);
+
+        // 运行期间被唤醒了(包括任务唤醒自己，例如 `yield_now`)，放到队尾，让其它任务先运行
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            self.state.store(SCHEDULED, Ordering::Release);
+            self.shared.schedule(self.clone(), false);
+        }
+    }
+
+    /// 运行时被 drop 时调用，这时没有线程在 poll 任务。之后再被唤醒也不会被调度
+    fn shutdown(&self) {
+        self.state.store(COMPLETE, Ordering::Release);
+        // 在锁外 drop，future 的 drop 可能唤醒这个任务
+        let future = self.future.lock().unwrap().take();
+        drop(future);
+    }
+}
+
+/// 运行时拥有的还没有完成的任务，key 是任务的地址
+pub(crate) struct OwnedTasks {
+    inner: Mutex<OwnedInner>,
+}
+
+struct OwnedInner {
+    tasks: HashMap<usize, Arc<Task>>,
+    /// 关闭之后不再接受新的任务
+    closed: bool,
+}
+
+impl OwnedTasks {
+    pub(crate) fn new() -> OwnedTasks {
+        OwnedTasks {
+            inner: Mutex::new(OwnedInner {
+                tasks: HashMap::new(),
+                closed: false,
+            }),
+        }
+    }
+
+    /// 已经关闭时返回 false
+    fn bind(&self, task: &Arc<Task>) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return false;
+        }
+        inner.tasks.insert(Arc::as_ptr(task) as usize, task.clone());
+        true
+    }
+
+    fn remove(&self, task: &Arc<Task>) {
+        self.inner
+            .lock()
+            .unwrap()
+            .tasks
+            .remove(&(Arc::as_ptr(task) as usize));
+    }
+
+    /// 关闭并 drop 所有任务的 future。drop future 时可能生成新的任务，它们在 `bind` 时就被取消了
+    pub(crate) fn close(&self) {
+        let tasks = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.closed = true;
+            std::mem::take(&mut inner.tasks)
+        };
+        for task in tasks.into_values() {
+            task.shutdown();
+        }
+    }
+}
+
+impl ArcWake for Task {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.schedule()
+    }
+}
+
+/// 包装用户的 future，把输出或者 panic 交给 `JoinHandle`
+struct Harness<F: Future> {
+    // 在 drop 中先 drop future 再通知 `JoinHandle`，等待者醒来时任务持有的资源都已经释放了
+    future: ManuallyDrop<F>,
+    output: Option<Result<F::Output, JoinError>>,
+    state: Arc<JoinState<F::Output>>,
+}
+
+impl<F: Future> Future for Harness<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // SAFETY: `future` 和 `self` 一起被 pin 住，这里不会把它移出去
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut *this.future) };
+
+        // panic 之后 future 可能处于不一致的状态，不会再 poll 它，所以可以断言 unwind safe
+        this.output = match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(output)) => Some(Ok(output)),
+            Err(payload) => Some(Err(JoinError::Panic(payload))),
+        };
+        Poll::Ready(())
+    }
+}
+
+impl<F: Future> Drop for Harness<F> {
+    fn drop(&mut self) {
+        // SAFETY: 之后不会再访问 `future`；原地 drop 不违反 pin 的约定
+        unsafe { ManuallyDrop::drop(&mut self.future) };
+        // 没有输出说明是被 `abort` 或者随运行时一起被 drop 的
+        let output = self.output.take().unwrap_or(Err(JoinError::Cancelled));
+        self.state.complete(output);
+    }
+}
+
+/// 任务和 `JoinHandle` 共享的输出
+pub(crate) struct JoinState<T> {
+    inner: Mutex<JoinInner<T>>,
+}
+
+struct JoinInner<T> {
+    output: Option<Result<T, JoinError>>,
+    /// 输出被 `JoinHandle` 取走之后 `output` 是 None，但任务仍然是完成的
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> JoinState<T> {
+    pub(crate) fn new() -> Arc<JoinState<T>> {
+        Arc::new(JoinState {
+            inner: Mutex::new(JoinInner {
+                output: None,
+                finished: false,
+                waker: None,
+            }),
+        })
+    }
+
+    pub(crate) fn complete(&self, output: Result<T, JoinError>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.output = Some(output);
+        inner.finished = true;
+        let waker = inner.waker.take();
+        drop(inner);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// `spawn` 和 `spawn_blocking` 返回的句柄，`.await` 得到任务的输出。drop 句柄不会取消任务，和 tokio 一样
+pub struct JoinHandle<T> {
+    /// `spawn_blocking` 的句柄没有对应的 `Task`
+    task: Option<Arc<Task>>,
+    state: Arc<JoinState<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub(crate) fn blocking(state: Arc<JoinState<T>>) -> JoinHandle<T> {
+        JoinHandle { task: None, state }
+    }
+
+    /// 取消任务。任务已经完成时什么也不做，否则 `.await` 得到 `JoinError::Cancelled`。
+    /// `spawn_blocking` 的任务不是 future，没有办法在中途停下，对它调用 `abort` 没有效果
+    pub fn abort(&self) {
+        if let Some(task) = &self.task {
+            if !self.is_finished() {
+                task.aborted.store(true, Ordering::Release);
+                task.schedule();
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.inner.lock().unwrap().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inner = self.state.inner.lock().unwrap();
+        if let Some(output) = inner.output.take() {
+            return Poll::Ready(output);
+        }
+        assert!(!inner.finished, "`JoinHandle` polled after completion");
+        match &inner.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => inner.waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+/// 任务没有正常完成
+pub enum JoinError {
+    Cancelled,
+    Panic(Box<dyn Any + Send + 'static>),
+}
+
+impl JoinError {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, JoinError::Cancelled)
+    }
+
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JoinError::Panic(_))
+    }
+
+    /// panic 的参数，通常是 `&str` 或者 `String`
+    fn panic_message(&self) -> Option<&str> {
+        match self {
+            JoinError::Panic(payload) => payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str)),
+            JoinError::Cancelled => None,
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => f.write_str("Cancelled"),
+            JoinError::Panic(_) => f
+                .debug_tuple("Panic")
+                .field(&self.panic_message().unwrap_or("..."))
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => f.write_str("task was cancelled"),
+            JoinError::Panic(_) => match self.panic_message() {
+                Some(message) => write!(f, "task panicked with message {:?}", message),
+                None => f.write_str("task panicked"),
+            },
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
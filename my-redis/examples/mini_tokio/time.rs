@@ -0,0 +1,712 @@
+//! mini-tokio 的计时器：分层时间轮(hierarchical timing wheel)，结构和 tokio 的 `runtime/time/wheel` 一样。
+//!
+//! 时间以毫秒为单位，共 6 层，每层 64 个槽：第 0 层每个槽 1ms，第 1 层每个槽 64ms，……，第 5 层每个槽 64^5ms(约 12 天)。
+//! 计时器放在哪一层，取决于它的到期时间和当前时间从哪一段 6 位开始不同，所以插入和删除都是 O(1)，
+//! 和计时器的总数无关。时间前进时找到最近的一个非空的槽：第 0 层的槽中的计时器直接到期，
+//! 更高层的槽中的计时器被重新插入到更低的层(cascade)，直到最后落到第 0 层。
+//!
+//! 以前的 `Delay` 为每个计时器创建一个线程，10 万个计时器就是 10 万个线程；
+//! 现在执行器在没有任务可以运行时最多阻塞到下一个计时器到期，然后调用 `Handle::process` 唤醒到期的任务。
+//!
+//! `Sleep` 被 drop 时会从时间轮中删除，`timeout` 超时之后被 drop 的内部 future 也就取消了它自己的计时器
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// 每层的槽数是 2^6
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+
+/// 时间轮能表示的最大时间跨度，2^36 ms，约 2 年。更远的计时器放在最高层，到期前会被反复重新插入
+const MAX_TICKS: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+
+/// 最高层每个槽的跨度
+const TOP_SLOT_RANGE: u64 = MAX_TICKS >> SLOT_BITS;
+
+thread_local! {
+    /// 当前线程所在的运行时的计时器，`sleep` 等函数通过它注册计时器
+    static CURRENT: RefCell<Option<Handle>> = const { RefCell::new(None) };
+}
+
+/// 时间轮的句柄，可以在多个线程之间共享
+#[derive(Clone)]
+pub struct Handle {
+    wheel: Arc<Mutex<Wheel>>,
+    /// 第 0 个 tick 对应的时刻
+    start: Instant,
+}
+
+/// `enter` 返回的守卫，drop 时恢复之前的句柄
+pub struct EnterGuard {
+    prev: Option<Handle>,
+}
+
+impl Handle {
+    pub fn new() -> Handle {
+        Handle {
+            wheel: Arc::new(Mutex::new(Wheel::new())),
+            start: Instant::now(),
+        }
+    }
+
+    /// 当前线程所在的运行时的计时器
+    ///
+    /// # Panics
+    ///
+    /// 不在 mini-tokio 的运行时中调用时 panic，和 tokio 一样
+    pub fn current() -> Handle {
+        CURRENT
+            .with(|current| current.borrow().clone())
+            .expect("must be called from the context of a mini-tokio runtime")
+    }
+
+    /// 把当前线程的计时器设置为 `self`，执行器在运行任务之前调用
+    pub fn enter(&self) -> EnterGuard {
+        let prev = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+        EnterGuard { prev }
+    }
+
+    /// 唤醒所有已经到期的计时器，返回唤醒的个数
+    pub fn process(&self) -> usize {
+        let now = self.tick_floor(Instant::now());
+        let mut wakers = vec![];
+        self.wheel.lock().unwrap().process(now, &mut wakers);
+
+        // 在锁外唤醒，被唤醒的任务可能在别的线程上立即开始运行并注册新的计时器
+        let count = wakers.len();
+        for waker in wakers {
+            waker.wake();
+        }
+        count
+    }
+
+    /// 下一次需要调用 `process` 的时刻，没有计时器时返回 None。
+    ///
+    /// 高层的槽返回的是槽的开始时间，早于其中的计时器，到时候只是把它们挪到更低的层
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let wheel = self.wheel.lock().unwrap();
+        wheel
+            .next_expiration()
+            .map(|expiration| self.start + Duration::from_millis(expiration.deadline))
+    }
+
+    /// 还没有到期的计时器个数
+    pub fn len(&self) -> usize {
+        self.wheel.lock().unwrap().len
+    }
+
+    /// 向上取整，保证计时器不会提前到期
+    fn tick_ceil(&self, instant: Instant) -> u64 {
+        let since = instant.saturating_duration_since(self.start);
+        let ticks = since.as_millis() as u64;
+        if since > Duration::from_millis(ticks) {
+            ticks + 1
+        } else {
+            ticks
+        }
+    }
+
+    fn tick_floor(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.start).as_millis() as u64
+    }
+}
+
+impl Default for Handle {
+    fn default() -> Handle {
+        Handle::new()
+    }
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.prev.take());
+    }
+}
+
+struct Wheel {
+    /// 已经处理到的 tick
+    elapsed: u64,
+    levels: Vec<Level>,
+    /// 所有计时器，下标就是 `Sleep` 持有的 key，空位通过 `free` 复用
+    entries: Vec<Entry>,
+    free: Vec<usize>,
+    /// 还在时间轮中的计时器个数
+    len: usize,
+}
+
+struct Level {
+    /// 第 i 位为 1 表示第 i 个槽不为空，用来快速找到下一个非空的槽
+    occupied: u64,
+    slots: Vec<Vec<usize>>,
+}
+
+struct Entry {
+    when: u64,
+    waker: Option<Waker>,
+    state: State,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Vacant,
+    /// 在 `levels[level].slots[slot][pos]` 中，记下位置是为了 O(1) 删除
+    Pending {
+        level: usize,
+        slot: usize,
+        pos: usize,
+    },
+    Fired,
+}
+
+/// 下一个需要处理的槽
+struct Expiration {
+    level: usize,
+    slot: usize,
+    /// 槽的开始时间
+    deadline: u64,
+}
+
+impl Wheel {
+    fn new() -> Wheel {
+        Wheel {
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: vec![Vec::new(); SLOTS],
+                })
+                .collect(),
+            entries: vec![],
+            free: vec![],
+            len: 0,
+        }
+    }
+
+    /// 在 `when` 到期时唤醒 `waker`，已经到期时返回 None
+    fn insert(&mut self, when: u64, waker: Waker) -> Option<usize> {
+        if when <= self.elapsed {
+            return None;
+        }
+
+        let entry = Entry {
+            when,
+            waker: Some(waker),
+            state: State::Vacant,
+        };
+        let key = match self.free.pop() {
+            Some(key) => {
+                self.entries[key] = entry;
+                key
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        self.place(key);
+        self.len += 1;
+        Some(key)
+    }
+
+    /// 释放 key，计时器还没到期时从槽中删除
+    fn remove(&mut self, key: usize) {
+        if let State::Pending { level, slot, pos } = self.entries[key].state {
+            self.unlink(level, slot, pos);
+            self.len -= 1;
+        }
+        self.entries[key] = Entry {
+            when: 0,
+            waker: None,
+            state: State::Vacant,
+        };
+        self.free.push(key);
+    }
+
+    fn is_fired(&self, key: usize) -> bool {
+        self.entries[key].state == State::Fired
+    }
+
+    fn set_waker(&mut self, key: usize, waker: &Waker) {
+        let entry = &mut self.entries[key];
+        match &entry.waker {
+            Some(old) if old.will_wake(waker) => {}
+            _ => entry.waker = Some(waker.clone()),
+        }
+    }
+
+    /// 根据到期时间和当前时间放进对应的槽
+    fn place(&mut self, key: usize) {
+        // 太远的计时器先放在不到一圈之后的位置，到时候再重新放置。
+        // 否则它可能落在最高层的当前槽中，挡住之后那些更早到期的槽
+        let when = self.entries[key]
+            .when
+            .min(self.elapsed + MAX_TICKS - TOP_SLOT_RANGE);
+        let level = level_for(self.elapsed, when);
+        let slot = ((when >> (level as u32 * SLOT_BITS)) as usize) & (SLOTS - 1);
+
+        let level_ref = &mut self.levels[level];
+        level_ref.slots[slot].push(key);
+        level_ref.occupied |= 1 << slot;
+        self.entries[key].state = State::Pending {
+            level,
+            slot,
+            pos: level_ref.slots[slot].len() - 1,
+        };
+    }
+
+    fn unlink(&mut self, level: usize, slot: usize, pos: usize) {
+        let level_ref = &mut self.levels[level];
+        let keys = &mut level_ref.slots[slot];
+        keys.swap_remove(pos);
+        // 原来的最后一个元素被挪到了 pos
+        if let Some(&moved) = keys.get(pos) {
+            if let State::Pending { pos: p, .. } = &mut self.entries[moved].state {
+                *p = pos;
+            }
+        }
+        if keys.is_empty() {
+            level_ref.occupied &= !(1 << slot);
+        }
+    }
+
+    /// 低层的槽总是比高层的槽先到期，所以从第 0 层开始找
+    fn next_expiration(&self) -> Option<Expiration> {
+        (0..LEVELS).find_map(|level| self.levels[level].next_expiration(level, self.elapsed))
+    }
+
+    /// 处理所有在 `now` 之前开始的槽，到期的计时器的 waker 放进 `wakers`
+    fn process(&mut self, now: u64, wakers: &mut Vec<Waker>) {
+        while let Some(expiration) = self.next_expiration() {
+            if expiration.deadline > now {
+                break;
+            }
+
+            let level = &mut self.levels[expiration.level];
+            let keys = std::mem::take(&mut level.slots[expiration.slot]);
+            level.occupied &= !(1 << expiration.slot);
+            self.elapsed = expiration.deadline;
+
+            for key in keys {
+                if self.entries[key].when <= self.elapsed {
+                    let entry = &mut self.entries[key];
+                    entry.state = State::Fired;
+                    wakers.extend(entry.waker.take());
+                    self.len -= 1;
+                } else {
+                    // 还没到期，挪到更低的层
+                    self.place(key);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+}
+
+impl Level {
+    fn next_expiration(&self, level: usize, elapsed: u64) -> Option<Expiration> {
+        if self.occupied == 0 {
+            return None;
+        }
+
+        let slot_range = 1u64 << (level as u32 * SLOT_BITS);
+        let level_range = slot_range << SLOT_BITS;
+
+        // 从当前时间所在的槽开始往后找第一个非空的槽
+        let now_slot = (elapsed / slot_range) as u32 % SLOTS as u32;
+        let zeros = self.occupied.rotate_right(now_slot).trailing_zeros();
+        let slot = ((zeros + now_slot) % SLOTS as u32) as usize;
+
+        let level_start = elapsed & !(level_range - 1);
+        let mut deadline = level_start + slot as u64 * slot_range;
+        if deadline <= elapsed {
+            // 最高层的槽可能已经绕回到了当前槽之前
+            deadline += level_range;
+        }
+        Some(Expiration {
+            level,
+            slot,
+            deadline,
+        })
+    }
+}
+
+/// 到期时间和当前时间从哪一段 6 位开始不同，计时器就放在哪一层
+fn level_for(elapsed: u64, when: u64) -> usize {
+    let mut masked = (elapsed ^ when) | (SLOTS as u64 - 1);
+    if masked >= MAX_TICKS {
+        masked = MAX_TICKS - 1;
+    }
+    let significant = 63 - masked.leading_zeros();
+    (significant / SLOT_BITS) as usize
+}
+
+/// `sleep` 和 `sleep_until` 返回的 future
+pub struct Sleep {
+    handle: Handle,
+    deadline: Instant,
+    /// 注册到时间轮之后得到的 key
+    key: Option<usize>,
+}
+
+/// 等待 `duration`
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// 等待到 `deadline`
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        handle: Handle::current(),
+        deadline,
+        key: None,
+    }
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// 修改到期时间，已经注册的计时器会被删除，下一次 poll 时重新注册
+    pub fn reset(&mut self, deadline: Instant) {
+        self.cancel();
+        self.deadline = deadline;
+    }
+
+    fn cancel(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.handle.wheel.lock().unwrap().remove(key);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        if Instant::now() >= this.deadline {
+            this.cancel();
+            return Poll::Ready(());
+        }
+
+        let mut wheel = this.handle.wheel.lock().unwrap();
+        match this.key {
+            Some(key) if wheel.is_fired(key) => {
+                wheel.remove(key);
+                this.key = None;
+                Poll::Ready(())
+            }
+            // 每次 poll 的 waker 可能不同(future 可能被挪到了别的任务中)，要保存最新的那个
+            Some(key) => {
+                wheel.set_waker(key, cx.waker());
+                Poll::Pending
+            }
+            None => {
+                let when = this.handle.tick_ceil(this.deadline);
+                match wheel.insert(when, cx.waker().clone()) {
+                    Some(key) => {
+                        this.key = Some(key);
+                        Poll::Pending
+                    }
+                    None => Poll::Ready(()),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// 每隔 `period` 完成一次 `tick`，第一次 `tick` 立即完成
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+}
+
+pub fn interval(period: Duration) -> Interval {
+    assert!(!period.is_zero(), "`period` must be non-zero");
+    Interval {
+        sleep: sleep_until(Instant::now()),
+        period,
+    }
+}
+
+impl Interval {
+    /// 等到下一个 tick，返回这个 tick 预定的时刻
+    pub async fn tick(&mut self) -> Instant {
+        poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        // 错过的 tick 不补，从现在开始重新计时，和 tokio 的 `MissedTickBehavior::Delay` 一样
+        let scheduled = self.sleep.deadline();
+        let now = Instant::now();
+        let next = if scheduled + self.period > now {
+            scheduled + self.period
+        } else {
+            now + self.period
+        };
+        self.sleep.reset(next);
+        Poll::Ready(scheduled)
+    }
+}
+
+/// `timeout` 返回的 future，`future` 在 `duration` 内没有完成时返回 `Err(Elapsed)`
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+/// 超时的错误
+#[derive(Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` 和 `self` 一起被 pin 住，这里不会把它移出去；`Sleep` 是 Unpin 的
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        // 先检查 future，同时完成时以 future 的结果为准
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "deadline has elapsed".fmt(f)
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+    use std::future::pending;
+
+    fn level_of(wheel: &Wheel, key: usize) -> Option<usize> {
+        match wheel.entries[key].state {
+            State::Pending { level, .. } => Some(level),
+            _ => None,
+        }
+    }
+
+    fn process(wheel: &mut Wheel, now: u64) -> usize {
+        let mut wakers = vec![];
+        wheel.process(now, &mut wakers);
+        wakers.len()
+    }
+
+    #[test]
+    fn level_for_boundaries() {
+        assert_eq!(level_for(0, 1), 0);
+        assert_eq!(level_for(0, 63), 0);
+        assert_eq!(level_for(0, 64), 1);
+        assert_eq!(level_for(0, 4095), 1);
+        assert_eq!(level_for(0, 4096), 2);
+        assert_eq!(level_for(0, MAX_TICKS - 1), LEVELS - 1);
+        assert_eq!(level_for(0, MAX_TICKS * 4), LEVELS - 1);
+        // 和当前时间在同一个 64ms 的段中时仍然在第 0 层
+        assert_eq!(level_for(100, 127), 0);
+        assert_eq!(level_for(100, 128), 1);
+    }
+
+    #[test]
+    fn cascades_to_lower_levels() {
+        let mut wheel = Wheel::new();
+        let when = 3 * 4096 + 5 * 64 + 7;
+        let key = wheel.insert(when, noop_waker()).unwrap();
+        assert_eq!(level_of(&wheel, key), Some(2));
+        assert_eq!(wheel.next_expiration().unwrap().deadline, 3 * 4096);
+
+        // 第 2 层的槽开始时挪到第 1 层，第 1 层的槽开始时挪到第 0 层
+        assert_eq!(process(&mut wheel, 3 * 4096), 0);
+        assert_eq!(level_of(&wheel, key), Some(1));
+        assert_eq!(process(&mut wheel, 3 * 4096 + 5 * 64), 0);
+        assert_eq!(level_of(&wheel, key), Some(0));
+
+        assert_eq!(process(&mut wheel, when - 1), 0);
+        assert!(!wheel.is_fired(key));
+        assert_eq!(process(&mut wheel, when), 1);
+        assert!(wheel.is_fired(key));
+        assert_eq!(wheel.len, 0);
+        assert!(wheel.next_expiration().is_none());
+    }
+
+    #[test]
+    fn fires_exactly_at_deadline() {
+        let whens = [
+            1,
+            63,
+            64,
+            65,
+            4095,
+            4096,
+            4097,
+            64 * 64 * 64 + 7,
+            64 * 64 * 64 * 64 + 3,
+            64 * 64 * 64 * 64 * 64 + 11,
+            MAX_TICKS - 1,
+        ];
+        // 一次跳到到期之前，和一步一步走到到期之前，都不会提前唤醒
+        for when in whens {
+            let mut wheel = Wheel::new();
+            let key = wheel.insert(when, noop_waker()).unwrap();
+            assert_eq!(process(&mut wheel, when - 1), 0, "{}", when);
+            assert_eq!(process(&mut wheel, when), 1, "{}", when);
+            assert!(wheel.is_fired(key));
+        }
+
+        let mut wheel = Wheel::new();
+        let keys: Vec<_> = whens[..8]
+            .iter()
+            .map(|&when| (when, wheel.insert(when, noop_waker()).unwrap()))
+            .collect();
+        let mut now = 0;
+        while wheel.len > 0 {
+            now += 13;
+            process(&mut wheel, now);
+            for &(when, key) in &keys {
+                assert_eq!(wheel.is_fired(key), when <= now, "{} at {}", when, now);
+            }
+        }
+    }
+
+    #[test]
+    fn timers_beyond_wheel_range() {
+        let mut wheel = Wheel::new();
+        let far = wheel.insert(MAX_TICKS * 2 + 100, noop_waker()).unwrap();
+        // 更早到期的计时器不会被最高层的远期计时器挡住
+        let near = wheel.insert(10, noop_waker()).unwrap();
+        assert_eq!(wheel.next_expiration().unwrap().deadline, 10);
+        assert_eq!(process(&mut wheel, 10), 1);
+        assert!(wheel.is_fired(near));
+
+        assert_eq!(process(&mut wheel, MAX_TICKS), 0);
+        assert_eq!(process(&mut wheel, MAX_TICKS * 2 + 99), 0);
+        assert!(!wheel.is_fired(far));
+        assert_eq!(process(&mut wheel, MAX_TICKS * 2 + 100), 1);
+        assert!(wheel.is_fired(far));
+    }
+
+    #[test]
+    fn expired_timer_is_not_inserted() {
+        let mut wheel = Wheel::new();
+        process(&mut wheel, 100);
+        assert!(wheel.insert(100, noop_waker()).is_none());
+        assert!(wheel.insert(50, noop_waker()).is_none());
+        assert_eq!(wheel.len, 0);
+    }
+
+    #[test]
+    fn remove_cancels_pending_timer() {
+        let mut wheel = Wheel::new();
+        let key = wheel.insert(5000, noop_waker()).unwrap();
+        let other = wheel.insert(6000, noop_waker()).unwrap();
+        assert_eq!(wheel.len, 2);
+
+        // 挪到更低的层之后再删除
+        process(&mut wheel, 4096);
+        assert_eq!(level_of(&wheel, key), Some(1));
+        wheel.remove(key);
+        assert_eq!(wheel.len, 1);
+        assert_eq!(process(&mut wheel, 5999), 0);
+        assert_eq!(process(&mut wheel, 6000), 1);
+        assert!(wheel.is_fired(other));
+        assert!(wheel.levels.iter().all(|level| level.occupied == 0));
+
+        // 删除后 key 被复用，新的计时器不受旧状态影响
+        wheel.remove(other);
+        let reused = wheel.insert(7000, noop_waker()).unwrap();
+        assert_eq!(reused, other);
+        assert!(!wheel.is_fired(reused));
+        assert_eq!(wheel.len, 1);
+    }
+
+    #[test]
+    fn remove_from_shared_slot() {
+        let mut wheel = Wheel::new();
+        let keys: Vec<_> = (0..3)
+            .map(|_| wheel.insert(50, noop_waker()).unwrap())
+            .collect();
+
+        // 删除第一个之后最后一个被挪到了它的位置，记下的位置也要跟着更新
+        wheel.remove(keys[0]);
+        wheel.remove(keys[2]);
+        let State::Pending { level, slot, pos } = wheel.entries[keys[1]].state else {
+            panic!("timer should be pending");
+        };
+        assert_eq!(wheel.levels[level].slots[slot], [keys[1]]);
+        assert_eq!(pos, 0);
+
+        assert_eq!(process(&mut wheel, 50), 1);
+        assert!(wheel.is_fired(keys[1]));
+
+        // 槽空了之后 occupied 中对应的位被清除
+        let key = wheel.insert(60, noop_waker()).unwrap();
+        wheel.remove(key);
+        assert!(wheel.levels.iter().all(|level| level.occupied == 0));
+        assert!(wheel.next_expiration().is_none());
+    }
+
+    #[test]
+    fn dropping_sleep_removes_timer() {
+        let handle = Handle::new();
+        let _enter = handle.enter();
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut sleep = sleep(Duration::from_secs(60));
+        assert!(Pin::new(&mut sleep).poll(&mut cx).is_pending());
+        assert_eq!(handle.len(), 1);
+        assert!(handle.next_deadline().is_some());
+
+        // reset 删除旧的计时器，下一次 poll 时重新注册
+        sleep.reset(Instant::now() + Duration::from_secs(120));
+        assert_eq!(handle.len(), 0);
+        assert!(Pin::new(&mut sleep).poll(&mut cx).is_pending());
+        assert_eq!(handle.len(), 1);
+        drop(sleep);
+        assert_eq!(handle.len(), 0);
+        assert!(handle.next_deadline().is_none());
+
+        // timeout 的内部 future 没有完成时被 drop，它的计时器也被删除
+        let mut timeout = Box::pin(timeout(Duration::from_secs(60), pending::<()>()));
+        assert!(timeout.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(handle.len(), 1);
+        drop(timeout);
+        assert_eq!(handle.len(), 0);
+    }
+}
@@ -0,0 +1,82 @@
+//! 把任意字节按任意的位置切开，通过内存中的 duplex 流写给 `Connection::read_frame`。
+//!
+//! 检查解析不会 panic，切分的方式不影响结果，并且解析出的帧重新编码之后还能得到同样的帧。
+//!
+//! ```text
+//! cargo +nightly fuzz run read_frame
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use my_redis::{Connection, Frame};
+use tokio::io::AsyncWriteExt;
+
+fuzz_target!(|input: (Vec<u8>, Vec<u8>)| {
+    let (bytes, sizes) = input;
+
+    let whole = read_all(vec![bytes.clone()]);
+    assert_eq!(read_all(split(&bytes, &sizes)), whole);
+
+    let frames: Vec<Frame> = whole.into_iter().filter_map(Result::ok).collect();
+    let mut encoded = vec![];
+    for frame in &frames {
+        frame.encode(&mut encoded);
+    }
+    let decoded: Vec<Frame> = read_all(vec![encoded])
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(decoded, frames);
+});
+
+/// `sizes` 中的每个字节是一块的长度，剩下的数据作为最后一块
+fn split(bytes: &[u8], sizes: &[u8]) -> Vec<Vec<u8>> {
+    let mut chunks = vec![];
+    let mut rest = bytes;
+    for &size in sizes {
+        let (chunk, tail) = rest.split_at((size as usize).min(rest.len()));
+        chunks.push(chunk.to_vec());
+        rest = tail;
+    }
+    chunks.push(rest.to_vec());
+    chunks
+}
+
+/// 和 `tests/frame.rs` 一样，返回读到的所有帧，出错时以错误结束
+fn read_all(chunks: Vec<Vec<u8>>) -> Vec<Result<Frame, String>> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    rt.block_on(async move {
+        let (mut client, server) = tokio::io::duplex(64);
+
+        let writer = async move {
+            for chunk in chunks {
+                if client.write_all(&chunk).await.is_err() {
+                    return;
+                }
+                tokio::task::yield_now().await;
+            }
+        };
+
+        let reader = async move {
+            let mut connection = Connection::new(server);
+            let mut frames = vec![];
+            loop {
+                match connection.read_frame().await {
+                    Ok(Some(frame)) => frames.push(Ok(frame)),
+                    Ok(None) => break,
+                    Err(err) => {
+                        frames.push(Err(err.to_string()));
+                        break;
+                    }
+                }
+            }
+            frames
+        };
+
+        tokio::join!(writer, reader).1
+    })
+}
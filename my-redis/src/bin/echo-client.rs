@@ -1,9 +1,37 @@
+use my_redis::codec::{self, Codec, FrameReader};
+
+use bytes::Bytes;
+use rand::Rng;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+const USAGE: &str =
+    "Usage: echo-client [--addr 127.0.0.1:6142] [--verify [--codec length|line|resp] \
+[--connections n] [--frames n] [--size bytes] [--max-frame-size bytes]]";
+
+/// `--verify` 模式的参数
+#[derive(Clone)]
+struct Verify {
+    addr: String,
+    codec: Arc<dyn Codec>,
+    connections: usize,
+    /// 每个连接发送的帧数
+    frames: usize,
+    /// 每个帧的数据长度在 `1..=size` 之间随机
+    size: usize,
+    max_frame_size: usize,
+}
 
 /// echo server 会将用户的输入内容直接返回给用户，就像回声壁一样。
 ///
+/// 不带参数时发送两行数据并打印收到的内容；`--verify` 模式下同时建立多个连接，每个连接按照 `--codec`
+/// 发送随机长度、随机内容的帧，并逐字节校验 echo-server(或者它前面的代理)写回的每一个帧。
+///
 /// io::split 可以用于任何同时实现了 AsyncRead 和 AsyncWrite 的值，它的内部使用了 Arc 和 Mutex 来实现相应的功能。
 /// 如果大家觉得这种实现有些重，可以使用 Tokio 提供的 TcpStream，它提供了两种方式进行分离:
 /// * TcpStream::split 会获取字节流的引用，然后将其分离成一个读取器和写入器。但由于使用了引用的方式，
@@ -11,7 +39,60 @@ use tokio::net::TcpStream;
 /// * TcpStream::into_split 还提供了一种分离实现，分离出来的结果可以在任务间移动，内部是通过 Arc 实现。
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let socket = TcpStream::connect("127.0.0.1:6142").await?;
+    let mut addr = "127.0.0.1:6142".to_string();
+    let mut verify = false;
+    let mut codec = codec::from_name("length").unwrap();
+    let mut connections = 16;
+    let mut frames = 1000;
+    let mut size = 1024;
+    let mut max_frame_size = codec::DEFAULT_MAX_FRAME_SIZE;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--verify" {
+            verify = true;
+            continue;
+        }
+        let value = args.next().unwrap_or_else(|| usage(&arg));
+        match &arg[..] {
+            "--addr" => addr = value,
+            "--codec" => codec = codec::from_name(&value).unwrap_or_else(|| usage(&value)),
+            "--connections" => connections = value.parse().unwrap_or_else(|_| usage(&value)),
+            "--frames" => frames = value.parse().unwrap_or_else(|_| usage(&value)),
+            "--size" => size = value.parse().unwrap_or_else(|_| usage(&value)),
+            "--max-frame-size" => max_frame_size = value.parse().unwrap_or_else(|_| usage(&value)),
+            _ => usage(&arg),
+        }
+    }
+
+    if !verify {
+        return hello(&addr).await;
+    }
+    if size == 0 {
+        usage("--size");
+    }
+
+    let verify = Verify {
+        addr,
+        codec,
+        connections,
+        frames,
+        size,
+        max_frame_size,
+    };
+    if !run_verify(verify).await {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn usage(arg: &str) -> ! {
+    eprintln!("invalid argument '{}'\n{}", arg, USAGE);
+    process::exit(1);
+}
+
+async fn hello(addr: &str) -> io::Result<()> {
+    let socket = TcpStream::connect(addr).await?;
     // TcpStream 实现 AsyncRead 和 AsyncWrite，我们需要将其功能分离来用。
     // io::copy(&mut socket, &mut socket).await // fails to compile
     let (mut rd, mut wr) = io::split(socket);
@@ -35,3 +116,133 @@ async fn main() -> io::Result<()> {
     }
     Ok(())
 }
+
+/// 一个连接的校验结果
+#[derive(Debug, Default)]
+struct Outcome {
+    frames: u64,
+    bytes: u64,
+    mismatches: u64,
+    error: Option<String>,
+}
+
+/// 所有连接都校验通过时返回 true
+async fn run_verify(verify: Verify) -> bool {
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..verify.connections)
+        .map(|_| tokio::spawn(verify_connection(verify.clone())))
+        .collect();
+
+    let mut total = Outcome::default();
+    let mut failed = 0;
+    for (i, task) in tasks.into_iter().enumerate() {
+        let outcome = task.await.unwrap_or_else(|err| Outcome {
+            error: Some(err.to_string()),
+            ..Outcome::default()
+        });
+        if outcome.mismatches > 0 || outcome.error.is_some() {
+            failed += 1;
+            eprintln!(
+                "connection {}: {} mismatches, error: {}",
+                i,
+                outcome.mismatches,
+                outcome.error.as_deref().unwrap_or("none")
+            );
+        }
+        total.frames += outcome.frames;
+        total.bytes += outcome.bytes;
+        total.mismatches += outcome.mismatches;
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "{} connections, {} frames ({} codec), {} bytes verified in {:.3}s ({:.0} frames/s), {} mismatches, {} failed connections",
+        verify.connections,
+        total.frames,
+        verify.codec.name(),
+        total.bytes,
+        elapsed.as_secs_f64(),
+        total.frames as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        total.mismatches,
+        failed
+    );
+    failed == 0
+}
+
+/// 写入和读取在两个任务中同时进行，写出去的帧通过通道按顺序交给读取端比较
+async fn verify_connection(verify: Verify) -> Outcome {
+    let mut outcome = Outcome::default();
+    let socket = match TcpStream::connect(&verify.addr).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            outcome.error = Some(err.to_string());
+            return outcome;
+        }
+    };
+    let (rd, wr) = socket.into_split();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let writer = tokio::spawn(write_frames(wr, verify.clone(), tx));
+
+    let mut reader = FrameReader::new(rd, verify.codec.clone(), verify.max_frame_size);
+    while let Some(expected) = rx.recv().await {
+        // 对端一直不回复时不会永远等下去
+        let frame = match tokio::time::timeout(Duration::from_secs(10), reader.read_frame()).await {
+            Ok(Ok(Some(frame))) => frame,
+            Ok(Ok(None)) => {
+                outcome.error = Some("connection closed before all frames were echoed".into());
+                break;
+            }
+            Ok(Err(err)) => {
+                outcome.error = Some(err.to_string());
+                break;
+            }
+            Err(_) => {
+                outcome.error = Some("timed out waiting for an echoed frame".into());
+                break;
+            }
+        };
+        if frame != expected {
+            outcome.mismatches += 1;
+        }
+        outcome.frames += 1;
+        outcome.bytes += frame.len() as u64;
+    }
+
+    // 读取端出错时对端可能已经不再读数据了，写入端会一直阻塞
+    if outcome.error.is_some() {
+        writer.abort();
+    }
+    if let Ok(Err(err)) = writer.await {
+        outcome.error.get_or_insert(err.to_string());
+    }
+    outcome
+}
+
+/// 发送随机的帧，写完之后关闭写入端，echo-server 读到 EOF 后会在写回所有数据之后关闭连接
+async fn write_frames(
+    wr: tokio::net::tcp::OwnedWriteHalf,
+    verify: Verify,
+    tx: mpsc::UnboundedSender<Bytes>,
+) -> io::Result<()> {
+    let mut wr = BufWriter::new(wr);
+    for _ in 0..verify.frames {
+        let frame = {
+            let mut rng = rand::thread_rng();
+            let len = rng.gen_range(1..=verify.size);
+            // 可打印字符，不会包含 `Lines` 的分隔符
+            let payload: Vec<u8> = (0..len).map(|_| rng.gen_range(b' '..=b'~')).collect();
+            let mut frame = Vec::with_capacity(len + 16);
+            verify.codec.encode(&payload, &mut frame);
+            Bytes::from(frame)
+        };
+
+        wr.write_all(&frame).await?;
+        if tx.send(frame).is_err() {
+            // 读取端已经出错退出了
+            break;
+        }
+    }
+    wr.flush().await?;
+    wr.shutdown().await
+}
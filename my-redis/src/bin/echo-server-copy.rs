@@ -1,3 +1,4 @@
+use std::time::Instant;
 use tokio::io;
 use tokio::net::TcpListener;
 
@@ -6,13 +7,20 @@ async fn main() -> io::Result<()> {
     let listener = TcpListener::bind("127.0.0.1:6142").await?;
 
     loop {
-        let (mut socket, _) = listener.accept().await?;
+        let (mut socket, peer) = listener.accept().await?;
 
         tokio::spawn(async move {
+            let start = Instant::now();
             // 根据使用场景，由于 io::copy() 调用时所在的任务和 split 所在的任务是同一个，因此可以使用性能最高的 TcpStream::split
             let (mut rd, mut wr) = socket.split();
             match io::copy(&mut rd, &mut wr).await {
-                Ok(_) => {}
+                // io::copy 返回拷贝的字节数，和 echo-server 一样在连接关闭时打印出来
+                Ok(n) => println!(
+                    "{} closed after {:.3}s: {} bytes echoed",
+                    peer,
+                    start.elapsed().as_secs_f64(),
+                    n
+                ),
                 Err(e) => {
                     eprintln!("{}", e);
                 }
@@ -1,39 +1,157 @@
-use tokio::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
+//! echo server，把收到的数据原样写回。
+//!
+//! ```text
+//! echo-server [--addr 127.0.0.1:6142] [--codec raw|length|line|resp] [--max-frame-size bytes]
+//! ```
+//!
+//! 默认的 `raw` 模式不关心数据的边界，读到多少写回多少；其它模式按 `my_redis::codec` 中的协议分帧，
+//! 每次写回一个完整的帧，帧超过最大长度时关闭连接。连接关闭时打印它的统计信息(字节数、帧数、持续时间)，
+//! 配合 `echo-client --verify` 可以验证代理是否正确地转发了帧。
+
+use my_redis::codec::{self, Codec, FrameReader};
+
+use std::net::SocketAddr;
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+
+const USAGE: &str =
+    "Usage: echo-server [--addr 127.0.0.1:6142] [--codec raw|length|line|resp] [--max-frame-size bytes]";
+
+/// 一个连接的统计信息
+#[derive(Debug, Default)]
+struct Stats {
+    bytes_in: u64,
+    bytes_out: u64,
+    /// raw 模式下是 read 的次数
+    frames: u64,
+}
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:6142").await?;
+    let mut addr = "127.0.0.1:6142".to_string();
+    let mut codec: Option<Arc<dyn Codec>> = None;
+    let mut max_frame_size = codec::DEFAULT_MAX_FRAME_SIZE;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage(&arg));
+        match &arg[..] {
+            "--addr" => addr = value,
+            "--codec" if value == "raw" => codec = None,
+            "--codec" => codec = Some(codec::from_name(&value).unwrap_or_else(|| usage(&value))),
+            "--max-frame-size" => {
+                max_frame_size = value.parse().unwrap_or_else(|_| usage(&value));
+            }
+            _ => usage(&arg),
+        }
+    }
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!(
+        "echo-server listening on {} ({})",
+        addr,
+        codec.as_ref().map_or("raw", |codec| codec.name())
+    );
 
     loop {
-        let (mut socket, _) = listener.accept().await?;
+        let (socket, peer) = listener.accept().await?;
+        let codec = codec.clone();
 
         tokio::spawn(async move {
-            // 此处的缓冲区是一个 Vec 动态数组，它的数据是存储在堆上，而不是栈上(若改成 let mut buf = [0; 1024];，则存储在栈上)。
-            // 一个数据如果想在 .await 调用过程中存在，那它必须存储在当前任务内。
-            // 在我们的代码中，buf 会在 .await 调用过程中被使用，因此它必须要存储在任务内。
-            // 当任务因为调度在线程间移动时，存储在栈上的数据需要进行保存和恢复，过大的栈上变量会带来不小的数据拷贝开销
-            // 因此，存储大量数据的变量最好放到堆上
-            let mut buf = vec![0; 1024];
+            let start = Instant::now();
+            let mut stats = Stats::default();
+            let res = match codec {
+                Some(codec) => echo_frames(socket, codec, max_frame_size, &mut stats).await,
+                None => echo_raw(socket, &mut stats).await,
+            };
+            report(peer, start, &stats, res);
+        });
+    }
+}
+
+fn usage(arg: &str) -> ! {
+    eprintln!("invalid argument '{}'\n{}", arg, USAGE);
+    process::exit(1);
+}
+
+async fn echo_raw(mut socket: TcpStream, stats: &mut Stats) -> my_redis::Result<()> {
+    // 此处的缓冲区是一个 Vec 动态数组，它的数据是存储在堆上，而不是栈上(若改成 let mut buf = [0; 1024];，则存储在栈上)。
+    // 一个数据如果想在 .await 调用过程中存在，那它必须存储在当前任务内。
+    // 在我们的代码中，buf 会在 .await 调用过程中被使用，因此它必须要存储在任务内。
+    // 当任务因为调度在线程间移动时，存储在栈上的数据需要进行保存和恢复，过大的栈上变量会带来不小的数据拷贝开销
+    // 因此，存储大量数据的变量最好放到堆上
+    let mut buf = vec![0; 1024];
+    loop {
+        // 返回值 `Ok(0)` 说明对端已经关闭
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        stats.bytes_in += n as u64;
+        stats.frames += 1;
+
+        // Copy the data back to socket
+        // 将数据拷贝回 socket 中
+        socket.write_all(&buf[..n]).await?;
+        stats.bytes_out += n as u64;
+    }
+}
+
+/// 按帧回显。已经读到的帧全部写进缓冲区之后才 flush，客户端 pipeline 发送时可以减少系统调用
+async fn echo_frames(
+    mut socket: TcpStream,
+    codec: Arc<dyn Codec>,
+    max_frame_size: usize,
+    stats: &mut Stats,
+) -> my_redis::Result<()> {
+    let (rd, wr) = socket.split();
+    let mut reader = FrameReader::new(rd, codec, max_frame_size);
+    let mut writer = BufWriter::new(wr);
+
+    let res: my_redis::Result<()> = async {
+        loop {
             loop {
-                match socket.read(&mut buf).await {
-                    // 返回值 `Ok(0)` 说明对端已经关闭{}
-                    Ok(0) => return,
-                    Ok(n) => {
-                        // Copy the data back to socket
-                        // 将数据拷贝回 socket 中
-                        if socket.write_all(&buf[..n]).await.is_err() {
-                            // 非预期错误，由于我们这里无需再做什么，因此直接停止处理
-                            return;
-                        }
+                match reader.try_frame() {
+                    Ok(Some(frame)) => {
+                        writer.write_all(&frame).await?;
+                        stats.frames += 1;
+                        stats.bytes_out += frame.len() as u64;
                     }
-                    Err(_) => {
-                        // 非预期错误，由于我们无需再做什么，因此直接停止处理
-                        return;
+                    Ok(None) => break,
+                    Err(err) => {
+                        // 出错之前的帧仍然写回去，然后再关闭连接
+                        writer.flush().await?;
+                        return Err(err);
                     }
                 }
             }
-        });
+            writer.flush().await?;
+            if !reader.fill().await? {
+                return Ok(());
+            }
+        }
     }
+    .await;
+
+    stats.bytes_in = reader.bytes_read();
+    res
+}
+
+fn report(peer: SocketAddr, start: Instant, stats: &Stats, res: my_redis::Result<()>) {
+    let error = match res {
+        Ok(()) => String::new(),
+        Err(err) => format!(", error: {}", err),
+    };
+    println!(
+        "{} closed after {:.3}s: {} frames, {} bytes in, {} bytes out{}",
+        peer,
+        start.elapsed().as_secs_f64(),
+        stats.frames,
+        stats.bytes_in,
+        stats.bytes_out,
+        error
+    );
 }
@@ -0,0 +1,517 @@
+//! 类似 redis-benchmark 的压测工具
+//!
+//! ```text
+//! my-redis-benchmark [-h host] [-p port] [-c clients] [-n requests] [-d size] [-r keyspacelen]
+//!                    [-P numreq] [-t tests | --mix op=weight,...] [-q | --csv | --json]
+//! ```
+//!
+//! * `-t set,get,incr,lpush` 依次单独压测每种命令，`--mix get=80,set=20` 按权重随机混合成一个压测
+//! * `-r` 指定后 key 在 `0..keyspacelen` 中随机选取，否则所有请求都访问同一个 key
+//! * `-P` 每个连接一次发出多少个请求(pipeline)，请求的延迟从这一批请求写出开始计算
+//! * 延迟记录在 HDR 直方图中，精度是微秒，结果可以输出为 CSV 或 JSON 方便长期对比
+
+use my_redis::{Connection, Frame};
+
+use bytes::Bytes;
+use hdrhistogram::Histogram;
+use rand::Rng;
+use std::fmt::Write;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+const USAGE: &str = "Usage: my-redis-benchmark [-h host] [-p port] [-c clients] [-n requests] \
+[-d size] [-r keyspacelen] [-P numreq] [-t tests | --mix op=weight,...] [-q | --csv | --json]";
+
+/// 直方图能记录的最大延迟(微秒)，更大的值按最大值记录
+const MAX_LATENCY_US: u64 = 60 * 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Get,
+    Set,
+    Incr,
+    LPush,
+}
+
+impl Op {
+    const ALL: [Op; 4] = [Op::Set, Op::Get, Op::Incr, Op::LPush];
+
+    fn parse(name: &str) -> Result<Op, String> {
+        match &name.to_lowercase()[..] {
+            "get" => Ok(Op::Get),
+            "set" => Ok(Op::Set),
+            "incr" => Ok(Op::Incr),
+            "lpush" => Ok(Op::LPush),
+            _ => Err(format!("unknown test '{}'", name)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Op::Get => "GET",
+            Op::Set => "SET",
+            Op::Incr => "INCR",
+            Op::LPush => "LPUSH",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Quiet,
+    Csv,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    host: String,
+    port: u16,
+    clients: usize,
+    requests: u64,
+    data_size: usize,
+    /// 为 0 时不随机选取 key
+    keyspace: u64,
+    pipeline: usize,
+    /// 每一项是一个压测，单独一种命令的压测只有一个权重为 1 的命令
+    workloads: Vec<Workload>,
+    format: Format,
+}
+
+/// 一个压测中按权重混合的命令
+#[derive(Debug, Clone)]
+struct Workload {
+    name: String,
+    ops: Vec<(Op, u32)>,
+}
+
+impl Workload {
+    fn single(op: Op) -> Workload {
+        Workload {
+            name: op.name().to_string(),
+            ops: vec![(op, 1)],
+        }
+    }
+
+    fn pick(&self, rng: &mut impl Rng) -> usize {
+        let total: u32 = self.ops.iter().map(|(_, weight)| weight).sum();
+        let mut n = rng.gen_range(0..total);
+        for (i, (_, weight)) in self.ops.iter().enumerate() {
+            if n < *weight {
+                return i;
+            }
+            n -= weight;
+        }
+        unreachable!("n is always smaller than the total weight")
+    }
+}
+
+/// 一个压测中某种命令的统计
+#[derive(Debug)]
+struct Stats {
+    latency: Histogram<u64>,
+    errors: u64,
+}
+
+impl Stats {
+    fn new() -> Stats {
+        Stats {
+            latency: Histogram::new_with_bounds(1, MAX_LATENCY_US, 3).unwrap(),
+            errors: 0,
+        }
+    }
+}
+
+/// 输出的一行结果
+#[derive(Debug)]
+struct Report {
+    test: String,
+    elapsed: Duration,
+    stats: Stats,
+}
+
+impl Report {
+    fn requests(&self) -> u64 {
+        self.stats.latency.len()
+    }
+
+    fn rps(&self) -> f64 {
+        self.requests() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// 百分位延迟，单位毫秒
+    fn percentile(&self, quantile: f64) -> f64 {
+        self.stats.latency.value_at_quantile(quantile) as f64 / 1000.0
+    }
+
+    fn avg(&self) -> f64 {
+        self.stats.latency.mean() / 1000.0
+    }
+
+    fn min(&self) -> f64 {
+        self.stats.latency.min() as f64 / 1000.0
+    }
+
+    fn max(&self) -> f64 {
+        self.stats.latency.max() as f64 / 1000.0
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(1);
+        }
+    };
+    let options = Arc::new(options);
+
+    let mut reports = vec![];
+    for workload in &options.workloads {
+        match run(&options, workload).await {
+            Ok(rows) => {
+                if options.format == Format::Text || options.format == Format::Quiet {
+                    for row in &rows {
+                        print_text(&options, row);
+                    }
+                }
+                reports.extend(rows);
+            }
+            Err(err) => {
+                eprintln!("{}: {}", workload.name, err);
+                process::exit(1);
+            }
+        }
+    }
+
+    match options.format {
+        Format::Csv => print!("{}", to_csv(&reports)),
+        Format::Json => println!("{}", to_json(&reports)),
+        Format::Text | Format::Quiet => {}
+    }
+}
+
+fn parse_options(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        host: "127.0.0.1".to_string(),
+        port: 6379,
+        clients: 50,
+        requests: 100_000,
+        data_size: 3,
+        keyspace: 0,
+        pipeline: 1,
+        workloads: vec![],
+        format: Format::Text,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for '{}'", name))
+        };
+        match &arg[..] {
+            "-h" => options.host = value("-h")?,
+            "-p" => options.port = parse_number("-p", &value("-p")?)?,
+            "-c" => options.clients = parse_number("-c", &value("-c")?)?,
+            "-n" => options.requests = parse_number("-n", &value("-n")?)?,
+            "-d" => options.data_size = parse_number("-d", &value("-d")?)?,
+            "-r" => options.keyspace = parse_number("-r", &value("-r")?)?,
+            "-P" => options.pipeline = parse_number("-P", &value("-P")?)?,
+            "-t" => {
+                for name in value("-t")?.split(',').filter(|name| !name.is_empty()) {
+                    options.workloads.push(Workload::single(Op::parse(name)?));
+                }
+            }
+            "--mix" => options.workloads.push(parse_mix(&value("--mix")?)?),
+            "-q" => options.format = Format::Quiet,
+            "--csv" => options.format = Format::Csv,
+            "--json" => options.format = Format::Json,
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unrecognized option '{}'", arg)),
+        }
+    }
+
+    if options.clients == 0 || options.pipeline == 0 {
+        return Err("-c and -P must be greater than 0".to_string());
+    }
+    if options.workloads.is_empty() {
+        options.workloads = Op::ALL.into_iter().map(Workload::single).collect();
+    }
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, name))
+}
+
+/// 解析 `get=80,set=20`，省略权重时为 1
+fn parse_mix(spec: &str) -> Result<Workload, String> {
+    let mut ops = vec![];
+    for item in spec.split(',').filter(|item| !item.is_empty()) {
+        let (name, weight) = match item.split_once('=') {
+            Some((name, weight)) => (name, parse_number("--mix", weight)?),
+            None => (item, 1),
+        };
+        if weight > 0 {
+            ops.push((Op::parse(name)?, weight));
+        }
+    }
+    if ops.is_empty() {
+        return Err(format!("invalid value '{}' for '--mix'", spec));
+    }
+
+    let name = ops
+        .iter()
+        .map(|(op, weight)| format!("{}={}", op.name(), weight))
+        .collect::<Vec<_>>()
+        .join(",");
+    Ok(Workload {
+        name: format!("MIX({})", name),
+        ops,
+    })
+}
+
+/// 执行一个压测：先建立所有连接，然后同时开始发请求，直到一共完成 `requests` 个请求。
+/// 混合压测除了总的结果之外，每种命令还会单独输出一行
+async fn run(options: &Arc<Options>, workload: &Workload) -> my_redis::Result<Vec<Report>> {
+    let addr = format!("{}:{}", options.host, options.port);
+    let mut connections = Vec::with_capacity(options.clients);
+    for _ in 0..options.clients {
+        let socket = TcpStream::connect(&addr).await?;
+        // 和 `Client` 一样关闭 Nagle 算法，否则 pipeline 的请求会被延迟发送
+        socket.set_nodelay(true)?;
+        connections.push(Connection::new(socket));
+    }
+
+    let remaining = Arc::new(AtomicU64::new(options.requests));
+    let start = Instant::now();
+    let tasks: Vec<_> = connections
+        .into_iter()
+        .map(|connection| {
+            let options = options.clone();
+            let workload = workload.clone();
+            let remaining = remaining.clone();
+            tokio::spawn(async move { client(connection, &options, &workload, &remaining).await })
+        })
+        .collect();
+
+    let mut per_op: Vec<Stats> = workload.ops.iter().map(|_| Stats::new()).collect();
+    for task in tasks {
+        let stats = task.await??;
+        for (total, stats) in per_op.iter_mut().zip(stats) {
+            total.latency.add(&stats.latency)?;
+            total.errors += stats.errors;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    if per_op.len() == 1 {
+        return Ok(vec![Report {
+            test: workload.name.clone(),
+            elapsed,
+            stats: per_op.pop().unwrap(),
+        }]);
+    }
+
+    let mut total = Stats::new();
+    for stats in &per_op {
+        total.latency.add(&stats.latency)?;
+        total.errors += stats.errors;
+    }
+    let mut reports = vec![Report {
+        test: workload.name.clone(),
+        elapsed,
+        stats: total,
+    }];
+    for ((op, _), stats) in workload.ops.iter().zip(per_op) {
+        reports.push(Report {
+            test: format!("{}:{}", workload.name, op.name()),
+            elapsed,
+            stats,
+        });
+    }
+    Ok(reports)
+}
+
+/// 一个连接上的压测循环，返回的统计和 `workload.ops` 一一对应
+async fn client(
+    mut connection: Connection,
+    options: &Options,
+    workload: &Workload,
+    remaining: &AtomicU64,
+) -> my_redis::Result<Vec<Stats>> {
+    let mut stats: Vec<Stats> = workload.ops.iter().map(|_| Stats::new()).collect();
+    let value = Bytes::from(vec![b'x'; options.data_size]);
+
+    loop {
+        // 从剩余的请求数中领取一批
+        let pipeline = options.pipeline as u64;
+        let batch = match remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            (n > 0).then(|| n - n.min(pipeline))
+        }) {
+            Ok(n) => n.min(pipeline) as usize,
+            Err(_) => return Ok(stats),
+        };
+
+        let requests = build_requests(options, workload, &value, batch);
+        let sent = Instant::now();
+        for (_, frame) in &requests {
+            connection.buffer_frame(frame).await?;
+        }
+        connection.flush().await?;
+
+        for (i, _) in &requests {
+            let reply = connection
+                .read_frame()
+                .await?
+                .ok_or("connection reset by server")?;
+            let stats = &mut stats[*i];
+            if let Frame::Error(_) = reply {
+                stats.errors += 1;
+            }
+            let micros = sent.elapsed().as_micros() as u64;
+            stats
+                .latency
+                .saturating_record(micros.clamp(1, MAX_LATENCY_US));
+        }
+    }
+}
+
+/// 生成一批请求，每个请求带着它在 `workload.ops` 中的下标
+fn build_requests(
+    options: &Options,
+    workload: &Workload,
+    value: &Bytes,
+    count: usize,
+) -> Vec<(usize, Frame)> {
+    let mut rng = rand::thread_rng();
+    let mut requests = Vec::with_capacity(count);
+    for _ in 0..count {
+        let i = workload.pick(&mut rng);
+        let mut key = |prefix: &str| -> Bytes {
+            if options.keyspace > 0 {
+                format!("{}:{:012}", prefix, rng.gen_range(0..options.keyspace)).into()
+            } else {
+                format!("{}:__rand_int__", prefix).into()
+            }
+        };
+        let args: Vec<Bytes> = match workload.ops[i].0 {
+            Op::Get => vec!["GET".into(), key("key")],
+            Op::Set => vec!["SET".into(), key("key"), value.clone()],
+            Op::Incr => vec!["INCR".into(), key("counter")],
+            Op::LPush => vec!["LPUSH".into(), key("mylist"), value.clone()],
+        };
+        requests.push((i, Frame::Array(args.into_iter().map(Frame::Bulk).collect())));
+    }
+    requests
+}
+
+fn print_text(options: &Options, report: &Report) {
+    if options.format == Format::Quiet {
+        println!(
+            "{}: {:.2} requests per second, p50={:.3} msec",
+            report.test,
+            report.rps(),
+            report.percentile(0.5)
+        );
+        return;
+    }
+
+    println!("====== {} ======", report.test);
+    println!(
+        "  {} requests completed in {:.2} seconds",
+        report.requests(),
+        report.elapsed.as_secs_f64()
+    );
+    println!("  {} parallel clients", options.clients);
+    println!("  {} bytes payload", options.data_size);
+    println!("  pipeline depth {}", options.pipeline);
+    if report.stats.errors > 0 {
+        println!("  {} error replies", report.stats.errors);
+    }
+    println!();
+    println!("Summary:");
+    println!(
+        "  throughput summary: {:.2} requests per second",
+        report.rps()
+    );
+    println!("  latency summary (msec):");
+    println!(
+        "  {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "avg", "min", "p50", "p99", "p99.9", "max"
+    );
+    println!(
+        "  {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+        report.avg(),
+        report.min(),
+        report.percentile(0.5),
+        report.percentile(0.99),
+        report.percentile(0.999),
+        report.max()
+    );
+    println!();
+}
+
+fn to_csv(reports: &[Report]) -> String {
+    let mut out = String::from(
+        "\"test\",\"requests\",\"errors\",\"rps\",\"avg_latency_ms\",\"min_latency_ms\",\
+\"p50_latency_ms\",\"p99_latency_ms\",\"p999_latency_ms\",\"max_latency_ms\"\n",
+    );
+    for r in reports {
+        let _ = writeln!(
+            out,
+            "\"{}\",\"{}\",\"{}\",\"{:.2}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\"",
+            r.test,
+            r.requests(),
+            r.stats.errors,
+            r.rps(),
+            r.avg(),
+            r.min(),
+            r.percentile(0.5),
+            r.percentile(0.99),
+            r.percentile(0.999),
+            r.max()
+        );
+    }
+    out
+}
+
+/// 测试名只包含字母、数字和 `:(),=`，不需要转义
+fn to_json(reports: &[Report]) -> String {
+    let mut out = String::from("[");
+    for (i, r) in reports.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{{\"test\":\"{}\",\"requests\":{},\"errors\":{},\"rps\":{:.2},\
+\"avg_latency_ms\":{:.3},\"min_latency_ms\":{:.3},\"p50_latency_ms\":{:.3},\
+\"p99_latency_ms\":{:.3},\"p999_latency_ms\":{:.3},\"max_latency_ms\":{:.3}}}",
+            r.test,
+            r.requests(),
+            r.stats.errors,
+            r.rps(),
+            r.avg(),
+            r.min(),
+            r.percentile(0.5),
+            r.percentile(0.99),
+            r.percentile(0.999),
+            r.max()
+        );
+    }
+    out.push(']');
+    out
+}
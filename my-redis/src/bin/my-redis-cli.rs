@@ -0,0 +1,376 @@
+//! 类似 redis-cli 的命令行客户端
+//!
+//! ```text
+//! my-redis-cli [-h host] [-p port] [-a password] [--pipe] [command [arg ...]]
+//! ```
+//!
+//! * 不带命令时进入交互模式，支持历史记录(保存在 `~/.my_redis_cli_history`)以及带引号的参数
+//! * 带命令时只执行这一条命令，例如 `my-redis-cli SET a b`。和 redis-cli 一样，错误回复也只是打印出来，退出码仍然是 0
+//! * `-a password` 在连接(以及重连)之后先发送 `AUTH password`
+//! * `--pipe` 把标准输入中的命令一次性发给服务端(批量导入)，输入可以是 RESP 协议也可以是每行一条命令
+
+use my_redis::cli::{format_reply, split_args};
+use my_redis::{frame, Connection, Frame};
+
+use bytes::{Buf, Bytes, BytesMut};
+use rustyline::error::ReadlineError;
+use std::io::{Cursor, IsTerminal, Read};
+use std::process;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+
+const USAGE: &str =
+    "Usage: my-redis-cli [-h host] [-p port] [-a password] [--pipe] [command [arg ...]]";
+
+#[derive(Debug)]
+struct Options {
+    host: String,
+    port: u16,
+    password: Option<String>,
+    pipe: bool,
+    /// 一次性执行的命令，为空时进入交互模式
+    command: Vec<String>,
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(1);
+        }
+    };
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let addr = format!("{}:{}", options.host, options.port);
+
+    if options.pipe {
+        if let Err(err) = rt.block_on(pipe(&addr, options.password.as_deref())) {
+            eprintln!("ERR {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut cli = match rt.block_on(Cli::connect(addr, options.password)) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!(
+                "Could not connect to my-redis at {}:{}: {}",
+                options.host, options.port, err
+            );
+            process::exit(1);
+        }
+    };
+
+    if options.command.is_empty() {
+        repl(&rt, &mut cli);
+    } else {
+        let args = options.command.into_iter().map(Bytes::from).collect();
+        // 错误回复已经打印出来了，和 redis-cli 一样退出码仍然是 0，只有连接出错时才是 1
+        if let Err(err) = rt.block_on(cli.execute(args)) {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// 解析命令行参数，第一个不是选项的参数开始就是要执行的命令
+fn parse_options(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        host: "127.0.0.1".to_string(),
+        port: 6379,
+        password: None,
+        pipe: false,
+        command: vec![],
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for '{}'", name))
+        };
+        match &arg[..] {
+            "-h" => options.host = value("-h")?,
+            "-p" => {
+                let port = value("-p")?;
+                options.port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port '{}'", port))?;
+            }
+            "-a" => options.password = Some(value("-a")?),
+            "--pipe" => options.pipe = true,
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') && options.command.is_empty() => {
+                return Err(format!("unrecognized option '{}'", arg));
+            }
+            _ => {
+                options.command.push(arg);
+                options.command.extend(args.by_ref());
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+/// 交互模式下的连接，断开之后在执行下一条命令时自动重连
+struct Cli {
+    addr: String,
+    /// `-a` 指定的密码，每次建立连接之后先 AUTH
+    password: Option<String>,
+    connection: Option<Connection>,
+    /// 当前选择的数据库，显示在提示符中
+    db: i64,
+}
+
+impl Cli {
+    async fn connect(addr: String, password: Option<String>) -> my_redis::Result<Cli> {
+        let mut cli = Cli {
+            addr,
+            password,
+            connection: None,
+            db: 0,
+        };
+        cli.reconnect().await?;
+        Ok(cli)
+    }
+
+    async fn reconnect(&mut self) -> my_redis::Result<()> {
+        let mut connection = Connection::new(TcpStream::connect(&self.addr).await?);
+
+        if let Some(password) = &self.password {
+            let auth = vec![Bytes::from("AUTH"), Bytes::from(password.clone())];
+            if let Frame::Error(err) = send(&mut connection, auth).await? {
+                return Err(err.into());
+            }
+        }
+
+        // 重连之后回到之前选择的数据库
+        if self.db != 0 {
+            let select = request(vec![
+                Bytes::from("SELECT"),
+                Bytes::from(self.db.to_string()),
+            ]);
+            connection.write_frame(&select).await?;
+            connection.read_frame().await?;
+        }
+
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    fn prompt(&self) -> String {
+        match (&self.connection, self.db) {
+            (None, _) => "not connected> ".to_string(),
+            (Some(_), 0) => format!("{}> ", self.addr),
+            (Some(_), db) => format!("{}[{}]> ", self.addr, db),
+        }
+    }
+
+    /// 执行一条命令并打印回复，SUBSCRIBE / MONITOR 会一直打印推送的消息直到连接关闭
+    async fn execute(&mut self, args: Vec<Bytes>) -> my_redis::Result<Frame> {
+        if self.connection.is_none() {
+            self.reconnect().await?;
+        }
+        let connection = self.connection.as_mut().unwrap();
+
+        let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+        let select = match &name[..] {
+            "select" => args
+                .get(1)
+                .and_then(|db| std::str::from_utf8(db).ok()?.parse().ok()),
+            _ => None,
+        };
+
+        let reply = match send(connection, args).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                self.connection = None;
+                return Err(err);
+            }
+        };
+        print_reply(&reply);
+
+        if let (Some(db), Frame::Simple(_)) = (select, &reply) {
+            self.db = db;
+        }
+
+        if matches!(&name[..], "subscribe" | "monitor") && !matches!(reply, Frame::Error(_)) {
+            while let Some(frame) = connection.read_frame().await? {
+                print_reply(&frame);
+            }
+            self.connection = None;
+        }
+
+        Ok(reply)
+    }
+}
+
+fn repl(rt: &Runtime, cli: &mut Cli) {
+    let mut editor = match rustyline::DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let history = std::env::var("HOME")
+        .map(|home| format!("{}/.my_redis_cli_history", home))
+        .ok();
+    if let Some(history) = &history {
+        // 第一次使用时文件还不存在
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let line = match editor.readline(&cli.prompt()) {
+            Ok(line) => line,
+            // Ctrl-C / Ctrl-D
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                break;
+            }
+        };
+
+        let args = match split_args(&line) {
+            Some(args) if args.is_empty() => continue,
+            Some(args) => args,
+            None => {
+                println!("Invalid argument(s)");
+                continue;
+            }
+        };
+        let _ = editor.add_history_entry(line.as_str());
+
+        if args[0].eq_ignore_ascii_case(b"quit") || args[0].eq_ignore_ascii_case(b"exit") {
+            break;
+        }
+
+        if let Err(err) = rt.block_on(cli.execute(args)) {
+            println!("Error: {}", err);
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+}
+
+/// 发送一条命令并等待回复
+async fn send(connection: &mut Connection, args: Vec<Bytes>) -> my_redis::Result<Frame> {
+    connection.write_frame(&request(args)).await?;
+    match connection.read_frame().await? {
+        Some(frame) => Ok(frame),
+        None => Err("Server closed the connection".into()),
+    }
+}
+
+fn request(args: Vec<Bytes>) -> Frame {
+    Frame::Array(args.into_iter().map(Frame::Bulk).collect())
+}
+
+/// 批量导入：一个任务写入所有命令，同时在当前任务中读取回复并统计错误，
+/// 避免命令很多时双方的缓冲区都被写满而互相等待
+async fn pipe(addr: &str, password: Option<&str>) -> my_redis::Result<()> {
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input)?;
+
+    // 设置了密码时 AUTH 作为第一条命令，它的回复和其它命令一样统计
+    let mut data = Vec::new();
+    if let Some(password) = password {
+        request(vec![Bytes::from("AUTH"), Bytes::from(password.to_string())]).encode(&mut data);
+    }
+
+    // 输入不是 RESP 协议时，把每一行当作交互模式下的一条命令
+    if input.first() == Some(&b'*') {
+        data.extend_from_slice(&input);
+    } else {
+        for line in String::from_utf8_lossy(&input).lines() {
+            match split_args(line) {
+                Some(args) if args.is_empty() => continue,
+                Some(args) => request(args).encode(&mut data),
+                None => return Err(format!("Invalid argument(s): {}", line).into()),
+            }
+        }
+    }
+
+    let commands = count_frames(&data)?;
+    let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+
+    let write = tokio::spawn(async move {
+        writer.write_all(&data).await?;
+        writer.flush().await
+    });
+
+    let mut buffer = BytesMut::with_capacity(64 * 1024);
+    let mut checker = frame::Checker::default();
+    let (mut replies, mut errors) = (0, 0);
+    while replies < commands {
+        if 0 == reader.read_buf(&mut buffer).await? {
+            return Err("Server closed the connection".into());
+        }
+        loop {
+            let len = match checker.check(&buffer) {
+                Ok(len) => len,
+                Err(_) => break,
+            };
+            let mut cursor = Cursor::new(&buffer[..len]);
+            if let Frame::Error(err) = Frame::parse(&mut cursor)? {
+                if errors == 0 {
+                    eprintln!("{}", err);
+                }
+                errors += 1;
+            }
+            buffer.advance(len);
+            replies += 1;
+        }
+    }
+    write.await??;
+
+    println!("All data transferred. Waiting for the last reply...");
+    println!("errors: {}, replies: {}", errors, replies);
+    Ok(())
+}
+
+/// 输入中完整的 RESP 帧的个数
+fn count_frames(data: &[u8]) -> my_redis::Result<usize> {
+    let mut cursor = Cursor::new(data);
+    let mut count = 0;
+    while (cursor.position() as usize) < data.len() {
+        Frame::check(&mut cursor).map_err(|_| "Invalid RESP input")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 和 redis-cli 一样打印回复：终端中带上类型，重定向到文件或管道时只输出原始内容
+fn print_reply(frame: &Frame) {
+    if std::io::stdout().is_terminal() {
+        for line in format_reply(frame) {
+            println!("{}", line);
+        }
+    } else {
+        print_raw(frame);
+    }
+}
+
+fn print_raw(frame: &Frame) {
+    match frame {
+        Frame::Simple(s) | Frame::Error(s) => println!("{}", s),
+        Frame::Integer(i) => println!("{}", i),
+        Frame::Null => println!(),
+        Frame::Bulk(data) => println!("{}", String::from_utf8_lossy(data)),
+        Frame::Array(items) => items.iter().for_each(print_raw),
+    }
+}
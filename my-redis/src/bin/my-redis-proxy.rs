@@ -0,0 +1,80 @@
+//! 类似 twemproxy 的分片代理，客户端连接代理就像连接一个 my-redis 服务端
+//!
+//! ```text
+//! my-redis-proxy [--listen 127.0.0.1:22121] --backend host:port[:weight] [--backend ...]
+//!                [--server-connections n] [--timeout ms]
+//! ```
+//!
+//! 例如先启动三个服务端 `server --port 6380`、`--port 6381`、`--port 6382`，再启动
+//! `my-redis-proxy --backend 127.0.0.1:6380 --backend 127.0.0.1:6381 --backend 127.0.0.1:6382`，
+//! 之后 `my-redis-cli -p 22121` 执行的命令会按 key 分散到三个服务端。支持的命令见 `my_redis::proxy`
+
+use my_redis::proxy::{self, Backend, ProxyConfig};
+
+use std::process;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const USAGE: &str =
+    "Usage: my-redis-proxy [--listen 127.0.0.1:22121] --backend host:port[:weight] \
+[--backend ...] [--server-connections n] [--timeout ms]";
+
+#[tokio::main]
+async fn main() -> my_redis::Result<()> {
+    // twemproxy 示例配置中使用的端口
+    let mut listen = "127.0.0.1:22121".to_string();
+    let mut config = ProxyConfig::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage(&arg));
+        match &arg[..] {
+            "--listen" => listen = value,
+            "--backend" => config
+                .backends
+                .push(parse_backend(&value).unwrap_or_else(|| usage(&value))),
+            "--server-connections" => {
+                config.server_connections = value.parse().unwrap_or_else(|_| usage(&value));
+            }
+            "--timeout" => {
+                let ms = value.parse().unwrap_or_else(|_| usage(&value));
+                config.timeout = Duration::from_millis(ms);
+            }
+            _ => usage(&arg),
+        }
+    }
+    if config.backends.is_empty() {
+        usage("--backend");
+    }
+
+    let proxy = proxy::connect(config.clone()).await?;
+    let listener = TcpListener::bind(&listen).await?;
+    println!(
+        "my-redis-proxy listening on {}, backends: {}",
+        listen,
+        config
+            .backends
+            .iter()
+            .map(|backend| format!("{} (weight {})", backend.addr, backend.weight))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    proxy.run(listener).await
+}
+
+fn usage(arg: &str) -> ! {
+    eprintln!("invalid argument '{}'\n{}", arg, USAGE);
+    process::exit(1);
+}
+
+/// `host:port` 或者 twemproxy 配置中的 `host:port:weight`，权重默认是 1
+fn parse_backend(value: &str) -> Option<Backend> {
+    let parts: Vec<_> = value.split(':').collect();
+    let (addr, weight) = match parts[..] {
+        [host, port] => (format!("{}:{}", host, port), 1),
+        [host, port, weight] => (format!("{}:{}", host, port), weight.parse().ok()?),
+        _ => return None,
+    };
+    Some(Backend { addr, weight })
+}
@@ -1,67 +1,39 @@
-use bytes::Bytes;
-use mini_redis::Command::{self, Get, Set};
-use mini_redis::{Connection, Frame};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use tokio::net::{TcpListener, TcpStream};
-
-type Db = Arc<Mutex<HashMap<String, Bytes>>>;
-
+use my_redis::server::{self, Listener};
+use my_redis::{tls, Config, Db};
+use tokio::net::TcpListener;
+
+/// 和 redis-server 一样，配置项可以通过命令行参数指定，例如
+/// `cargo run --bin server -- --slowlog-log-slower-than 0`
+///
+/// 开启 TLS：`--tls-port 6380 --tls-cert-file server.crt --tls-key-file server.key`，
+/// 需要双向认证时再加上 `--tls-ca-cert-file ca.crt --tls-auth-clients yes`，`--port 0` 关闭非 TLS 的端口。
+///
+/// 监听 Unix socket：`--unixsocket /tmp/my-redis.sock --unixsocketperm 700`
 #[tokio::main]
-async fn main() {
-    // Bind the listener to the address
-    let listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
-
-    // Tokio 提供的异步锁只应该在跨多个 .await调用时使用，而且 Tokio 的 Mutex 实际上内部使用的也是 std::sync::Mutex。
-    // 1. 锁如果在多个 .await 过程中持有，应该使用 Tokio 提供的锁，原因是 .await 的过程中锁可能在线程间转移，若使用标准库的同步锁存在死锁的可能性，
-    // 例如某个任务刚获取完锁，还没使用完就因为 .await 让出了当前线程的所有权，结果下个任务又去获取了锁，造成死锁
-    // 2. 锁竞争不多的情况下，使用 std::sync::Mutex
-    // 3. 锁竞争多，可以考虑使用三方库提供的性能更高的锁，例如 parking_lot::Mutex
-    let db = Arc::new(Mutex::new(HashMap::new()));
-
-    loop {
-        // The second item contains the ip and port of the new connection.
-        let (socket, _) = listener.accept().await.unwrap();
-        let db = db.clone();
-        println!("Accepted");
-        // A new task is spawned for each inbound socket.  The socket is
-        // moved to the new task and processed there.
-        tokio::spawn(async move {
-            process(socket, db).await;
-        });
+async fn main() -> my_redis::Result<()> {
+    let config = Config::from_args(std::env::args().skip(1))?;
+
+    let mut listeners = vec![];
+    if config.port != 0 {
+        // Bind the listener to the address
+        let listener = TcpListener::bind(("127.0.0.1", config.port)).await?;
+        listeners.push(Listener::Tcp(listener));
+    }
+    if config.tls_port != 0 {
+        let acceptor = tls::acceptor(&config)?;
+        let listener = TcpListener::bind(("127.0.0.1", config.tls_port)).await?;
+        listeners.push(Listener::Tls(listener, acceptor));
+    }
+    if let Some(path) = &config.unixsocket {
+        let listener = server::bind_unix(path, config.unixsocketperm)?;
+        listeners.push(Listener::Unix(listener));
+    }
+    if listeners.is_empty() {
+        return Err("no port, tls-port or unixsocket to listen on".into());
     }
-}
-
-async fn process(socket: TcpStream, db: Db) {
-
-    // Connection, provided by `mini-redis`, handles parsing frames from
-    // the socket
-    let mut connection = Connection::new(socket);
 
-    // Use `read_frame` to receive a command from the connection.
-    while let Some(frame) = connection.read_frame().await.unwrap() {
-        let response = match Command::from_frame(frame).unwrap() {
-            Set(cmd) => {
-                let mut db = db.lock().unwrap();
-                // The value is stored as `Vec<u8>`
-                db.insert(cmd.key().to_string(), cmd.value().clone());
-                Frame::Simple("OK".to_string())
-            }
-            Get(cmd) => {
-                let db = db.lock().unwrap();
-                if let Some(value) = db.get(cmd.key()) {
-                    // `Frame::Bulk` expects data to be of type `Bytes`. This
-                    // type will be covered later in the tutorial. For now,
-                    // `&Vec<u8>` is converted to `Bytes` using `into()`.
-                    Frame::Bulk(value.clone())
-                } else {
-                    Frame::Null
-                }
-            }
-            cmd => panic!("unimplemented {:?}", cmd),
-        };
+    // 键空间、脚本缓存、慢日志等状态都放在 `Db` 中，详见 src/db.rs
+    let db = Db::with_config(config);
 
-        // Write the response to the client
-        connection.write_frame(&response).await.unwrap();
-    }
+    server::serve(listeners, db).await
 }
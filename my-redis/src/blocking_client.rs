@@ -0,0 +1,140 @@
+//! 同步的客户端，给命令行工具、GUI 这类不想引入 async 的代码使用。
+//!
+//! 思路来自 `examples/tokio_blocking_client.rs`：在内部持有一个 Tokio 运行时，每个方法都通过 `block_on`
+//! 执行 `client` 模块中对应的异步方法。这里使用的是 `current_thread` 运行时，它不会创建额外的线程，
+//! 后台任务(例如 `Client` 的管理任务)只在 `block_on` 期间运行，对于"发一个请求、等一个回复"的用法已经足够。
+
+use crate::client::{self, Client, Message, Subscriber};
+use crate::tls::ClientTls;
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// 同步的客户端
+#[derive(Debug)]
+pub struct BlockingClient {
+    inner: Client,
+    rt: Runtime,
+}
+
+/// 进入订阅模式的同步客户端，通过 `BlockingClient::subscribe` 建立
+#[derive(Debug)]
+pub struct BlockingSubscriber {
+    inner: Subscriber,
+    rt: Runtime,
+}
+
+/// `BlockingSubscriber::into_iter` 返回的迭代器，连接关闭时结束
+#[derive(Debug)]
+pub struct SubscriberIterator {
+    inner: Subscriber,
+    rt: Runtime,
+}
+
+/// 连接到 `addr`
+pub fn connect(addr: impl ToString) -> crate::Result<BlockingClient> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let inner = rt.block_on(client::connect(addr))?;
+
+    Ok(BlockingClient { inner, rt })
+}
+
+/// 使用 TLS 连接到 `addr`
+pub fn connect_tls(addr: impl ToString, tls: ClientTls) -> crate::Result<BlockingClient> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let inner = rt.block_on(client::connect_tls(addr, tls))?;
+
+    Ok(BlockingClient { inner, rt })
+}
+
+impl BlockingClient {
+    pub fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.rt.block_on(self.inner.get(key))
+    }
+
+    pub fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        self.rt.block_on(self.inner.set(key, value))
+    }
+
+    pub fn set_expires(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        expiration: Duration,
+    ) -> crate::Result<()> {
+        self.rt
+            .block_on(self.inner.set_expires(key, value, expiration))
+    }
+
+    /// 发布一条消息，返回收到消息的订阅者个数
+    pub fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<usize> {
+        self.rt.block_on(self.inner.publish(channel, message))
+    }
+
+    /// 订阅频道。和 mini-redis 一样会消耗掉 `self`，运行时转交给返回的订阅者
+    pub fn subscribe(self, channels: Vec<String>) -> crate::Result<BlockingSubscriber> {
+        let subscriber = self.rt.block_on(self.inner.subscribe(&channels))?;
+        Ok(BlockingSubscriber {
+            inner: subscriber,
+            rt: self.rt,
+        })
+    }
+}
+
+impl BlockingSubscriber {
+    /// 当前订阅的频道
+    pub fn get_subscribed(&self) -> &[String] {
+        self.inner.get_subscribed()
+    }
+
+    /// 等待下一条消息，连接被关闭时返回 None
+    pub fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        self.rt.block_on(self.inner.next_message())
+    }
+
+    /// 等待下一条消息，超过 `timeout` 时返回 None
+    pub fn next_message_timeout(&mut self, timeout: Duration) -> crate::Result<Option<Message>> {
+        self.rt.block_on(async {
+            match tokio::time::timeout(timeout, self.inner.next_message()).await {
+                Ok(message) => message,
+                Err(_) => Ok(None),
+            }
+        })
+    }
+
+    pub fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.subscribe(channels))
+    }
+
+    pub fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.unsubscribe(channels))
+    }
+}
+
+/// 转换成阻塞的迭代器，每次 `next` 等待一条消息
+impl IntoIterator for BlockingSubscriber {
+    type Item = crate::Result<Message>;
+    type IntoIter = SubscriberIterator;
+
+    fn into_iter(self) -> SubscriberIterator {
+        SubscriberIterator {
+            inner: self.inner,
+            rt: self.rt,
+        }
+    }
+}
+
+impl Iterator for SubscriberIterator {
+    type Item = crate::Result<Message>;
+
+    fn next(&mut self) -> Option<crate::Result<Message>> {
+        self.rt.block_on(self.inner.next_message()).transpose()
+    }
+}
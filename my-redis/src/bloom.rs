@@ -0,0 +1,139 @@
+//! 可扩展的 Bloom 过滤器，行为参考 RedisBloom 的 `BF.*` 命令。
+//!
+//! 过滤器由一层或多层位数组组成。每层按照容量 `n` 和误判率 `p` 计算大小：
+//! 每个元素占 `-ln(p) / ln(2)^2` 位，哈希函数的个数是 `ceil(ln(2) * 每个元素的位数)`。
+//! 最后一层装满之后新建一层，容量是上一层的 `expansion` 倍，误判率是上一层的一半，
+//! 这样所有层加起来的误判率仍然不超过 `2p`。`NONSCALING` 的过滤器装满之后不能再加入新元素。
+//!
+//! 第 i 个哈希值用双重哈希 `h1 + i * h2` 计算，`h1`、`h2` 都是 MurmurHash64A
+
+use crate::murmur::murmur64a;
+
+/// BF.ADD 自动创建过滤器时使用的误判率和容量，和 RedisBloom 的默认值一样
+pub const DEFAULT_ERROR_RATE: f64 = 0.01;
+pub const DEFAULT_CAPACITY: u64 = 100;
+pub const DEFAULT_EXPANSION: u32 = 2;
+
+/// 每新建一层，误判率乘以这个系数
+const TIGHTENING_RATIO: f64 = 0.5;
+
+const HASH_SEED: u64 = 0xc6a4a7935bd1e995;
+
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    /// 为 0 时不扩展
+    expansion: u32,
+    layers: Vec<Layer>,
+}
+
+/// 不扩展的过滤器已经装满
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Full;
+
+#[derive(Debug, Clone)]
+struct Layer {
+    bits: Vec<u64>,
+    /// 位数组的长度
+    len: u64,
+    hashes: u32,
+    capacity: u64,
+    /// 这一层加入的元素个数
+    items: u64,
+    error_rate: f64,
+}
+
+impl BloomFilter {
+    /// `error_rate` 必须在 (0, 1) 之间，`capacity` 必须大于 0，`expansion` 为 0 表示不扩展
+    pub fn new(error_rate: f64, capacity: u64, expansion: u32) -> BloomFilter {
+        BloomFilter {
+            expansion,
+            layers: vec![Layer::new(error_rate, capacity)],
+        }
+    }
+
+    /// 加入一个元素，元素(可能)已经存在时返回 `Ok(false)`
+    pub fn add(&mut self, item: &[u8]) -> Result<bool, Full> {
+        let (h1, h2) = hash(item);
+        if self.layers.iter().any(|layer| layer.contains(h1, h2)) {
+            return Ok(false);
+        }
+
+        let last = self.layers.last().expect("filter has at least one layer");
+        if last.items >= last.capacity {
+            if self.expansion == 0 {
+                return Err(Full);
+            }
+            let layer = Layer::new(
+                last.error_rate * TIGHTENING_RATIO,
+                last.capacity.saturating_mul(self.expansion as u64),
+            );
+            self.layers.push(layer);
+        }
+
+        self.layers.last_mut().unwrap().insert(h1, h2);
+        Ok(true)
+    }
+
+    /// 元素是否(可能)存在，返回 false 时一定不存在
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let (h1, h2) = hash(item);
+        self.layers.iter().any(|layer| layer.contains(h1, h2))
+    }
+
+    /// 加入的元素个数
+    pub fn len(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.items).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 层数，扩展过几次就比 1 多几
+    pub fn layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// 所有层的容量之和，超过之后(扩展的过滤器)会新建一层
+    pub fn capacity(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.capacity).sum()
+    }
+}
+
+impl Layer {
+    fn new(error_rate: f64, capacity: u64) -> Layer {
+        let ln2 = std::f64::consts::LN_2;
+        let bits_per_item = -error_rate.ln() / (ln2 * ln2);
+        let len = ((capacity as f64 * bits_per_item).ceil() as u64).max(64);
+        Layer {
+            bits: vec![0; len.div_ceil(64) as usize],
+            len,
+            hashes: (ln2 * bits_per_item).ceil().max(1.0) as u32,
+            capacity,
+            items: 0,
+            error_rate,
+        }
+    }
+
+    fn positions(&self, h1: u64, h2: u64) -> impl Iterator<Item = u64> + '_ {
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.len)
+    }
+
+    fn contains(&self, h1: u64, h2: u64) -> bool {
+        self.positions(h1, h2)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, h1: u64, h2: u64) {
+        let positions: Vec<_> = self.positions(h1, h2).collect();
+        for bit in positions {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.items += 1;
+    }
+}
+
+fn hash(item: &[u8]) -> (u64, u64) {
+    let h1 = murmur64a(item, HASH_SEED);
+    (h1, murmur64a(item, h1))
+}
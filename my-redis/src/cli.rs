@@ -0,0 +1,127 @@
+//! `my-redis-cli` 解析输入和显示回复的函数，放在库中以便在 `tests/cli.rs` 中测试
+
+use crate::monitor::repr;
+use crate::Frame;
+
+use bytes::Bytes;
+
+/// 和 Redis 的 sdssplitargs 一样拆分一行输入，引号不匹配时返回 None。
+///
+/// 双引号中支持 `\n`、`\r`、`\t`、`\b`、`\a`、`\xHH` 等转义，单引号中只支持 `\'`。
+/// 和 Redis 一样，引号可以出现在参数中间(`a"b c"` 是一个参数)，但是右引号之后必须是空白或者行尾
+pub fn split_args(line: &str) -> Option<Vec<Bytes>> {
+    let input = line.as_bytes();
+    let mut args = vec![];
+    let mut i = 0;
+
+    loop {
+        while input.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        if i == input.len() {
+            return Some(args);
+        }
+
+        let mut arg = Vec::new();
+        while let Some(&c) = input.get(i).filter(|c| !c.is_ascii_whitespace()) {
+            i = match c {
+                b'"' => double_quoted(input, i + 1, &mut arg)?,
+                b'\'' => single_quoted(input, i + 1, &mut arg)?,
+                c => {
+                    arg.push(c);
+                    i + 1
+                }
+            };
+        }
+        args.push(Bytes::from(arg));
+    }
+}
+
+/// 从左引号之后开始解析双引号中的内容，返回右引号之后的位置
+fn double_quoted(input: &[u8], mut i: usize, arg: &mut Vec<u8>) -> Option<usize> {
+    loop {
+        match *input.get(i)? {
+            b'"' => return after_quote(input, i + 1),
+            b'\\' => {
+                // `\x` 后面不是两个十六进制数字时只是普通的 `x`
+                let hex = input
+                    .get(i + 2..i + 4)
+                    .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+                match (*input.get(i + 1)?, hex) {
+                    (b'x', Some(hex)) => {
+                        let hex = std::str::from_utf8(hex).ok()?;
+                        arg.push(u8::from_str_radix(hex, 16).ok()?);
+                        i += 4;
+                        continue;
+                    }
+                    (b'n', _) => arg.push(b'\n'),
+                    (b'r', _) => arg.push(b'\r'),
+                    (b't', _) => arg.push(b'\t'),
+                    (b'b', _) => arg.push(0x08),
+                    (b'a', _) => arg.push(0x07),
+                    (c, _) => arg.push(c),
+                }
+                i += 2;
+            }
+            c => {
+                arg.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+fn single_quoted(input: &[u8], mut i: usize, arg: &mut Vec<u8>) -> Option<usize> {
+    loop {
+        match *input.get(i)? {
+            b'\'' => return after_quote(input, i + 1),
+            b'\\' if input.get(i + 1) == Some(&b'\'') => {
+                arg.push(b'\'');
+                i += 2;
+            }
+            c => {
+                arg.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// 右引号之后必须是空白或者行尾
+fn after_quote(input: &[u8], i: usize) -> Option<usize> {
+    match input.get(i) {
+        Some(c) if !c.is_ascii_whitespace() => None,
+        _ => Some(i),
+    }
+}
+
+/// 终端中显示的回复，每个元素是一行：带上类型，数组的元素前面加上序号
+pub fn format_reply(frame: &Frame) -> Vec<String> {
+    match frame {
+        Frame::Simple(s) => vec![s.clone()],
+        Frame::Error(err) => vec![format!("(error) {}", err)],
+        Frame::Integer(i) => vec![format!("(integer) {}", i)],
+        Frame::Null => vec!["(nil)".to_string()],
+        Frame::Bulk(data) => {
+            let mut line = String::new();
+            repr(&mut line, data);
+            vec![line]
+        }
+        Frame::Array(items) if items.is_empty() => vec!["(empty array)".to_string()],
+        Frame::Array(items) => {
+            // 序号右对齐，嵌套的数组缩进到序号之后
+            let width = items.len().to_string().len();
+            let mut lines = vec![];
+            for (i, item) in items.iter().enumerate() {
+                for (j, line) in format_reply(item).into_iter().enumerate() {
+                    if j == 0 {
+                        lines.push(format!("{:>width$}) {}", i + 1, line, width = width));
+                    } else {
+                        lines.push(format!("{:width$}  {}", "", line, width = width));
+                    }
+                }
+            }
+            lines
+        }
+    }
+}
@@ -0,0 +1,1203 @@
+//! my-redis 的异步客户端，在服务中直接嵌入使用。
+//!
+//! 写法沿用 `src/bin/client.rs` 中的 actor 模式：`Client` 只是一个 `mpsc` 发送端，可以随意 `Clone`
+//! 并在多个任务之间共享；真正持有连接的是后台的管理任务，它通过 `oneshot` 把回复传回给发出命令的任务。
+//!
+//! 和那个示例不同的是：
+//! * 每个请求都有超时时间，默认 5 秒，可以通过 `with_timeout` 修改
+//! * 连接断开后管理任务会按照指数退避自动重连，重连期间收到的请求会立即返回 `Error::Disconnected`
+//! * 连接断开时正在执行的请求会返回 `Error::Disconnected`，而不是一直挂起。
+//!   请求可能已经被服务端执行了，所以不会自动重试
+//! * 管理任务记录通过 `SELECT` 选择的数据库，重连之后先重新发送 `SELECT`
+//! * 所有的句柄都被 drop 之后，管理任务会把还在等待的回复读完再关闭连接
+//! * 管理任务不会等上一个请求的回复，多个任务的请求在同一个连接上 pipeline 执行(见 `Manager::serve`)。
+//!   因此阻塞命令和事务不能使用共享的 `Client`，需要从 `pool::Pool` 中取出独占的连接
+//!
+//! 通过 `connect_tls` / `connect_unix` 建立的客户端使用 TLS / Unix socket 连接，重连和 SUBSCRIBE 新建的连接也一样。
+//!
+//! 所有方法返回的都是 `crate::Result`，需要区分错误类型时可以 `downcast_ref::<client::Error>()`
+
+use crate::tls::ClientTls;
+use crate::{Connection, Frame};
+
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, Instant};
+
+/// 请求默认的超时时间
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 建立 TCP 连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 第一次重连前等待的时间，之后每次失败翻倍，直到 `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// 管理任务一次最多连续写入多少个请求再 flush
+const MAX_BATCH: usize = 32;
+
+/// 客户端的句柄，`Clone` 之后共享同一个连接。命令方法定义在 `Commands` 上，通过 `Deref` 调用
+#[derive(Debug)]
+pub struct Client {
+    commands: Commands,
+}
+
+/// 在连接上执行命令的方法。
+///
+/// 和 `Client` 分开是因为 `pool::PooledConnection` 也要提供这些方法，但是不能让调用方 `Clone` 出一个
+/// 脱离连接池的句柄，所以 `Commands` 本身不实现 `Clone`
+#[derive(Debug)]
+pub struct Commands {
+    tx: mpsc::Sender<Request>,
+    timeout: Duration,
+    /// SUBSCRIBE 需要单独建立连接
+    endpoint: Arc<Endpoint>,
+    /// 连接当前选择的数据库，由管理任务更新
+    db: Arc<AtomicUsize>,
+}
+
+/// 服务端的地址以及连接的方式
+#[derive(Debug)]
+struct Endpoint {
+    /// TCP 的 `host:port`，或者 Unix socket 的路径
+    addr: String,
+    transport: Transport,
+}
+
+#[derive(Debug)]
+enum Transport {
+    Tcp,
+    Tls(ClientTls),
+    Unix,
+}
+
+/// TCP、TLS 或者 Unix socket 连接，对 `Connection` 来说都一样
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug> Stream for T {}
+
+/// `SET` 的可选参数，见 `Commands::set_with`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetOptions {
+    pub expiration: Option<Expiration>,
+    pub condition: Option<Condition>,
+}
+
+/// `SET` 的过期时间参数
+#[derive(Debug, Clone, Copy)]
+pub enum Expiration {
+    /// `PX milliseconds`
+    After(Duration),
+    /// `PXAT unix-time-milliseconds`
+    At(SystemTime),
+    /// `KEEPTTL`，保留原来的过期时间
+    KeepTtl,
+}
+
+/// `SET` 的写入条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// `NX`，只在 key 不存在时写入
+    Nx,
+    /// `XX`，只在 key 已经存在时写入
+    Xx,
+}
+
+/// 客户端特有的错误，其它错误(例如 IO 错误)会原样返回
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// 请求在超时时间内没有收到回复
+    Timeout(Duration),
+    /// 连接已经断开，正在重连
+    Disconnected(String),
+    /// 服务端返回了错误回复，例如 `WRONGTYPE ...`
+    Server(String),
+    /// 回复的类型和命令不符
+    UnexpectedReply(Frame),
+    /// 管理任务已经退出，通常是因为运行时正在关闭
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Timeout(timeout) => write!(f, "request timed out after {:?}", timeout),
+            Error::Disconnected(reason) => write!(f, "connection lost: {}", reason),
+            Error::Server(msg) => msg.fmt(f),
+            Error::UnexpectedReply(frame) => write!(f, "unexpected reply: {}", frame),
+            Error::Closed => "client has been shut down".fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// 管理任务可以使用该发送端将命令执行的结果传回给发出命令的任务
+type Responder<T> = oneshot::Sender<crate::Result<T>>;
+
+/// 发给管理任务的一个请求
+#[derive(Debug)]
+struct Request {
+    frame: Frame,
+    resp: Responder<Frame>,
+}
+
+/// 已经交给管理任务、还在等待回复的命令，见 `Commands::send`
+#[derive(Debug)]
+pub struct PendingReply {
+    rx: oneshot::Receiver<crate::Result<Frame>>,
+    deadline: Instant,
+    timeout: Duration,
+}
+
+impl PendingReply {
+    /// 等待回复，错误回复也原样返回，不会转换成 `Error::Server`
+    pub async fn recv(self) -> crate::Result<Frame> {
+        time::timeout_at(self.deadline, self.rx)
+            .await
+            .map_err(|_| Error::Timeout(self.timeout))?
+            .map_err(|_| Error::Closed)?
+    }
+}
+
+/// 连接到 `addr`，第一次连接失败时直接返回错误，之后断线由管理任务负责重连
+pub async fn connect(addr: impl ToString) -> crate::Result<Client> {
+    connect_endpoint(Endpoint {
+        addr: addr.to_string(),
+        transport: Transport::Tcp,
+    })
+    .await
+}
+
+/// 和 `connect` 一样，但是使用 TLS 连接，`addr` 是服务端的 `tls-port`
+pub async fn connect_tls(addr: impl ToString, tls: ClientTls) -> crate::Result<Client> {
+    connect_endpoint(Endpoint {
+        addr: addr.to_string(),
+        transport: Transport::Tls(tls),
+    })
+    .await
+}
+
+/// 通过 Unix socket 连接，`path` 是服务端的 `unixsocket`
+pub async fn connect_unix(path: impl AsRef<Path>) -> crate::Result<Client> {
+    connect_endpoint(Endpoint {
+        addr: path.as_ref().display().to_string(),
+        transport: Transport::Unix,
+    })
+    .await
+}
+
+async fn connect_endpoint(endpoint: Endpoint) -> crate::Result<Client> {
+    let endpoint = Arc::new(endpoint);
+    let stream = endpoint.open().await?;
+
+    // 缓冲队列的长度和 `src/bin/client.rs` 一样是 32，满了之后调用方会在 send 时等待
+    let (tx, rx) = mpsc::channel(32);
+    let db = Arc::new(AtomicUsize::new(0));
+    let manager = Manager {
+        endpoint: endpoint.clone(),
+        rx,
+        db: db.clone(),
+    };
+    tokio::spawn(manager.run(stream));
+
+    Ok(Client {
+        commands: Commands {
+            tx,
+            timeout: DEFAULT_TIMEOUT,
+            endpoint,
+            db,
+        },
+    })
+}
+
+impl Client {
+    /// 返回一个使用新超时时间的句柄，和原来的句柄共享连接
+    pub fn with_timeout(&self, timeout: Duration) -> Client {
+        Client {
+            commands: Commands {
+                tx: self.tx.clone(),
+                timeout,
+                endpoint: self.endpoint.clone(),
+                db: self.db.clone(),
+            },
+        }
+    }
+
+    /// 修改这个句柄的超时时间，连接池放回连接时用来恢复默认值
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.commands.timeout = timeout;
+    }
+}
+
+impl Clone for Client {
+    fn clone(&self) -> Client {
+        self.with_timeout(self.timeout)
+    }
+}
+
+impl Deref for Client {
+    type Target = Commands;
+
+    fn deref(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl Commands {
+    /// 请求的超时时间
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// 连接当前选择的数据库，即最后一个成功的 `SELECT` 的参数
+    pub(crate) fn selected_db(&self) -> usize {
+        self.db.load(Ordering::Relaxed)
+    }
+
+    /// 发送任意命令并返回原始的回复，错误回复会转换成 `Error::Server`
+    pub async fn command(&self, args: Vec<Bytes>) -> crate::Result<Frame> {
+        match self.send(args).await?.recv().await? {
+            Frame::Error(msg) => Err(Error::Server(msg).into()),
+            reply => Ok(reply),
+        }
+    }
+
+    /// 把命令交给管理任务，不等待回复。
+    ///
+    /// 同一个 `Client` 上按照 `send` 返回的顺序执行，代理可以先按顺序发出多个命令，再分别等待回复
+    pub async fn send(&self, args: Vec<Bytes>) -> crate::Result<PendingReply> {
+        let frame = Frame::Array(args.into_iter().map(Frame::Bulk).collect());
+
+        // 发送和等待回复都计算在超时时间内。超时后 rx 被 drop，管理任务会跳过还没发送的请求
+        let deadline = Instant::now() + self.timeout;
+        let (resp, rx) = oneshot::channel();
+        let request = Request { frame, resp };
+
+        time::timeout_at(deadline, self.tx.send(request))
+            .await
+            .map_err(|_| Error::Timeout(self.timeout))?
+            .map_err(|_| Error::Closed)?;
+
+        Ok(PendingReply {
+            rx,
+            deadline,
+            timeout: self.timeout,
+        })
+    }
+
+    async fn call<const N: usize>(&self, args: [&[u8]; N]) -> crate::Result<Frame> {
+        self.command(args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect())
+            .await
+    }
+
+    /// `name` 后面跟多个参数，例如 `DEL key [key ...]`
+    async fn call_many(&self, name: &str, args: &[&[u8]]) -> crate::Result<Frame> {
+        let mut argv = vec![Bytes::copy_from_slice(name.as_bytes())];
+        argv.extend(args.iter().map(|arg| Bytes::copy_from_slice(arg)));
+        self.command(argv).await
+    }
+
+    pub async fn ping(&self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        let reply = match &msg {
+            Some(msg) => self.call([b"PING", msg]).await?,
+            None => self.call([b"PING"]).await?,
+        };
+        match reply {
+            Frame::Simple(value) => Ok(Bytes::from(value)),
+            Frame::Bulk(value) => Ok(value),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> crate::Result<Option<Bytes>> {
+        optional_bulk(self.call([b"GET", key.as_bytes()]).await?)
+    }
+
+    pub async fn set(&self, key: &str, value: Bytes) -> crate::Result<()> {
+        ok(self.call([b"SET", key.as_bytes(), &value]).await?)
+    }
+
+    /// `SET key value PX milliseconds`
+    pub async fn set_expires(
+        &self,
+        key: &str,
+        value: Bytes,
+        expiration: Duration,
+    ) -> crate::Result<()> {
+        let millis = expiration.as_millis().to_string();
+        ok(self
+            .call([b"SET", key.as_bytes(), &value, b"PX", millis.as_bytes()])
+            .await?)
+    }
+
+    /// `SET key value NX`，写入成功返回 true
+    pub async fn set_nx(&self, key: &str, value: Bytes) -> crate::Result<bool> {
+        match self.call([b"SET", key.as_bytes(), &value, b"NX"]).await? {
+            Frame::Null => Ok(false),
+            reply => ok(reply).map(|_| true),
+        }
+    }
+
+    /// `SETNX key value`，写入成功返回 true
+    pub async fn setnx(&self, key: &str, value: Bytes) -> crate::Result<bool> {
+        boolean(self.call([b"SETNX", key.as_bytes(), &value]).await?)
+    }
+
+    /// 带选项的 `SET`，因为 `NX` / `XX` 没有写入时返回 false
+    pub async fn set_with(
+        &self,
+        key: &str,
+        value: Bytes,
+        options: SetOptions,
+    ) -> crate::Result<bool> {
+        match self.set_command(key, value, options, false).await? {
+            Frame::Null => Ok(false),
+            reply => ok(reply).map(|_| true),
+        }
+    }
+
+    /// `SET ... GET`，返回 key 原来的值。不管是否因为 `NX` / `XX` 没有写入都返回原来的值
+    pub async fn set_get(
+        &self,
+        key: &str,
+        value: Bytes,
+        options: SetOptions,
+    ) -> crate::Result<Option<Bytes>> {
+        optional_bulk(self.set_command(key, value, options, true).await?)
+    }
+
+    async fn set_command(
+        &self,
+        key: &str,
+        value: Bytes,
+        options: SetOptions,
+        get: bool,
+    ) -> crate::Result<Frame> {
+        let mut args = vec![Bytes::copy_from_slice(key.as_bytes()), value];
+        match options.expiration {
+            Some(Expiration::After(ttl)) => {
+                args.push(Bytes::from_static(b"PX"));
+                args.push(Bytes::from(ttl.as_millis().to_string()));
+            }
+            Some(Expiration::At(at)) => {
+                let millis = at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                args.push(Bytes::from_static(b"PXAT"));
+                args.push(Bytes::from(millis.to_string()));
+            }
+            Some(Expiration::KeepTtl) => args.push(Bytes::from_static(b"KEEPTTL")),
+            None => {}
+        }
+        match options.condition {
+            Some(Condition::Nx) => args.push(Bytes::from_static(b"NX")),
+            Some(Condition::Xx) => args.push(Bytes::from_static(b"XX")),
+            None => {}
+        }
+        if get {
+            args.push(Bytes::from_static(b"GET"));
+        }
+        let args: Vec<&[u8]> = args.iter().map(|arg| &arg[..]).collect();
+        self.call_many("SET", &args).await
+    }
+
+    pub async fn get_set(&self, key: &str, value: Bytes) -> crate::Result<Option<Bytes>> {
+        optional_bulk(self.call([b"GETSET", key.as_bytes(), &value]).await?)
+    }
+
+    pub async fn mget(&self, keys: &[&str]) -> crate::Result<Vec<Option<Bytes>>> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        array(self.call_many("MGET", &keys).await?)?
+            .into_iter()
+            .map(optional_bulk)
+            .collect()
+    }
+
+    pub async fn mset(&self, pairs: &[(&str, Bytes)]) -> crate::Result<()> {
+        let args: Vec<&[u8]> = pairs
+            .iter()
+            .flat_map(|(key, value)| [key.as_bytes(), &value[..]])
+            .collect();
+        ok(self.call_many("MSET", &args).await?)
+    }
+
+    /// 只有所有的 key 都不存在时才写入，写入成功返回 true
+    pub async fn msetnx(&self, pairs: &[(&str, Bytes)]) -> crate::Result<bool> {
+        let args: Vec<&[u8]> = pairs
+            .iter()
+            .flat_map(|(key, value)| [key.as_bytes(), &value[..]])
+            .collect();
+        boolean(self.call_many("MSETNX", &args).await?)
+    }
+
+    pub async fn incr(&self, key: &str) -> crate::Result<i64> {
+        integer(self.call([b"INCR", key.as_bytes()]).await?)
+    }
+
+    pub async fn incr_by(&self, key: &str, delta: i64) -> crate::Result<i64> {
+        let delta = delta.to_string();
+        integer(
+            self.call([b"INCRBY", key.as_bytes(), delta.as_bytes()])
+                .await?,
+        )
+    }
+
+    pub async fn decr(&self, key: &str) -> crate::Result<i64> {
+        integer(self.call([b"DECR", key.as_bytes()]).await?)
+    }
+
+    pub async fn decr_by(&self, key: &str, delta: i64) -> crate::Result<i64> {
+        let delta = delta.to_string();
+        integer(
+            self.call([b"DECRBY", key.as_bytes(), delta.as_bytes()])
+                .await?,
+        )
+    }
+
+    pub async fn incr_by_float(&self, key: &str, delta: f64) -> crate::Result<f64> {
+        let delta = delta.to_string();
+        let reply = bulk(
+            self.call([b"INCRBYFLOAT", key.as_bytes(), delta.as_bytes()])
+                .await?,
+        )?;
+        std::str::from_utf8(&reply)?
+            .parse()
+            .map_err(|_| unexpected(Frame::Bulk(reply)))
+    }
+
+    pub async fn append(&self, key: &str, value: Bytes) -> crate::Result<usize> {
+        length(self.call([b"APPEND", key.as_bytes(), &value]).await?)
+    }
+
+    pub async fn strlen(&self, key: &str) -> crate::Result<usize> {
+        length(self.call([b"STRLEN", key.as_bytes()]).await?)
+    }
+
+    pub async fn get_range(&self, key: &str, start: i64, end: i64) -> crate::Result<Bytes> {
+        let (start, end) = (start.to_string(), end.to_string());
+        bulk(
+            self.call([
+                b"GETRANGE",
+                key.as_bytes(),
+                start.as_bytes(),
+                end.as_bytes(),
+            ])
+            .await?,
+        )
+    }
+
+    pub async fn set_range(&self, key: &str, offset: usize, value: Bytes) -> crate::Result<usize> {
+        let offset = offset.to_string();
+        length(
+            self.call([b"SETRANGE", key.as_bytes(), offset.as_bytes(), &value])
+                .await?,
+        )
+    }
+
+    /// 返回删除的 key 的个数
+    pub async fn del(&self, keys: &[&str]) -> crate::Result<usize> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        length(self.call_many("DEL", &keys).await?)
+    }
+
+    pub async fn exists(&self, keys: &[&str]) -> crate::Result<usize> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        length(self.call_many("EXISTS", &keys).await?)
+    }
+
+    /// `PEXPIRE key milliseconds`，key 不存在时返回 false
+    pub async fn expire(&self, key: &str, ttl: Duration) -> crate::Result<bool> {
+        let millis = ttl.as_millis().to_string();
+        boolean(
+            self.call([b"PEXPIRE", key.as_bytes(), millis.as_bytes()])
+                .await?,
+        )
+    }
+
+    /// 剩余的存活时间，key 不存在或者没有过期时间时返回 None
+    pub async fn ttl(&self, key: &str) -> crate::Result<Option<Duration>> {
+        let millis = integer(self.call([b"PTTL", key.as_bytes()]).await?)?;
+        Ok(u64::try_from(millis).ok().map(Duration::from_millis))
+    }
+
+    pub async fn persist(&self, key: &str) -> crate::Result<bool> {
+        boolean(self.call([b"PERSIST", key.as_bytes()]).await?)
+    }
+
+    /// key 的类型，不存在时返回 `"none"`
+    pub async fn key_type(&self, key: &str) -> crate::Result<String> {
+        match self.call([b"TYPE", key.as_bytes()]).await? {
+            Frame::Simple(name) => Ok(name),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub async fn sadd(&self, key: &str, members: &[Bytes]) -> crate::Result<usize> {
+        length(self.call_many("SADD", &with_key(key, members)).await?)
+    }
+
+    pub async fn srem(&self, key: &str, members: &[Bytes]) -> crate::Result<usize> {
+        length(self.call_many("SREM", &with_key(key, members)).await?)
+    }
+
+    pub async fn smembers(&self, key: &str) -> crate::Result<Vec<Bytes>> {
+        bulks(self.call([b"SMEMBERS", key.as_bytes()]).await?)
+    }
+
+    pub async fn scard(&self, key: &str) -> crate::Result<usize> {
+        length(self.call([b"SCARD", key.as_bytes()]).await?)
+    }
+
+    pub async fn sismember(&self, key: &str, member: Bytes) -> crate::Result<bool> {
+        boolean(self.call([b"SISMEMBER", key.as_bytes(), &member]).await?)
+    }
+
+    pub async fn smismember(&self, key: &str, members: &[Bytes]) -> crate::Result<Vec<bool>> {
+        array(
+            self.call_many("SMISMEMBER", &with_key(key, members))
+                .await?,
+        )?
+        .into_iter()
+        .map(boolean)
+        .collect()
+    }
+
+    pub async fn sinter(&self, keys: &[&str]) -> crate::Result<Vec<Bytes>> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        bulks(self.call_many("SINTER", &keys).await?)
+    }
+
+    pub async fn sunion(&self, keys: &[&str]) -> crate::Result<Vec<Bytes>> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        bulks(self.call_many("SUNION", &keys).await?)
+    }
+
+    pub async fn sdiff(&self, keys: &[&str]) -> crate::Result<Vec<Bytes>> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        bulks(self.call_many("SDIFF", &keys).await?)
+    }
+
+    /// `SINTERSTORE destination key [key ...]`，返回结果的元素个数
+    pub async fn sinterstore(&self, destination: &str, keys: &[&str]) -> crate::Result<usize> {
+        self.store("SINTERSTORE", destination, keys).await
+    }
+
+    pub async fn sunionstore(&self, destination: &str, keys: &[&str]) -> crate::Result<usize> {
+        self.store("SUNIONSTORE", destination, keys).await
+    }
+
+    pub async fn sdiffstore(&self, destination: &str, keys: &[&str]) -> crate::Result<usize> {
+        self.store("SDIFFSTORE", destination, keys).await
+    }
+
+    async fn store(&self, name: &str, destination: &str, keys: &[&str]) -> crate::Result<usize> {
+        let mut args = vec![destination.as_bytes()];
+        args.extend(keys.iter().map(|key| key.as_bytes()));
+        length(self.call_many(name, &args).await?)
+    }
+
+    /// `SSCAN key cursor [MATCH pattern] [COUNT count]`，返回下一次的游标和这一批元素，游标为 0 表示结束
+    pub async fn sscan(
+        &self,
+        key: &str,
+        cursor: u64,
+        pattern: Option<&str>,
+        count: Option<usize>,
+    ) -> crate::Result<(u64, Vec<Bytes>)> {
+        let cursor = cursor.to_string();
+        let count = count.map(|count| count.to_string());
+        let mut args = vec![key.as_bytes(), cursor.as_bytes()];
+        if let Some(pattern) = pattern {
+            args.extend([&b"MATCH"[..], pattern.as_bytes()]);
+        }
+        if let Some(count) = &count {
+            args.extend([&b"COUNT"[..], count.as_bytes()]);
+        }
+
+        let mut parts = array(self.call_many("SSCAN", &args).await?)?;
+        if parts.len() != 2 {
+            return Err(unexpected(Frame::Array(parts)));
+        }
+        let members = bulks(parts.pop().expect("two parts"))?;
+        let next = bulk(parts.pop().expect("two parts"))?;
+        let next = std::str::from_utf8(&next)?
+            .parse()
+            .map_err(|_| unexpected(Frame::Bulk(next.clone())))?;
+        Ok((next, members))
+    }
+
+    pub async fn spop(&self, key: &str) -> crate::Result<Option<Bytes>> {
+        optional_bulk(self.call([b"SPOP", key.as_bytes()]).await?)
+    }
+
+    pub async fn srandmember(&self, key: &str, count: i64) -> crate::Result<Vec<Bytes>> {
+        let count = count.to_string();
+        bulks(
+            self.call([b"SRANDMEMBER", key.as_bytes(), count.as_bytes()])
+                .await?,
+        )
+    }
+
+    /// `EVAL script numkeys key [key ...] arg [arg ...]`，返回原始的回复
+    pub async fn eval(&self, script: &str, keys: &[&str], args: &[Bytes]) -> crate::Result<Frame> {
+        let numkeys = keys.len().to_string();
+        let mut argv: Vec<&[u8]> = vec![script.as_bytes(), numkeys.as_bytes()];
+        argv.extend(keys.iter().map(|key| key.as_bytes()));
+        argv.extend(args.iter().map(|arg| &arg[..]));
+        self.call_many("EVAL", &argv).await
+    }
+
+    /// `EVALSHA sha1 numkeys key [key ...] arg [arg ...]`，脚本不在缓存中时返回 `NOSCRIPT` 错误
+    pub async fn evalsha(&self, sha1: &str, keys: &[&str], args: &[Bytes]) -> crate::Result<Frame> {
+        let numkeys = keys.len().to_string();
+        let mut argv: Vec<&[u8]> = vec![sha1.as_bytes(), numkeys.as_bytes()];
+        argv.extend(keys.iter().map(|key| key.as_bytes()));
+        argv.extend(args.iter().map(|arg| &arg[..]));
+        self.call_many("EVALSHA", &argv).await
+    }
+
+    /// 把脚本加入缓存，返回脚本的 sha1
+    pub async fn script_load(&self, script: &str) -> crate::Result<String> {
+        let sha1 = bulk(self.call([b"SCRIPT", b"LOAD", script.as_bytes()]).await?)?;
+        Ok(String::from_utf8(sha1.to_vec())?)
+    }
+
+    pub async fn script_exists(&self, sha1s: &[&str]) -> crate::Result<Vec<bool>> {
+        let mut args = vec![&b"EXISTS"[..]];
+        args.extend(sha1s.iter().map(|sha1| sha1.as_bytes()));
+        array(self.call_many("SCRIPT", &args).await?)?
+            .into_iter()
+            .map(boolean)
+            .collect()
+    }
+
+    pub async fn script_flush(&self) -> crate::Result<()> {
+        ok(self.call([b"SCRIPT", b"FLUSH"]).await?)
+    }
+
+    pub async fn dbsize(&self) -> crate::Result<usize> {
+        length(self.call([b"DBSIZE"]).await?)
+    }
+
+    pub async fn flushdb(&self) -> crate::Result<()> {
+        ok(self.call([b"FLUSHDB"]).await?)
+    }
+
+    pub async fn flushall(&self) -> crate::Result<()> {
+        ok(self.call([b"FLUSHALL"]).await?)
+    }
+
+    /// 交换两个数据库。所有连接都会看到交换之后的数据，包括共享这个连接的其它 `Client`
+    pub async fn swapdb(&self, index1: usize, index2: usize) -> crate::Result<()> {
+        let (index1, index2) = (index1.to_string(), index2.to_string());
+        ok(self
+            .call([b"SWAPDB", index1.as_bytes(), index2.as_bytes()])
+            .await?)
+    }
+
+    /// `MOVE key db`，key 不存在或者目标数据库中已经存在同名的 key 时返回 false
+    pub async fn move_key(&self, key: &str, db: usize) -> crate::Result<bool> {
+        let db = db.to_string();
+        boolean(self.call([b"MOVE", key.as_bytes(), db.as_bytes()]).await?)
+    }
+
+    /// 发布一条消息，返回收到消息的订阅者个数
+    pub async fn publish(&self, channel: &str, message: Bytes) -> crate::Result<usize> {
+        length(
+            self.call([b"PUBLISH", channel.as_bytes(), &message])
+                .await?,
+        )
+    }
+
+    /// 订阅频道。订阅模式下的连接不能再执行其它命令，所以会单独建立一个连接，不影响当前的 `Client`
+    pub async fn subscribe(&self, channels: &[String]) -> crate::Result<Subscriber> {
+        let mut subscriber = Subscriber {
+            connection: Connection::new(self.endpoint.open().await?),
+            subscribed: vec![],
+            pending: VecDeque::new(),
+        };
+        subscriber.subscribe(channels).await?;
+        Ok(subscriber)
+    }
+}
+
+/// 进入订阅模式的连接，通过 `Commands::subscribe` 建立
+#[derive(Debug)]
+pub struct Subscriber {
+    connection: Connection<Box<dyn Stream>>,
+    subscribed: Vec<String>,
+    /// 等待订阅确认的过程中先收到的消息
+    pending: VecDeque<Message>,
+}
+
+/// 订阅的频道收到的一条消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    pub content: Bytes,
+}
+
+/// 订阅模式下服务端推送的帧
+enum Push {
+    Message(Message),
+    /// SUBSCRIBE / UNSUBSCRIBE 的确认
+    Confirm,
+}
+
+impl Subscriber {
+    /// 当前订阅的频道
+    pub fn get_subscribed(&self) -> &[String] {
+        &self.subscribed
+    }
+
+    /// 等待下一条消息，连接被关闭时返回 None
+    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(Some(message));
+        }
+
+        loop {
+            match self.read_push().await? {
+                Some(Push::Message(message)) => return Ok(Some(message)),
+                // 不应该出现，忽略即可
+                Some(Push::Confirm) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// 订阅更多的频道
+    pub async fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.send("SUBSCRIBE", channels).await?;
+        self.wait_confirms(channels.len()).await?;
+
+        for channel in channels {
+            if !self.subscribed.contains(channel) {
+                self.subscribed.push(channel.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// 取消订阅，`channels` 为空时取消所有订阅
+    pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.send("UNSUBSCRIBE", channels).await?;
+
+        // 不带参数时服务端对每个订阅的频道回复一次，没有订阅时也会回复一次
+        let confirms = match channels.len() {
+            0 => self.subscribed.len().max(1),
+            n => n,
+        };
+        self.wait_confirms(confirms).await?;
+
+        if channels.is_empty() {
+            self.subscribed.clear();
+        } else {
+            self.subscribed
+                .retain(|channel| !channels.contains(channel));
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, name: &str, channels: &[String]) -> crate::Result<()> {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::copy_from_slice(name.as_bytes()));
+        for channel in channels {
+            frame.push_bulk(Bytes::copy_from_slice(channel.as_bytes()));
+        }
+        self.connection.write_frame(&frame).await?;
+        Ok(())
+    }
+
+    /// 等待 `count` 个确认，期间收到的消息先保存起来
+    async fn wait_confirms(&mut self, mut count: usize) -> crate::Result<()> {
+        while count > 0 {
+            match self.read_push().await? {
+                Some(Push::Confirm) => count -= 1,
+                Some(Push::Message(message)) => self.pending.push_back(message),
+                None => {
+                    return Err(
+                        Error::Disconnected("connection closed by server".to_string()).into(),
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_push(&mut self) -> crate::Result<Option<Push>> {
+        let frame = match self.connection.read_frame().await? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        match frame {
+            Frame::Array(ref parts) => match &parts[..] {
+                [Frame::Bulk(kind), Frame::Bulk(channel), Frame::Bulk(content)]
+                    if &kind[..] == b"message" =>
+                {
+                    Ok(Some(Push::Message(Message {
+                        channel: String::from_utf8_lossy(channel).into_owned(),
+                        content: content.clone(),
+                    })))
+                }
+                [Frame::Bulk(kind), _, Frame::Integer(_)]
+                    if &kind[..] == b"subscribe" || &kind[..] == b"unsubscribe" =>
+                {
+                    Ok(Some(Push::Confirm))
+                }
+                _ => Err(unexpected(frame)),
+            },
+            Frame::Error(msg) => Err(Error::Server(msg).into()),
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+/// 后台的管理任务，独占连接
+struct Manager {
+    endpoint: Arc<Endpoint>,
+    rx: mpsc::Receiver<Request>,
+    /// 连接当前选择的数据库，重连之后通过 SELECT 恢复
+    db: Arc<AtomicUsize>,
+}
+
+/// 已经写入连接、等待回复的请求
+struct Pending {
+    /// 重连之后补发的 SELECT 没有调用方
+    resp: Option<Responder<Frame>>,
+    /// 如果是 SELECT 命令，这是要选择的数据库，回复 OK 之后更新 `Manager::db`
+    select: Option<usize>,
+}
+
+impl Manager {
+    async fn run(mut self, mut stream: Box<dyn Stream>) {
+        loop {
+            // `serve` 返回说明连接断开了，所有的句柄都被 drop 时 `reconnect` 返回 None
+            if !self.serve(stream).await {
+                return;
+            }
+            stream = match self.reconnect().await {
+                Some(stream) => stream,
+                None => return,
+            };
+        }
+    }
+
+    /// 执行请求直到连接断开，所有的句柄都被 drop 时返回 false。
+    ///
+    /// 请求到达后立即写入连接，不等待前一个请求的回复；Redis 按照收到命令的顺序回复，
+    /// 所以只要把等待回复的 `Responder` 按发送顺序放进队列，收到回复时从队首取出即可。
+    /// 这样多个任务共享一个连接也能充分利用 pipeline。
+    ///
+    /// 读和写必须同时进行：如果写一批请求的时候不读回复，服务端的发送缓冲区满了之后就不再读取请求，
+    /// 两边都会卡在写上。所以把连接拆成读写两半，分别由 `write_requests` 和 `read_replies` 驱动
+    async fn serve(&mut self, stream: Box<dyn Stream>) -> bool {
+        let (read, write) = tokio::io::split(stream);
+        // `Connection` 要求流同时可读可写，用 sink / empty 补上另一半
+        let mut reader = Connection::new(tokio::io::join(read, tokio::io::sink()));
+        let mut writer = Connection::new(tokio::io::join(tokio::io::empty(), write));
+        // 两个 future 在同一个任务中轮流执行，锁不会被争用，也不会跨越 await 持有
+        let pending = Mutex::new(VecDeque::new());
+        // `write_requests` 借用了 `self`，`read_replies` 通过另一个引用更新
+        let db = self.db.clone();
+
+        let reason = tokio::select! {
+            reason = self.write_requests(&mut writer, &pending, db.load(Ordering::Relaxed)) => reason,
+            // 空闲时也在读连接，这样服务端关闭连接(例如 CLIENT KILL)之后可以马上开始重连，
+            // 而不是等到下一个请求失败
+            reason = read_replies(&mut reader, &pending, &db) => Some(reason),
+        };
+
+        let reason = match reason {
+            Some(reason) => reason,
+            // 所有的句柄都被 drop 了，但 `send` 返回的 `PendingReply` 可能还在等待回复，
+            // 读完这些回复之后连接才随着管理任务一起关闭
+            None => {
+                if let Some(reason) = drain_replies(&mut reader, &pending, &db).await {
+                    fail_pending(pending, &reason);
+                }
+                return false;
+            }
+        };
+
+        fail_pending(pending, &reason);
+        true
+    }
+
+    /// 不断把请求写入连接，返回连接断开的原因，通道关闭时返回 None
+    ///
+    /// 重连之后 `db` 不是 0 时先补发 SELECT，它在所有新请求之前写入，服务端也会先执行它
+    async fn write_requests<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut Connection<S>,
+        pending: &Mutex<VecDeque<Pending>>,
+        db: usize,
+    ) -> Option<String> {
+        if db != 0 {
+            pending.lock().unwrap().push_back(Pending {
+                resp: None,
+                select: Some(db),
+            });
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"SELECT")),
+                Frame::Bulk(Bytes::from(db.to_string())),
+            ]);
+            if let Err(err) = writer.write_frame(&frame).await {
+                return Some(err.to_string());
+            }
+        }
+
+        loop {
+            let request = self.rx.recv().await?;
+            if let Err(err) = self.write_batch(writer, request, pending).await {
+                return Some(err.to_string());
+            }
+        }
+    }
+
+    /// 把 `request` 以及通道中已经在排队的请求一起写入连接，最后只 flush 一次
+    async fn write_batch<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut Connection<S>,
+        request: Request,
+        pending: &Mutex<VecDeque<Pending>>,
+    ) -> std::io::Result<()> {
+        let mut next = Some(request);
+        let mut written = 0;
+
+        while let Some(request) = next.take() {
+            // 调用方已经超时放弃了，不再发送这个请求
+            if !request.resp.is_closed() {
+                // 先放进队列再写：写到一半连接断开时，这个请求也会收到 `Error::Disconnected`
+                pending.lock().unwrap().push_back(Pending {
+                    select: select_index(&request.frame),
+                    resp: Some(request.resp),
+                });
+                writer.buffer_frame(&request.frame).await?;
+            }
+
+            // 一次最多写 MAX_BATCH 个就 flush，避免请求不断到达时前面的请求一直留在写缓冲中
+            written += 1;
+            if written < MAX_BATCH {
+                next = self.rx.try_recv().ok();
+            }
+        }
+
+        writer.flush().await
+    }
+
+    /// 按照指数退避不断重连，等待期间收到的请求直接返回错误
+    async fn reconnect(&mut self) -> Option<Box<dyn Stream>> {
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            let reason = match self.endpoint.open().await {
+                Ok(stream) => return Some(stream),
+                Err(err) => err.to_string(),
+            };
+
+            let sleep = time::sleep(backoff);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    request = self.rx.recv() => {
+                        let request = request?;
+                        let err = Error::Disconnected(format!(
+                            "reconnecting to {} ({}), retry in {:?}",
+                            self.endpoint.addr, reason, backoff
+                        ));
+                        let _ = request.resp.send(Err(err.into()));
+                    }
+                }
+            }
+
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+impl Endpoint {
+    /// 建立连接，TLS 握手也算在连接超时之内
+    async fn open(&self) -> crate::Result<Box<dyn Stream>> {
+        time::timeout(CONNECT_TIMEOUT, async {
+            if let Transport::Unix = self.transport {
+                let socket = UnixStream::connect(&self.addr).await?;
+                return Ok(Box::new(socket) as Box<dyn Stream>);
+            }
+
+            let socket = TcpStream::connect(&self.addr).await?;
+            socket.set_nodelay(true)?;
+            let stream: Box<dyn Stream> = match &self.transport {
+                Transport::Tls(tls) => Box::new(tls.connect(socket).await?),
+                _ => Box::new(socket),
+            };
+            Ok(stream)
+        })
+        .await
+        .map_err(|_| format!("connect to {} timed out", self.addr))?
+    }
+}
+
+/// 按顺序把回复交给等待的调用方，返回连接断开的原因
+async fn read_replies<S: AsyncRead + AsyncWrite + Unpin>(
+    reader: &mut Connection<S>,
+    pending: &Mutex<VecDeque<Pending>>,
+    db: &AtomicUsize,
+) -> String {
+    loop {
+        if let Err(reason) = read_reply(reader, pending, db).await {
+            return reason;
+        }
+    }
+}
+
+/// 所有的句柄都被 drop 之后继续读取回复，直到没有调用方在等待。连接断开时返回原因
+async fn drain_replies<S: AsyncRead + AsyncWrite + Unpin>(
+    reader: &mut Connection<S>,
+    pending: &Mutex<VecDeque<Pending>>,
+    db: &AtomicUsize,
+) -> Option<String> {
+    loop {
+        // 调用方已经超时放弃的请求不用再等
+        let waiting = pending
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|p| p.resp.as_ref().is_some_and(|resp| !resp.is_closed()));
+        if !waiting {
+            return None;
+        }
+        if let Err(reason) = read_reply(reader, pending, db).await {
+            return Some(reason);
+        }
+    }
+}
+
+/// 读取一个回复，交给队首的调用方
+async fn read_reply<S: AsyncRead + AsyncWrite + Unpin>(
+    reader: &mut Connection<S>,
+    pending: &Mutex<VecDeque<Pending>>,
+    db: &AtomicUsize,
+) -> Result<(), String> {
+    let frame = match reader.read_frame().await {
+        Ok(Some(frame)) => frame,
+        Ok(None) => return Err("connection closed by server".to_string()),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let request = match pending.lock().unwrap().pop_front() {
+        Some(request) => request,
+        None => return Err(format!("unexpected frame from server: {}", frame)),
+    };
+
+    if let Some(index) = request.select {
+        match &frame {
+            Frame::Simple(s) if s == "OK" => db.store(index, Ordering::Relaxed),
+            // 补发的 SELECT 失败了，继续使用这个连接会把命令发到错误的数据库
+            _ if request.resp.is_none() => {
+                return Err(format!("failed to restore SELECT {}: {}", index, frame))
+            }
+            _ => {}
+        }
+    }
+
+    // 调用方可能已经超时放弃了，回复直接丢弃
+    if let Some(resp) = request.resp {
+        let _ = resp.send(Ok(frame));
+    }
+    Ok(())
+}
+
+/// 已经发出去的请求可能已经被服务端执行了，只能返回错误，由调用方决定是否重试
+fn fail_pending(pending: Mutex<VecDeque<Pending>>, reason: &str) {
+    for request in pending.into_inner().unwrap() {
+        if let Some(resp) = request.resp {
+            let _ = resp.send(Err(Error::Disconnected(reason.to_string()).into()));
+        }
+    }
+}
+
+/// 如果 `frame` 是 `SELECT index` 命令，返回要选择的数据库
+fn select_index(frame: &Frame) -> Option<usize> {
+    match frame {
+        Frame::Array(parts) => match &parts[..] {
+            [Frame::Bulk(cmd), Frame::Bulk(index)] if cmd.eq_ignore_ascii_case(b"select") => {
+                std::str::from_utf8(index).ok()?.parse().ok()
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn with_key<'a>(key: &'a str, members: &'a [Bytes]) -> Vec<&'a [u8]> {
+    let mut args = vec![key.as_bytes()];
+    args.extend(members.iter().map(|member| &member[..]));
+    args
+}
+
+fn unexpected(frame: Frame) -> crate::Error {
+    Error::UnexpectedReply(frame).into()
+}
+
+fn ok(frame: Frame) -> crate::Result<()> {
+    match frame {
+        Frame::Simple(ref s) if s == "OK" => Ok(()),
+        frame => Err(unexpected(frame)),
+    }
+}
+
+fn integer(frame: Frame) -> crate::Result<i64> {
+    match frame {
+        Frame::Integer(value) => Ok(value),
+        frame => Err(unexpected(frame)),
+    }
+}
+
+fn length(frame: Frame) -> crate::Result<usize> {
+    let value = integer(frame)?;
+    usize::try_from(value).map_err(|_| format!("protocol error; invalid length {}", value).into())
+}
+
+fn boolean(frame: Frame) -> crate::Result<bool> {
+    integer(frame).map(|value| value != 0)
+}
+
+fn bulk(frame: Frame) -> crate::Result<Bytes> {
+    match frame {
+        Frame::Bulk(value) => Ok(value),
+        frame => Err(unexpected(frame)),
+    }
+}
+
+fn optional_bulk(frame: Frame) -> crate::Result<Option<Bytes>> {
+    match frame {
+        Frame::Null => Ok(None),
+        frame => bulk(frame).map(Some),
+    }
+}
+
+fn array(frame: Frame) -> crate::Result<Vec<Frame>> {
+    match frame {
+        Frame::Array(frames) => Ok(frames),
+        frame => Err(unexpected(frame)),
+    }
+}
+
+fn bulks(frame: Frame) -> crate::Result<Vec<Bytes>> {
+    array(frame)?.into_iter().map(bulk).collect()
+}
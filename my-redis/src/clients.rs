@@ -0,0 +1,195 @@
+//! 已连接客户端的注册表，`CLIENT LIST` / `CLIENT KILL` 等命令基于它实现。
+//!
+//! 每个连接在建立时注册并得到一个 `Client`，连接处理结束(`Client` 被 drop)时自动注销。
+//! `CLIENT KILL` 通过 `Notify` 通知目标连接的处理任务退出，从而关闭连接。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// 注册表本身，保存在 `Db` 中
+#[derive(Debug, Default)]
+pub(crate) struct Clients {
+    next_id: AtomicU64,
+    entries: Mutex<HashMap<u64, Arc<Entry>>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    info: Mutex<ClientInfo>,
+    /// CLIENT KILL 时通知连接处理任务退出
+    kill: Notify,
+}
+
+/// 某个客户端在某一时刻的状态
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    pub name: Option<String>,
+    /// 连接建立的时间
+    pub created: Instant,
+    /// 最近一次执行命令的时间
+    pub last_interaction: Instant,
+    /// 当前选择的数据库
+    pub db: usize,
+    /// 最近一次执行的命令
+    pub last_cmd: String,
+}
+
+/// `CLIENT KILL` 的过滤条件，多个条件之间是"且"的关系
+#[derive(Debug, Default)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    /// 不为 None 时，跳过该 id 对应的客户端(SKIPME yes)
+    pub skip: Option<u64>,
+}
+
+/// 每个连接持有一个，drop 时从注册表中注销
+#[derive(Debug)]
+pub struct Client {
+    id: u64,
+    entry: Arc<Entry>,
+    clients: Arc<Clients>,
+    /// 是否已经通过 AUTH，只有连接自己会读写，不需要放在 `Entry` 中
+    authenticated: AtomicBool,
+}
+
+impl Clients {
+    pub(crate) fn register(self: &Arc<Self>, addr: String) -> Client {
+        // 和 Redis 一样，id 从 1 开始递增，不会复用
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let entry = Arc::new(Entry {
+            info: Mutex::new(ClientInfo {
+                id,
+                addr,
+                name: None,
+                created: now,
+                last_interaction: now,
+                db: 0,
+                last_cmd: "NULL".to_string(),
+            }),
+            kill: Notify::new(),
+        });
+
+        self.entries.lock().unwrap().insert(id, entry.clone());
+
+        Client {
+            id,
+            entry,
+            clients: self.clone(),
+            authenticated: AtomicBool::new(false),
+        }
+    }
+
+    /// 所有客户端的状态，按 id 排序
+    pub(crate) fn list(&self) -> Vec<ClientInfo> {
+        let mut list: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.info.lock().unwrap().clone())
+            .collect();
+        list.sort_by_key(|info| info.id);
+        list
+    }
+
+    /// 关闭所有符合条件的客户端，返回关闭的个数
+    pub(crate) fn kill(&self, filter: &KillFilter) -> usize {
+        let entries = self.entries.lock().unwrap();
+        let mut killed = 0;
+
+        for (id, entry) in entries.iter() {
+            let info = entry.info.lock().unwrap();
+            if filter.id.is_some_and(|want| want != *id)
+                || filter.addr.as_ref().is_some_and(|want| *want != info.addr)
+                || filter.skip == Some(*id)
+            {
+                continue;
+            }
+
+            entry.kill.notify_one();
+            killed += 1;
+        }
+
+        killed
+    }
+}
+
+impl Client {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn info(&self) -> ClientInfo {
+        self.entry.info.lock().unwrap().clone()
+    }
+
+    pub fn addr(&self) -> String {
+        self.entry.info.lock().unwrap().addr.clone()
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.entry.info.lock().unwrap().name.clone()
+    }
+
+    pub fn set_name(&self, name: Option<String>) {
+        self.entry.info.lock().unwrap().name = name;
+    }
+
+    pub fn db(&self) -> usize {
+        self.entry.info.lock().unwrap().db
+    }
+
+    pub fn set_db(&self, db: usize) {
+        self.entry.info.lock().unwrap().db = db;
+    }
+
+    pub fn authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
+
+    pub fn set_authenticated(&self) {
+        self.authenticated.store(true, Ordering::Relaxed);
+    }
+
+    /// 每执行一个命令都要调用，用于更新 idle 和 cmd
+    pub fn touch(&self, cmd: &str) {
+        let mut info = self.entry.info.lock().unwrap();
+        info.last_interaction = Instant::now();
+        info.last_cmd = cmd.to_string();
+    }
+
+    /// 等待连接被 CLIENT KILL
+    pub async fn killed(&self) {
+        self.entry.kill.notified().await
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.clients.entries.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl ClientInfo {
+    /// `CLIENT LIST` 中的一行，例如 `id=3 addr=127.0.0.1:6379 name= age=10 idle=0 db=0 cmd=get`
+    pub fn to_line(&self) -> String {
+        let now = Instant::now();
+        format!(
+            "id={} addr={} name={} age={} idle={} db={} cmd={}",
+            self.id,
+            self.addr,
+            self.name.as_deref().unwrap_or(""),
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(self.last_interaction).as_secs(),
+            self.db,
+            self.last_cmd,
+        )
+    }
+}
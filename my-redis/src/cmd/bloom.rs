@@ -0,0 +1,131 @@
+use crate::bloom::{self, BloomFilter, Full};
+use crate::db::Keyspace;
+use crate::parse::ParseError;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+
+/// BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+#[derive(Debug)]
+pub struct BfReserve {
+    key: String,
+    error_rate: f64,
+    capacity: u64,
+    /// 为 0 时不扩展
+    expansion: u32,
+}
+
+impl BfReserve {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BfReserve, ParseError> {
+        let key = parse.next_string()?;
+
+        let error_rate = match parse.next_f64() {
+            Err(ParseError::Other(_)) => return Err("bad error rate".into()),
+            res => res?,
+        };
+        if error_rate <= 0.0 || error_rate >= 1.0 {
+            return Err("(0 < error rate range < 1)".into());
+        }
+
+        let capacity = match parse.next_int() {
+            Err(ParseError::Other(_)) => return Err("bad capacity".into()),
+            res => res?,
+        };
+        if capacity <= 0 {
+            return Err("(capacity should be larger than 0)".into());
+        }
+
+        let mut expansion = bloom::DEFAULT_EXPANSION;
+        let mut nonscaling = false;
+        while parse.remaining() > 0 {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "EXPANSION" => {
+                    expansion = match parse.next_int()? {
+                        n @ 1..=32768 => n as u32,
+                        _ => return Err("expansion should be greater or equal to 1".into()),
+                    };
+                }
+                "NONSCALING" => nonscaling = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        Ok(BfReserve {
+            key,
+            error_rate,
+            capacity: capacity as u64,
+            expansion: if nonscaling { 0 } else { expansion },
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        if keyspace.contains_key(&self.key) {
+            return Frame::Error("ERR item exists".to_string());
+        }
+
+        let filter = BloomFilter::new(self.error_rate, self.capacity, self.expansion);
+        match keyspace.get_or_insert_bloom(&self.key, || filter) {
+            Ok(_) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// BF.ADD key item
+#[derive(Debug)]
+pub struct BfAdd {
+    key: String,
+    item: Bytes,
+}
+
+impl BfAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BfAdd, ParseError> {
+        Ok(BfAdd {
+            key: parse.next_string()?,
+            item: parse.next_bytes()?,
+        })
+    }
+
+    /// 新加入时回复 1，(可能)已经存在时回复 0。key 不存在时使用默认的误判率和容量创建过滤器
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let filter = keyspace.get_or_insert_bloom(&self.key, || {
+            BloomFilter::new(
+                bloom::DEFAULT_ERROR_RATE,
+                bloom::DEFAULT_CAPACITY,
+                bloom::DEFAULT_EXPANSION,
+            )
+        });
+
+        match filter.map(|filter| filter.add(&self.item)) {
+            Ok(Ok(added)) => Frame::Integer(added as i64),
+            Ok(Err(Full)) => Frame::Error("ERR non scaling filter is full".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// BF.EXISTS key item
+#[derive(Debug)]
+pub struct BfExists {
+    key: String,
+    item: Bytes,
+}
+
+impl BfExists {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BfExists, ParseError> {
+        Ok(BfExists {
+            key: parse.next_string()?,
+            item: parse.next_bytes()?,
+        })
+    }
+
+    /// key 不存在时回复 0
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        match keyspace.get_bloom(&self.key) {
+            Ok(filter) => {
+                Frame::Integer(filter.is_some_and(|filter| filter.contains(&self.item)) as i64)
+            }
+            Err(err) => err.into(),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::clients::{self, KillFilter};
+use crate::parse::ParseError;
+use crate::{Db, Frame, Parse};
+
+use bytes::Bytes;
+
+/// PING [message]
+#[derive(Debug, Default)]
+pub struct Ping {
+    msg: Option<Bytes>,
+}
+
+impl Ping {
+    pub fn new(msg: Option<Bytes>) -> Ping {
+        Ping { msg }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Ping, ParseError> {
+        match parse.next_bytes() {
+            Ok(msg) => Ok(Ping::new(Some(msg))),
+            Err(ParseError::EndOfStream) => Ok(Ping::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn execute(self) -> Frame {
+        match self.msg {
+            None => Frame::Simple("PONG".to_string()),
+            Some(msg) => Frame::Bulk(msg),
+        }
+    }
+}
+
+/// CLIENT ID | LIST | GETNAME | SETNAME name | KILL ...
+#[derive(Debug)]
+pub enum Client {
+    Id,
+    List,
+    GetName,
+    SetName(String),
+    /// 旧的写法 `CLIENT KILL addr:port`，回复 OK 或错误
+    KillAddr(String),
+    /// 新的写法 `CLIENT KILL [ID id] [ADDR addr] [SKIPME yes/no]`，回复关闭的个数
+    Kill {
+        id: Option<u64>,
+        addr: Option<String>,
+        skipme: bool,
+    },
+}
+
+impl Client {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Client, ParseError> {
+        let subcommand = parse.next_string()?.to_lowercase();
+        match &subcommand[..] {
+            "id" => Ok(Client::Id),
+            "list" => Ok(Client::List),
+            "getname" => Ok(Client::GetName),
+            "setname" => {
+                let name = parse.next_string()?;
+                if name.chars().any(|c| c == ' ' || c == '\n') {
+                    return Err(
+                        "Client names cannot contain spaces, newlines or special characters."
+                            .into(),
+                    );
+                }
+                Ok(Client::SetName(name))
+            }
+            "kill" => {
+                if parse.remaining() == 1 {
+                    return Ok(Client::KillAddr(parse.next_string()?));
+                }
+
+                let (mut id, mut addr, mut skipme) = (None, None, true);
+                while parse.remaining() > 0 {
+                    let filter = parse.next_string()?.to_lowercase();
+                    match &filter[..] {
+                        "id" => {
+                            // 和 Redis 一样 id 从 1 开始，0 和负数都是错误，不能转换成一个很大的 id
+                            match parse.next_int()? {
+                                n if n > 0 => id = Some(n as u64),
+                                _ => return Err("client-id should be greater than 0".into()),
+                            }
+                        }
+                        "addr" => addr = Some(parse.next_string()?),
+                        "skipme" => {
+                            skipme = match &parse.next_string()?.to_lowercase()[..] {
+                                "yes" => true,
+                                "no" => false,
+                                _ => return Err("syntax error".into()),
+                            }
+                        }
+                        _ => return Err("syntax error".into()),
+                    }
+                }
+                // 没有任何过滤条件时参数个数不对
+                if id.is_none() && addr.is_none() {
+                    return Err(ParseError::EndOfStream);
+                }
+                Ok(Client::Kill { id, addr, skipme })
+            }
+            _ => Err(format!("unknown subcommand '{}'", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db, client: &clients::Client) -> Frame {
+        match self {
+            Client::Id => Frame::Integer(client.id() as i64),
+            Client::List => {
+                let mut list = String::new();
+                for info in db.clients() {
+                    list.push_str(&info.to_line());
+                    list.push('\n');
+                }
+                Frame::Bulk(Bytes::from(list))
+            }
+            Client::GetName => match client.name() {
+                Some(name) => Frame::Bulk(Bytes::from(name)),
+                None => Frame::Null,
+            },
+            Client::SetName(name) => {
+                // 设置为空字符串表示清除名称
+                client.set_name(Some(name).filter(|name| !name.is_empty()));
+                Frame::Simple("OK".to_string())
+            }
+            Client::KillAddr(addr) => {
+                let filter = KillFilter {
+                    addr: Some(addr),
+                    ..KillFilter::default()
+                };
+                match db.kill_clients(&filter) {
+                    0 => Frame::Error("ERR No such client".to_string()),
+                    _ => Frame::Simple("OK".to_string()),
+                }
+            }
+            Client::Kill { id, addr, skipme } => {
+                let filter = KillFilter {
+                    id,
+                    addr,
+                    skip: skipme.then(|| client.id()),
+                };
+                Frame::Integer(db.kill_clients(&filter) as i64)
+            }
+        }
+    }
+}
+
+/// AUTH [username] password，只有 `default` 一个用户，密码是配置项 `requirepass`
+#[derive(Debug)]
+pub struct Auth {
+    username: Option<String>,
+    password: Bytes,
+}
+
+impl Auth {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Auth, ParseError> {
+        let first = parse.next_bytes()?;
+        match parse.next_bytes() {
+            Ok(password) => Ok(Auth {
+                username: Some(String::from_utf8_lossy(&first).into_owned()),
+                password,
+            }),
+            Err(ParseError::EndOfStream) => Ok(Auth {
+                username: None,
+                password: first,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db, client: &clients::Client) -> Frame {
+        if !db.auth_required() {
+            return Frame::Error(
+                "ERR AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?"
+                    .to_string(),
+            );
+        }
+
+        let user = self.username.as_deref().unwrap_or("default");
+        if user == "default" && db.check_password(&self.password) {
+            client.set_authenticated();
+            Frame::Simple("OK".to_string())
+        } else {
+            Frame::Error(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+            )
+        }
+    }
+}
+
+/// SELECT index
+#[derive(Debug)]
+pub struct Select {
+    index: i64,
+}
+
+impl Select {
+    pub fn new(index: i64) -> Select {
+        Select { index }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Select, ParseError> {
+        Ok(Select {
+            index: parse.next_int()?,
+        })
+    }
+
+    /// 检查编号是否合法，返回可以直接使用的下标
+    pub(crate) fn index(&self, databases: usize) -> Result<usize, Frame> {
+        if self.index < 0 || self.index as usize >= databases {
+            return Err(Frame::Error("ERR DB index is out of range".to_string()));
+        }
+        Ok(self.index as usize)
+    }
+
+    pub(crate) fn apply(self, db: &Db, client: &clients::Client) -> Frame {
+        match self.index(db.databases()) {
+            Ok(index) => {
+                client.set_db(index);
+                Frame::Simple("OK".to_string())
+            }
+            Err(err) => err,
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use crate::db::{Databases, Keyspace, Value};
+use crate::parse::ParseError;
+use crate::{Frame, Parse};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+/// MOVE key db
+#[derive(Debug)]
+pub struct Move {
+    key: String,
+    db: i64,
+}
+
+impl Move {
+    pub fn new(key: impl ToString, db: i64) -> Move {
+        Move {
+            key: key.to_string(),
+            db,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Move, ParseError> {
+        let key = parse.next_string()?;
+        let db = parse.next_int()?;
+        Ok(Move { key, db })
+    }
+
+    /// 只有当 key 在当前数据库存在、且目标数据库中不存在时才移动，返回 1，否则返回 0
+    pub(crate) fn execute(self, dbs: &mut Databases, index: usize) -> Frame {
+        if self.db < 0 || self.db as usize >= dbs.len() {
+            return Frame::Error("ERR DB index is out of range".to_string());
+        }
+
+        let target = self.db as usize;
+        if target == index {
+            return Frame::Error("ERR source and destination objects are the same".to_string());
+        }
+
+        if dbs[target].contains_key(&self.key) {
+            return Frame::Integer(0);
+        }
+
+        match dbs[index].remove(&self.key) {
+            Some(entry) => {
+                // 过期时间也一起移动
+                dbs[target].insert(self.key, entry);
+                Frame::Integer(1)
+            }
+            None => Frame::Integer(0),
+        }
+    }
+}
+
+/// DEL key [key ...]
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+}
+
+impl Del {
+    pub fn new(keys: Vec<String>) -> Del {
+        Del { keys }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Del, ParseError> {
+        Ok(Del {
+            keys: parse_keys(parse)?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let removed = self
+            .keys
+            .iter()
+            .filter(|key| keyspace.remove(key).is_some())
+            .count();
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// EXISTS key [key ...]，同一个 key 出现多次会被计算多次
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<String>,
+}
+
+impl Exists {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Exists, ParseError> {
+        Ok(Exists {
+            keys: parse_keys(parse)?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let count = self
+            .keys
+            .iter()
+            .filter(|key| keyspace.contains_key(key))
+            .count();
+        Frame::Integer(count as i64)
+    }
+}
+
+/// EXPIRE / PEXPIRE / EXPIREAT / PEXPIREAT
+#[derive(Debug)]
+pub struct Expire {
+    name: &'static str,
+    key: String,
+    expires_at: Instant,
+}
+
+impl Expire {
+    /// `name` 是小写的命令名称，决定参数的单位以及是相对时间还是时间戳
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> Result<Expire, ParseError> {
+        let name = match name {
+            "expire" => "expire",
+            "pexpire" => "pexpire",
+            "expireat" => "expireat",
+            _ => "pexpireat",
+        };
+        let key = parse.next_string()?;
+        let time = parse.next_int()?;
+
+        let millis = match name {
+            "expire" | "expireat" => time.checked_mul(1000),
+            _ => Some(time),
+        }
+        .ok_or_else(|| format!("invalid expire time in '{}' command", name))?;
+
+        // 负数或者已经过去的时间会让 key 立即过期
+        let expires_at = if name.ends_with("at") {
+            unix_time_to_instant(millis.max(0) as u64)
+        } else {
+            let millis = u64::try_from(millis).unwrap_or(0);
+            instant_after(Duration::from_millis(millis))
+        };
+
+        Ok(Expire {
+            name,
+            key,
+            expires_at,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        if self.expires_at <= Instant::now() {
+            return Frame::Integer(keyspace.remove(&self.key).is_some() as i64);
+        }
+        Frame::Integer(keyspace.set_expiry(&self.key, Some(self.expires_at)) as i64)
+    }
+}
+
+/// TTL key / PTTL key
+#[derive(Debug)]
+pub struct Ttl {
+    key: String,
+    millis: bool,
+}
+
+impl Ttl {
+    pub(crate) fn parse_frames(parse: &mut Parse, millis: bool) -> Result<Ttl, ParseError> {
+        Ok(Ttl {
+            key: parse.next_string()?,
+            millis,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.millis {
+            "pttl"
+        } else {
+            "ttl"
+        }
+    }
+
+    /// key 不存在返回 -2，没有过期时间返回 -1
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let ttl = match keyspace.get_entry(&self.key) {
+            None => return Frame::Integer(-2),
+            Some(entry) => match entry.ttl() {
+                None => return Frame::Integer(-1),
+                Some(ttl) => ttl,
+            },
+        };
+
+        if self.millis {
+            Frame::Integer(ttl.as_millis() as i64)
+        } else {
+            // 和 Redis 一样四舍五入到秒
+            Frame::Integer(((ttl.as_millis() + 500) / 1000) as i64)
+        }
+    }
+}
+
+/// PERSIST key
+#[derive(Debug)]
+pub struct Persist {
+    key: String,
+}
+
+impl Persist {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Persist, ParseError> {
+        Ok(Persist {
+            key: parse.next_string()?,
+        })
+    }
+
+    /// 只有 key 存在并且之前有过期时间时才返回 1
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let has_ttl = keyspace
+            .get_entry(&self.key)
+            .is_some_and(|entry| entry.expires_at.is_some());
+        if has_ttl {
+            keyspace.set_expiry(&self.key, None);
+        }
+        Frame::Integer(has_ttl as i64)
+    }
+}
+
+/// TYPE key
+#[derive(Debug)]
+pub struct Type {
+    key: String,
+}
+
+impl Type {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Type, ParseError> {
+        Ok(Type {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let name = match keyspace.get_entry(&self.key).map(|entry| &entry.value) {
+            None => "none",
+            Some(Value::String(_)) => "string",
+            Some(Value::Set(_)) => "set",
+            Some(Value::List(_)) => "list",
+            // Redis 中 HyperLogLog 是一个特殊格式的字符串，Bloom 过滤器是 RedisBloom 模块的类型
+            Some(Value::HyperLogLog(_)) => "string",
+            Some(Value::Bloom(_)) => "MBbloom--",
+            Some(Value::SortedSet(_)) => "zset",
+        };
+        Frame::Simple(name.to_string())
+    }
+}
+
+/// 把 Unix 时间戳(毫秒)换算成 `Instant`，EXPIREAT 以及 SET 的 EXAT/PXAT 选项使用
+pub(crate) fn unix_time_to_instant(millis: u64) -> Instant {
+    let target = UNIX_EPOCH + Duration::from_millis(millis);
+    let now = SystemTime::now();
+    match target.duration_since(now) {
+        Ok(remaining) => instant_after(remaining),
+        // 已经过去的时间
+        Err(_) => Instant::now(),
+    }
+}
+
+/// 当前时间之后 `duration` 的时刻，参数过大时取一个足够远的时间，避免溢出
+pub(crate) fn instant_after(duration: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_add(duration)
+        .unwrap_or_else(|| now + Duration::from_secs(100 * 365 * 24 * 3600))
+}
+
+/// 解析至少一个 key
+fn parse_keys(parse: &mut Parse) -> Result<Vec<String>, ParseError> {
+    let mut keys = vec![parse.next_string()?];
+    while parse.remaining() > 0 {
+        keys.push(parse.next_string()?);
+    }
+    Ok(keys)
+}
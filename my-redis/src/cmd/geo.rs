@@ -0,0 +1,445 @@
+use crate::db::Keyspace;
+use crate::geohash::{self, Shape};
+use crate::parse::ParseError;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+
+/// 距离的单位，`factor` 是一个单位等于多少米
+#[derive(Debug, Clone, Copy)]
+struct Unit {
+    factor: f64,
+}
+
+impl Unit {
+    fn parse(parse: &mut Parse) -> Result<Unit, ParseError> {
+        let factor = match &parse.next_string()?.to_lowercase()[..] {
+            "m" => 1.0,
+            "km" => 1000.0,
+            "ft" => 0.3048,
+            "mi" => 1609.34,
+            _ => return Err("unsupported unit provided. please use M, KM, FT, MI".into()),
+        };
+        Ok(Unit { factor })
+    }
+
+    /// 距离保留 4 位小数，和 Redis 一样
+    fn format(self, meters: f64) -> Frame {
+        Frame::Bulk(Bytes::from(format!("{:.4}", meters / self.factor)))
+    }
+}
+
+/// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: String,
+    points: Vec<(f64, f64, Bytes)>,
+    nx: bool,
+    xx: bool,
+    /// 回复新增以及位置发生变化的成员个数，而不只是新增的个数
+    ch: bool,
+}
+
+impl GeoAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut args = vec![];
+        while parse.remaining() > 0 {
+            args.push(parse.next_bytes()?);
+        }
+
+        let (mut nx, mut xx, mut ch) = (false, false, false);
+        let mut options = 0;
+        for arg in &args {
+            match &arg.to_ascii_uppercase()[..] {
+                b"NX" => nx = true,
+                b"XX" => xx = true,
+                b"CH" => ch = true,
+                _ => break,
+            }
+            options += 1;
+        }
+        if nx && xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+
+        let triples = &args[options..];
+        if triples.is_empty() || triples.len() % 3 != 0 {
+            return Err(ParseError::EndOfStream);
+        }
+        let mut points = Vec::with_capacity(triples.len() / 3);
+        for triple in triples.chunks(3) {
+            let (lon, lat) = (parse_f64(&triple[0])?, parse_f64(&triple[1])?);
+            validate(lon, lat)?;
+            points.push((lon, lat, triple[2].clone()));
+        }
+
+        Ok(GeoAdd {
+            key,
+            points,
+            nx,
+            xx,
+            ch,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_or_insert_sorted_set(&self.key) {
+            Ok(set) => set,
+            Err(err) => return err.into(),
+        };
+
+        let mut changed = 0;
+        for (lon, lat, member) in self.points {
+            let exists = set.score(&member);
+            if (self.nx && exists.is_some()) || (self.xx && exists.is_none()) {
+                continue;
+            }
+            let score = geohash::encode(lon, lat) as f64;
+            set.insert(member, score);
+            match exists {
+                None => changed += 1,
+                Some(prev) if self.ch && prev != score => changed += 1,
+                Some(_) => {}
+            }
+        }
+
+        // XX 时可能一个成员都没有加入
+        keyspace.remove_if_empty(&self.key);
+        Frame::Integer(changed)
+    }
+}
+
+/// GEOPOS key [member [member ...]]
+#[derive(Debug)]
+pub struct GeoPos {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl GeoPos {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoPos, ParseError> {
+        let key = parse.next_string()?;
+        let members = parse_members(parse)?;
+        Ok(GeoPos { key, members })
+    }
+
+    /// 每个成员回复 `[经度, 纬度]`，成员不存在时回复 Null
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_sorted_set(&self.key) {
+            Ok(set) => set,
+            Err(err) => return err.into(),
+        };
+
+        let positions =
+            self.members
+                .iter()
+                .map(|member| match set.and_then(|set| set.score(member)) {
+                    Some(score) => coordinates(geohash::decode(score as u64)),
+                    None => Frame::Null,
+                });
+        Frame::Array(positions.collect())
+    }
+}
+
+/// GEODIST key member1 member2 [M | KM | FT | MI]
+#[derive(Debug)]
+pub struct GeoDist {
+    key: String,
+    members: (Bytes, Bytes),
+    unit: Unit,
+}
+
+impl GeoDist {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoDist, ParseError> {
+        let key = parse.next_string()?;
+        let members = (parse.next_bytes()?, parse.next_bytes()?);
+        let unit = if parse.remaining() > 0 {
+            Unit::parse(parse)?
+        } else {
+            Unit { factor: 1.0 }
+        };
+        Ok(GeoDist { key, members, unit })
+    }
+
+    /// 任意一个成员不存在时回复 Null
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_sorted_set(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Null,
+            Err(err) => return err.into(),
+        };
+
+        match (set.score(&self.members.0), set.score(&self.members.1)) {
+            (Some(a), Some(b)) => {
+                let (lon1, lat1) = geohash::decode(a as u64);
+                let (lon2, lat2) = geohash::decode(b as u64);
+                self.unit.format(geohash::distance(lon1, lat1, lon2, lat2))
+            }
+            _ => Frame::Null,
+        }
+    }
+}
+
+/// GEOHASH key [member [member ...]]
+#[derive(Debug)]
+pub struct GeoHash {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl GeoHash {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoHash, ParseError> {
+        let key = parse.next_string()?;
+        let members = parse_members(parse)?;
+        Ok(GeoHash { key, members })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_sorted_set(&self.key) {
+            Ok(set) => set,
+            Err(err) => return err.into(),
+        };
+
+        let hashes =
+            self.members
+                .iter()
+                .map(|member| match set.and_then(|set| set.score(member)) {
+                    Some(score) => Frame::Bulk(Bytes::from(geohash::to_string(score as u64))),
+                    None => Frame::Null,
+                });
+        Frame::Array(hashes.collect())
+    }
+}
+
+/// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+///     BYRADIUS radius unit | BYBOX width height unit
+///     [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: String,
+    from: Origin,
+    shape: Shape,
+    unit: Unit,
+    /// `Some(true)` 表示 ASC
+    ascending: Option<bool>,
+    count: Option<usize>,
+    /// 找到 `count` 个就停止，不保证是最近的
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+#[derive(Debug)]
+enum Origin {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+/// 查询到的一个成员
+struct Found<'a> {
+    member: &'a Bytes,
+    distance: f64,
+    bits: u64,
+    position: (f64, f64),
+}
+
+impl GeoSearch {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoSearch, ParseError> {
+        let key = parse.next_string()?;
+        let mut from = None;
+        let mut by = None;
+        let mut ascending = None;
+        let mut count = None;
+        let mut any = false;
+        let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+
+        while parse.remaining() > 0 {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "FROMMEMBER" if from.is_none() => from = Some(Origin::Member(parse.next_bytes()?)),
+                "FROMLONLAT" if from.is_none() => {
+                    let (lon, lat) = (parse.next_f64()?, parse.next_f64()?);
+                    validate(lon, lat)?;
+                    from = Some(Origin::LonLat(lon, lat));
+                }
+                "FROMMEMBER" | "FROMLONLAT" => {
+                    return Err(
+                        "exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+                            .into(),
+                    )
+                }
+                "BYRADIUS" if by.is_none() => {
+                    let radius = parse.next_f64()?;
+                    if radius < 0.0 {
+                        return Err("radius cannot be negative".into());
+                    }
+                    let unit = Unit::parse(parse)?;
+                    by = Some((Shape::Radius(radius * unit.factor), unit));
+                }
+                "BYBOX" if by.is_none() => {
+                    let (width, height) = (parse.next_f64()?, parse.next_f64()?);
+                    if width < 0.0 || height < 0.0 {
+                        return Err("height or width cannot be negative".into());
+                    }
+                    let unit = Unit::parse(parse)?;
+                    let shape = Shape::Box {
+                        width: width * unit.factor,
+                        height: height * unit.factor,
+                    };
+                    by = Some((shape, unit));
+                }
+                "BYRADIUS" | "BYBOX" => {
+                    return Err(
+                        "exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".into(),
+                    )
+                }
+                "ASC" => ascending = Some(true),
+                "DESC" => ascending = Some(false),
+                "COUNT" => {
+                    let n = parse.next_int()?;
+                    if n <= 0 {
+                        return Err("COUNT must be > 0".into());
+                    }
+                    count = Some(n as usize);
+                }
+                "ANY" => any = true,
+                "WITHCOORD" => with_coord = true,
+                "WITHDIST" => with_dist = true,
+                "WITHHASH" => with_hash = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        let from =
+            from.ok_or("exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH")?;
+        let (shape, unit) =
+            by.ok_or("exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH")?;
+        if any && count.is_none() {
+            return Err("the ANY argument requires COUNT argument".into());
+        }
+        // 和 Redis 一样，只指定了 COUNT 时返回最近的几个
+        if count.is_some() && !any && ascending.is_none() {
+            ascending = Some(true);
+        }
+
+        Ok(GeoSearch {
+            key,
+            from,
+            shape,
+            unit,
+            ascending,
+            count,
+            any,
+            with_coord,
+            with_dist,
+            with_hash,
+        })
+    }
+
+    /// 先用 geohash 找出覆盖查询区域的几段分数范围，只扫描这些范围内的成员，再按精确的距离过滤
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_sorted_set(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::array(),
+            Err(err) => return err.into(),
+        };
+
+        let center = match &self.from {
+            Origin::LonLat(lon, lat) => (*lon, *lat),
+            Origin::Member(member) => match set.score(member) {
+                Some(score) => geohash::decode(score as u64),
+                None => {
+                    return Frame::Error("ERR could not decode requested zset member".to_string())
+                }
+            },
+        };
+
+        let mut found = vec![];
+        'scan: for (min, max) in self.shape.ranges(center) {
+            for (member, score) in set.range(min as f64, max as f64) {
+                let position = geohash::decode(score as u64);
+                if let Some(distance) = self.shape.distance(center, position) {
+                    found.push(Found {
+                        member,
+                        distance,
+                        bits: score as u64,
+                        position,
+                    });
+                    if self.any && Some(found.len()) == self.count {
+                        break 'scan;
+                    }
+                }
+            }
+        }
+
+        // 距离相同时按成员排序，结果是确定的
+        match self.ascending {
+            Some(true) => found.sort_by(|a, b| {
+                a.distance
+                    .total_cmp(&b.distance)
+                    .then_with(|| a.member.cmp(b.member))
+            }),
+            Some(false) => found.sort_by(|a, b| {
+                b.distance
+                    .total_cmp(&a.distance)
+                    .then_with(|| b.member.cmp(a.member))
+            }),
+            None => {}
+        }
+        if let Some(count) = self.count {
+            found.truncate(count);
+        }
+
+        let items = found.into_iter().map(|found| {
+            if !(self.with_coord || self.with_dist || self.with_hash) {
+                return Frame::Bulk(found.member.clone());
+            }
+            let mut item = vec![Frame::Bulk(found.member.clone())];
+            if self.with_dist {
+                item.push(self.unit.format(found.distance));
+            }
+            if self.with_hash {
+                item.push(Frame::Integer(found.bits as i64));
+            }
+            if self.with_coord {
+                item.push(coordinates(found.position));
+            }
+            Frame::Array(item)
+        });
+        Frame::Array(items.collect())
+    }
+}
+
+fn parse_members(parse: &mut Parse) -> Result<Vec<Bytes>, ParseError> {
+    let mut members = vec![];
+    while parse.remaining() > 0 {
+        members.push(parse.next_bytes()?);
+    }
+    Ok(members)
+}
+
+fn parse_f64(value: &[u8]) -> Result<f64, ParseError> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| "value is not a valid float".into())
+}
+
+/// 坐标必须在 geohash 能表示的范围内
+fn validate(lon: f64, lat: f64) -> Result<(), ParseError> {
+    if !(geohash::LON_MIN..=geohash::LON_MAX).contains(&lon)
+        || !(geohash::LAT_MIN..=geohash::LAT_MAX).contains(&lat)
+    {
+        return Err(format!("invalid longitude,latitude pair {:.6},{:.6}", lon, lat).into());
+    }
+    Ok(())
+}
+
+fn coordinates((lon, lat): (f64, f64)) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(lon.to_string())),
+        Frame::Bulk(Bytes::from(lat.to_string())),
+    ])
+}
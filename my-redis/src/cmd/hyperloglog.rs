@@ -0,0 +1,149 @@
+use crate::db::Keyspace;
+use crate::hyperloglog::HyperLogLog;
+use crate::parse::ParseError;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+
+/// PFADD key [element [element ...]]
+#[derive(Debug)]
+pub struct PfAdd {
+    key: String,
+    elements: Vec<Bytes>,
+}
+
+impl PfAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut elements = vec![];
+        while parse.remaining() > 0 {
+            elements.push(parse.next_bytes()?);
+        }
+        Ok(PfAdd { key, elements })
+    }
+
+    /// 创建了 key 或者有寄存器被更新时回复 1，否则回复 0
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let (hll, created) = match keyspace.get_or_insert_hyperloglog(&self.key) {
+            Ok(hll) => hll,
+            Err(err) => return err.into(),
+        };
+
+        let mut updated = created;
+        for element in &self.elements {
+            updated |= hll.add(element);
+        }
+        Frame::Integer(updated as i64)
+    }
+}
+
+/// PFCOUNT key [key ...]
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<String>,
+}
+
+impl PfCount {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfCount, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            keys.push(parse.next_string()?);
+        }
+        Ok(PfCount { keys })
+    }
+
+    /// 多个 key 时回复它们并集的基数，不存在的 key 视为空
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        if let [key] = &self.keys[..] {
+            return match keyspace.get_hyperloglog(key) {
+                Ok(hll) => Frame::Integer(hll.map_or(0, |hll| hll.count()) as i64),
+                Err(err) => err.into(),
+            };
+        }
+
+        match union(keyspace, &self.keys) {
+            Ok(hll) => Frame::Integer(hll.count() as i64),
+            Err(frame) => frame,
+        }
+    }
+}
+
+/// PFMERGE destkey [sourcekey [sourcekey ...]]
+#[derive(Debug)]
+pub struct PfMerge {
+    destination: String,
+    sources: Vec<String>,
+}
+
+impl PfMerge {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfMerge, ParseError> {
+        let destination = parse.next_string()?;
+        let mut sources = vec![];
+        while parse.remaining() > 0 {
+            sources.push(parse.next_string()?);
+        }
+        Ok(PfMerge {
+            destination,
+            sources,
+        })
+    }
+
+    /// 和 Redis 一样，`destkey` 已经存在时它本身也参与合并
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let merged = match union(keyspace, &self.sources) {
+            Ok(hll) => hll,
+            Err(frame) => return frame,
+        };
+
+        match keyspace.get_or_insert_hyperloglog(&self.destination) {
+            Ok((hll, _)) => {
+                hll.merge(&merged);
+                Frame::Simple("OK".to_string())
+            }
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// PFDEBUG ENCODING key，测试中用来确认稀疏编码和密集编码之间的转换
+#[derive(Debug)]
+pub struct PfDebug {
+    subcommand: String,
+    key: String,
+}
+
+impl PfDebug {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfDebug, ParseError> {
+        Ok(PfDebug {
+            subcommand: parse.next_string()?.to_lowercase(),
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let hll = match keyspace.get_hyperloglog(&self.key) {
+            Ok(Some(hll)) => hll,
+            Ok(None) => return Frame::Error("ERR The specified key does not exist".to_string()),
+            Err(err) => return err.into(),
+        };
+
+        match &self.subcommand[..] {
+            "encoding" => Frame::Simple(hll.encoding().to_string()),
+            _ => Frame::Error(format!(
+                "ERR Unknown PFDEBUG subcommand '{}'",
+                self.subcommand
+            )),
+        }
+    }
+}
+
+/// 所有 key 的并集，任意一个 key 的类型不对时返回 WRONGTYPE
+fn union(keyspace: &Keyspace, keys: &[String]) -> Result<HyperLogLog, Frame> {
+    let mut merged = HyperLogLog::new();
+    for key in keys {
+        if let Some(hll) = keyspace.get_hyperloglog(key)? {
+            merged.merge(hll);
+        }
+    }
+    Ok(merged)
+}
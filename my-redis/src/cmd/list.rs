@@ -0,0 +1,186 @@
+use crate::db::Keyspace;
+use crate::parse::ParseError;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+
+/// LPUSH key element [element ...] / RPUSH key element [element ...]
+#[derive(Debug)]
+pub struct Push {
+    key: String,
+    elements: Vec<Bytes>,
+    /// LPUSH 从队首插入
+    left: bool,
+}
+
+impl Push {
+    pub(crate) fn parse_frames(parse: &mut Parse, left: bool) -> Result<Push, ParseError> {
+        let key = parse.next_string()?;
+        let mut elements = vec![parse.next_bytes()?];
+        while parse.remaining() > 0 {
+            elements.push(parse.next_bytes()?);
+        }
+        Ok(Push {
+            key,
+            elements,
+            left,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.left {
+            "lpush"
+        } else {
+            "rpush"
+        }
+    }
+
+    /// 返回插入之后列表的长度
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let list = match keyspace.get_or_insert_list(&self.key) {
+            Ok(list) => list,
+            Err(err) => return err.into(),
+        };
+
+        // 和 Redis 一样逐个插入，`LPUSH key a b c` 之后列表是 c b a
+        for element in self.elements {
+            if self.left {
+                list.push_front(element);
+            } else {
+                list.push_back(element);
+            }
+        }
+        Frame::Integer(list.len() as i64)
+    }
+}
+
+/// LPOP key [count] / RPOP key [count]
+#[derive(Debug)]
+pub struct Pop {
+    key: String,
+    count: Option<usize>,
+    left: bool,
+}
+
+impl Pop {
+    pub(crate) fn parse_frames(parse: &mut Parse, left: bool) -> Result<Pop, ParseError> {
+        let key = parse.next_string()?;
+        let count = if parse.remaining() > 0 {
+            let count = parse.next_int()?;
+            if count < 0 {
+                return Err("value is out of range, must be positive".into());
+            }
+            Some(count as usize)
+        } else {
+            None
+        };
+        Ok(Pop { key, count, left })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.left {
+            "lpop"
+        } else {
+            "rpop"
+        }
+    }
+
+    /// 不带 count 时回复单个元素，带 count 时回复数组，key 不存在时都回复 Null
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let list = match keyspace.get_list_mut(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Null,
+            Err(err) => return err.into(),
+        };
+
+        let mut pop = || {
+            if self.left {
+                list.pop_front()
+            } else {
+                list.pop_back()
+            }
+        };
+
+        let frame = match self.count {
+            None => pop().map_or(Frame::Null, Frame::Bulk),
+            Some(count) => {
+                let mut frame = Frame::array();
+                for element in std::iter::from_fn(pop).take(count) {
+                    frame.push_bulk(element);
+                }
+                frame
+            }
+        };
+
+        keyspace.remove_if_empty(&self.key);
+        frame
+    }
+}
+
+/// LLEN key
+#[derive(Debug)]
+pub struct LLen {
+    key: String,
+}
+
+impl LLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LLen, ParseError> {
+        Ok(LLen {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        match keyspace.get_list(&self.key) {
+            Ok(list) => Frame::Integer(list.map_or(0, |list| list.len()) as i64),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// LRANGE key start stop
+#[derive(Debug)]
+pub struct LRange {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl LRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LRange, ParseError> {
+        Ok(LRange {
+            key: parse.next_string()?,
+            start: parse.next_int()?,
+            stop: parse.next_int()?,
+        })
+    }
+
+    /// 下标的处理和 GETRANGE 一样，负数从末尾开始计算，超出范围的部分被截掉
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let list = match keyspace.get_list(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::array(),
+            Err(err) => return err.into(),
+        };
+
+        let len = list.len() as i64;
+        let start = if self.start < 0 {
+            (len + self.start).max(0)
+        } else {
+            self.start
+        };
+        let stop = if self.stop < 0 {
+            len + self.stop
+        } else {
+            self.stop.min(len - 1)
+        };
+
+        let mut frame = Frame::array();
+        if start <= stop {
+            for element in list.range(start as usize..=stop as usize) {
+                frame.push_bulk(element.clone());
+            }
+        }
+        frame
+    }
+}
@@ -0,0 +1,418 @@
+//! 服务端支持的命令，按照 Redis 文档中的分组放在不同的文件中。
+//!
+//! 每个命令都有一个 `parse_frames` 用于从帧中解析参数。只读写键空间的命令实现
+//! `execute(&mut Keyspace)`，这样连接处理和脚本中的 `redis.call()` 可以共用同一个分发器。
+
+mod bloom;
+pub use bloom::{BfAdd, BfExists, BfReserve};
+
+mod connection;
+pub use connection::{Auth, Client, Ping, Select};
+
+mod generic;
+pub use generic::{Del, Exists, Expire, Move, Persist, Ttl, Type};
+
+mod geo;
+pub use geo::{GeoAdd, GeoDist, GeoHash, GeoPos, GeoSearch};
+
+mod hyperloglog;
+pub use hyperloglog::{PfAdd, PfCount, PfDebug, PfMerge};
+
+mod list;
+pub use list::{LLen, LRange, Pop, Push};
+
+mod pubsub;
+pub(crate) use pubsub::{message_frame, subscription_frame};
+pub use pubsub::{Publish, Subscribe, Unsubscribe};
+
+mod scripting;
+pub use scripting::{Eval, EvalSha, Script};
+
+mod server;
+pub use server::{Config, DbSize, FlushAll, FlushDb, Monitor, Slowlog, SwapDb};
+
+mod set;
+pub use set::{SAdd, SIsMember, SMembers, SRandom, SRem, SScan, SetAlgebra, SetOp};
+
+mod sorted_set;
+pub use sorted_set::{ZCard, ZRem};
+
+mod string;
+pub use string::{
+    Append, Condition, Expiry, Get, GetRange, GetSet, IncrBy, IncrByFloat, MGet, MSet, Set, SetNx,
+    SetRange, StrLen,
+};
+
+mod unknown;
+pub use unknown::Unknown;
+
+use crate::clients;
+use crate::db::Databases;
+use crate::parse::ParseError;
+use crate::{Db, Frame, Parse};
+
+/// 服务端支持的命令
+#[derive(Debug)]
+pub enum Command {
+    Get(Get),
+    Set(Set),
+    SetNx(SetNx),
+    GetSet(GetSet),
+    MGet(MGet),
+    MSet(MSet),
+    IncrBy(IncrBy),
+    IncrByFloat(IncrByFloat),
+    Append(Append),
+    StrLen(StrLen),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    Del(Del),
+    Exists(Exists),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    Type(Type),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SIsMember(SIsMember),
+    SetAlgebra(SetAlgebra),
+    SRandom(SRandom),
+    SScan(SScan),
+    Push(Push),
+    Pop(Pop),
+    LLen(LLen),
+    LRange(LRange),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    PfDebug(PfDebug),
+    BfReserve(BfReserve),
+    BfAdd(BfAdd),
+    BfExists(BfExists),
+    GeoAdd(GeoAdd),
+    GeoPos(GeoPos),
+    GeoDist(GeoDist),
+    GeoHash(GeoHash),
+    GeoSearch(GeoSearch),
+    ZRem(ZRem),
+    ZCard(ZCard),
+    Ping(Ping),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    Slowlog(Slowlog),
+    Config(Config),
+    Monitor(Monitor),
+    Publish(Publish),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Client(Client),
+    Auth(Auth),
+    Select(Select),
+    SwapDb(SwapDb),
+    Move(Move),
+    DbSize(DbSize),
+    FlushDb(FlushDb),
+    FlushAll(FlushAll),
+    Unknown(Unknown),
+}
+
+impl Command {
+    /// 从帧中解析出命令，帧必须是数组帧
+    ///
+    /// 解析失败时返回的错误信息已经是 Redis 风格的(例如 `ERR wrong number of arguments`)，
+    /// 可以直接作为错误帧回复给客户端
+    pub fn from_frame(frame: Frame) -> crate::Result<Command> {
+        let mut parse = Parse::new(frame).map_err(|e| format!("ERR {}", e))?;
+
+        let command_name = parse
+            .next_string()
+            .map_err(|e| format!("ERR {}", e))?
+            .to_lowercase();
+
+        match Command::parse_command(&command_name, &mut parse) {
+            Ok(command) => Ok(command),
+            Err(ParseError::EndOfStream) => Err(format!(
+                "ERR wrong number of arguments for '{}' command",
+                command_name
+            )
+            .into()),
+            Err(ParseError::Other(err)) => Err(format!("ERR {}", err).into()),
+        }
+    }
+
+    fn parse_command(name: &str, parse: &mut Parse) -> Result<Command, ParseError> {
+        let command = match name {
+            "get" => Command::Get(Get::parse_frames(parse)?),
+            "set" => Command::Set(Set::parse_frames(parse)?),
+            "setnx" => Command::SetNx(SetNx::parse_frames(parse)?),
+            "getset" => Command::GetSet(GetSet::parse_frames(parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(parse)?),
+            "mset" => Command::MSet(MSet::parse_frames(parse, false)?),
+            "msetnx" => Command::MSet(MSet::parse_frames(parse, true)?),
+            "incr" | "decr" | "incrby" | "decrby" => {
+                Command::IncrBy(IncrBy::parse_frames(parse, name)?)
+            }
+            "incrbyfloat" => Command::IncrByFloat(IncrByFloat::parse_frames(parse)?),
+            "append" => Command::Append(Append::parse_frames(parse)?),
+            "strlen" => Command::StrLen(StrLen::parse_frames(parse)?),
+            "getrange" => Command::GetRange(GetRange::parse_frames(parse)?),
+            "setrange" => Command::SetRange(SetRange::parse_frames(parse)?),
+            "del" => Command::Del(Del::parse_frames(parse)?),
+            "exists" => Command::Exists(Exists::parse_frames(parse)?),
+            "expire" | "pexpire" | "expireat" | "pexpireat" => {
+                Command::Expire(Expire::parse_frames(parse, name)?)
+            }
+            "ttl" => Command::Ttl(Ttl::parse_frames(parse, false)?),
+            "pttl" => Command::Ttl(Ttl::parse_frames(parse, true)?),
+            "persist" => Command::Persist(Persist::parse_frames(parse)?),
+            "type" => Command::Type(Type::parse_frames(parse)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(parse)?),
+            "srem" => Command::SRem(SRem::parse_frames(parse)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(parse, false)?),
+            "scard" => Command::SMembers(SMembers::parse_frames(parse, true)?),
+            "sismember" => Command::SIsMember(SIsMember::parse_frames(parse, false)?),
+            "smismember" => Command::SIsMember(SIsMember::parse_frames(parse, true)?),
+            "sinter" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Inter, false)?),
+            "sunion" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Union, false)?),
+            "sdiff" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Diff, false)?),
+            "sinterstore" => {
+                Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Inter, true)?)
+            }
+            "sunionstore" => {
+                Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Union, true)?)
+            }
+            "sdiffstore" => {
+                Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Diff, true)?)
+            }
+            "spop" => Command::SRandom(SRandom::parse_frames(parse, true)?),
+            "srandmember" => Command::SRandom(SRandom::parse_frames(parse, false)?),
+            "sscan" => Command::SScan(SScan::parse_frames(parse)?),
+            "lpush" => Command::Push(Push::parse_frames(parse, true)?),
+            "rpush" => Command::Push(Push::parse_frames(parse, false)?),
+            "lpop" => Command::Pop(Pop::parse_frames(parse, true)?),
+            "rpop" => Command::Pop(Pop::parse_frames(parse, false)?),
+            "llen" => Command::LLen(LLen::parse_frames(parse)?),
+            "lrange" => Command::LRange(LRange::parse_frames(parse)?),
+            "pfadd" => Command::PfAdd(PfAdd::parse_frames(parse)?),
+            "pfcount" => Command::PfCount(PfCount::parse_frames(parse)?),
+            "pfmerge" => Command::PfMerge(PfMerge::parse_frames(parse)?),
+            "pfdebug" => Command::PfDebug(PfDebug::parse_frames(parse)?),
+            "bf.reserve" => Command::BfReserve(BfReserve::parse_frames(parse)?),
+            "bf.add" => Command::BfAdd(BfAdd::parse_frames(parse)?),
+            "bf.exists" => Command::BfExists(BfExists::parse_frames(parse)?),
+            "geoadd" => Command::GeoAdd(GeoAdd::parse_frames(parse)?),
+            "geopos" => Command::GeoPos(GeoPos::parse_frames(parse)?),
+            "geodist" => Command::GeoDist(GeoDist::parse_frames(parse)?),
+            "geohash" => Command::GeoHash(GeoHash::parse_frames(parse)?),
+            "geosearch" => Command::GeoSearch(GeoSearch::parse_frames(parse)?),
+            "zrem" => Command::ZRem(ZRem::parse_frames(parse)?),
+            "zcard" => Command::ZCard(ZCard::parse_frames(parse)?),
+            "ping" => Command::Ping(Ping::parse_frames(parse)?),
+            "eval" => Command::Eval(Eval::parse_frames(parse)?),
+            "evalsha" => Command::EvalSha(EvalSha::parse_frames(parse)?),
+            "script" => Command::Script(Script::parse_frames(parse)?),
+            "slowlog" => Command::Slowlog(Slowlog::parse_frames(parse)?),
+            "config" => Command::Config(Config::parse_frames(parse)?),
+            "monitor" => Command::Monitor(Monitor::parse_frames(parse)?),
+            "publish" => Command::Publish(Publish::parse_frames(parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(parse)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse)?),
+            "client" => Command::Client(Client::parse_frames(parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(parse)?),
+            "select" => Command::Select(Select::parse_frames(parse)?),
+            "swapdb" => Command::SwapDb(SwapDb::parse_frames(parse)?),
+            "move" => Command::Move(Move::parse_frames(parse)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(parse)?),
+            "flushdb" => Command::FlushDb(FlushDb::parse_frames(parse)?),
+            "flushall" => Command::FlushAll(FlushAll::parse_frames(parse)?),
+            _ => {
+                // 未知命令直接返回，剩余的参数不需要校验
+                return Ok(Command::Unknown(Unknown::new(name)));
+            }
+        };
+
+        // 多余的参数同样视为参数个数错误
+        if parse.finish().is_err() {
+            return Err(ParseError::EndOfStream);
+        }
+
+        Ok(command)
+    }
+
+    /// 执行命令并返回回复，`client` 是发出命令的连接。
+    ///
+    /// 这里只包含命令本身的执行，不涉及 socket 读写，慢日志统计的就是这部分时间。
+    /// MONITOR 这类会接管连接的命令由 server.rs 中的连接处理逻辑负责
+    pub(crate) fn apply(self, db: &Db, client: &clients::Client) -> Frame {
+        match self {
+            Command::Client(cmd) => cmd.apply(db, client),
+            Command::Auth(cmd) => cmd.apply(db, client),
+            Command::Select(cmd) => cmd.apply(db, client),
+            Command::Eval(_) | Command::EvalSha(_) => self.apply_script(db, client.db()),
+            Command::Script(cmd) => cmd.apply(db),
+            Command::Slowlog(cmd) => cmd.apply(db),
+            Command::Config(cmd) => cmd.apply(db),
+            Command::Publish(cmd) => cmd.apply(db),
+            Command::Monitor(_) | Command::Subscribe(_) | Command::Unsubscribe(_) => Frame::Error(
+                format!("ERR {} must be handled by the connection", self.get_name()),
+            ),
+            // 脚本执行期间回复 BUSY，不阻塞运行时的线程
+            cmd => match db.lock_unless_busy() {
+                Ok(mut dbs) => cmd.execute(&mut dbs, client.db()),
+                Err(busy) => busy.into(),
+            },
+        }
+    }
+
+    /// 执行 EVAL / EVALSHA，`index` 是连接选择的数据库。
+    ///
+    /// 脚本可能运行很久，而且是同步执行的，连接处理逻辑通过 `spawn_blocking` 调用它，不在运行时的线程中执行
+    pub(crate) fn apply_script(self, db: &Db, index: usize) -> Frame {
+        match self {
+            Command::Eval(cmd) => cmd.apply(db, index),
+            Command::EvalSha(cmd) => cmd.apply(db, index),
+            cmd => Frame::Error(format!("ERR {} is not a script command", cmd.get_name())),
+        }
+    }
+
+    /// 在已经加锁的数据库上执行命令，`index` 是当前选择的数据库，脚本中的 `redis.call()` 也走这里
+    pub fn execute(self, dbs: &mut Databases, index: usize) -> Frame {
+        match self {
+            Command::Get(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Set(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SetNx(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GetSet(cmd) => cmd.execute(&mut dbs[index]),
+            Command::MGet(cmd) => cmd.execute(&mut dbs[index]),
+            Command::MSet(cmd) => cmd.execute(&mut dbs[index]),
+            Command::IncrBy(cmd) => cmd.execute(&mut dbs[index]),
+            Command::IncrByFloat(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Append(cmd) => cmd.execute(&mut dbs[index]),
+            Command::StrLen(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GetRange(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SetRange(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Del(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Exists(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Expire(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Ttl(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Persist(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Type(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SAdd(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SRem(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SMembers(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SIsMember(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SetAlgebra(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SRandom(cmd) => cmd.execute(&mut dbs[index]),
+            Command::SScan(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Push(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Pop(cmd) => cmd.execute(&mut dbs[index]),
+            Command::LLen(cmd) => cmd.execute(&mut dbs[index]),
+            Command::LRange(cmd) => cmd.execute(&mut dbs[index]),
+            Command::PfAdd(cmd) => cmd.execute(&mut dbs[index]),
+            Command::PfCount(cmd) => cmd.execute(&mut dbs[index]),
+            Command::PfMerge(cmd) => cmd.execute(&mut dbs[index]),
+            Command::PfDebug(cmd) => cmd.execute(&mut dbs[index]),
+            Command::BfReserve(cmd) => cmd.execute(&mut dbs[index]),
+            Command::BfAdd(cmd) => cmd.execute(&mut dbs[index]),
+            Command::BfExists(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GeoAdd(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GeoPos(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GeoDist(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GeoHash(cmd) => cmd.execute(&mut dbs[index]),
+            Command::GeoSearch(cmd) => cmd.execute(&mut dbs[index]),
+            Command::ZRem(cmd) => cmd.execute(&mut dbs[index]),
+            Command::ZCard(cmd) => cmd.execute(&mut dbs[index]),
+            Command::Move(cmd) => cmd.execute(dbs, index),
+            Command::DbSize(cmd) => cmd.execute(&mut dbs[index]),
+            Command::FlushDb(cmd) => cmd.execute(&mut dbs[index]),
+            Command::FlushAll(cmd) => cmd.execute(dbs),
+            Command::SwapDb(cmd) => cmd.execute(dbs),
+            Command::Ping(cmd) => cmd.execute(),
+            Command::Unknown(cmd) => cmd.execute(),
+            // 脚本中只允许执行读写键空间的命令
+            Command::Eval(_)
+            | Command::EvalSha(_)
+            | Command::Script(_)
+            | Command::Slowlog(_)
+            | Command::Config(_)
+            | Command::Monitor(_)
+            | Command::Publish(_)
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::Client(_)
+            | Command::Auth(_)
+            | Command::Select(_) => {
+                Frame::Error("ERR This Redis command is not allowed from script".to_string())
+            }
+        }
+    }
+
+    /// 命令的名称
+    pub fn get_name(&self) -> &str {
+        match self {
+            Command::Get(_) => "get",
+            Command::Set(_) => "set",
+            Command::SetNx(_) => "setnx",
+            Command::GetSet(_) => "getset",
+            Command::MGet(_) => "mget",
+            Command::MSet(cmd) => cmd.get_name(),
+            Command::IncrBy(cmd) => cmd.get_name(),
+            Command::IncrByFloat(_) => "incrbyfloat",
+            Command::Append(_) => "append",
+            Command::StrLen(_) => "strlen",
+            Command::GetRange(_) => "getrange",
+            Command::SetRange(_) => "setrange",
+            Command::Del(_) => "del",
+            Command::Exists(_) => "exists",
+            Command::Expire(cmd) => cmd.get_name(),
+            Command::Ttl(cmd) => cmd.get_name(),
+            Command::Persist(_) => "persist",
+            Command::Type(_) => "type",
+            Command::SAdd(_) => "sadd",
+            Command::SRem(_) => "srem",
+            Command::SMembers(cmd) => cmd.get_name(),
+            Command::SIsMember(cmd) => cmd.get_name(),
+            Command::SetAlgebra(cmd) => cmd.get_name(),
+            Command::SRandom(cmd) => cmd.get_name(),
+            Command::SScan(_) => "sscan",
+            Command::Push(cmd) => cmd.get_name(),
+            Command::Pop(cmd) => cmd.get_name(),
+            Command::LLen(_) => "llen",
+            Command::LRange(_) => "lrange",
+            Command::PfAdd(_) => "pfadd",
+            Command::PfCount(_) => "pfcount",
+            Command::PfMerge(_) => "pfmerge",
+            Command::PfDebug(_) => "pfdebug",
+            Command::BfReserve(_) => "bf.reserve",
+            Command::BfAdd(_) => "bf.add",
+            Command::BfExists(_) => "bf.exists",
+            Command::GeoAdd(_) => "geoadd",
+            Command::GeoPos(_) => "geopos",
+            Command::GeoDist(_) => "geodist",
+            Command::GeoHash(_) => "geohash",
+            Command::GeoSearch(_) => "geosearch",
+            Command::ZRem(_) => "zrem",
+            Command::ZCard(_) => "zcard",
+            Command::Ping(_) => "ping",
+            Command::Eval(_) => "eval",
+            Command::EvalSha(_) => "evalsha",
+            Command::Script(_) => "script",
+            Command::Slowlog(_) => "slowlog",
+            Command::Config(_) => "config",
+            Command::Monitor(_) => "monitor",
+            Command::Publish(_) => "publish",
+            Command::Subscribe(_) => "subscribe",
+            Command::Unsubscribe(_) => "unsubscribe",
+            Command::Client(_) => "client",
+            Command::Auth(_) => "auth",
+            Command::Select(_) => "select",
+            Command::SwapDb(_) => "swapdb",
+            Command::Move(_) => "move",
+            Command::DbSize(_) => "dbsize",
+            Command::FlushDb(_) => "flushdb",
+            Command::FlushAll(_) => "flushall",
+            Command::Unknown(cmd) => cmd.get_name(),
+        }
+    }
+}
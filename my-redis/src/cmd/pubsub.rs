@@ -0,0 +1,111 @@
+use crate::parse::ParseError;
+use crate::{Db, Frame, Parse};
+
+use bytes::Bytes;
+
+/// PUBLISH channel message
+#[derive(Debug)]
+pub struct Publish {
+    channel: String,
+    message: Bytes,
+}
+
+/// SUBSCRIBE channel [channel ...]
+///
+/// 执行后连接进入订阅模式，由连接处理逻辑接管(见 server.rs)
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<String>,
+}
+
+/// UNSUBSCRIBE [channel [channel ...]]，不带参数时取消所有订阅
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<String>,
+}
+
+impl Publish {
+    pub fn new(channel: impl ToString, message: Bytes) -> Publish {
+        Publish {
+            channel: channel.to_string(),
+            message,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Publish, ParseError> {
+        let channel = parse.next_string()?;
+        let message = parse.next_bytes()?;
+        Ok(Publish { channel, message })
+    }
+
+    /// 回复收到消息的订阅者个数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.publish(&self.channel, self.message) as i64)
+    }
+}
+
+impl Subscribe {
+    pub fn new(channels: Vec<String>) -> Subscribe {
+        Subscribe { channels }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Subscribe, ParseError> {
+        let mut channels = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            channels.push(parse.next_string()?);
+        }
+        Ok(Subscribe { channels })
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub(crate) fn into_channels(self) -> Vec<String> {
+        self.channels
+    }
+}
+
+impl Unsubscribe {
+    pub fn new(channels: Vec<String>) -> Unsubscribe {
+        Unsubscribe { channels }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Unsubscribe, ParseError> {
+        let mut channels = vec![];
+        while parse.remaining() > 0 {
+            channels.push(parse.next_string()?);
+        }
+        Ok(Unsubscribe { channels })
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub(crate) fn into_channels(self) -> Vec<String> {
+        self.channels
+    }
+}
+
+/// 订阅模式下对 SUBSCRIBE / UNSUBSCRIBE 的回复，`count` 是当前订阅的频道个数
+pub(crate) fn subscription_frame(kind: &'static str, channel: Option<&str>, count: usize) -> Frame {
+    let channel = match channel {
+        Some(channel) => Frame::Bulk(Bytes::copy_from_slice(channel.as_bytes())),
+        None => Frame::Null,
+    };
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(kind)),
+        channel,
+        Frame::Integer(count as i64),
+    ])
+}
+
+/// 推送给订阅者的消息
+pub(crate) fn message_frame(channel: &str, message: Bytes) -> Frame {
+    let mut frame = Frame::array();
+    frame.push_bulk(Bytes::from_static(b"message"));
+    frame.push_bulk(Bytes::copy_from_slice(channel.as_bytes()));
+    frame.push_bulk(message);
+    frame
+}
@@ -0,0 +1,136 @@
+use crate::parse::ParseError;
+use crate::{script, Db, Frame, Parse};
+
+use bytes::Bytes;
+
+/// EVAL script numkeys [key ...] [arg ...]
+#[derive(Debug)]
+pub struct Eval {
+    script: String,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+}
+
+/// EVALSHA sha1 numkeys [key ...] [arg ...]
+#[derive(Debug)]
+pub struct EvalSha {
+    sha: String,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+}
+
+/// SCRIPT LOAD script | SCRIPT EXISTS sha1 [sha1 ...] | SCRIPT FLUSH
+#[derive(Debug)]
+pub enum Script {
+    Load(String),
+    Exists(Vec<String>),
+    Flush,
+}
+
+impl Eval {
+    pub fn new(script: impl ToString, keys: Vec<Bytes>, args: Vec<Bytes>) -> Eval {
+        Eval {
+            script: script.to_string(),
+            keys,
+            args,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Eval, ParseError> {
+        let script = parse.next_string()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+        Ok(Eval { script, keys, args })
+    }
+
+    /// `index` 是当前连接选择的数据库
+    pub(crate) fn apply(self, db: &Db, index: usize) -> Frame {
+        // 和 Redis 一样，EVAL 执行过的脚本也会被缓存，之后可以直接 EVALSHA
+        db.load_script(&self.script);
+        script::eval(db, index, &self.script, self.keys, self.args)
+    }
+}
+
+impl EvalSha {
+    pub fn new(sha: impl ToString, keys: Vec<Bytes>, args: Vec<Bytes>) -> EvalSha {
+        EvalSha {
+            sha: sha.to_string(),
+            keys,
+            args,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<EvalSha, ParseError> {
+        let sha = parse.next_string()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+        Ok(EvalSha { sha, keys, args })
+    }
+
+    pub(crate) fn apply(self, db: &Db, index: usize) -> Frame {
+        match db.script(&self.sha) {
+            Some(source) => script::eval(db, index, &source, self.keys, self.args),
+            None => Frame::Error("NOSCRIPT No matching script. Please use EVAL.".to_string()),
+        }
+    }
+}
+
+impl Script {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Script, ParseError> {
+        let subcommand = parse.next_string()?.to_lowercase();
+        match &subcommand[..] {
+            "load" => Ok(Script::Load(parse.next_string()?)),
+            "exists" => {
+                let mut shas = vec![parse.next_string()?];
+                while parse.remaining() > 0 {
+                    shas.push(parse.next_string()?);
+                }
+                Ok(Script::Exists(shas))
+            }
+            "flush" => {
+                // 忽略 ASYNC / SYNC 参数，脚本缓存总是同步清空
+                if parse.remaining() > 0 {
+                    parse.next_string()?;
+                }
+                Ok(Script::Flush)
+            }
+            _ => Err(format!("unknown subcommand '{}'", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Script::Load(source) => Frame::Bulk(Bytes::from(db.load_script(&source))),
+            Script::Exists(shas) => Frame::Array(
+                shas.iter()
+                    .map(|sha| Frame::Integer(db.script_exists(sha) as i64))
+                    .collect(),
+            ),
+            Script::Flush => {
+                db.flush_scripts();
+                Frame::Simple("OK".to_string())
+            }
+        }
+    }
+}
+
+/// 解析 `numkeys [key ...] [arg ...]`
+fn parse_keys_and_args(parse: &mut Parse) -> Result<(Vec<Bytes>, Vec<Bytes>), ParseError> {
+    let numkeys = parse.next_int()?;
+    if numkeys < 0 {
+        return Err("Number of keys can't be negative".into());
+    }
+    if numkeys as usize > parse.remaining() {
+        return Err("Number of keys can't be greater than number of args".into());
+    }
+
+    let mut keys = Vec::with_capacity(numkeys as usize);
+    for _ in 0..numkeys {
+        keys.push(parse.next_bytes()?);
+    }
+
+    let mut args = Vec::with_capacity(parse.remaining());
+    while parse.remaining() > 0 {
+        args.push(parse.next_bytes()?);
+    }
+
+    Ok((keys, args))
+}
@@ -0,0 +1,194 @@
+use crate::db::{Databases, Keyspace};
+use crate::parse::ParseError;
+use crate::{Db, Frame, Parse};
+
+use bytes::Bytes;
+
+/// SLOWLOG GET [count] | SLOWLOG LEN | SLOWLOG RESET
+#[derive(Debug)]
+pub enum Slowlog {
+    /// `None` 表示返回全部记录
+    Get(Option<usize>),
+    Len,
+    Reset,
+}
+
+/// CONFIG GET pattern | CONFIG SET parameter value
+#[derive(Debug)]
+pub enum Config {
+    Get(String),
+    Set(String, String),
+}
+
+/// MONITOR，执行后连接进入监控模式，由连接处理逻辑接管(见 server.rs)
+#[derive(Debug, Default)]
+pub struct Monitor;
+
+impl Slowlog {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Slowlog, ParseError> {
+        let subcommand = parse.next_string()?.to_lowercase();
+        match &subcommand[..] {
+            "get" => {
+                if parse.remaining() == 0 {
+                    // 和 Redis 一样默认返回最近的 10 条
+                    return Ok(Slowlog::Get(Some(10)));
+                }
+                match parse.next_int()? {
+                    -1 => Ok(Slowlog::Get(None)),
+                    count if count >= 0 => Ok(Slowlog::Get(Some(count as usize))),
+                    _ => Err("count should be greater than or equal to -1".into()),
+                }
+            }
+            "len" => Ok(Slowlog::Len),
+            "reset" => Ok(Slowlog::Reset),
+            _ => Err(format!("unknown subcommand '{}'", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut slowlog = db.slowlog();
+        match self {
+            Slowlog::Get(count) => Frame::Array(slowlog.get(count).map(|e| e.to_frame()).collect()),
+            Slowlog::Len => Frame::Integer(slowlog.len() as i64),
+            Slowlog::Reset => {
+                slowlog.reset();
+                Frame::Simple("OK".to_string())
+            }
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Config, ParseError> {
+        let subcommand = parse.next_string()?.to_lowercase();
+        match &subcommand[..] {
+            "get" => Ok(Config::Get(parse.next_string()?)),
+            "set" => Ok(Config::Set(parse.next_string()?, parse.next_string()?)),
+            _ => Err(format!("unknown subcommand '{}'", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Config::Get(pattern) => {
+                let mut response = Frame::array();
+                for (name, value) in db.config().get(&pattern) {
+                    response.push_bulk(Bytes::from(name));
+                    response.push_bulk(Bytes::from(value));
+                }
+                response
+            }
+            Config::Set(name, value) => match db.set_config(&name, &value) {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(msg) => Frame::Error(format!("ERR {}", msg)),
+            },
+        }
+    }
+}
+
+impl Monitor {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Monitor, ParseError> {
+        Ok(Monitor)
+    }
+}
+
+/// DBSIZE
+#[derive(Debug, Default)]
+pub struct DbSize;
+
+/// FLUSHDB [ASYNC | SYNC]
+#[derive(Debug, Default)]
+pub struct FlushDb {
+    lazy: bool,
+}
+
+/// FLUSHALL [ASYNC | SYNC]
+#[derive(Debug, Default)]
+pub struct FlushAll {
+    lazy: bool,
+}
+
+/// SWAPDB index1 index2
+#[derive(Debug)]
+pub struct SwapDb {
+    a: i64,
+    b: i64,
+}
+
+impl DbSize {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<DbSize, ParseError> {
+        Ok(DbSize)
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        Frame::Integer(keyspace.len() as i64)
+    }
+}
+
+impl FlushDb {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<FlushDb, ParseError> {
+        Ok(FlushDb {
+            lazy: parse_flush_mode(parse)?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        free(keyspace.flush(), self.lazy);
+        Frame::Simple("OK".to_string())
+    }
+}
+
+impl FlushAll {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<FlushAll, ParseError> {
+        Ok(FlushAll {
+            lazy: parse_flush_mode(parse)?,
+        })
+    }
+
+    pub(crate) fn execute(self, dbs: &mut Databases) -> Frame {
+        free(dbs.flush_all(), self.lazy);
+        Frame::Simple("OK".to_string())
+    }
+}
+
+impl SwapDb {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SwapDb, ParseError> {
+        let a = parse.next_int()?;
+        let b = parse.next_int()?;
+        Ok(SwapDb { a, b })
+    }
+
+    pub(crate) fn execute(self, dbs: &mut Databases) -> Frame {
+        let valid = |index: i64| index >= 0 && (index as usize) < dbs.len();
+        if !valid(self.a) || !valid(self.b) {
+            return Frame::Error("ERR DB index is out of range".to_string());
+        }
+
+        // 连接记录的只是编号，交换之后选择了这两个库的连接自然会看到对方的数据
+        dbs.swap(self.a as usize, self.b as usize);
+        Frame::Simple("OK".to_string())
+    }
+}
+
+/// 解析可选的 `ASYNC` / `SYNC` 参数，返回是否需要异步释放
+fn parse_flush_mode(parse: &mut Parse) -> Result<bool, ParseError> {
+    if parse.remaining() == 0 {
+        return Ok(false);
+    }
+
+    match &parse.next_string()?.to_lowercase()[..] {
+        "async" => Ok(true),
+        "sync" => Ok(false),
+        _ => Err("syntax error".into()),
+    }
+}
+
+/// 释放被清空的数据。`lazy` 为 true 时在后台线程中 drop，
+/// 这样释放大量内存的开销不会阻塞持有锁的命令，对应 Redis 的 lazyfree
+fn free<T: Send + 'static>(data: T, lazy: bool) {
+    if lazy {
+        std::thread::spawn(move || drop(data));
+    } else {
+        drop(data);
+    }
+}
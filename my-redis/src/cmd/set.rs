@@ -0,0 +1,450 @@
+use crate::db::{Keyspace, Value, WrongType};
+use crate::parse::ParseError;
+use crate::scan_set::ScanSet;
+use crate::{glob, Frame, Parse};
+
+use bytes::Bytes;
+use rand::seq::{IteratorRandom, SliceRandom};
+use std::collections::HashSet;
+
+/// SRANDMEMBER 的 count 为负数时最多返回的元素个数。
+///
+/// 回复需要在持有数据库锁的时候生成，太大的 count 会长时间阻塞其它连接并耗尽内存，
+/// Redis 在这里拒绝的是绝对值超过 `i64::MAX / 2` 的 count，这里更严格一些
+const MAX_RANDOM_REPEATS: u64 = 1 << 20;
+
+/// SADD key member [member ...]
+#[derive(Debug)]
+pub struct SAdd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SAdd {
+    pub fn new(key: impl ToString, members: Vec<Bytes>) -> SAdd {
+        SAdd {
+            key: key.to_string(),
+            members,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SAdd, ParseError> {
+        let key = parse.next_string()?;
+        let members = parse_members(parse)?;
+        Ok(SAdd { key, members })
+    }
+
+    /// 返回新加入的元素个数，已经存在的不计算在内
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_or_insert_set(&self.key) {
+            Ok(set) => set,
+            Err(err) => return err.into(),
+        };
+
+        let added = self
+            .members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count();
+        Frame::Integer(added as i64)
+    }
+}
+
+/// SREM key member [member ...]
+#[derive(Debug)]
+pub struct SRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SRem {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SRem, ParseError> {
+        let key = parse.next_string()?;
+        let members = parse_members(parse)?;
+        Ok(SRem { key, members })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let removed = match keyspace.get_set_mut(&self.key) {
+            Ok(Some(set)) => self
+                .members
+                .iter()
+                .filter(|member| set.remove(member))
+                .count(),
+            Ok(None) => 0,
+            Err(err) => return err.into(),
+        };
+
+        keyspace.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// SMEMBERS key / SCARD key
+#[derive(Debug)]
+pub struct SMembers {
+    key: String,
+    /// SCARD 只返回元素个数
+    card: bool,
+}
+
+impl SMembers {
+    pub(crate) fn parse_frames(parse: &mut Parse, card: bool) -> Result<SMembers, ParseError> {
+        Ok(SMembers {
+            key: parse.next_string()?,
+            card,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.card {
+            "scard"
+        } else {
+            "smembers"
+        }
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        match keyspace.get_set(&self.key) {
+            Ok(set) if self.card => Frame::Integer(set.map_or(0, |set| set.len()) as i64),
+            Ok(set) => members_frame(set.into_iter().flatten().cloned()),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// SISMEMBER key member / SMISMEMBER key member [member ...]
+#[derive(Debug)]
+pub struct SIsMember {
+    key: String,
+    members: Vec<Bytes>,
+    /// SMISMEMBER 返回数组
+    multi: bool,
+}
+
+impl SIsMember {
+    pub(crate) fn parse_frames(parse: &mut Parse, multi: bool) -> Result<SIsMember, ParseError> {
+        let key = parse.next_string()?;
+        let members = if multi {
+            parse_members(parse)?
+        } else {
+            vec![parse.next_bytes()?]
+        };
+        Ok(SIsMember {
+            key,
+            members,
+            multi,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.multi {
+            "smismember"
+        } else {
+            "sismember"
+        }
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_set(&self.key) {
+            Ok(set) => set,
+            Err(err) => return err.into(),
+        };
+
+        let mut found = self
+            .members
+            .iter()
+            .map(|member| Frame::Integer(set.is_some_and(|set| set.contains(member)) as i64));
+
+        if self.multi {
+            Frame::Array(found.collect())
+        } else {
+            found.next().unwrap_or(Frame::Integer(0))
+        }
+    }
+}
+
+/// 集合运算的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// SINTER / SUNION / SDIFF key [key ...] 以及对应的 `*STORE destination key [key ...]`
+#[derive(Debug)]
+pub struct SetAlgebra {
+    op: SetOp,
+    /// `*STORE` 变体把结果写入这个 key，回复结果的元素个数
+    destination: Option<String>,
+    keys: Vec<String>,
+}
+
+impl SetAlgebra {
+    pub fn new(op: SetOp, destination: Option<String>, keys: Vec<String>) -> SetAlgebra {
+        SetAlgebra {
+            op,
+            destination,
+            keys,
+        }
+    }
+
+    pub(crate) fn parse_frames(
+        parse: &mut Parse,
+        op: SetOp,
+        store: bool,
+    ) -> Result<SetAlgebra, ParseError> {
+        let destination = if store {
+            Some(parse.next_string()?)
+        } else {
+            None
+        };
+
+        let mut keys = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            keys.push(parse.next_string()?);
+        }
+
+        Ok(SetAlgebra {
+            op,
+            destination,
+            keys,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        match (self.op, self.destination.is_some()) {
+            (SetOp::Inter, false) => "sinter",
+            (SetOp::Inter, true) => "sinterstore",
+            (SetOp::Union, false) => "sunion",
+            (SetOp::Union, true) => "sunionstore",
+            (SetOp::Diff, false) => "sdiff",
+            (SetOp::Diff, true) => "sdiffstore",
+        }
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let result = match self.compute(keyspace) {
+            Ok(result) => result,
+            Err(err) => return err.into(),
+        };
+
+        match self.destination {
+            Some(destination) => {
+                let len = result.len();
+                // 结果为空时删除目标 key，否则不管原来是什么类型都直接覆盖
+                if result.is_empty() {
+                    keyspace.remove(&destination);
+                } else {
+                    keyspace.set_value(destination, Value::Set(result.into_iter().collect()));
+                }
+                Frame::Integer(len as i64)
+            }
+            None => members_frame(result.into_iter()),
+        }
+    }
+
+    /// 不存在的 key 当作空集合，任何一个 key 不是集合都会返回 WRONGTYPE
+    fn compute(&self, keyspace: &Keyspace) -> Result<HashSet<Bytes>, WrongType> {
+        let sets = self
+            .keys
+            .iter()
+            .map(|key| keyspace.get_set(key))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let empty = ScanSet::new();
+        let mut sets = sets.into_iter().map(|set| set.unwrap_or(&empty));
+        let first = sets.next().expect("at least one key");
+
+        let result = match self.op {
+            SetOp::Union => sets.fold(
+                first.iter().cloned().collect(),
+                |mut acc: HashSet<_>, set| {
+                    acc.extend(set.iter().cloned());
+                    acc
+                },
+            ),
+            SetOp::Diff => {
+                let others: Vec<_> = sets.collect();
+                first
+                    .iter()
+                    .filter(|member| !others.iter().any(|set| set.contains(member)))
+                    .cloned()
+                    .collect()
+            }
+            SetOp::Inter => {
+                // 从最小的集合开始检查，减少查找次数
+                let mut all: Vec<_> = std::iter::once(first).chain(sets).collect();
+                all.sort_by_key(|set| set.len());
+                let (smallest, others) = all.split_first().expect("at least one key");
+                smallest
+                    .iter()
+                    .filter(|member| others.iter().all(|set| set.contains(member)))
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        Ok(result)
+    }
+}
+
+/// SPOP key [count] / SRANDMEMBER key [count]
+#[derive(Debug)]
+pub struct SRandom {
+    key: String,
+    count: Option<i64>,
+    /// SPOP 会删除返回的元素
+    pop: bool,
+}
+
+impl SRandom {
+    pub(crate) fn parse_frames(parse: &mut Parse, pop: bool) -> Result<SRandom, ParseError> {
+        let key = parse.next_string()?;
+        let count = if parse.remaining() > 0 {
+            let count = parse.next_int()?;
+            // SPOP 不允许负数，SRANDMEMBER 的负数表示允许重复
+            if pop && count < 0 {
+                return Err("value is out of range, must be positive".into());
+            }
+            if count < 0 && count.unsigned_abs() > MAX_RANDOM_REPEATS {
+                return Err("value is out of range".into());
+            }
+            Some(count)
+        } else {
+            None
+        };
+        Ok(SRandom { key, count, pop })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.pop {
+            "spop"
+        } else {
+            "srandmember"
+        }
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let mut rng = rand::thread_rng();
+
+        let set = match keyspace.get_set(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) if self.count.is_some() => return Frame::array(),
+            Ok(None) => return Frame::Null,
+            Err(err) => return err.into(),
+        };
+
+        let chosen: Vec<Bytes> = match self.count {
+            None => set.iter().choose(&mut rng).into_iter().cloned().collect(),
+            // count 不小于集合大小时返回整个集合，和 Redis 一样
+            Some(count) if count >= 0 && count as u64 >= set.len() as u64 => {
+                set.iter().cloned().collect()
+            }
+            Some(count) if count >= 0 => set
+                .iter()
+                .cloned()
+                .choose_multiple(&mut rng, count as usize),
+            Some(count) => {
+                // 负数：返回 |count| 个元素，同一个元素可能出现多次
+                let members: Vec<&Bytes> = set.iter().collect();
+                (0..count.unsigned_abs())
+                    .filter_map(|_| members.choose(&mut rng).map(|member| (*member).clone()))
+                    .collect()
+            }
+        };
+
+        if self.pop {
+            if let Ok(Some(set)) = keyspace.get_set_mut(&self.key) {
+                for member in &chosen {
+                    set.remove(member);
+                }
+            }
+            keyspace.remove_if_empty(&self.key);
+        }
+
+        match self.count {
+            Some(_) => members_frame(chosen.into_iter()),
+            None => match chosen.into_iter().next() {
+                Some(member) => Frame::Bulk(member),
+                None => Frame::Null,
+            },
+        }
+    }
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+#[derive(Debug)]
+pub struct SScan {
+    key: String,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+}
+
+impl SScan {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SScan, ParseError> {
+        let key = parse.next_string()?;
+        let cursor = parse.next_string()?.parse().map_err(|_| "invalid cursor")?;
+
+        let mut scan = SScan {
+            key,
+            cursor,
+            pattern: None,
+            count: 10,
+        };
+
+        while parse.remaining() > 0 {
+            match &parse.next_string()?.to_lowercase()[..] {
+                "match" => scan.pattern = Some(parse.next_bytes()?),
+                "count" => match parse.next_int()? {
+                    count if count >= 1 => scan.count = count as usize,
+                    _ => return Err("syntax error".into()),
+                },
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// 游标的含义见 `ScanSet::scan`，MATCH 在取出 `count` 个元素之后再过滤，和 Redis 一样可能返回空的一批
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let (next, members) = match keyspace.get_set(&self.key) {
+            Ok(Some(set)) => set.scan(self.cursor, self.count),
+            Ok(None) => (0, vec![]),
+            Err(err) => return err.into(),
+        };
+
+        let members = members
+            .into_iter()
+            .filter(|member| match &self.pattern {
+                Some(pattern) => glob::matches(pattern, member),
+                None => true,
+            })
+            .cloned();
+
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(next.to_string())),
+            members_frame(members),
+        ])
+    }
+}
+
+/// 解析至少一个元素
+fn parse_members(parse: &mut Parse) -> Result<Vec<Bytes>, ParseError> {
+    let mut members = vec![parse.next_bytes()?];
+    while parse.remaining() > 0 {
+        members.push(parse.next_bytes()?);
+    }
+    Ok(members)
+}
+
+fn members_frame(members: impl Iterator<Item = Bytes>) -> Frame {
+    let mut frame = Frame::array();
+    for member in members {
+        frame.push_bulk(member);
+    }
+    frame
+}
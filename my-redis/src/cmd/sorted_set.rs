@@ -0,0 +1,61 @@
+use crate::db::Keyspace;
+use crate::parse::ParseError;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+
+/// ZREM key member [member ...]，GEO 没有单独的删除命令，和 Redis 一样用 ZREM 删除位置
+#[derive(Debug)]
+pub struct ZRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl ZRem {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZRem, ParseError> {
+        let key = parse.next_string()?;
+        let mut members = vec![parse.next_bytes()?];
+        while parse.remaining() > 0 {
+            members.push(parse.next_bytes()?);
+        }
+        Ok(ZRem { key, members })
+    }
+
+    /// 返回删除的成员个数
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let set = match keyspace.get_sorted_set_mut(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Integer(0),
+            Err(err) => return err.into(),
+        };
+
+        let removed = self
+            .members
+            .iter()
+            .filter(|member| set.remove(member))
+            .count();
+        keyspace.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// ZCARD key
+#[derive(Debug)]
+pub struct ZCard {
+    key: String,
+}
+
+impl ZCard {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZCard, ParseError> {
+        Ok(ZCard {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        match keyspace.get_sorted_set(&self.key) {
+            Ok(set) => Frame::Integer(set.map_or(0, |set| set.len()) as i64),
+            Err(err) => err.into(),
+        }
+    }
+}
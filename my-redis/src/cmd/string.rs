@@ -0,0 +1,544 @@
+use crate::cmd::generic::{instant_after, unix_time_to_instant};
+use crate::db::{Entry, Keyspace, Value, WrongType};
+use crate::parse::{parse_i64, ParseError};
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// SETRANGE 允许的最大偏移量，和 Redis 一样字符串最大 512MB
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// GET key
+#[derive(Debug)]
+pub struct Get {
+    key: String,
+}
+
+impl Get {
+    pub fn new(key: impl ToString) -> Get {
+        Get {
+            key: key.to_string(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Get, ParseError> {
+        let key = parse.next_string()?;
+        Ok(Get { key })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        match keyspace.get(&self.key) {
+            Ok(value) => bulk_or_null(value),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// SET 的过期时间选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// EX seconds / PX milliseconds
+    In(Duration),
+    /// EXAT timestamp / PXAT milliseconds-timestamp
+    At(Instant),
+    /// KEEPTTL
+    Keep,
+}
+
+/// SET 的条件选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// NX，只有 key 不存在时才写入
+    NotExists,
+    /// XX，只有 key 存在时才写入
+    Exists,
+}
+
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT timestamp | PXAT milliseconds-timestamp | KEEPTTL]
+#[derive(Debug)]
+pub struct Set {
+    key: String,
+    value: Bytes,
+    expire: Option<Expiry>,
+    condition: Option<Condition>,
+    /// 是否返回旧的值
+    get: bool,
+}
+
+impl Set {
+    pub fn new(key: impl ToString, value: Bytes, expire: Option<Expiry>) -> Set {
+        Set {
+            key: key.to_string(),
+            value,
+            expire,
+            condition: None,
+            get: false,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    pub fn expire(&self) -> Option<Expiry> {
+        self.expire
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Set, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        let mut set = Set::new(key, value, None);
+
+        while parse.remaining() > 0 {
+            let option = parse.next_string()?.to_uppercase();
+            match &option[..] {
+                "NX" | "XX" if set.condition.is_none() => {
+                    set.condition = Some(if option == "NX" {
+                        Condition::NotExists
+                    } else {
+                        Condition::Exists
+                    });
+                }
+                "GET" => set.get = true,
+                "KEEPTTL" if set.expire.is_none() => set.expire = Some(Expiry::Keep),
+                "EX" | "PX" | "EXAT" | "PXAT" if set.expire.is_none() => {
+                    let time = parse.next_int()?;
+                    if time <= 0 {
+                        return Err("invalid expire time in 'set' command".into());
+                    }
+                    let time = time as u64;
+                    set.expire = Some(match &option[..] {
+                        "EX" => Expiry::In(Duration::from_secs(time)),
+                        "PX" => Expiry::In(Duration::from_millis(time)),
+                        "EXAT" => Expiry::At(unix_time_to_instant(time.saturating_mul(1000))),
+                        _ => Expiry::At(unix_time_to_instant(time)),
+                    });
+                }
+                // 选项重复或者互相冲突
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        Ok(set)
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let old = keyspace.get_entry(&self.key).cloned();
+
+        // 带 GET 选项时旧的值必须是字符串，其它情况下不管旧的值是什么类型都直接覆盖
+        let old_value = match old.as_ref().map(|entry| &entry.value) {
+            Some(Value::String(value)) => Some(value.clone()),
+            Some(_) if self.get => return WrongType.into(),
+            _ => None,
+        };
+
+        let allowed = match self.condition {
+            Some(Condition::NotExists) => old.is_none(),
+            Some(Condition::Exists) => old.is_some(),
+            None => true,
+        };
+
+        if allowed {
+            let expires_at = match self.expire {
+                None => None,
+                Some(Expiry::In(duration)) => Some(instant_after(duration)),
+                Some(Expiry::At(when)) => Some(when),
+                Some(Expiry::Keep) => old.as_ref().and_then(|entry| entry.expires_at),
+            };
+            keyspace.insert(
+                self.key,
+                Entry {
+                    value: Value::String(self.value),
+                    expires_at,
+                },
+            );
+        }
+
+        match (self.get, allowed) {
+            (true, _) => bulk_or_null(old_value.as_ref()),
+            (false, true) => Frame::Simple("OK".to_string()),
+            // NX / XX 条件不满足
+            (false, false) => Frame::Null,
+        }
+    }
+}
+
+/// SETNX key value
+#[derive(Debug)]
+pub struct SetNx {
+    key: String,
+    value: Bytes,
+}
+
+impl SetNx {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetNx, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        Ok(SetNx { key, value })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        if keyspace.contains_key(&self.key) {
+            return Frame::Integer(0);
+        }
+        keyspace.set(self.key, self.value);
+        Frame::Integer(1)
+    }
+}
+
+/// GETSET key value
+#[derive(Debug)]
+pub struct GetSet {
+    key: String,
+    value: Bytes,
+}
+
+impl GetSet {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetSet, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        Ok(GetSet { key, value })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let old = match keyspace.get(&self.key) {
+            Ok(old) => old.cloned(),
+            Err(err) => return err.into(),
+        };
+        keyspace.set(self.key, self.value);
+        bulk_or_null(old.as_ref())
+    }
+}
+
+/// MGET key [key ...]
+#[derive(Debug)]
+pub struct MGet {
+    keys: Vec<String>,
+}
+
+impl MGet {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<MGet, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            keys.push(parse.next_string()?);
+        }
+        Ok(MGet { keys })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        Frame::Array(
+            self.keys
+                .iter()
+                // 不是字符串的 key 返回 nil
+                .map(|key| bulk_or_null(keyspace.get(key).ok().flatten()))
+                .collect(),
+        )
+    }
+}
+
+/// MSET key value [key value ...] 以及 MSETNX
+///
+/// 命令在持有锁的情况下一次写完所有的 key，因此对其它连接来说是原子的
+#[derive(Debug)]
+pub struct MSet {
+    pairs: Vec<(String, Bytes)>,
+    /// MSETNX：只要有一个 key 已经存在，就一个都不写
+    nx: bool,
+}
+
+impl MSet {
+    pub(crate) fn parse_frames(parse: &mut Parse, nx: bool) -> Result<MSet, ParseError> {
+        let mut pairs = vec![(parse.next_string()?, parse.next_bytes()?)];
+        while parse.remaining() > 0 {
+            pairs.push((parse.next_string()?, parse.next_bytes()?));
+        }
+        Ok(MSet { pairs, nx })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.nx {
+            "msetnx"
+        } else {
+            "mset"
+        }
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        if self.nx && self.pairs.iter().any(|(key, _)| keyspace.contains_key(key)) {
+            return Frame::Integer(0);
+        }
+
+        for (key, value) in self.pairs {
+            keyspace.set(key, value);
+        }
+
+        if self.nx {
+            Frame::Integer(1)
+        } else {
+            Frame::Simple("OK".to_string())
+        }
+    }
+}
+
+/// INCR / DECR / INCRBY / DECRBY
+#[derive(Debug)]
+pub struct IncrBy {
+    name: &'static str,
+    key: String,
+    delta: i64,
+}
+
+impl IncrBy {
+    pub fn new(key: impl ToString, delta: i64) -> IncrBy {
+        IncrBy {
+            name: "incrby",
+            key: key.to_string(),
+            delta,
+        }
+    }
+
+    /// `name` 是小写的命令名称，INCR/DECR 没有增量参数
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> Result<IncrBy, ParseError> {
+        let key = parse.next_string()?;
+        let (name, delta) = match name {
+            "incr" => ("incr", 1),
+            "decr" => ("decr", -1),
+            "incrby" => ("incrby", parse.next_int()?),
+            _ => (
+                "decrby",
+                parse
+                    .next_int()?
+                    .checked_neg()
+                    .ok_or("decrement would overflow")?,
+            ),
+        };
+        Ok(IncrBy { name, key, delta })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let current = match keyspace.get(&self.key) {
+            Ok(Some(value)) => match parse_i64(value) {
+                Some(current) => current,
+                None => return not_an_integer(),
+            },
+            Ok(None) => 0,
+            Err(err) => return err.into(),
+        };
+
+        match current.checked_add(self.delta) {
+            Some(value) => {
+                keyspace.set_keep_ttl(self.key, Bytes::from(value.to_string()));
+                Frame::Integer(value)
+            }
+            None => Frame::Error("ERR increment or decrement would overflow".to_string()),
+        }
+    }
+}
+
+/// INCRBYFLOAT key increment
+#[derive(Debug)]
+pub struct IncrByFloat {
+    key: String,
+    delta: f64,
+}
+
+impl IncrByFloat {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<IncrByFloat, ParseError> {
+        let key = parse.next_string()?;
+        let delta = parse_f64(&parse.next_bytes()?).ok_or("value is not a valid float")?;
+        Ok(IncrByFloat { key, delta })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let current = match keyspace.get(&self.key) {
+            Ok(Some(value)) => match parse_f64(value) {
+                Some(current) => current,
+                None => return Frame::Error("ERR value is not a valid float".to_string()),
+            },
+            Ok(None) => 0.0,
+            Err(err) => return err.into(),
+        };
+
+        let value = current + self.delta;
+        if !value.is_finite() {
+            return Frame::Error("ERR increment would produce NaN or Infinity".to_string());
+        }
+
+        // `{}` 输出最短的能精确表示该浮点数的字符串，和 Redis 一样不会有多余的 0
+        let value = Bytes::from(value.to_string());
+        keyspace.set_keep_ttl(self.key, value.clone());
+        Frame::Bulk(value)
+    }
+}
+
+/// APPEND key value
+#[derive(Debug)]
+pub struct Append {
+    key: String,
+    value: Bytes,
+}
+
+impl Append {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Append, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        Ok(Append { key, value })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let mut value = match keyspace.get(&self.key) {
+            Ok(value) => value.map(|value| value.to_vec()).unwrap_or_default(),
+            Err(err) => return err.into(),
+        };
+        value.extend_from_slice(&self.value);
+
+        let len = value.len();
+        keyspace.set_keep_ttl(self.key, Bytes::from(value));
+        Frame::Integer(len as i64)
+    }
+}
+
+/// STRLEN key
+#[derive(Debug)]
+pub struct StrLen {
+    key: String,
+}
+
+impl StrLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<StrLen, ParseError> {
+        Ok(StrLen {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        match keyspace.get(&self.key) {
+            Ok(value) => Frame::Integer(value.map_or(0, |value| value.len()) as i64),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// GETRANGE key start end
+#[derive(Debug)]
+pub struct GetRange {
+    key: String,
+    start: i64,
+    end: i64,
+}
+
+impl GetRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetRange, ParseError> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let end = parse.next_int()?;
+        Ok(GetRange { key, start, end })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        let value = match keyspace.get(&self.key) {
+            Ok(Some(value)) => value,
+            Ok(None) => return Frame::Bulk(Bytes::new()),
+            Err(err) => return err.into(),
+        };
+
+        // start 和 end 都是闭区间，负数表示从末尾开始计算
+        let len = value.len() as i64;
+        let start = if self.start < 0 {
+            (len + self.start).max(0)
+        } else {
+            self.start
+        };
+        let end = if self.end < 0 {
+            len + self.end
+        } else {
+            self.end.min(len - 1)
+        };
+
+        if start > end || len == 0 {
+            return Frame::Bulk(Bytes::new());
+        }
+        Frame::Bulk(value.slice(start as usize..=end as usize))
+    }
+}
+
+/// SETRANGE key offset value
+#[derive(Debug)]
+pub struct SetRange {
+    key: String,
+    offset: i64,
+    value: Bytes,
+}
+
+impl SetRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetRange, ParseError> {
+        let key = parse.next_string()?;
+        let offset = parse.next_int()?;
+        let value = parse.next_bytes()?;
+        Ok(SetRange { key, offset, value })
+    }
+
+    pub(crate) fn execute(self, keyspace: &mut Keyspace) -> Frame {
+        // 很大的 offset 相加时不能溢出
+        if self.offset < 0
+            || (self.offset as u64).saturating_add(self.value.len() as u64) > MAX_STRING_LEN as u64
+        {
+            return Frame::Error("ERR offset is out of range".to_string());
+        }
+
+        let offset = self.offset as usize;
+        let mut value = match keyspace.get(&self.key) {
+            Ok(value) => value.map(|value| value.to_vec()).unwrap_or_default(),
+            Err(err) => return err.into(),
+        };
+
+        // 写入空字符串不会创建 key
+        if self.value.is_empty() {
+            return Frame::Integer(value.len() as i64);
+        }
+
+        // 不够长的部分用 0 填充
+        let end = offset + self.value.len();
+        if value.len() < end {
+            value.resize(end, 0);
+        }
+        value[offset..end].copy_from_slice(&self.value);
+
+        let len = value.len();
+        keyspace.set_keep_ttl(self.key, Bytes::from(value));
+        Frame::Integer(len as i64)
+    }
+}
+
+fn bulk_or_null(value: Option<&Bytes>) -> Frame {
+    match value {
+        Some(value) => Frame::Bulk(value.clone()),
+        None => Frame::Null,
+    }
+}
+
+fn not_an_integer() -> Frame {
+    Frame::Error("ERR value is not an integer or out of range".to_string())
+}
+
+fn parse_f64(value: &[u8]) -> Option<f64> {
+    let value: f64 = std::str::from_utf8(value).ok()?.trim().parse().ok()?;
+    if value.is_nan() {
+        return None;
+    }
+    Some(value)
+}
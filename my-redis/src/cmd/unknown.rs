@@ -0,0 +1,23 @@
+use crate::Frame;
+
+/// 不支持的命令，回复一个错误而不是像之前那样 panic
+#[derive(Debug)]
+pub struct Unknown {
+    command_name: String,
+}
+
+impl Unknown {
+    pub(crate) fn new(key: impl ToString) -> Unknown {
+        Unknown {
+            command_name: key.to_string(),
+        }
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
+        &self.command_name
+    }
+
+    pub(crate) fn execute(self) -> Frame {
+        Frame::Error(format!("ERR unknown command '{}'", self.command_name))
+    }
+}
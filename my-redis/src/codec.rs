@@ -0,0 +1,195 @@
+//! echo-server / echo-client 使用的分帧协议。
+//!
+//! 和 `Connection` 不同，这里只关心帧的边界，不解析帧的内容：`Codec::decode` 返回缓冲区开头一个完整帧的长度，
+//! echo-server 把这段字节原样写回，echo-client 逐字节比较写出去的帧和收到的帧。
+//! 这样同一套代码可以用来验证各种代理是否正确地转发了帧。
+//!
+//! * `LengthPrefixed`：4 字节大端序的长度 + 数据
+//! * `Lines`：以 `\n` 结尾的一行
+//! * `Resp`：一个完整的 RESP 帧，边界检查复用 `Frame::check`
+
+use crate::frame::{self, Frame};
+
+use bytes::{Buf, Bytes, BytesMut};
+use std::io::Cursor;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// 默认的最大帧长度
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// 分帧协议
+pub trait Codec: Send + Sync {
+    /// 协议的名称，命令行参数中使用
+    fn name(&self) -> &'static str;
+
+    /// 如果 `buf` 开头已经是一个完整的帧，返回它的长度(包括长度前缀、分隔符等)，数据还不够时返回 None。
+    ///
+    /// 帧的长度超过 `max_frame_size` 时返回错误，不会一直等待数据
+    fn decode(&self, buf: &[u8], max_frame_size: usize) -> crate::Result<Option<usize>>;
+
+    /// 把 `payload` 编码成一个帧追加到 `dst`，`Lines` 要求 `payload` 中不包含 `\n`
+    fn encode(&self, payload: &[u8], dst: &mut Vec<u8>);
+}
+
+/// 4 字节大端序的长度前缀
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthPrefixed;
+
+impl Codec for LengthPrefixed {
+    fn name(&self) -> &'static str {
+        "length"
+    }
+
+    fn decode(&self, buf: &[u8], max_frame_size: usize) -> crate::Result<Option<usize>> {
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        let len = 4 + u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        check_size(len, max_frame_size)?;
+        Ok((buf.len() >= len).then_some(len))
+    }
+
+    fn encode(&self, payload: &[u8], dst: &mut Vec<u8>) {
+        dst.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        dst.extend_from_slice(payload);
+    }
+}
+
+/// 以 `\n` 分隔的行，帧中包含 `\n`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lines;
+
+impl Codec for Lines {
+    fn name(&self) -> &'static str {
+        "line"
+    }
+
+    fn decode(&self, buf: &[u8], max_frame_size: usize) -> crate::Result<Option<usize>> {
+        // 只在最大帧长度的范围内查找，超过了还没有找到说明这一行太长了
+        let limit = buf.len().min(max_frame_size);
+        match buf[..limit].iter().position(|&b| b == b'\n') {
+            Some(end) => Ok(Some(end + 1)),
+            None if buf.len() >= max_frame_size => {
+                Err(format!("line exceeds max frame size {}", max_frame_size).into())
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn encode(&self, payload: &[u8], dst: &mut Vec<u8>) {
+        dst.extend_from_slice(payload);
+        dst.push(b'\n');
+    }
+}
+
+/// RESP 协议的帧，`encode` 把数据编码成 bulk 帧
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Resp;
+
+impl Codec for Resp {
+    fn name(&self) -> &'static str {
+        "resp"
+    }
+
+    fn decode(&self, buf: &[u8], max_frame_size: usize) -> crate::Result<Option<usize>> {
+        let mut cursor = Cursor::new(buf);
+        match Frame::check(&mut cursor) {
+            Ok(()) => {
+                let len = cursor.position() as usize;
+                check_size(len, max_frame_size)?;
+                Ok(Some(len))
+            }
+            Err(frame::Error::Incomplete) => {
+                check_size(buf.len() + 1, max_frame_size)?;
+                Ok(None)
+            }
+            Err(frame::Error::Other(err)) => Err(err),
+        }
+    }
+
+    fn encode(&self, payload: &[u8], dst: &mut Vec<u8>) {
+        Frame::Bulk(Bytes::copy_from_slice(payload)).encode(dst);
+    }
+}
+
+/// 根据名称选择协议：`length`、`line` 或 `resp`
+pub fn from_name(name: &str) -> Option<Arc<dyn Codec>> {
+    match name {
+        "length" => Some(Arc::new(LengthPrefixed)),
+        "line" => Some(Arc::new(Lines)),
+        "resp" => Some(Arc::new(Resp)),
+        _ => None,
+    }
+}
+
+fn check_size(len: usize, max_frame_size: usize) -> crate::Result<()> {
+    if len > max_frame_size {
+        return Err(format!(
+            "frame of {} bytes exceeds max frame size {}",
+            len, max_frame_size
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// 从读取端按帧读取
+pub struct FrameReader<R> {
+    reader: R,
+    codec: Arc<dyn Codec>,
+    buffer: BytesMut,
+    max_frame_size: usize,
+    /// 一共读到的字节数
+    bytes: u64,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(reader: R, codec: Arc<dyn Codec>, max_frame_size: usize) -> FrameReader<R> {
+        FrameReader {
+            reader,
+            codec,
+            buffer: BytesMut::with_capacity(4 * 1024),
+            max_frame_size,
+            bytes: 0,
+        }
+    }
+
+    /// 读取一个完整的帧，对端正常关闭时返回 None
+    pub async fn read_frame(&mut self) -> crate::Result<Option<Bytes>> {
+        loop {
+            if let Some(frame) = self.try_frame()? {
+                return Ok(Some(frame));
+            }
+            if !self.fill().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// 从已经读到的数据中取出一个帧，不会等待
+    pub fn try_frame(&mut self) -> crate::Result<Option<Bytes>> {
+        match self.codec.decode(&self.buffer, self.max_frame_size)? {
+            Some(len) => Ok(Some(self.buffer.split_to(len).freeze())),
+            None => Ok(None),
+        }
+    }
+
+    /// 从连接读取更多数据，对端关闭时返回 false。关闭时还剩下不完整的帧属于错误
+    pub async fn fill(&mut self) -> crate::Result<bool> {
+        let n = self.reader.read_buf(&mut self.buffer).await?;
+        if n == 0 {
+            if self.buffer.has_remaining() {
+                return Err("connection reset by peer with a partial frame".into());
+            }
+            return Ok(false);
+        }
+        self.bytes += n as u64;
+        Ok(true)
+    }
+
+    /// 一共读到的字节数
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes
+    }
+}
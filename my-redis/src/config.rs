@@ -0,0 +1,204 @@
+use crate::glob;
+use crate::tls::AuthClients;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 服务端的配置项，名称和含义与 redis.conf 保持一致。
+///
+/// 既可以在启动时通过 `--name value` 指定(和 redis-server 一样)，也可以在运行时通过 `CONFIG SET` 修改
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 数据库的个数，只能在启动时指定
+    pub databases: usize,
+    /// 客户端空闲多久之后关闭连接，0 表示永不关闭
+    pub timeout: Duration,
+    /// 脚本最长的执行时间
+    pub lua_time_limit: Duration,
+    /// 执行时间超过多少微秒的命令会被记录到慢日志，负数表示关闭，0 表示记录所有命令
+    pub slowlog_log_slower_than: i64,
+    /// 慢日志最多保留多少条
+    pub slowlog_max_len: usize,
+    /// 监听的 TCP 端口，0 表示不监听(例如只接受 TLS 连接)。端口和 TLS 相关的配置只能在启动时指定
+    pub port: u16,
+    /// 监听 TLS 连接的端口，0 表示不开启 TLS
+    pub tls_port: u16,
+    /// 服务端证书，PEM 格式，可以包含中间证书
+    pub tls_cert_file: Option<PathBuf>,
+    /// 服务端证书的私钥，PEM 格式
+    pub tls_key_file: Option<PathBuf>,
+    /// 用来验证客户端证书的 CA
+    pub tls_ca_cert_file: Option<PathBuf>,
+    /// 是否要求客户端提供证书(双向认证)
+    pub tls_auth_clients: AuthClients,
+    /// 监听的 Unix domain socket 路径，可以和 TCP 端口同时开启。和端口一样只能在启动时指定
+    pub unixsocket: Option<PathBuf>,
+    /// socket 文件的权限，和 redis.conf 一样是八进制，0 表示使用默认的权限
+    pub unixsocketperm: u32,
+    /// 设置之后客户端需要先 `AUTH password` 才能执行其它命令
+    pub requirepass: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            databases: 16,
+            timeout: Duration::ZERO,
+            lua_time_limit: crate::script::DEFAULT_TIME_LIMIT,
+            slowlog_log_slower_than: 10_000,
+            slowlog_max_len: 128,
+            port: 6379,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: AuthClients::No,
+            unixsocket: None,
+            unixsocketperm: 0,
+            requirepass: None,
+        }
+    }
+}
+
+/// 所有支持的配置项名称，`CONFIG GET` 按照这个顺序返回
+const NAMES: &[&str] = &[
+    "databases",
+    "timeout",
+    "lua-time-limit",
+    "slowlog-log-slower-than",
+    "slowlog-max-len",
+    "port",
+    "tls-port",
+    "tls-cert-file",
+    "tls-key-file",
+    "tls-ca-cert-file",
+    "tls-auth-clients",
+    "unixsocket",
+    "unixsocketperm",
+    "requirepass",
+];
+
+impl Config {
+    /// 从命令行参数中解析配置，格式为 `--name value`，不包括程序名
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument '{}'", arg))?;
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for '--{}'", name))?;
+            config.set(name, &value)?;
+        }
+
+        Ok(config)
+    }
+
+    /// 修改一个配置项，失败时返回的错误信息可以直接回复给客户端
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid argument '{}' for CONFIG SET '{}'", value, name);
+
+        match &name.to_lowercase()[..] {
+            "databases" => {
+                let count: usize = value.parse().map_err(|_| invalid())?;
+                if count == 0 {
+                    return Err(invalid());
+                }
+                self.databases = count;
+            }
+            "timeout" => {
+                let secs: u64 = value.parse().map_err(|_| invalid())?;
+                self.timeout = Duration::from_secs(secs);
+            }
+            "lua-time-limit" => {
+                let ms: u64 = value.parse().map_err(|_| invalid())?;
+                self.lua_time_limit = Duration::from_millis(ms);
+            }
+            "slowlog-log-slower-than" => {
+                self.slowlog_log_slower_than = value.parse().map_err(|_| invalid())?;
+            }
+            "slowlog-max-len" => {
+                self.slowlog_max_len = value.parse().map_err(|_| invalid())?;
+            }
+            "port" => self.port = value.parse().map_err(|_| invalid())?,
+            "tls-port" => self.tls_port = value.parse().map_err(|_| invalid())?,
+            "tls-cert-file" => self.tls_cert_file = optional_path(value),
+            "tls-key-file" => self.tls_key_file = optional_path(value),
+            "tls-ca-cert-file" => self.tls_ca_cert_file = optional_path(value),
+            "tls-auth-clients" => {
+                self.tls_auth_clients = match &value.to_lowercase()[..] {
+                    "no" => AuthClients::No,
+                    "yes" => AuthClients::Yes,
+                    "optional" => AuthClients::Optional,
+                    _ => return Err(invalid()),
+                };
+            }
+            "unixsocket" => self.unixsocket = optional_path(value),
+            "unixsocketperm" => {
+                let perm = u32::from_str_radix(value, 8).map_err(|_| invalid())?;
+                if perm > 0o777 {
+                    return Err(invalid());
+                }
+                self.unixsocketperm = perm;
+            }
+            // 和 redis.conf 一样，空字符串表示不需要密码
+            "requirepass" => self.requirepass = (!value.is_empty()).then(|| value.to_string()),
+            _ => {
+                return Err(format!(
+                    "Unknown option or number of arguments for CONFIG SET - '{}'",
+                    name
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 返回名称匹配 `pattern` 的配置项
+    pub fn get(&self, pattern: &str) -> Vec<(&'static str, String)> {
+        NAMES
+            .iter()
+            .filter(|name| glob::matches(pattern.to_lowercase().as_bytes(), name.as_bytes()))
+            .map(|&name| (name, self.value(name)))
+            .collect()
+    }
+
+    fn value(&self, name: &str) -> String {
+        match name {
+            "databases" => self.databases.to_string(),
+            "timeout" => self.timeout.as_secs().to_string(),
+            "lua-time-limit" => self.lua_time_limit.as_millis().to_string(),
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than.to_string(),
+            "slowlog-max-len" => self.slowlog_max_len.to_string(),
+            "port" => self.port.to_string(),
+            "tls-port" => self.tls_port.to_string(),
+            "tls-cert-file" => path_value(&self.tls_cert_file),
+            "tls-key-file" => path_value(&self.tls_key_file),
+            "tls-ca-cert-file" => path_value(&self.tls_ca_cert_file),
+            "tls-auth-clients" => match self.tls_auth_clients {
+                AuthClients::No => "no",
+                AuthClients::Yes => "yes",
+                AuthClients::Optional => "optional",
+            }
+            .to_string(),
+            "unixsocket" => path_value(&self.unixsocket),
+            "unixsocketperm" => format!("{:o}", self.unixsocketperm),
+            "requirepass" => self.requirepass.clone().unwrap_or_default(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// 和 redis.conf 一样，空字符串表示没有设置
+fn optional_path(value: &str) -> Option<PathBuf> {
+    (!value.is_empty()).then(|| PathBuf::from(value))
+}
+
+fn path_value(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
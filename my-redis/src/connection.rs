@@ -0,0 +1,88 @@
+use crate::frame::{self, Frame};
+
+use bytes::{Buf, BytesMut};
+use std::io::Cursor;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::net::TcpStream;
+
+/// 包含了一个 TcpStream 以及对帧进行读写的方法，完整的讲解见 `examples/mini_redis_frame.rs`
+///
+/// 底层的流可以是任意实现了 `AsyncRead + AsyncWrite` 的类型，例如 TLS 连接，默认是 TcpStream
+#[derive(Debug)]
+pub struct Connection<S = TcpStream> {
+    stream: BufWriter<S>,
+    buffer: BytesMut,
+    /// 缓冲区中不完整的帧已经检查到了哪里，新数据到达后从这里继续
+    checker: frame::Checker,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(socket: S) -> Connection<S> {
+        Connection {
+            stream: BufWriter::new(socket),
+            // 分配一个缓冲区，具有4kb的缓冲长度
+            buffer: BytesMut::with_capacity(4 * 1024),
+            checker: frame::Checker::default(),
+        }
+    }
+
+    /// 从连接读取一个帧
+    ///
+    /// 如果遇到EOF，则返回 None
+    pub async fn read_frame(&mut self) -> crate::Result<Option<Frame>> {
+        loop {
+            // 尝试从缓冲区的数据中解析出一个数据帧，
+            // 只有当数据足够被解析时，才返回对应的帧
+            if let Some(frame) = self.parse_frame()? {
+                return Ok(Some(frame));
+            }
+
+            // 读取成功时，会返回读取到的字节数，0 代表着读到了数据流的末尾
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                // 若缓冲区还有数据，说明对端在发送帧的过程中断开了连接，导致只发送了部分数据
+                return if self.buffer.is_empty() {
+                    Ok(None)
+                } else {
+                    Err("connection reset by peer".into())
+                };
+            }
+        }
+    }
+
+    fn parse_frame(&mut self) -> crate::Result<Option<Frame>> {
+        use frame::Error::Incomplete;
+
+        // 检查是否读取了足够解析出一个帧的数据，得到组成该帧的字节数
+        match self.checker.check(&self.buffer) {
+            Ok(len) => {
+                let mut buf = Cursor::new(&self.buffer[..len]);
+                let frame = Frame::parse(&mut buf)?;
+
+                // 解析完成，将缓冲区该帧的数据移除
+                self.buffer.advance(len);
+
+                Ok(Some(frame))
+            }
+            // 缓冲区的数据不足以解析出一个完整的帧
+            Err(Incomplete) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 将帧写入到连接中，写完后会 flush
+    pub async fn write_frame(&mut self, frame: &Frame) -> std::io::Result<()> {
+        self.buffer_frame(frame).await?;
+        self.flush().await
+    }
+
+    /// 将帧写入到写缓冲中但不 flush，pipeline 时连续写入多个帧之后再统一 `flush`
+    pub async fn buffer_frame(&mut self, frame: &Frame) -> std::io::Result<()> {
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        self.stream.write_all(&buf).await
+    }
+
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush().await
+    }
+}
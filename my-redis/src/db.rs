@@ -0,0 +1,710 @@
+use crate::bloom::BloomFilter;
+use crate::clients::{Client, ClientInfo, Clients, KillFilter};
+use crate::config::Config;
+use crate::hyperloglog::HyperLogLog;
+use crate::monitor;
+use crate::scan_set::ScanSet;
+use crate::slowlog::SlowLog;
+use crate::sorted_set::SortedSet;
+use crate::Frame;
+
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+/// 只能在启动时指定的配置项：数据库的个数，以及监听的端口、TLS 证书和 Unix socket，服务端启动之后就不会再读取它们
+const IMMUTABLE_CONFIG: &[&str] = &[
+    "databases",
+    "port",
+    "tls-port",
+    "tls-cert-file",
+    "tls-key-file",
+    "tls-ca-cert-file",
+    "tls-auth-clients",
+    "unixsocket",
+    "unixsocketperm",
+];
+
+/// 服务端共享的数据库，`Clone` 只是增加引用计数，每个连接持有一份
+///
+/// Tokio 提供的异步锁只应该在跨多个 .await调用时使用，而且 Tokio 的 Mutex 实际上内部使用的也是 std::sync::Mutex。
+/// 1. 锁如果在多个 .await 过程中持有，应该使用 Tokio 提供的锁，原因是 .await 的过程中锁可能在线程间转移，若使用标准库的同步锁存在死锁的可能性，
+///    例如某个任务刚获取完锁，还没使用完就因为 .await 让出了当前线程的所有权，结果下个任务又去获取了锁，造成死锁
+/// 2. 锁竞争不多的情况下，使用 std::sync::Mutex
+/// 3. 锁竞争多，可以考虑使用三方库提供的性能更高的锁，例如 parking_lot::Mutex
+#[derive(Debug, Clone)]
+pub struct Db {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// 所有编号的数据库共用一把锁，这样 SWAPDB、MOVE、FLUSHALL 以及脚本都是原子的
+    databases: Mutex<Databases>,
+    /// 脚本持有 `databases` 的锁期间为 true，这时其它命令回复 BUSY 而不是等待
+    script_running: AtomicBool,
+    /// `SCRIPT LOAD` 缓存的脚本，key 是脚本的 sha1
+    scripts: Mutex<HashMap<String, Arc<str>>>,
+    config: Mutex<Config>,
+    slowlog: Mutex<SlowLog>,
+    /// 所有执行 MONITOR 的连接都订阅这个通道
+    monitor: broadcast::Sender<String>,
+    /// 发布订阅的频道，和 mini-redis 一样每个频道对应一个广播通道
+    pub_sub: Mutex<HashMap<String, broadcast::Sender<Bytes>>>,
+    clients: Arc<Clients>,
+}
+
+/// 和 Redis 一样，服务端有多个编号的数据库(默认 16 个)，每个连接通过 SELECT 选择其中一个
+#[derive(Debug)]
+pub struct Databases {
+    keyspaces: Vec<Keyspace>,
+}
+
+/// 真正存放键值对的地方。命令在持有锁的情况下对它进行读写，
+/// 因此同一时刻只有一个命令(或一个脚本)在修改数据，这也是 EVAL 原子性的来源
+#[derive(Debug, Default)]
+pub struct Keyspace {
+    entries: HashMap<String, Entry>,
+    /// 按过期时间排序的 key，后台清理时只需要从头开始检查，写法参考 mini-redis 的 db.rs。
+    /// 可能会有多个 key 同时过期，因此把 key 也放进来保证唯一
+    expirations: BTreeSet<(Instant, String)>,
+}
+
+/// 键空间中的一个值
+///
+/// 过期时间使用 `tokio::time::Instant`，这样测试中可以通过 `tokio::time::pause` 控制时间
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: Value,
+    pub expires_at: Option<Instant>,
+}
+
+/// 值的类型，命令只能操作对应类型的值，否则回复 WRONGTYPE
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Bytes),
+    Set(ScanSet),
+    List(VecDeque<Bytes>),
+    /// PFADD 创建的 HyperLogLog
+    HyperLogLog(HyperLogLog),
+    /// BF.ADD / BF.RESERVE 创建的 Bloom 过滤器
+    Bloom(BloomFilter),
+    /// 有序集合，目前由 GEOADD 创建
+    SortedSet(SortedSet),
+}
+
+/// 脚本正在执行，命令没有执行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Busy;
+
+impl From<Busy> for Frame {
+    fn from(_: Busy) -> Frame {
+        Frame::Error(
+            "BUSY Redis is busy running a script. Wait for it to finish or hit lua-time-limit"
+                .to_string(),
+        )
+    }
+}
+
+/// `Db::lock_for_script` 返回的锁，drop 时先清除标记再释放锁
+pub(crate) struct ScriptGuard<'a> {
+    guard: MutexGuard<'a, Databases>,
+    running: &'a AtomicBool,
+}
+
+impl Deref for ScriptGuard<'_> {
+    type Target = Databases;
+
+    fn deref(&self) -> &Databases {
+        &self.guard
+    }
+}
+
+impl DerefMut for ScriptGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Databases {
+        &mut self.guard
+    }
+}
+
+impl Drop for ScriptGuard<'_> {
+    fn drop(&mut self) {
+        // 之后 `guard` 才被 drop。这期间等待的命令看不到标记，会重试直到拿到锁
+        self.running.store(false, Ordering::Release);
+    }
+}
+
+/// 对类型不符的 key 执行命令，例如对集合执行 GET
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongType;
+
+impl From<WrongType> for Frame {
+    fn from(_: WrongType) -> Frame {
+        Frame::Error(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        )
+    }
+}
+
+impl Db {
+    pub fn new() -> Db {
+        Db::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Db {
+        let (monitor, _) = broadcast::channel(1024);
+        Db {
+            shared: Arc::new(Shared {
+                databases: Mutex::new(Databases::new(config.databases)),
+                script_running: AtomicBool::new(false),
+                scripts: Mutex::new(HashMap::new()),
+                config: Mutex::new(config),
+                slowlog: Mutex::new(SlowLog::default()),
+                monitor,
+                pub_sub: Mutex::new(HashMap::new()),
+                clients: Arc::new(Clients::default()),
+            }),
+        }
+    }
+
+    /// 锁住所有数据库，注意不要在持有锁时 .await
+    pub fn lock(&self) -> MutexGuard<'_, Databases> {
+        self.shared.databases.lock().unwrap()
+    }
+
+    /// 锁被占用时(例如正在执行一个很长的脚本)直接返回 None，不阻塞当前线程
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, Databases>> {
+        self.shared.databases.try_lock().ok()
+    }
+
+    /// 命令在运行时的线程中获取锁。脚本持有锁时不等待，返回 `Busy`，和 Redis 回复 `-BUSY` 一样；
+    /// 其它命令持有锁的时间都很短，让出线程之后重试，不会像 `lock` 那样把线程阻塞到脚本结束
+    pub fn lock_unless_busy(&self) -> Result<MutexGuard<'_, Databases>, Busy> {
+        loop {
+            match self.shared.databases.try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::WouldBlock)
+                    if self.shared.script_running.load(Ordering::Acquire) =>
+                {
+                    return Err(Busy)
+                }
+                Err(TryLockError::WouldBlock) => std::thread::yield_now(),
+                Err(TryLockError::Poisoned(err)) => panic!("{}", err),
+            }
+        }
+    }
+
+    /// 脚本获取锁并一直持有到脚本结束，期间其它命令回复 BUSY。已经有脚本在执行时同样返回 `Busy`
+    pub(crate) fn lock_for_script(&self) -> Result<ScriptGuard<'_>, Busy> {
+        let guard = self.lock_unless_busy()?;
+        // 先拿到锁再设置标记，没有拿到锁的命令看到标记时锁一定被脚本持有
+        self.shared.script_running.store(true, Ordering::Release);
+        Ok(ScriptGuard {
+            guard,
+            running: &self.shared.script_running,
+        })
+    }
+
+    /// 缓存脚本并返回它的 sha1
+    pub fn load_script(&self, source: &str) -> String {
+        let sha = sha1_hex(source.as_bytes());
+        self.shared
+            .scripts
+            .lock()
+            .unwrap()
+            .entry(sha.clone())
+            .or_insert_with(|| source.into());
+        sha
+    }
+
+    pub fn script(&self, sha: &str) -> Option<Arc<str>> {
+        let sha = sha.to_ascii_lowercase();
+        self.shared.scripts.lock().unwrap().get(&sha).cloned()
+    }
+
+    pub fn script_exists(&self, sha: &str) -> bool {
+        self.script(sha).is_some()
+    }
+
+    pub fn flush_scripts(&self) {
+        self.shared.scripts.lock().unwrap().clear();
+    }
+
+    /// 当前配置的一份拷贝
+    pub fn config(&self) -> Config {
+        self.shared.config.lock().unwrap().clone()
+    }
+
+    pub fn set_config(&self, name: &str, value: &str) -> Result<(), String> {
+        if IMMUTABLE_CONFIG
+            .iter()
+            .any(|immutable| name.eq_ignore_ascii_case(immutable))
+        {
+            return Err(format!(
+                "CONFIG SET failed (possibly related to argument '{}') - can't set immutable config",
+                name
+            ));
+        }
+        self.shared.config.lock().unwrap().set(name, value)
+    }
+
+    /// 客户端的空闲超时，每读取一个命令都要检查，不拷贝整个配置
+    pub fn client_timeout(&self) -> Duration {
+        self.shared.config.lock().unwrap().timeout
+    }
+
+    pub fn script_time_limit(&self) -> Duration {
+        self.shared.config.lock().unwrap().lua_time_limit
+    }
+
+    /// 是否设置了 `requirepass`，每读取一个命令都要检查，不拷贝整个配置
+    pub fn auth_required(&self) -> bool {
+        self.shared.config.lock().unwrap().requirepass.is_some()
+    }
+
+    /// `password` 是否和 `requirepass` 一致，没有设置密码时返回 false
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        let config = self.shared.config.lock().unwrap();
+        config
+            .requirepass
+            .as_ref()
+            .is_some_and(|requirepass| requirepass.as_bytes() == password)
+    }
+
+    /// 数据库的个数，SELECT 每次都要检查，不拷贝整个配置，也不需要键空间的锁
+    pub fn databases(&self) -> usize {
+        self.shared.config.lock().unwrap().databases
+    }
+
+    /// 如果命令的执行时间超过了阈值，就记录到慢日志中
+    pub fn record_slowlog(
+        &self,
+        args: &[Bytes],
+        duration: Duration,
+        client_addr: &str,
+        client_name: &str,
+    ) {
+        // 每个命令都会调用，只在锁中读出需要的两项，不拷贝整个配置
+        let (slower_than, max_len) = {
+            let config = self.shared.config.lock().unwrap();
+            (config.slowlog_log_slower_than, config.slowlog_max_len)
+        };
+        if slower_than < 0 || duration.as_micros() < slower_than as u128 {
+            return;
+        }
+
+        self.slowlog()
+            .push(args, duration, client_addr, client_name, max_len);
+    }
+
+    pub fn slowlog(&self) -> MutexGuard<'_, SlowLog> {
+        self.shared.slowlog.lock().unwrap()
+    }
+
+    /// 注册一个新连接，返回的 `Client` 被 drop 时自动注销
+    pub fn register_client(&self, addr: String) -> Client {
+        self.shared.clients.register(addr)
+    }
+
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        self.shared.clients.list()
+    }
+
+    /// 关闭符合条件的连接，返回关闭的个数
+    pub fn kill_clients(&self, filter: &KillFilter) -> usize {
+        self.shared.clients.kill(filter)
+    }
+
+    /// 订阅一个频道，频道不存在时创建
+    pub fn subscribe(&self, channel: String) -> broadcast::Receiver<Bytes> {
+        self.shared
+            .pub_sub
+            .lock()
+            .unwrap()
+            .entry(channel)
+            .or_insert_with(|| broadcast::channel(1024).0)
+            .subscribe()
+    }
+
+    /// 发布一条消息，返回收到消息的订阅者个数
+    pub fn publish(&self, channel: &str, message: Bytes) -> usize {
+        let mut pub_sub = self.shared.pub_sub.lock().unwrap();
+        match pub_sub.get(channel).map(|tx| tx.send(message)) {
+            Some(Ok(receivers)) => receivers,
+            Some(Err(_)) => {
+                // 订阅者都已经退出了，顺便删掉这个频道
+                pub_sub.remove(channel);
+                0
+            }
+            None => 0,
+        }
+    }
+
+    /// 订阅 MONITOR 的输出
+    pub fn monitor(&self) -> broadcast::Receiver<String> {
+        self.shared.monitor.subscribe()
+    }
+
+    /// 将即将执行的命令发送给所有 MONITOR 连接，没有监听者时不做任何格式化
+    pub fn feed_monitors(&self, db: usize, client: &str, args: &[Bytes]) {
+        if self.shared.monitor.receiver_count() == 0 {
+            return;
+        }
+
+        let _ = self.shared.monitor.send(monitor::format(db, client, args));
+    }
+}
+
+impl Default for Db {
+    fn default() -> Db {
+        Db::new()
+    }
+}
+
+impl Databases {
+    fn new(count: usize) -> Databases {
+        Databases {
+            keyspaces: (0..count).map(|_| Keyspace::default()).collect(),
+        }
+    }
+
+    /// 数据库的个数
+    pub fn len(&self) -> usize {
+        self.keyspaces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyspaces.is_empty()
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.keyspaces.swap(a, b);
+    }
+
+    /// 删除所有数据库中已经过期的 key
+    pub fn purge_expired(&mut self) {
+        for keyspace in &mut self.keyspaces {
+            keyspace.purge_expired();
+        }
+    }
+
+    /// 清空所有数据库，返回旧的数据，调用方可以决定在哪里释放它们
+    pub fn flush_all(&mut self) -> Vec<Keyspace> {
+        let count = self.keyspaces.len();
+        std::mem::replace(&mut self.keyspaces, Databases::new(count).keyspaces)
+    }
+}
+
+impl Index<usize> for Databases {
+    type Output = Keyspace;
+
+    fn index(&self, index: usize) -> &Keyspace {
+        &self.keyspaces[index]
+    }
+}
+
+impl IndexMut<usize> for Databases {
+    fn index_mut(&mut self, index: usize) -> &mut Keyspace {
+        &mut self.keyspaces[index]
+    }
+}
+
+impl Keyspace {
+    /// 读取一个字符串，已经过期的 key 视为不存在
+    pub fn get(&self, key: &str) -> Result<Option<&Bytes>, WrongType> {
+        match self.get_entry(key).map(|entry| &entry.value) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 读取一个集合
+    pub fn get_set(&self, key: &str) -> Result<Option<&ScanSet>, WrongType> {
+        match self.get_entry(key).map(|entry| &entry.value) {
+            None => Ok(None),
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    pub fn get_entry(&self, key: &str) -> Option<&Entry> {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(Instant::now()))
+    }
+
+    /// 获取集合的可变引用，不会改变过期时间。
+    ///
+    /// 调用方删除元素之后如果集合变成空的，需要调用 `remove_if_empty`，和 Redis 一样不保留空集合
+    pub fn get_set_mut(&mut self, key: &str) -> Result<Option<&mut ScanSet>, WrongType> {
+        self.remove_if_expired(key);
+        match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            None => Ok(None),
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取集合的可变引用，key 不存在时创建一个空集合
+    pub fn get_or_insert_set(&mut self, key: &str) -> Result<&mut ScanSet, WrongType> {
+        if !self.contains_key(key) {
+            self.set_value(key.to_string(), Value::Set(ScanSet::new()));
+        }
+        Ok(self.get_set_mut(key)?.expect("set was just inserted"))
+    }
+
+    /// 读取一个列表
+    pub fn get_list(&self, key: &str) -> Result<Option<&VecDeque<Bytes>>, WrongType> {
+        match self.get_entry(key).map(|entry| &entry.value) {
+            None => Ok(None),
+            Some(Value::List(list)) => Ok(Some(list)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取列表的可变引用，和 `get_set_mut` 一样，弹出元素之后需要调用 `remove_if_empty`
+    pub fn get_list_mut(&mut self, key: &str) -> Result<Option<&mut VecDeque<Bytes>>, WrongType> {
+        self.remove_if_expired(key);
+        match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            None => Ok(None),
+            Some(Value::List(list)) => Ok(Some(list)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取列表的可变引用，key 不存在时创建一个空列表
+    pub fn get_or_insert_list(&mut self, key: &str) -> Result<&mut VecDeque<Bytes>, WrongType> {
+        if !self.contains_key(key) {
+            self.set_value(key.to_string(), Value::List(VecDeque::new()));
+        }
+        Ok(self.get_list_mut(key)?.expect("list was just inserted"))
+    }
+
+    /// 读取一个有序集合
+    pub fn get_sorted_set(&self, key: &str) -> Result<Option<&SortedSet>, WrongType> {
+        match self.get_entry(key).map(|entry| &entry.value) {
+            None => Ok(None),
+            Some(Value::SortedSet(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取有序集合的可变引用，和 `get_set_mut` 一样，删除成员之后需要调用 `remove_if_empty`
+    pub fn get_sorted_set_mut(&mut self, key: &str) -> Result<Option<&mut SortedSet>, WrongType> {
+        self.remove_if_expired(key);
+        match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            None => Ok(None),
+            Some(Value::SortedSet(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取有序集合的可变引用，key 不存在时创建一个空的有序集合
+    pub fn get_or_insert_sorted_set(&mut self, key: &str) -> Result<&mut SortedSet, WrongType> {
+        if !self.contains_key(key) {
+            self.set_value(key.to_string(), Value::SortedSet(SortedSet::new()));
+        }
+        Ok(self
+            .get_sorted_set_mut(key)?
+            .expect("sorted set was just inserted"))
+    }
+
+    /// 读取一个 HyperLogLog
+    pub fn get_hyperloglog(&self, key: &str) -> Result<Option<&HyperLogLog>, WrongType> {
+        match self.get_entry(key).map(|entry| &entry.value) {
+            None => Ok(None),
+            Some(Value::HyperLogLog(hll)) => Ok(Some(hll)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取 HyperLogLog 的可变引用，key 不存在时创建一个空的，第二个返回值表示是否是新创建的
+    pub fn get_or_insert_hyperloglog(
+        &mut self,
+        key: &str,
+    ) -> Result<(&mut HyperLogLog, bool), WrongType> {
+        self.remove_if_expired(key);
+        let created = !self.entries.contains_key(key);
+        if created {
+            self.set_value(key.to_string(), Value::HyperLogLog(HyperLogLog::new()));
+        }
+        match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::HyperLogLog(hll)) => Ok((hll, created)),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// 读取一个 Bloom 过滤器
+    pub fn get_bloom(&self, key: &str) -> Result<Option<&BloomFilter>, WrongType> {
+        match self.get_entry(key).map(|entry| &entry.value) {
+            None => Ok(None),
+            Some(Value::Bloom(filter)) => Ok(Some(filter)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// 获取 Bloom 过滤器的可变引用，key 不存在时用 `create` 创建
+    pub fn get_or_insert_bloom(
+        &mut self,
+        key: &str,
+        create: impl FnOnce() -> BloomFilter,
+    ) -> Result<&mut BloomFilter, WrongType> {
+        self.remove_if_expired(key);
+        if !self.entries.contains_key(key) {
+            self.set_value(key.to_string(), Value::Bloom(create()));
+        }
+        match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::Bloom(filter)) => Ok(filter),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// 删除空的集合、列表或有序集合
+    pub fn remove_if_empty(&mut self, key: &str) {
+        let empty = match self.get_entry(key).map(|entry| &entry.value) {
+            Some(Value::Set(set)) => set.is_empty(),
+            Some(Value::List(list)) => list.is_empty(),
+            Some(Value::SortedSet(set)) => set.is_empty(),
+            _ => false,
+        };
+        if empty {
+            self.remove(key);
+        }
+    }
+
+    /// 写入一个没有过期时间的字符串，和 SET 一样会清除之前的过期时间
+    pub fn set(&mut self, key: String, value: Bytes) {
+        self.set_value(key, Value::String(value));
+    }
+
+    /// 写入一个没有过期时间的值，之前的值不管是什么类型都会被覆盖
+    pub fn set_value(&mut self, key: String, value: Value) {
+        self.insert(
+            key,
+            Entry {
+                value,
+                expires_at: None,
+            },
+        );
+    }
+
+    /// 写入一个字符串，如果 key 已经存在则保留原来的过期时间，INCR、APPEND 等命令使用
+    pub fn set_keep_ttl(&mut self, key: String, value: Bytes) {
+        let expires_at = self.get_entry(&key).and_then(|entry| entry.expires_at);
+        self.insert(
+            key,
+            Entry {
+                value: Value::String(value),
+                expires_at,
+            },
+        );
+    }
+
+    pub fn insert(&mut self, key: String, entry: Entry) {
+        if let Some(when) = entry.expires_at {
+            self.expirations.insert((when, key.clone()));
+        }
+
+        if let Some(prev) = self.entries.insert(key.clone(), entry) {
+            // 之前的过期时间已经没用了
+            if let Some(when) = prev.expires_at {
+                self.expirations.remove(&(when, key));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Entry> {
+        self.remove_if_expired(key);
+
+        let entry = self.entries.remove(key)?;
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, key.to_string()));
+        }
+        Some(entry)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get_entry(key).is_some()
+    }
+
+    /// 修改过期时间，`None` 表示永不过期。key 不存在时返回 false
+    pub fn set_expiry(&mut self, key: &str, expires_at: Option<Instant>) -> bool {
+        match self.remove(key) {
+            Some(mut entry) => {
+                entry.expires_at = expires_at;
+                self.insert(key.to_string(), entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 删除所有已经过期的 key
+    pub fn purge_expired(&mut self) {
+        let now = Instant::now();
+
+        while let Some((when, _)) = self.expirations.first() {
+            if *when > now {
+                break;
+            }
+
+            if let Some((_, key)) = self.expirations.pop_first() {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    /// 键的个数，和 Redis 一样可能包括已经过期但还没有被清理的 key
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 清空当前数据库，返回旧的数据
+    pub fn flush(&mut self) -> Keyspace {
+        std::mem::take(self)
+    }
+
+    /// 惰性删除：访问到已经过期的 key 时顺便删掉
+    fn remove_if_expired(&mut self, key: &str) {
+        let expired = match self.entries.get(key) {
+            Some(entry) => entry.is_expired(Instant::now()),
+            None => false,
+        };
+
+        if expired {
+            if let Some(Entry {
+                expires_at: Some(when),
+                ..
+            }) = self.entries.remove(key)
+            {
+                self.expirations.remove(&(when, key.to_string()));
+            }
+        }
+    }
+}
+
+impl Entry {
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|when| when <= now)
+    }
+
+    /// 剩余的存活时间，没有过期时间时返回 None
+    pub fn ttl(&self) -> Option<std::time::Duration> {
+        self.expires_at
+            .map(|when| when.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// 计算 sha1 并返回小写的十六进制字符串，EVALSHA 和 redis.sha1hex 都用它
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
@@ -0,0 +1,456 @@
+//! Redis 协议(RESP)的帧，写法参考 `examples/mini_redis_frame.rs` 以及 mini-redis 的 frame.rs。
+//!
+//! 和 mini-redis 不同的是，这里的 `Integer` 使用 `i64`：`DECR` 等命令需要返回负数。
+//!
+//! 帧的内容来自对端，解析时不能相信其中的长度：任何输入都只会得到错误而不会 panic，
+//! 长度超过上限、嵌套过深的帧直接报错，分配的内存不会超过实际收到的数据。
+//! `tests/frame.rs` 和 `fuzz/` 中的模糊测试检查了这些性质
+//!
+//! 帧分多次到达时，`Checker` 记住上次检查到的位置，不会每次都从缓冲区的开头重新扫描
+
+use bytes::{Buf, Bytes};
+use std::fmt;
+use std::io::Cursor;
+use std::num::TryFromIntError;
+use std::string::FromUtf8Error;
+
+/// bulk 的最大长度，和 Redis 的 `proto-max-bulk-len` 默认值一样是 512MB
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// 数组的最大长度，和 Redis 的 multibulk 长度上限一样
+const MAX_ARRAY_LEN: i64 = i32::MAX as i64;
+
+/// 数组的最大嵌套层数，避免恶意的深层嵌套耗尽栈空间
+const MAX_DEPTH: usize = 128;
+
+/// 简单字符串、错误以及类型字节后面的长度、整数这些行的最大长度，和 Redis 的 `PROTO_INLINE_MAX_SIZE` 一样是 64KB。
+/// 没有这个限制时，对端只要一直不发送 `\r\n`，缓冲区就会无限增长
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// 帧除了数据之外，并不具备任何语义。命令解析和实现会在更高的层次进行(相比帧解析层）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    Null,
+    Array(Vec<Frame>),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// 缓冲区中的数据还不足以解析出一个完整的帧
+    Incomplete,
+
+    /// 非法的帧编码
+    Other(crate::Error),
+}
+
+impl Frame {
+    /// 返回一个空的数组帧
+    pub fn array() -> Frame {
+        Frame::Array(vec![])
+    }
+
+    /// 往数组帧中追加一个 bulk 帧
+    ///
+    /// # Panics
+    ///
+    /// `self` 不是数组帧时 panic
+    pub fn push_bulk(&mut self, bytes: Bytes) {
+        match self {
+            Frame::Array(vec) => vec.push(Frame::Bulk(bytes)),
+            _ => panic!("not an array frame"),
+        }
+    }
+
+    /// 往数组帧中追加一个整数帧
+    ///
+    /// # Panics
+    ///
+    /// `self` 不是数组帧时 panic
+    pub fn push_int(&mut self, value: i64) {
+        match self {
+            Frame::Array(vec) => vec.push(Frame::Integer(value)),
+            _ => panic!("not an array frame"),
+        }
+    }
+
+    /// 检查缓冲区中是否已经有一个完整的帧，游标会停在该帧的末尾。
+    ///
+    /// 每次调用都从游标处重新检查，同一个缓冲区需要反复检查时使用 `Checker`
+    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        let start = src.position() as usize;
+        let len = Checker::default().check(&src.get_ref()[start..])?;
+        src.set_position((start + len) as u64);
+        Ok(())
+    }
+
+    /// 解析一个帧，调用前需要先通过 `check` 确认帧是完整的
+    ///
+    /// 没有经过 `check` 时也不会 panic，只是可能返回 `Incomplete`
+    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        parse(src, 0)
+    }
+
+    /// 将帧编码成 RESP 字节，`Connection::write_frame` 和需要直接拼接请求的地方都会用到
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        match self {
+            Frame::Simple(val) => {
+                dst.push(b'+');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(val) => {
+                dst.push(b'-');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(val) => {
+                dst.push(b':');
+                dst.extend_from_slice(val.to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Null => dst.extend_from_slice(b"$-1\r\n"),
+            Frame::Bulk(val) => {
+                dst.push(b'$');
+                dst.extend_from_slice(val.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                dst.extend_from_slice(val);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Array(val) => {
+                dst.push(b'*');
+                dst.extend_from_slice(val.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                for entry in val {
+                    entry.encode(dst);
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq<&str> for Frame {
+    fn eq(&self, other: &&str) -> bool {
+        match self {
+            Frame::Simple(s) => s.eq(other),
+            Frame::Bulk(s) => s.eq(other),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::str;
+
+        match self {
+            Frame::Simple(response) => response.fmt(fmt),
+            Frame::Error(msg) => write!(fmt, "error: {}", msg),
+            Frame::Integer(num) => num.fmt(fmt),
+            Frame::Bulk(msg) => match str::from_utf8(msg) {
+                Ok(string) => string.fmt(fmt),
+                Err(_) => write!(fmt, "{:?}", msg),
+            },
+            Frame::Null => "(nil)".fmt(fmt),
+            Frame::Array(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    part.fmt(fmt)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 增量地检查缓冲区开头的帧是否完整。
+///
+/// 数据不完整时保留进度：已经检查完的元素，以及当前元素的首行中已经查找过 `\r\n` 的位置。
+/// 新的数据追加到缓冲区之后从上次停下的地方继续，很大的数组或者很长的行分多次到达时，总的开销仍然和数据量成正比。
+/// 两次调用之间缓冲区只能在末尾追加数据，返回完整的帧或者错误之后状态被重置
+#[derive(Debug, Default)]
+pub struct Checker {
+    /// 下一个要检查的元素的起始位置
+    pos: usize,
+    /// 还没有检查完的数组中剩下的元素个数，最外层的数组在前
+    arrays: Vec<usize>,
+    /// 当前元素的首行中，这个位置之前已经确认没有 `\r\n`
+    scanned: usize,
+}
+
+impl Checker {
+    /// `buf` 开头是一个完整的帧时返回它的长度，数据不够时返回 `Incomplete`
+    pub fn check(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let result = self.resume(buf);
+        if !matches!(result, Err(Error::Incomplete)) {
+            self.pos = 0;
+            self.arrays.clear();
+            self.scanned = 0;
+        }
+        result
+    }
+
+    fn resume(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        loop {
+            let mut src = Cursor::new(buf);
+            src.set_position(self.pos as u64);
+
+            let complete = match get_u8(&mut src)? {
+                b'+' | b'-' => {
+                    self.line(&mut src)?;
+                    true
+                }
+                b':' => {
+                    decimal(self.line(&mut src)?)?;
+                    true
+                }
+                b'$' => match bulk_len(decimal(self.line(&mut src)?)?)? {
+                    // 数据不完整时下次从这个元素的开头重新检查，只需要再读一次长度
+                    Some(len) => {
+                        skip(&mut src, len)?;
+                        get_crlf(&mut src)?;
+                        true
+                    }
+                    None => true,
+                },
+                b'*' => {
+                    check_depth(self.arrays.len())?;
+                    match array_len(decimal(self.line(&mut src)?)?)? {
+                        Some(len) if len > 0 => {
+                            self.arrays.push(len);
+                            false
+                        }
+                        _ => true,
+                    }
+                }
+                actual => {
+                    return Err(
+                        format!("protocol error; invalid frame type byte `{}`", actual).into(),
+                    )
+                }
+            };
+
+            self.pos = src.position() as usize;
+            self.scanned = 0;
+            if !complete {
+                continue;
+            }
+
+            // 一个元素完整了，它所在的数组也可能随之完整
+            loop {
+                match self.arrays.last_mut() {
+                    None => return Ok(self.pos),
+                    Some(remaining) => {
+                        *remaining -= 1;
+                        if *remaining > 0 {
+                            break;
+                        }
+                        self.arrays.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// 读取当前元素的首行，找不到 `\r\n` 时记住已经查找过的位置
+    fn line<'a>(&mut self, src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+        let result = get_line_from(src, self.scanned);
+        if let Err(Error::Incomplete) = result {
+            // 最后一个字节可能是 \r，下次从它开始查找
+            self.scanned = src.get_ref().len().saturating_sub(1);
+        }
+        result
+    }
+}
+
+fn parse(src: &mut Cursor<&[u8]>, depth: usize) -> Result<Frame, Error> {
+    match get_u8(src)? {
+        b'+' => {
+            let line = get_line(src)?.to_vec();
+            let string = String::from_utf8(line)?;
+            Ok(Frame::Simple(string))
+        }
+        b'-' => {
+            let line = get_line(src)?.to_vec();
+            let string = String::from_utf8(line)?;
+            Ok(Frame::Error(string))
+        }
+        b':' => {
+            let len = get_decimal(src)?;
+            Ok(Frame::Integer(len))
+        }
+        b'$' => match get_bulk_len(src)? {
+            Some(len) => {
+                if src.remaining() < len {
+                    return Err(Error::Incomplete);
+                }
+
+                let data = Bytes::copy_from_slice(&src.chunk()[..len]);
+
+                // 跳过数据以及末尾的 \r\n
+                src.advance(len);
+                get_crlf(src)?;
+
+                Ok(Frame::Bulk(data))
+            }
+            None => Ok(Frame::Null),
+        },
+        b'*' => match get_array_len(src, depth)? {
+            Some(len) => {
+                // 长度来自对端，不能直接用来分配内存：每个元素至少占 3 个字节
+                let mut out = Vec::with_capacity(len.min(src.remaining() / 3));
+
+                for _ in 0..len {
+                    out.push(parse(src, depth + 1)?);
+                }
+
+                Ok(Frame::Array(out))
+            }
+            None => Ok(Frame::Null),
+        },
+        actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+    }
+}
+
+/// 读取 bulk 的长度，`$-1` 表示 Null，返回 None
+fn get_bulk_len(src: &mut Cursor<&[u8]>) -> Result<Option<usize>, Error> {
+    bulk_len(get_decimal(src)?)
+}
+
+fn bulk_len(len: i64) -> Result<Option<usize>, Error> {
+    match len {
+        -1 => Ok(None),
+        len if (0..=MAX_BULK_LEN).contains(&len) => Ok(Some(len as usize)),
+        _ => Err("protocol error; invalid bulk length".into()),
+    }
+}
+
+/// 读取数组的长度，`*-1` 和 `$-1` 一样表示 Null，返回 None
+fn get_array_len(src: &mut Cursor<&[u8]>, depth: usize) -> Result<Option<usize>, Error> {
+    check_depth(depth)?;
+    array_len(get_decimal(src)?)
+}
+
+fn check_depth(depth: usize) -> Result<(), Error> {
+    if depth >= MAX_DEPTH {
+        return Err("protocol error; too many nested arrays".into());
+    }
+    Ok(())
+}
+
+fn array_len(len: i64) -> Result<Option<usize>, Error> {
+    match len {
+        -1 => Ok(None),
+        len if (0..=MAX_ARRAY_LEN).contains(&len) => Ok(Some(len as usize)),
+        _ => Err("protocol error; invalid multibulk length".into()),
+    }
+}
+
+/// bulk 数据之后必须紧跟着 \r\n
+fn get_crlf(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    if src.remaining() < 2 {
+        return Err(Error::Incomplete);
+    }
+    if &src.chunk()[..2] != b"\r\n" {
+        return Err("protocol error; invalid frame format".into());
+    }
+    src.advance(2);
+    Ok(())
+}
+
+fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+    if !src.has_remaining() {
+        return Err(Error::Incomplete);
+    }
+
+    Ok(src.get_u8())
+}
+
+fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+
+    src.advance(n);
+    Ok(())
+}
+
+/// 读取一个以 \r\n 结尾的十进制整数
+fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    decimal(get_line(src)?)
+}
+
+fn decimal(line: &[u8]) -> Result<i64, Error> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+/// 找到下一个 \r\n，返回它之前的内容
+fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    get_line_from(src, 0)
+}
+
+/// 和 `get_line` 一样，但是从 `from` 开始查找，调用方已经确认 `from` 之前没有 \r\n
+fn get_line_from<'a>(src: &mut Cursor<&'a [u8]>, from: usize) -> Result<&'a [u8], Error> {
+    let buf: &'a [u8] = src.get_ref();
+    let start = src.position() as usize;
+    let from = from.clamp(start, buf.len());
+
+    match buf[from..].windows(2).position(|window| window == b"\r\n") {
+        Some(i) => {
+            let end = from + i;
+            if end - start > MAX_LINE_LEN {
+                return Err("protocol error; line too long".into());
+            }
+            // 将游标移到 \n 之后
+            src.set_position((end + 2) as u64);
+            Ok(&buf[start..end])
+        }
+        // 最后一个字节可能是 \r，除此之外的内容一定属于这一行，已经超过上限时不用再等
+        None if buf.len() - start > MAX_LINE_LEN + 1 => Err("protocol error; line too long".into()),
+        None => Err(Error::Incomplete),
+    }
+}
+
+impl From<String> for Error {
+    fn from(src: String) -> Error {
+        Error::Other(src.into())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(src: &str) -> Error {
+        src.to_string().into()
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(_src: FromUtf8Error) -> Error {
+        "protocol error; invalid frame format".into()
+    }
+}
+
+impl From<TryFromIntError> for Error {
+    fn from(_src: TryFromIntError) -> Error {
+        "protocol error; invalid frame format".into()
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Incomplete => "stream ended early".fmt(fmt),
+            Error::Other(err) => err.fmt(fmt),
+        }
+    }
+}
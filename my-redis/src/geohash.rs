@@ -0,0 +1,266 @@
+//! GEO 命令使用的 geohash 编码和距离计算，常量和算法都和 Redis 的 geohash.c / geohash_helper.c 一样。
+//!
+//! 经度和纬度各自被二分 26 次，得到的两个 26 位整数交错成一个 52 位整数(纬度在偶数位，经度在奇数位)，
+//! 作为有序集合的分数保存。前缀相同的分数落在同一个矩形区域中，所以一个区域对应有序集合中的一段分数范围，
+//! 范围查询只需要扫描覆盖查询区域的几个格子，再用精确的距离过滤。
+//!
+//! 纬度的范围和 Web Mercator 投影一样是 ±85.05112878，GEOHASH 命令回复的字符串则使用标准的 ±90 重新编码
+
+/// 每个坐标二分的次数
+const STEP: u32 = 26;
+
+pub const LON_MIN: f64 = -180.0;
+pub const LON_MAX: f64 = 180.0;
+pub const LAT_MIN: f64 = -85.05112878;
+pub const LAT_MAX: f64 = 85.05112878;
+
+/// Redis 使用的地球半径(米)
+const EARTH_RADIUS: f64 = 6372797.560856;
+
+/// Web Mercator 投影中赤道长度的一半
+const MERCATOR_MAX: f64 = 20037726.37;
+
+/// 范围查询最多扫描多少个格子，超过时换成更大的格子
+const MAX_CELLS: usize = 16;
+
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// 经纬度编码成 52 位的 geohash，调用方需要保证坐标在合法范围内
+pub fn encode(lon: f64, lat: f64) -> u64 {
+    encode_in(lon, lat, (LAT_MIN, LAT_MAX), STEP)
+}
+
+/// geohash 所在格子的中心点 `(经度, 纬度)`
+pub fn decode(bits: u64) -> (f64, f64) {
+    let (lat_index, lon_index) = deinterleave(bits);
+    let cells = (1u64 << STEP) as f64;
+    let lat_scale = LAT_MAX - LAT_MIN;
+    let lon_scale = LON_MAX - LON_MIN;
+
+    let lat_min = LAT_MIN + (lat_index as f64 / cells) * lat_scale;
+    let lat_max = LAT_MIN + ((lat_index as f64 + 1.0) / cells) * lat_scale;
+    let lon_min = LON_MIN + (lon_index as f64 / cells) * lon_scale;
+    let lon_max = LON_MIN + ((lon_index as f64 + 1.0) / cells) * lon_scale;
+
+    (
+        ((lon_min + lon_max) / 2.0).clamp(LON_MIN, LON_MAX),
+        ((lat_min + lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX),
+    )
+}
+
+/// GEOHASH 回复的 11 个字符的标准 geohash 字符串
+pub fn to_string(bits: u64) -> String {
+    let (lon, lat) = decode(bits);
+    let bits = encode_in(lon, lat, (-90.0, 90.0), STEP);
+    (0..11)
+        .map(|i| {
+            // 52 位只够 10 个字符多 2 位，和 Redis 一样最后一个字符固定为 '0'
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+/// 两点之间的球面距离(米)，haversine 公式
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// GEOSEARCH 的查询区域，长度的单位都是米
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    /// BYRADIUS
+    Radius(f64),
+    /// BYBOX，宽度沿纬线，高度沿经线
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// `point` 在以 `center` 为中心的区域中时返回两者之间的距离
+    pub fn distance(&self, center: (f64, f64), point: (f64, f64)) -> Option<f64> {
+        let (lon1, lat1) = center;
+        let (lon2, lat2) = point;
+        match *self {
+            Shape::Radius(radius) => {
+                let distance = distance(lon1, lat1, lon2, lat2);
+                (distance <= radius).then_some(distance)
+            }
+            Shape::Box { width, height } => {
+                // 和 Redis 一样分别比较南北方向和东西方向(在 point 所在的纬线上)的距离
+                let lat_distance = EARTH_RADIUS * (lat2.to_radians() - lat1.to_radians()).abs();
+                if lat_distance > height / 2.0 {
+                    return None;
+                }
+                if distance(lon2, lat2, lon1, lat2) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(lon1, lat1, lon2, lat2))
+            }
+        }
+    }
+
+    /// 需要扫描的分数范围 `[min, max)`，覆盖以 `center` 为中心的整个区域，按分数排序并且互不重叠
+    pub fn ranges(&self, center: (f64, f64)) -> Vec<(u64, u64)> {
+        let (lon, lat) = center;
+        let (lat_delta, lon_delta) = self.extent(lat);
+        let lat_range = (
+            (lat - lat_delta).max(LAT_MIN),
+            (lat + lat_delta).min(LAT_MAX),
+        );
+
+        let mut step = estimate_step(self.radius(), lat);
+        let cells = loop {
+            let cells = cells(lon, lon_delta, lat_range, step);
+            if cells.len() <= MAX_CELLS || step == 1 {
+                break cells;
+            }
+            step -= 1;
+        };
+
+        // 每个格子对应 52 位分数中前 2 * step 位相同的一段
+        let shift = 2 * (STEP - step);
+        let mut ranges: Vec<(u64, u64)> = cells
+            .into_iter()
+            .map(|bits| (bits << shift, (bits + 1) << shift))
+            .collect();
+        ranges.sort_unstable();
+        ranges.dedup();
+
+        // 合并相邻的范围
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (min, max) in ranges {
+            match merged.last_mut() {
+                Some(last) if last.1 >= min => last.1 = last.1.max(max),
+                _ => merged.push((min, max)),
+            }
+        }
+        merged
+    }
+
+    /// 能包住整个区域的圆的半径
+    fn radius(&self) -> f64 {
+        match *self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+        }
+    }
+
+    /// 区域在纬度和经度方向上距离中心最远的角度，经度方向覆盖了所有经度时返回 180
+    fn extent(&self, lat: f64) -> (f64, f64) {
+        let lat = lat.to_radians();
+        match *self {
+            Shape::Radius(radius) => {
+                let angle = radius / EARTH_RADIUS;
+                // 球冠在经度方向上最宽处的半角，包含了极点时覆盖所有经度
+                let lon_delta = if angle.sin() >= lat.cos() {
+                    180.0
+                } else {
+                    (angle.sin() / lat.cos()).asin().to_degrees()
+                };
+                (angle.to_degrees(), lon_delta)
+            }
+            Shape::Box { width, height } => {
+                let lat_delta = height / 2.0 / EARTH_RADIUS;
+                // 离极点最近的纬线上，同样的东西距离对应的经度差最大
+                let farthest = (lat.abs() + lat_delta).min(std::f64::consts::FRAC_PI_2);
+                let arg = (width / 4.0 / EARTH_RADIUS).sin() / farthest.cos();
+                let lon_delta = if arg >= 1.0 {
+                    180.0
+                } else {
+                    2.0 * arg.asin().to_degrees()
+                };
+                (lat_delta.to_degrees(), lon_delta)
+            }
+        }
+    }
+}
+
+/// 和 Redis 的 `geohashEstimateStepsByRadius` 一样，根据半径估计格子的大小
+fn estimate_step(radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP;
+    }
+    let mut range = radius;
+    let mut step: i32 = 1;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // 保证中心格子和周围的格子能够覆盖整个区域
+    step -= 2;
+
+    // 高纬度地区的格子在东西方向上更窄
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP as i32) as u32
+}
+
+/// 覆盖 `[lon - lon_delta, lon + lon_delta] x lat_range` 的所有 `step` 级格子
+fn cells(lon: f64, lon_delta: f64, lat_range: (f64, f64), step: u32) -> Vec<u64> {
+    let count = 1i64 << step;
+    let lat_index = |lat: f64| {
+        (((lat - LAT_MIN) / (LAT_MAX - LAT_MIN) * count as f64).floor() as i64).clamp(0, count - 1)
+    };
+    // 经度可以超出 ±180，取模之后回到另一侧
+    let lon_index =
+        |lon: f64| ((lon - LON_MIN) / (LON_MAX - LON_MIN) * count as f64).floor() as i64;
+
+    let lon_range = if lon_delta >= 180.0 {
+        0..=count - 1
+    } else {
+        let (min, max) = (lon_index(lon - lon_delta), lon_index(lon + lon_delta));
+        if max - min + 1 >= count {
+            0..=count - 1
+        } else {
+            min..=max
+        }
+    };
+
+    let mut cells = vec![];
+    for y in lat_index(lat_range.0)..=lat_index(lat_range.1) {
+        for x in lon_range.clone() {
+            cells.push(interleave(y as u32, x.rem_euclid(count) as u32));
+        }
+    }
+    cells
+}
+
+fn encode_in(lon: f64, lat: f64, (lat_min, lat_max): (f64, f64), step: u32) -> u64 {
+    let cells = (1u64 << step) as f64;
+    let lat_offset = (lat - lat_min) / (lat_max - lat_min) * cells;
+    let lon_offset = (lon - LON_MIN) / (LON_MAX - LON_MIN) * cells;
+    // 恰好在最大值上的坐标归到最后一个格子
+    let max = (1u64 << step) - 1;
+    interleave(
+        (lat_offset as u64).min(max) as u32,
+        (lon_offset as u64).min(max) as u32,
+    )
+}
+
+/// `x` 的各位放在偶数位，`y` 的各位放在奇数位
+fn interleave(x: u32, y: u32) -> u64 {
+    (0..32).fold(0, |bits, i| {
+        bits | ((x as u64 >> i) & 1) << (2 * i) | ((y as u64 >> i) & 1) << (2 * i + 1)
+    })
+}
+
+fn deinterleave(bits: u64) -> (u32, u32) {
+    (0..32).fold((0, 0), |(x, y), i| {
+        (
+            x | (((bits >> (2 * i)) & 1) as u32) << i,
+            y | (((bits >> (2 * i + 1)) & 1) as u32) << i,
+        )
+    })
+}
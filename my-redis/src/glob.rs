@@ -0,0 +1,70 @@
+//! Redis 风格的 glob 匹配，`CONFIG GET`、`KEYS`、`SCAN ... MATCH` 等命令使用。
+//!
+//! 支持 `*`、`?`、`[abc]`、`[^abc]`、`[a-z]` 以及 `\` 转义。
+
+/// `string` 是否匹配 `pattern`
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => string.is_empty(),
+        Some((b'*', rest)) => {
+            // 连续的 * 等价于一个
+            let rest = trim_stars(rest);
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=string.len()).any(|i| matches(rest, &string[i..]))
+        }
+        Some((b'?', rest)) => !string.is_empty() && matches(rest, &string[1..]),
+        Some((b'[', rest)) => match string.split_first() {
+            Some((&c, string)) => match match_class(rest, c) {
+                Some((true, rest)) => matches(rest, string),
+                _ => false,
+            },
+            None => false,
+        },
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            string.first() == Some(&rest[0]) && matches(&rest[1..], &string[1..])
+        }
+        Some((&p, rest)) => string.first() == Some(&p) && matches(rest, &string[1..]),
+    }
+}
+
+fn trim_stars(mut pattern: &[u8]) -> &[u8] {
+    while let Some((b'*', rest)) = pattern.split_first() {
+        pattern = rest;
+    }
+    pattern
+}
+
+/// 匹配 `[...]` 字符类，返回是否匹配以及 `]` 之后剩余的模式；没有 `]` 时返回 None
+fn match_class(mut pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        pattern = &pattern[1..];
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negate, rest)),
+            [b'\\', x, rest @ ..] => {
+                matched |= *x == c;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (lo, hi) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= lo <= c && c <= hi;
+                pattern = rest;
+            }
+            [x, rest @ ..] => {
+                matched |= *x == c;
+                pattern = rest;
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! HyperLogLog 基数估计，参数和 Redis 一样：2^14 个寄存器，标准误差约 0.81%。
+//!
+//! * 元素的哈希是种子为 `0xadc83b19` 的 MurmurHash64A，低 14 位选择寄存器，剩下的位中从低位开始
+//!   第一个 1 的位置(从 1 开始计数)就是写入寄存器的值，寄存器只保留最大值
+//! * 基数使用 Redis 5 之后的估计方法(Otmar Ertl, "New cardinality estimation algorithms for HyperLogLog sketches")，
+//!   只依赖寄存器值的直方图，小基数和大基数都不需要额外的修正
+//!
+//! 和 Redis 一样有两种编码：寄存器大多为 0 时使用稀疏编码，只保存非 0 的寄存器；
+//! 非 0 的寄存器太多或者出现了稀疏编码放不下的大值时转换成密集编码，每个寄存器一个字节。
+//! 转换只会从稀疏到密集，不会反过来
+
+use crate::murmur::murmur64a;
+
+/// 选择寄存器的位数
+const P: u32 = 14;
+
+/// 寄存器的个数
+const REGISTERS: usize = 1 << P;
+
+/// 参与计算寄存器值的位数
+const Q: u32 = 64 - P;
+
+const HASH_SEED: u64 = 0xadc83b19;
+
+/// 稀疏编码最多保存多少个非 0 的寄存器，和 Redis 默认的 `hll-sparse-max-bytes 3000` 是同一个数量级
+const SPARSE_MAX_REGISTERS: usize = 1024;
+
+/// 稀疏编码能保存的最大值，和 Redis 稀疏编码中 VAL 操作码的上限一样
+const SPARSE_MAX_VALUE: u8 = 32;
+
+/// `alpha_inf = 1 / (2 ln 2)`
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Encoding {
+    /// 按寄存器下标排序的 `(下标, 值)`，不包含值为 0 的寄存器
+    Sparse(Vec<(u16, u8)>),
+    Dense(Box<[u8; REGISTERS]>),
+}
+
+impl HyperLogLog {
+    /// 空的 HyperLogLog，使用稀疏编码
+    pub fn new() -> HyperLogLog {
+        HyperLogLog {
+            encoding: Encoding::Sparse(vec![]),
+        }
+    }
+
+    /// 加入一个元素，有寄存器被更新时返回 true
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let (index, count) = pattern(element);
+        self.set_max(index, count)
+    }
+
+    /// 合并另一个 HyperLogLog，每个寄存器取两者中较大的值
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        match &other.encoding {
+            Encoding::Sparse(registers) => {
+                for &(index, value) in registers {
+                    self.set_max(index as usize, value);
+                }
+            }
+            Encoding::Dense(registers) => {
+                for (index, &value) in registers.iter().enumerate() {
+                    if value > 0 {
+                        self.set_max(index, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 估计的基数
+    pub fn count(&self) -> u64 {
+        // histogram[v] 是值为 v 的寄存器个数
+        let mut histogram = [0u32; Q as usize + 2];
+        match &self.encoding {
+            Encoding::Sparse(registers) => {
+                histogram[0] = (REGISTERS - registers.len()) as u32;
+                for &(_, value) in registers {
+                    histogram[value as usize] += 1;
+                }
+            }
+            Encoding::Dense(registers) => {
+                for &value in registers.iter() {
+                    histogram[value as usize] += 1;
+                }
+            }
+        }
+
+        let m = REGISTERS as f64;
+        let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+        for &count in histogram[1..=Q as usize].iter().rev() {
+            z += count as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+
+        (ALPHA_INF * m * m / z).round() as u64
+    }
+
+    /// 当前的编码，PFDEBUG ENCODING 使用
+    pub fn encoding(&self) -> &'static str {
+        match self.encoding {
+            Encoding::Sparse(_) => "sparse",
+            Encoding::Dense(_) => "dense",
+        }
+    }
+
+    /// 寄存器的值小于 `value` 时更新它，更新了返回 true
+    fn set_max(&mut self, index: usize, value: u8) -> bool {
+        let registers = match &mut self.encoding {
+            Encoding::Dense(registers) => {
+                if registers[index] >= value {
+                    return false;
+                }
+                registers[index] = value;
+                return true;
+            }
+            Encoding::Sparse(registers) => registers,
+        };
+
+        match registers.binary_search_by_key(&(index as u16), |&(i, _)| i) {
+            Ok(pos) if registers[pos].1 >= value => return false,
+            Ok(pos) if value <= SPARSE_MAX_VALUE => {
+                registers[pos].1 = value;
+                return true;
+            }
+            Err(pos) if value <= SPARSE_MAX_VALUE && registers.len() < SPARSE_MAX_REGISTERS => {
+                registers.insert(pos, (index as u16, value));
+                return true;
+            }
+            _ => {}
+        }
+
+        // 稀疏编码放不下了
+        self.make_dense();
+        self.set_max(index, value)
+    }
+
+    fn make_dense(&mut self) {
+        if let Encoding::Sparse(sparse) = &self.encoding {
+            let mut registers = Box::new([0u8; REGISTERS]);
+            for &(index, value) in sparse {
+                registers[index as usize] = value;
+            }
+            self.encoding = Encoding::Dense(registers);
+        }
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> HyperLogLog {
+        HyperLogLog::new()
+    }
+}
+
+/// 元素对应的寄存器下标以及写入的值
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmur64a(element, HASH_SEED);
+    let index = (hash as usize) & (REGISTERS - 1);
+    // 最高位之上补一个 1，保证全 0 时得到的值是 Q + 1
+    let hash = (hash >> P) | (1 << Q);
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! ketama 一致性哈希，`my-redis-proxy` 用它把 key 分配到后端服务器。
+//!
+//! 算法和 libketama / twemproxy 的 `distribution: ketama` 一样：每个服务器按照权重在一个 32 位的环上放置
+//! 约 160 个点，点的位置是 `"名称-i"` 的 MD5 摘要，每个摘要切成 4 个点。key 的 MD5 摘要的前 4 个字节
+//! 落在环上，顺时针找到的第一个点属于哪个服务器，key 就分配给哪个服务器。
+//! 增加或者删除一个服务器时，只有落在它附近的 key 会改变位置。
+//!
+//! 和 twemproxy 的 `hash_tag: "{}"` 一样，key 中包含 `{...}` 时只对括号中的内容求哈希，
+//! 这样可以让多个 key 落在同一个服务器上。
+
+/// 每个摘要切出的点数
+const POINTS_PER_HASH: usize = 4;
+
+/// 权重相同时每个服务器的点数
+const POINTS_PER_SERVER: usize = 160;
+
+/// 哈希环，只保存服务器的下标，服务器本身由调用方管理
+#[derive(Debug, Clone)]
+pub struct Ketama {
+    /// 按位置排序的 `(位置, 服务器下标)`
+    points: Vec<(u32, usize)>,
+}
+
+impl Ketama {
+    /// `servers` 是 `(名称, 权重)`，名称通常是 `host:port`。更换服务器的地址时保持名称不变，key 的分布就不会变化
+    pub fn new<S: AsRef<str>>(servers: &[(S, u32)]) -> Ketama {
+        let total_weight: u64 = servers.iter().map(|(_, weight)| *weight as u64).sum();
+        let mut points = Vec::with_capacity(servers.len() * POINTS_PER_SERVER);
+
+        for (index, (name, weight)) in servers.iter().enumerate() {
+            if *weight == 0 {
+                continue;
+            }
+            let share = *weight as f64 / total_weight as f64;
+            let hashes =
+                (share * (POINTS_PER_SERVER / POINTS_PER_HASH * servers.len()) as f64) as usize;
+
+            // 权重很小时也至少放一组点
+            for i in 0..hashes.max(1) {
+                let digest = md5::compute(format!("{}-{}", name.as_ref(), i));
+                for chunk in digest.0.chunks(4) {
+                    let point = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    points.push((point, index));
+                }
+            }
+        }
+
+        points.sort_unstable();
+        Ketama { points }
+    }
+
+    /// key 所在的服务器下标，环是空的(没有服务器或者权重都是 0)时返回 None
+    pub fn get(&self, key: &[u8]) -> Option<usize> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let hash = hash(hash_tag(key));
+        // 第一个位置不小于 hash 的点，超过最后一个点时回到环的起点
+        let i = self.points.partition_point(|&(point, _)| point < hash);
+        Some(self.points[i % self.points.len()].1)
+    }
+}
+
+/// key 中第一个 `{` 和它后面第一个 `}` 之间的内容不为空时，只使用这部分内容
+fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(start) = key.iter().position(|&b| b == b'{') {
+        if let Some(len) = key[start + 1..].iter().position(|&b| b == b'}') {
+            if len > 0 {
+                return &key[start + 1..start + 1 + len];
+            }
+        }
+    }
+    key
+}
+
+fn hash(key: &[u8]) -> u32 {
+    let digest = md5::compute(key);
+    u32::from_le_bytes([digest.0[0], digest.0[1], digest.0[2], digest.0[3]])
+}
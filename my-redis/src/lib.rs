@@ -0,0 +1,73 @@
+//! my-redis 服务端的核心实现，`src/bin/server.rs` 只负责监听端口。
+//!
+//! 帧和连接的实现参考了 `examples/mini_redis_frame.rs`，命令的组织方式参考了 mini-redis。
+//! `client` 模块是配套的异步客户端，`pool` 是它的连接池，`blocking_client` 是给同步代码使用的包装，
+//! 它们都可以通过 `tls` 模块使用 TLS 连接。
+//! `proxy` 是 `my-redis-proxy` 的实现，通过 `ketama` 一致性哈希把命令分发到多个服务端。
+//! `testing` 在测试中启动进程内的服务端，可以通过内存中的流连接，只在启用 `testing` feature 时编译。
+
+pub mod blocking_client;
+
+pub mod bloom;
+
+pub mod cli;
+
+pub mod client;
+
+pub mod clients;
+
+pub mod cmd;
+pub use cmd::Command;
+
+pub mod codec;
+
+pub mod config;
+pub use config::Config;
+
+mod connection;
+pub use connection::Connection;
+
+pub mod db;
+pub use db::Db;
+
+pub mod frame;
+pub use frame::Frame;
+
+pub mod geohash;
+
+mod glob;
+
+pub mod hyperloglog;
+
+pub mod ketama;
+
+pub mod monitor;
+
+mod murmur;
+
+mod parse;
+use parse::Parse;
+
+pub mod pool;
+
+pub mod proxy;
+
+pub mod scan_set;
+
+pub mod script;
+
+pub mod server;
+
+pub mod slowlog;
+
+pub mod sorted_set;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub mod tls;
+
+/// 大多数函数返回的错误类型，和 mini-redis 一样使用 `Box<dyn Error>`
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T> = std::result::Result<T, Error>;
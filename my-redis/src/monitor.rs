@@ -0,0 +1,46 @@
+//! MONITOR 输出的格式，例如 `1339518083.107412 [0 127.0.0.1:60866] "set" "foo" "bar"`
+
+use bytes::Bytes;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 格式化一条 MONITOR 记录，`client` 是客户端地址，脚本中执行的命令为 `lua`
+pub fn format(db: usize, client: &str, args: &[Bytes]) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut line = format!(
+        "{}.{:06} [{} {}]",
+        now.as_secs(),
+        now.subsec_micros(),
+        db,
+        client
+    );
+    for arg in args {
+        line.push(' ');
+        repr(&mut line, arg);
+    }
+    line
+}
+
+/// 和 Redis 的 sdscatrepr 一样，用双引号包裹参数并转义不可打印的字符，`my-redis-cli` 打印字符串时也用它
+pub fn repr(out: &mut String, arg: &[u8]) {
+    out.push('"');
+    for &b in arg {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => out.push(b as char),
+            b => {
+                let _ = write!(out, "\\x{:02x}", b);
+            }
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,34 @@
+//! MurmurHash64A，Redis 的 HyperLogLog 和 RedisBloom 的 Bloom 过滤器都使用这个哈希函数。
+//! 使用同样的哈希和种子，相同的元素在 my-redis 和 Redis 中落在同一个寄存器上，PFCOUNT 的结果也相同
+
+const M: u64 = 0xc6a4a7935bd1e995;
+const R: u32 = 47;
+
+/// 按小端序读取 8 字节的分组，和 Redis 在小端机器上的结果一致
+pub(crate) fn murmur64a(key: &[u8], seed: u64) -> u64 {
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let chunks = key.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
@@ -0,0 +1,140 @@
+use crate::Frame;
+
+use bytes::Bytes;
+use std::{fmt, str, vec};
+
+/// 用于解析命令的工具：命令是一个数组帧，`Parse` 依次取出数组中的每一项
+#[derive(Debug)]
+pub struct Parse {
+    parts: vec::IntoIter<Frame>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// 帧已经被完全消费，取不到更多参数
+    EndOfStream,
+
+    Other(crate::Error),
+}
+
+impl Parse {
+    /// 创建一个 `Parse`，只接受数组帧
+    pub fn new(frame: Frame) -> Result<Parse, ParseError> {
+        let array = match frame {
+            Frame::Array(array) => array,
+            frame => return Err(format!("protocol error; expected array, got {:?}", frame).into()),
+        };
+
+        Ok(Parse {
+            parts: array.into_iter(),
+        })
+    }
+
+    /// 剩余的参数个数
+    pub fn remaining(&self) -> usize {
+        self.parts.len()
+    }
+
+    fn next(&mut self) -> Result<Frame, ParseError> {
+        self.parts.next().ok_or(ParseError::EndOfStream)
+    }
+
+    /// 以字符串的形式返回下一项
+    pub fn next_string(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Frame::Simple(s) => Ok(s),
+            Frame::Bulk(data) => str::from_utf8(&data[..])
+                .map(|s| s.to_string())
+                .map_err(|_| "protocol error; invalid string".into()),
+            frame => Err(format!(
+                "protocol error; expected simple frame or bulk frame, got {:?}",
+                frame
+            )
+            .into()),
+        }
+    }
+
+    /// 以原始字节的形式返回下一项
+    pub fn next_bytes(&mut self) -> Result<Bytes, ParseError> {
+        match self.next()? {
+            Frame::Simple(s) => Ok(Bytes::from(s.into_bytes())),
+            Frame::Bulk(data) => Ok(data),
+            frame => Err(format!(
+                "protocol error; expected simple frame or bulk frame, got {:?}",
+                frame
+            )
+            .into()),
+        }
+    }
+
+    /// 以整数的形式返回下一项，不是整数时返回 Redis 风格的错误信息
+    pub fn next_int(&mut self) -> Result<i64, ParseError> {
+        const MSG: &str = "value is not an integer or out of range";
+
+        match self.next()? {
+            Frame::Integer(v) => Ok(v),
+            Frame::Simple(data) => parse_i64(data.as_bytes()).ok_or_else(|| MSG.into()),
+            Frame::Bulk(data) => parse_i64(&data).ok_or_else(|| MSG.into()),
+            frame => Err(format!("protocol error; expected int frame but got {:?}", frame).into()),
+        }
+    }
+
+    /// 以浮点数的形式返回下一项，NaN 也视为无效
+    pub fn next_f64(&mut self) -> Result<f64, ParseError> {
+        const MSG: &str = "value is not a valid float";
+
+        let value = match self.next()? {
+            Frame::Integer(v) => return Ok(v as f64),
+            Frame::Simple(data) => data.parse::<f64>().ok(),
+            Frame::Bulk(data) => str::from_utf8(&data[..])
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok()),
+            frame => {
+                return Err(
+                    format!("protocol error; expected float frame but got {:?}", frame).into(),
+                )
+            }
+        };
+        value.filter(|v| !v.is_nan()).ok_or_else(|| MSG.into())
+    }
+
+    /// 确认所有参数都已经被消费
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        if self.parts.next().is_none() {
+            Ok(())
+        } else {
+            Err("protocol error; expected end of frame, but there was more".into())
+        }
+    }
+}
+
+/// 按照 Redis 的 `string2ll` 解析整数：`str::parse` 接受的前导 `+` 在 Redis 中不是合法的整数
+pub(crate) fn parse_i64(value: &[u8]) -> Option<i64> {
+    if value.first() == Some(&b'+') {
+        return None;
+    }
+    str::from_utf8(value).ok()?.parse().ok()
+}
+
+impl From<String> for ParseError {
+    fn from(src: String) -> ParseError {
+        ParseError::Other(src.into())
+    }
+}
+
+impl From<&str> for ParseError {
+    fn from(src: &str) -> ParseError {
+        src.to_string().into()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::EndOfStream => "protocol error; unexpected end of stream".fmt(f),
+            ParseError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
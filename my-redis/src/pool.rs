@@ -0,0 +1,268 @@
+//! 客户端连接池。
+//!
+//! `Client` 会把多个任务的请求 pipeline 到同一个连接上，阻塞命令和事务这类需要独占连接的场景不能共享它。
+//! 连接池中的每个连接都是一个通过 `client::connect` 建立的独立 `Client`，`get()` 取出之后由调用方独占，
+//! `PooledConnection` 被 drop 时自动放回池中。
+//!
+//! * 连接的总数不超过 `max_size`，池子耗尽时 `get()` 会等待，超过 `checkout_timeout` 返回错误
+//! * 取出连接时先 `PING` 一次，不健康的连接直接丢弃
+//! * 放回时连接选择的数据库不是 0 的话直接关闭，不会把 `SELECT` 的状态带给下一个调用方
+//! * 空闲超过 `idle_timeout` 的连接会被后台任务关闭，但至少保留 `min_size` 个
+//!
+//! 取出的连接只能通过 `Deref` 使用命令方法，不能复制出脱离连接池的 `Client`：
+//!
+//! ```compile_fail
+//! # async fn example(pool: my_redis::pool::Pool) -> my_redis::Result<()> {
+//! let conn = pool.get().await?;
+//! let escaped: my_redis::client::Client = (*conn).clone();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{self, Client, Commands};
+use crate::tls::ClientTls;
+
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{self, Instant};
+
+/// 健康检查 `PING` 的超时时间
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// 连接池的配置
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// 创建连接池时建立、之后空闲清理也会保留的连接数
+    pub min_size: usize,
+    /// 最多同时存在的连接数
+    pub max_size: usize,
+    /// 空闲多久的连接会被关闭
+    pub idle_timeout: Duration,
+    /// `get()` 最多等待多久
+    pub checkout_timeout: Duration,
+    /// 设置之后所有连接都使用 TLS
+    pub tls: Option<ClientTls>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            min_size: 1,
+            max_size: 16,
+            idle_timeout: Duration::from_secs(300),
+            checkout_timeout: Duration::from_secs(5),
+            tls: None,
+        }
+    }
+}
+
+/// 连接池的句柄，`Clone` 只是增加引用计数
+#[derive(Debug, Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    addr: String,
+    config: PoolConfig,
+    /// 空闲的连接，最近放回的在队尾。取的时候从队尾取，这样很少使用的连接会留在队首被清理掉
+    idle: Mutex<VecDeque<Idle>>,
+    /// 每个被取出的连接持有一个许可，许可的总数是 `max_size`
+    permits: Arc<Semaphore>,
+}
+
+#[derive(Debug)]
+struct Idle {
+    client: Client,
+    since: Instant,
+}
+
+/// 从连接池中取出的连接，通过 `Deref` 使用 `Commands` 上的命令方法，drop 时放回池中。
+///
+/// 不提供 `Client` 本身：`Client` 可以 `Clone`，复制出来的句柄会在连接放回池中之后继续使用它，
+/// 和下一个取出连接的调用方共享同一个连接。确实需要 `Client` 时用 `detach` 把连接从池中拿走
+#[derive(Debug)]
+pub struct PooledConnection {
+    client: Option<Client>,
+    pool: Pool,
+    /// 放回池中之后才释放许可，这样等待的 `get()` 一定能取到刚放回的连接
+    _permit: OwnedSemaphorePermit,
+}
+
+/// 建立连接池，并预先建立 `min_size` 个连接
+pub async fn connect(addr: impl ToString, config: PoolConfig) -> crate::Result<Pool> {
+    if config.max_size == 0 || config.min_size > config.max_size {
+        return Err("pool size must satisfy 0 < max_size and min_size <= max_size".into());
+    }
+
+    let addr = addr.to_string();
+    let mut idle = VecDeque::with_capacity(config.max_size);
+    for _ in 0..config.min_size {
+        idle.push_back(Idle {
+            client: open(&addr, &config).await?,
+            since: Instant::now(),
+        });
+    }
+
+    let shared = Arc::new(Shared {
+        addr,
+        permits: Arc::new(Semaphore::new(config.max_size)),
+        idle: Mutex::new(idle),
+        config,
+    });
+    tokio::spawn(evict_idle(Arc::downgrade(&shared)));
+
+    Ok(Pool { shared })
+}
+
+impl Pool {
+    /// 取出一个连接，池子耗尽时等待其它连接被放回。
+    ///
+    /// 等待许可、健康检查和新建连接都计算在 `checkout_timeout` 之内
+    pub async fn get(&self) -> crate::Result<PooledConnection> {
+        let timeout = self.shared.config.checkout_timeout;
+        time::timeout(timeout, self.checkout()).await.map_err(|_| {
+            format!(
+                "timed out waiting for a pooled connection after {:?}",
+                timeout
+            )
+        })?
+    }
+
+    async fn checkout(&self) -> crate::Result<PooledConnection> {
+        let permit = self
+            .shared
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| "pool has been closed")?;
+
+        // 从空闲连接中找一个健康的，都不健康时新建一个
+        while let Some(client) = self.pop_idle() {
+            if client
+                .with_timeout(HEALTH_CHECK_TIMEOUT)
+                .ping(None)
+                .await
+                .is_ok()
+            {
+                return Ok(self.wrap(client, permit));
+            }
+        }
+
+        let client = open(&self.shared.addr, &self.shared.config).await?;
+        Ok(self.wrap(client, permit))
+    }
+
+    /// 当前空闲的连接数
+    pub fn idle(&self) -> usize {
+        self.shared.idle.lock().unwrap().len()
+    }
+
+    /// 当前被取出使用的连接数
+    pub fn in_use(&self) -> usize {
+        self.shared.config.max_size - self.shared.permits.available_permits()
+    }
+
+    fn pop_idle(&self) -> Option<Client> {
+        self.shared
+            .idle
+            .lock()
+            .unwrap()
+            .pop_back()
+            .map(|idle| idle.client)
+    }
+
+    fn wrap(&self, client: Client, permit: OwnedSemaphorePermit) -> PooledConnection {
+        PooledConnection {
+            client: Some(client),
+            pool: self.clone(),
+            _permit: permit,
+        }
+    }
+}
+
+impl PooledConnection {
+    /// 修改这个连接上请求的超时时间，放回池中时恢复默认值
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.client_mut().set_timeout(timeout);
+    }
+
+    /// 不再放回池中，例如事务执行到一半出错、连接的状态已经不确定时
+    pub fn detach(mut self) -> Client {
+        self.client.take().expect("client is only taken on drop")
+    }
+
+    fn client_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("client is only taken on drop")
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Commands;
+
+    fn deref(&self) -> &Commands {
+        self.client.as_ref().expect("client is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(mut client) = self.client.take() {
+            // 调用方 SELECT 了其它数据库，放回池中的话下一个调用方会在这个数据库上执行命令。
+            // drop 中不能等待 `SELECT 0` 的回复，直接关闭这个连接
+            if client.selected_db() != 0 {
+                return;
+            }
+            client.set_timeout(client::DEFAULT_TIMEOUT);
+            self.pool.shared.idle.lock().unwrap().push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+async fn open(addr: &str, config: &PoolConfig) -> crate::Result<Client> {
+    match &config.tls {
+        Some(tls) => client::connect_tls(addr, tls.clone()).await,
+        None => client::connect(addr).await,
+    }
+}
+
+/// 后台任务：定期关闭空闲太久的连接，连接池被 drop 之后退出
+async fn evict_idle(shared: Weak<Shared>) {
+    let period = match shared.upgrade() {
+        Some(shared) => shared.config.idle_timeout / 2,
+        None => return,
+    };
+    let mut interval = time::interval(period.max(Duration::from_millis(10)));
+
+    loop {
+        interval.tick().await;
+
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+
+        let in_use = shared.config.max_size - shared.permits.available_permits();
+        let mut idle = shared.idle.lock().unwrap();
+        let now = Instant::now();
+
+        // 最久没用的在队首
+        while idle.len() + in_use > shared.config.min_size {
+            match idle.front() {
+                Some(oldest) if now - oldest.since >= shared.config.idle_timeout => {
+                    // drop `Client` 之后它的管理任务会关闭连接
+                    idle.pop_front();
+                }
+                _ => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! `my-redis-proxy` 的实现：和 twemproxy 一样，客户端把代理当作一个普通的 my-redis 服务端使用，
+//! 代理按照 key 把命令分发到多个后端服务器，客户端不需要知道数据是怎么分片的。
+//!
+//! * 命令用 `Connection` 读取，再用 `Command::from_frame` 校验参数，参数错误时直接由代理回复
+//! * 单个 key 的命令通过 `ketama` 一致性哈希选择后端，原样转发
+//! * `MGET`、`DEL`、`EXISTS`、`MSET` 按后端拆分成多个命令并发执行，再把回复合并成一个
+//! * `SINTER`、`PFMERGE`、`MSETNX`、`EVAL` 等涉及多个 key 的命令要求所有 key 落在同一个后端(可以使用 `{hash tag}`)，
+//!   否则回复 `CROSSSLOT` 错误
+//! * `SELECT`、`SUBSCRIBE`、`MONITOR` 等和连接状态相关或者没有 key 的命令不支持
+//!
+//! 每个后端预先建立 `server_connections` 个 `Client`，所有客户端连接共享它们，多个请求在同一个后端连接上
+//! pipeline 执行。一个客户端连接总是使用每个后端的同一个 `Client`，`Client` 按照 `send` 的顺序执行命令，
+//! 所以同一个客户端发往同一个 key 的命令不会乱序。
+
+use crate::client::{self, Client, PendingReply};
+use crate::ketama::Ketama;
+use crate::{Command, Connection, Frame};
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+/// 一个客户端连接最多有多少个还没有回复的命令，超过之后暂停读取，直到前面的命令有了回复
+const MAX_PENDING: usize = 1024;
+
+/// 只有一个 key 并且 key 是第一个参数的命令，原样转发
+const SINGLE_KEY: &[&str] = &[
+    "get",
+    "set",
+    "setnx",
+    "getset",
+    "incr",
+    "decr",
+    "incrby",
+    "decrby",
+    "incrbyfloat",
+    "append",
+    "strlen",
+    "getrange",
+    "setrange",
+    "expire",
+    "pexpire",
+    "expireat",
+    "pexpireat",
+    "ttl",
+    "pttl",
+    "persist",
+    "type",
+    "sadd",
+    "srem",
+    "smembers",
+    "scard",
+    "sismember",
+    "smismember",
+    "spop",
+    "srandmember",
+    "sscan",
+    "lpush",
+    "rpush",
+    "lpop",
+    "rpop",
+    "llen",
+    "lrange",
+    "pfadd",
+    "bf.reserve",
+    "bf.add",
+    "bf.exists",
+    "geoadd",
+    "geopos",
+    "geodist",
+    "geohash",
+    "geosearch",
+    // 服务端没有 ZADD，有序集合只能由 GEOADD 写入。和 Redis 一样用 ZREM / ZCARD 删除、统计地理位置的成员
+    "zrem",
+    "zcard",
+];
+
+/// 一个后端服务器
+#[derive(Debug, Clone)]
+pub struct Backend {
+    /// `host:port`，同时也是它在哈希环上的名称
+    pub addr: String,
+    /// 权重越大分到的 key 越多
+    pub weight: u32,
+}
+
+/// 代理的配置
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub backends: Vec<Backend>,
+    /// 每个后端建立的连接数，对应 twemproxy 的 `server_connections`
+    pub server_connections: usize,
+    /// 转发的命令等待后端回复的超时时间
+    pub timeout: Duration,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> ProxyConfig {
+        ProxyConfig {
+            backends: vec![],
+            server_connections: 1,
+            timeout: client::DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// 代理的句柄，`Clone` 只是增加引用计数
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    ring: Ketama,
+    backends: Vec<BackendConnections>,
+    /// 用于给客户端连接分配后端连接
+    sessions: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct BackendConnections {
+    addr: String,
+    clients: Vec<Client>,
+}
+
+/// 一个命令的回复，可能要等待一个或者多个后端
+type Reply = Pin<Box<dyn Future<Output = Frame> + Send>>;
+
+/// 把命令交给后端的过程，见 `Proxy::dispatch`
+type Dispatch<'a> = Pin<Box<dyn Future<Output = Reply> + Send + 'a>>;
+
+/// 连接所有的后端，任意一个连接失败时返回错误。之后的断线由 `Client` 负责重连
+pub async fn connect(config: ProxyConfig) -> crate::Result<Proxy> {
+    if config.backends.is_empty() || config.server_connections == 0 {
+        return Err("proxy requires at least one backend and one connection per backend".into());
+    }
+
+    let mut backends = Vec::with_capacity(config.backends.len());
+    for backend in &config.backends {
+        let mut clients = Vec::with_capacity(config.server_connections);
+        for _ in 0..config.server_connections {
+            let client = client::connect(&backend.addr)
+                .await
+                .map_err(|err| format!("failed to connect to {}: {}", backend.addr, err))?;
+            clients.push(client.with_timeout(config.timeout));
+        }
+        backends.push(BackendConnections {
+            addr: backend.addr.clone(),
+            clients,
+        });
+    }
+
+    let servers: Vec<_> = config
+        .backends
+        .iter()
+        .map(|backend| (backend.addr.as_str(), backend.weight))
+        .collect();
+
+    Ok(Proxy {
+        shared: Arc::new(Shared {
+            ring: Ketama::new(&servers),
+            backends,
+            sessions: AtomicUsize::new(0),
+        }),
+    })
+}
+
+impl Proxy {
+    /// 接受客户端连接，每个连接一个任务
+    pub async fn run(&self, listener: TcpListener) -> crate::Result<()> {
+        loop {
+            let (socket, addr) = listener.accept().await?;
+            // 和服务端一样，设置失败只影响这一个连接，不能让 accept 循环退出
+            if let Err(err) = socket.set_nodelay(true) {
+                eprintln!("failed to set TCP_NODELAY for {}: {}", addr, err);
+                continue;
+            }
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = proxy.process(socket).await {
+                    eprintln!("connection {} error: {}", addr, err);
+                }
+            });
+        }
+    }
+
+    /// 处理一个客户端连接。
+    ///
+    /// 读取命令和写回回复同时进行：命令按顺序交给后端之后，回复放进队列，按照命令的顺序写回。
+    /// 客户端 pipeline 发送的多个命令因此也可以在后端 pipeline 执行。
+    ///
+    /// 后端 `Client` 的请求队列满了的时候，交给后端这一步需要等待。等待期间不再读取新的命令(保证顺序)，
+    /// 但是已经就绪的回复照常写回，不会被一个繁忙的后端挡住
+    async fn process(&self, socket: TcpStream) -> crate::Result<()> {
+        let mut connection = Connection::new(socket);
+        let session = self.shared.sessions.fetch_add(1, Ordering::Relaxed);
+        let mut pending: VecDeque<Reply> = VecDeque::new();
+        // 正在交给后端的命令，完成之后得到的回复放进 `pending`
+        let mut dispatching: Option<Dispatch<'_>> = None;
+        // 写入了回复但是还没有 flush
+        let mut unflushed = false;
+
+        loop {
+            tokio::select! {
+                // 先写回已经就绪的回复，都没有就绪时再 flush，这样多个回复只需要一次系统调用
+                biased;
+                frame = async { pending.front_mut().unwrap().await }, if !pending.is_empty() => {
+                    pending.pop_front();
+                    connection.buffer_frame(&frame).await?;
+                    unflushed = true;
+                }
+                _ = std::future::ready(()), if unflushed => {
+                    connection.flush().await?;
+                    unflushed = false;
+                }
+                // 分支被禁用时表达式仍然会被求值，所以 unwrap 放在 poll 里面
+                reply = std::future::poll_fn(|cx| dispatching.as_mut().unwrap().as_mut().poll(cx)),
+                    if dispatching.is_some() =>
+                {
+                    dispatching = None;
+                    pending.push_back(reply);
+                }
+                // read_frame 是取消安全的：已读到的数据保存在连接的缓冲区中
+                frame = connection.read_frame(), if dispatching.is_none() && pending.len() < MAX_PENDING => {
+                    let frame = match frame? {
+                        Some(frame) => frame,
+                        None => return Ok(()),
+                    };
+                    let args = match command_args(frame.clone()) {
+                        Ok(args) => args,
+                        Err(err) => {
+                            pending.push_back(ready(Frame::Error(err)));
+                            continue;
+                        }
+                    };
+
+                    if args[0].eq_ignore_ascii_case(b"quit") {
+                        // 前面的命令仍然要等到回复
+                        while let Some(reply) = pending.pop_front() {
+                            connection.buffer_frame(&reply.await).await?;
+                        }
+                        connection.write_frame(&Frame::Simple("OK".to_string())).await?;
+                        return Ok(());
+                    }
+
+                    match Command::from_frame(frame) {
+                        Ok(Command::Unknown(cmd)) => pending.push_back(ready(cmd.execute())),
+                        Ok(_) => {
+                            let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+                            dispatching = Some(Box::pin(self.dispatch(name, args, session)));
+                        }
+                        Err(err) => pending.push_back(ready(Frame::Error(err.to_string()))),
+                    }
+                }
+            }
+        }
+    }
+
+    /// 把命令交给后端，返回等待回复的 future。命令在返回之前就已经按顺序交给了后端的 `Client`
+    async fn dispatch(&self, name: String, args: Vec<Bytes>, session: usize) -> Reply {
+        let name = name.as_str();
+        match name {
+            _ if SINGLE_KEY.contains(&name) => {
+                let backend = self.backend(&args[1]);
+                self.forward(backend, args, session).await
+            }
+            "ping" => ready(match args.get(1) {
+                Some(msg) => Frame::Bulk(msg.clone()),
+                None => Frame::Simple("PONG".to_string()),
+            }),
+            "mget" => self.mget(args, session).await,
+            "del" | "exists" => self.sum(args, session).await,
+            "mset" => self.mset(args, session).await,
+            "msetnx" => {
+                let keys: Vec<_> = args[1..].iter().step_by(2).cloned().collect();
+                self.forward_same_backend(keys, args, session).await
+            }
+            "sinter" | "sunion" | "sdiff" | "sinterstore" | "sunionstore" | "sdiffstore"
+            | "pfcount" | "pfmerge" => {
+                let keys = args[1..].to_vec();
+                self.forward_same_backend(keys, args, session).await
+            }
+            "eval" | "evalsha" => {
+                // 参数已经校验过，numkeys 一定是合法的
+                let numkeys: usize = std::str::from_utf8(&args[2])
+                    .ok()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(0);
+                if numkeys == 0 {
+                    return ready(Frame::Error(format!(
+                        "ERR {} without keys can't be routed by my-redis-proxy",
+                        name.to_uppercase()
+                    )));
+                }
+                let keys = args[3..3 + numkeys].to_vec();
+                self.forward_same_backend(keys, args, session).await
+            }
+            _ => ready(Frame::Error(format!(
+                "ERR command '{}' is not supported by my-redis-proxy",
+                name
+            ))),
+        }
+    }
+
+    fn backend(&self, key: &[u8]) -> usize {
+        // `connect` 保证至少有一个后端
+        self.shared.ring.get(key).unwrap_or(0)
+    }
+
+    /// 把 key 按后端分组，返回每个后端以及分给它的 key 在 `keys` 中的位置
+    fn group<'a>(&self, keys: impl Iterator<Item = &'a Bytes>) -> BTreeMap<usize, Vec<usize>> {
+        let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, key) in keys.enumerate() {
+            groups.entry(self.backend(key)).or_default().push(i);
+        }
+        groups
+    }
+
+    async fn send(
+        &self,
+        backend: usize,
+        args: Vec<Bytes>,
+        session: usize,
+    ) -> Result<Pending, Frame> {
+        let backend = &self.shared.backends[backend];
+        let client = &backend.clients[session % backend.clients.len()];
+        match client.send(args).await {
+            Ok(reply) => Ok(Pending {
+                addr: backend.addr.clone(),
+                reply,
+            }),
+            Err(err) => Err(backend_error(&backend.addr, err)),
+        }
+    }
+
+    async fn forward(&self, backend: usize, args: Vec<Bytes>, session: usize) -> Reply {
+        match self.send(backend, args, session).await {
+            Ok(pending) => Box::pin(pending.recv()),
+            Err(err) => ready(err),
+        }
+    }
+
+    /// 所有 key 都在同一个后端时原样转发
+    async fn forward_same_backend(
+        &self,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+        session: usize,
+    ) -> Reply {
+        let groups = self.group(keys.iter());
+        if groups.len() > 1 {
+            return ready(Frame::Error(
+                "CROSSSLOT Keys in request don't hash to the same backend".to_string(),
+            ));
+        }
+        let backend = self.backend(&keys[0]);
+        self.forward(backend, args, session).await
+    }
+
+    /// MGET 拆分到各个后端，回复按照 key 原来的顺序合并
+    async fn mget(&self, args: Vec<Bytes>, session: usize) -> Reply {
+        let keys = &args[1..];
+        let mut parts = vec![];
+        for (backend, positions) in self.group(keys.iter()) {
+            let mut argv = vec![args[0].clone()];
+            argv.extend(positions.iter().map(|&i| keys[i].clone()));
+            match self.send(backend, argv, session).await {
+                Ok(pending) => parts.push((pending, positions)),
+                Err(err) => return ready(err),
+            }
+        }
+
+        let len = keys.len();
+        Box::pin(async move {
+            let mut values = vec![Frame::Null; len];
+            for (pending, positions) in parts {
+                match pending.recv().await {
+                    Frame::Array(items) if items.len() == positions.len() => {
+                        for (i, item) in positions.into_iter().zip(items) {
+                            values[i] = item;
+                        }
+                    }
+                    frame @ Frame::Error(_) => return frame,
+                    frame => return unexpected(frame),
+                }
+            }
+            Frame::Array(values)
+        })
+    }
+
+    /// DEL / EXISTS 拆分到各个后端，回复的整数相加
+    async fn sum(&self, args: Vec<Bytes>, session: usize) -> Reply {
+        let keys = &args[1..];
+        let mut parts = vec![];
+        for (backend, positions) in self.group(keys.iter()) {
+            let mut argv = vec![args[0].clone()];
+            argv.extend(positions.iter().map(|&i| keys[i].clone()));
+            match self.send(backend, argv, session).await {
+                Ok(pending) => parts.push(pending),
+                Err(err) => return ready(err),
+            }
+        }
+
+        Box::pin(async move {
+            let mut total = 0;
+            for pending in parts {
+                match pending.recv().await {
+                    Frame::Integer(n) => total += n,
+                    frame @ Frame::Error(_) => return frame,
+                    frame => return unexpected(frame),
+                }
+            }
+            Frame::Integer(total)
+        })
+    }
+
+    /// MSET 拆分到各个后端，都成功时回复 OK。和 twemproxy 一样，不同后端之间不是原子的
+    async fn mset(&self, args: Vec<Bytes>, session: usize) -> Reply {
+        let pairs: Vec<_> = args[1..].chunks(2).collect();
+        let mut parts = vec![];
+        for (backend, positions) in self.group(pairs.iter().map(|pair| &pair[0])) {
+            let mut argv = vec![args[0].clone()];
+            argv.extend(positions.iter().flat_map(|&i| pairs[i].iter().cloned()));
+            match self.send(backend, argv, session).await {
+                Ok(pending) => parts.push(pending),
+                Err(err) => return ready(err),
+            }
+        }
+
+        Box::pin(async move {
+            for pending in parts {
+                match pending.recv().await {
+                    Frame::Simple(_) => {}
+                    frame @ Frame::Error(_) => return frame,
+                    frame => return unexpected(frame),
+                }
+            }
+            Frame::Simple("OK".to_string())
+        })
+    }
+}
+
+/// 发给某个后端、还在等待回复的命令
+struct Pending {
+    addr: String,
+    reply: PendingReply,
+}
+
+impl Pending {
+    /// 后端的错误回复原样返回，超时、断线等错误转换成错误回复
+    async fn recv(self) -> Frame {
+        match self.reply.recv().await {
+            Ok(frame) => frame,
+            Err(err) => backend_error(&self.addr, err),
+        }
+    }
+}
+
+fn ready(frame: Frame) -> Reply {
+    Box::pin(std::future::ready(frame))
+}
+
+fn backend_error(addr: &str, err: crate::Error) -> Frame {
+    Frame::Error(format!("ERR backend {}: {}", addr, err))
+}
+
+fn unexpected(frame: Frame) -> Frame {
+    Frame::Error(format!("ERR unexpected reply from backend: {}", frame))
+}
+
+/// 取出命令的参数，命令必须是非空的数组帧
+fn command_args(frame: Frame) -> Result<Vec<Bytes>, String> {
+    let parts = match frame {
+        Frame::Array(parts) if !parts.is_empty() => parts,
+        frame => return Err(format!("ERR protocol error, expected array, got {}", frame)),
+    };
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Frame::Bulk(data) => Ok(data),
+            Frame::Simple(s) => Ok(Bytes::from(s)),
+            Frame::Integer(i) => Ok(Bytes::from(i.to_string())),
+            frame => Err(format!("ERR protocol error, unexpected argument {}", frame)),
+        })
+        .collect()
+}
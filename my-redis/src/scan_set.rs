@@ -0,0 +1,114 @@
+//! 集合类型的值，SADD 等命令操作的就是它。
+//!
+//! 成员放在 `HashSet` 中，按 `(哈希值, 成员)` 排序的副本放在 `BTreeSet` 中，写法和 `SortedSet` 一样。
+//! SSCAN 的游标是成员的哈希值，每次调用只需要在 `BTreeSet` 中从游标开始取 `count` 个成员，
+//! 不需要遍历和排序整个集合，完整地扫描一遍的代价和集合的大小成正比
+
+use bytes::Bytes;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{hash_set, BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanSet {
+    members: HashSet<Bytes>,
+    ordered: BTreeSet<(u64, Bytes)>,
+}
+
+impl ScanSet {
+    pub fn new() -> ScanSet {
+        ScanSet::default()
+    }
+
+    /// 加入成员，成员原来不存在时返回 true
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if !self.members.insert(member.clone()) {
+            return false;
+        }
+        self.ordered.insert((scan_hash(&member), member));
+        true
+    }
+
+    /// 删除成员，成员存在时返回 true
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.members.take(member) {
+            Some(member) => {
+                self.ordered.remove(&(scan_hash(&member), member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.members.contains(member)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_set::Iter<'_, Bytes> {
+        self.members.iter()
+    }
+
+    /// 从游标开始按哈希值从小到大返回至少 `count` 个成员，以及下一次的游标，0 表示扫描结束。
+    ///
+    /// 哈希值只取决于成员本身，所以即使两次调用之间集合被修改，
+    /// 从开始到结束一直存在的成员也一定会被返回，这和 Redis 的 SCAN 保证一致。
+    /// 哈希值相同的成员必须在同一次返回，否则下一次的游标无法区分它们，因此可能会多返回几个
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&Bytes>) {
+        let mut range = self
+            .ordered
+            .range((Bound::Included((cursor, Bytes::new())), Bound::Unbounded))
+            .peekable();
+
+        let mut members = vec![];
+        while let Some((hash, member)) = range.next() {
+            members.push(member);
+            if members.len() >= count && range.peek().is_some_and(|(next, _)| next != hash) {
+                break;
+            }
+        }
+
+        let next = range.next().map_or(0, |(hash, _)| *hash);
+        (next, members)
+    }
+}
+
+impl<'a> IntoIterator for &'a ScanSet {
+    type Item = &'a Bytes;
+    type IntoIter = hash_set::Iter<'a, Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Bytes> for ScanSet {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> ScanSet {
+        let mut set = ScanSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<Bytes> for ScanSet {
+    fn extend<I: IntoIterator<Item = Bytes>>(&mut self, iter: I) {
+        for member in iter {
+            self.insert(member);
+        }
+    }
+}
+
+/// 游标使用的哈希函数，必须在进程的整个生命周期内保持稳定，因此使用固定 key 的 `DefaultHasher`
+fn scan_hash(member: &Bytes) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    member.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,254 @@
+//! EVAL / EVALSHA 使用的 Lua 脚本引擎，基于 mlua 内嵌(vendored)的 Lua 5.4。
+//!
+//! 脚本执行期间一直持有键空间的锁，`redis.call()` 直接在这把锁下执行命令，
+//! 因此脚本对其它连接来说是原子的，这期间其它连接访问键空间的命令回复 `-BUSY`，和 Redis 一样。
+//! 为了避免一个死循环的脚本把整个服务端卡住，每执行一定数量的指令就检查一次是否超时，超时后脚本会被中断并返回错误。
+//! 注意被中断的脚本已经执行的写命令不会回滚，这一点和 Redis 不同(Redis 拒绝 kill 已经写过数据的脚本)。
+//!
+//! `eval` 是同步执行的，服务端通过 `spawn_blocking` 在阻塞线程池中调用它，不会占用运行时的 worker 线程。
+
+use crate::db::{sha1_hex, ScriptGuard};
+use crate::{Command, Db, Frame};
+
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// 默认的脚本超时时间，与 Redis 的 `lua-time-limit` 一致
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// 每执行多少条 Lua 指令检查一次超时
+const HOOK_INSTRUCTIONS: u32 = 1000;
+
+/// 在编号为 `index` 的数据库上执行一段脚本，`keys` 和 `args` 分别对应脚本中的 `KEYS` 和 `ARGV`
+pub fn eval(db: &Db, index: usize, source: &str, keys: Vec<Bytes>, args: Vec<Bytes>) -> Frame {
+    match run(db, index, source, keys, args) {
+        Ok(frame) => frame,
+        Err(err) => Frame::Error(error_message(&err)),
+    }
+}
+
+fn run(
+    db: &Db,
+    index: usize,
+    source: &str,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> mlua::Result<Frame> {
+    let lua = sandbox()?;
+
+    let limit = db.script_time_limit();
+    let started = Instant::now();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+        move |_, _| {
+            if started.elapsed() > limit {
+                Err(mlua::Error::RuntimeError(
+                    "ERR script killed: exceeded the time limit".to_string(),
+                ))
+            } else {
+                Ok(())
+            }
+        },
+    );
+
+    let globals = lua.globals();
+    globals.set("KEYS", to_lua_strings(&lua, &keys)?)?;
+    globals.set("ARGV", to_lua_strings(&lua, &args)?)?;
+
+    // 在整个脚本执行期间持有锁。脚本中的 SELECT 只影响脚本自己，不会改变连接选择的数据库
+    let guard = match db.lock_for_script() {
+        Ok(guard) => guard,
+        Err(busy) => return Ok(busy.into()),
+    };
+    let state = RefCell::new((guard, index));
+
+    lua.scope(|scope| {
+        let redis: Table = globals.get("redis")?;
+        redis.set(
+            "call",
+            scope.create_function(|lua, argv: Variadic<Value>| {
+                match call(db, &mut state.borrow_mut(), argv)? {
+                    Frame::Error(msg) => Err(mlua::Error::RuntimeError(msg)),
+                    frame => to_lua(lua, frame),
+                }
+            })?,
+        )?;
+        redis.set(
+            "pcall",
+            scope.create_function(|lua, argv: Variadic<Value>| {
+                let frame = call(db, &mut state.borrow_mut(), argv)?;
+                to_lua(lua, frame)
+            })?,
+        )?;
+
+        let value: Value = lua.load(source).set_name("@user_script").eval()?;
+        Ok(to_frame(value))
+    })
+}
+
+/// 创建一个只加载了 table、string、math 标准库的 Lua 虚拟机，并注册 `redis` 表
+fn sandbox() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+
+    let globals = lua.globals();
+    // base 库中可以访问文件系统的函数
+    for name in ["dofile", "loadfile"] {
+        globals.set(name, Value::Nil)?;
+    }
+
+    let redis = lua.create_table()?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, msg: mlua::String| {
+            let reply = lua.create_table()?;
+            reply.set("ok", msg)?;
+            Ok(reply)
+        })?,
+    )?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, msg: mlua::String| {
+            let reply = lua.create_table()?;
+            reply.set("err", msg)?;
+            Ok(reply)
+        })?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, data: mlua::String| Ok(sha1_hex(data.as_bytes())))?,
+    )?;
+    globals.set("redis", redis)?;
+    drop(globals);
+
+    Ok(lua)
+}
+
+/// `redis.call()` / `redis.pcall()` 的实现：把参数拼成一个命令帧，再交给命令分发器执行
+///
+/// `state` 是持有的锁以及脚本当前选择的数据库
+fn call(
+    db: &Db,
+    state: &mut (ScriptGuard<'_>, usize),
+    argv: Variadic<Value>,
+) -> mlua::Result<Frame> {
+    let (dbs, index) = state;
+
+    if argv.is_empty() {
+        return Err(mlua::Error::RuntimeError(
+            "Please specify at least one argument for redis.call()".to_string(),
+        ));
+    }
+
+    let mut args = Vec::with_capacity(argv.len());
+    for arg in argv.iter() {
+        let bytes = match arg {
+            Value::String(s) => Bytes::copy_from_slice(s.as_bytes()),
+            Value::Integer(i) => Bytes::from(i.to_string()),
+            Value::Number(n) => Bytes::from(n.to_string()),
+            _ => {
+                return Err(mlua::Error::RuntimeError(
+                    "Lua redis() command arguments must be strings or integers".to_string(),
+                ))
+            }
+        };
+        args.push(bytes);
+    }
+
+    // 和 Redis 一样，脚本中执行的命令在 MONITOR 中显示为来自 lua
+    db.feed_monitors(*index, "lua", &args);
+
+    let frame = Frame::Array(args.into_iter().map(Frame::Bulk).collect());
+    Ok(match Command::from_frame(frame) {
+        Ok(Command::Select(cmd)) => match cmd.index(dbs.len()) {
+            Ok(selected) => {
+                *index = selected;
+                Frame::Simple("OK".to_string())
+            }
+            Err(err) => err,
+        },
+        Ok(cmd) => cmd.execute(dbs, *index),
+        Err(err) => Frame::Error(err.to_string()),
+    })
+}
+
+fn to_lua_strings<'lua>(lua: &'lua Lua, items: &[Bytes]) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table_with_capacity(items.len(), 0)?;
+    for item in items {
+        table.raw_push(lua.create_string(&item[..])?)?;
+    }
+    Ok(table)
+}
+
+/// 按照 Redis 的规则将命令的返回值转换成 Lua 值
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    Ok(match frame {
+        Frame::Integer(i) => Value::Integer(i),
+        Frame::Bulk(data) => Value::String(lua.create_string(&data[..])?),
+        Frame::Null => Value::Boolean(false),
+        Frame::Simple(msg) => {
+            let table = lua.create_table()?;
+            table.set("ok", msg)?;
+            Value::Table(table)
+        }
+        Frame::Error(msg) => {
+            let table = lua.create_table()?;
+            table.set("err", msg)?;
+            Value::Table(table)
+        }
+        Frame::Array(frames) => {
+            let table = lua.create_table_with_capacity(frames.len(), 0)?;
+            for frame in frames {
+                table.raw_push(to_lua(lua, frame)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+/// 按照 Redis 的规则将脚本的返回值转换成帧
+fn to_frame(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(i) => Frame::Integer(i),
+        // Lua 的浮点数会被截断成整数
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(mlua::Value::String(msg)) = table.raw_get("err") {
+                return Frame::Error(msg.to_string_lossy().into_owned());
+            }
+            if let Ok(mlua::Value::String(msg)) = table.raw_get("ok") {
+                return Frame::Simple(msg.to_string_lossy().into_owned());
+            }
+
+            // 数组遇到第一个 nil 就结束
+            let mut frames = vec![];
+            for i in 1.. {
+                match table.raw_get::<_, Value>(i) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => frames.push(to_frame(value)),
+                }
+            }
+            Frame::Array(frames)
+        }
+        _ => Frame::Null,
+    }
+}
+
+fn error_message(err: &mlua::Error) -> String {
+    match err {
+        mlua::Error::SyntaxError { message, .. } => {
+            format!("ERR Error compiling script: {}", message)
+        }
+        mlua::Error::CallbackError { cause, .. } => error_message(cause),
+        mlua::Error::RuntimeError(msg) if msg.starts_with("ERR ") => {
+            format!("ERR Error running script: {}", &msg[4..])
+        }
+        err => format!("ERR Error running script: {}", err),
+    }
+}
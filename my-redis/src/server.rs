@@ -0,0 +1,426 @@
+use crate::clients::Client;
+use crate::cmd::{message_frame, subscription_frame};
+use crate::{Command, Connection, Db, Frame};
+
+use bytes::Bytes;
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{StreamExt, StreamMap};
+
+/// 多久清理一次过期的 key
+const PURGE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 服务端监听的一个端口
+pub enum Listener {
+    Tcp(TcpListener),
+    /// 接受连接之后先完成 TLS 握手
+    Tls(TcpListener, TlsAcceptor),
+    /// Unix domain socket，同一台机器上的客户端可以跳过 TCP 协议栈
+    Unix(UnixListener),
+}
+
+/// 监听 Unix domain socket，对应 redis.conf 中的 `unixsocket` 和 `unixsocketperm`。
+///
+/// 和 Redis 一样先删除已经存在的文件(通常是上次没有正常退出留下的)，`perm` 为 0 时使用默认的权限
+pub fn bind_unix(path: &Path, perm: u32) -> std::io::Result<UnixListener> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    if perm != 0 {
+        fs::set_permissions(path, Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+/// 接受连接并为每个连接生成一个任务，`db` 在所有连接之间共享
+pub async fn run(listener: TcpListener, db: Db) -> crate::Result<()> {
+    serve(vec![Listener::Tcp(listener)], db).await
+}
+
+/// 同时在多个端口上接受连接，例如普通端口、TLS 端口和 Unix socket，任意一个出错时返回。
+///
+/// 连接的任务由各自的 accept 循环持有，`serve` 返回或者被取消(例如 `testing::TestServer` 被 drop)时，
+/// 所有的连接也随之关闭
+pub async fn serve(listeners: Vec<Listener>, db: Db) -> crate::Result<()> {
+    let mut accept_loops = JoinSet::new();
+    for listener in listeners {
+        accept_loops.spawn(accept(listener, db.clone()));
+    }
+
+    // 所有端口共用一个清理任务，`serve` 结束时清理也随之停止
+    tokio::select! {
+        _ = purge_expired(db) => unreachable!("purge loop never returns"),
+        res = accept_loops.join_next() => match res {
+            Some(res) => res?,
+            None => Ok(()),
+        },
+    }
+}
+
+/// 访问 key 时会惰性删除过期的 key，这里定期清理那些不再被访问的 key，永远不会返回
+pub(crate) async fn purge_expired(db: Db) {
+    let mut purge = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        purge.tick().await;
+        // 锁被脚本占用时跳过这一轮，不阻塞运行时的线程
+        if let Some(mut dbs) = db.try_lock() {
+            dbs.purge_expired();
+        }
+    }
+}
+
+/// 一个端口的 accept 循环
+async fn accept(listener: Listener, db: Db) -> crate::Result<()> {
+    match listener {
+        Listener::Tcp(listener) => accept_tcp(listener, None, db).await,
+        Listener::Tls(listener, acceptor) => accept_tcp(listener, Some(acceptor), db).await,
+        Listener::Unix(listener) => accept_unix(listener, db).await,
+    }
+}
+
+async fn accept_tcp(listener: TcpListener, tls: Option<TlsAcceptor>, db: Db) -> crate::Result<()> {
+    let mut connections = JoinSet::new();
+    loop {
+        // The second item contains the ip and port of the new connection.
+        let (socket, addr) = listener.accept().await?;
+        // 和 Redis 一样关闭 Nagle 算法，否则 pipeline 的多个回复会等待对端的延迟确认(约 40ms)。
+        // 失败通常是因为连接已经被对端重置了，只影响这一个连接，不能让整个 accept 循环退出
+        if let Err(err) = socket.set_nodelay(true) {
+            eprintln!("failed to set TCP_NODELAY for {}: {}", addr, err);
+            continue;
+        }
+        let db = db.clone();
+        let tls = tls.clone();
+        println!("Accepted {}", addr);
+        // 顺便回收已经结束的连接，JoinSet 不会一直增长
+        while connections.try_join_next().is_some() {}
+        // A new task is spawned for each inbound socket.  The socket is
+        // moved to the new task and processed there.
+        connections.spawn(async move {
+            let addr = addr.to_string();
+            match tls {
+                // 握手放在连接自己的任务中，慢的客户端不会阻塞 accept
+                Some(acceptor) => match acceptor.accept(socket).await {
+                    Ok(stream) => process(stream, addr, db).await,
+                    Err(err) => eprintln!("TLS handshake with {} failed: {}", addr, err),
+                },
+                None => process(socket, addr, db).await,
+            }
+        });
+    }
+}
+
+async fn accept_unix(listener: UnixListener, db: Db) -> crate::Result<()> {
+    // Unix socket 的客户端没有地址，和 Redis 一样显示为 `路径:0`
+    let path = listener.local_addr()?;
+    let addr = match path.as_pathname() {
+        Some(path) => format!("{}:0", path.display()),
+        None => "unixsocket:0".to_string(),
+    };
+
+    let mut connections = JoinSet::new();
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let db = db.clone();
+        let addr = addr.clone();
+        println!("Accepted {}", addr);
+        while connections.try_join_next().is_some() {}
+        connections.spawn(process(socket, addr, db));
+    }
+}
+
+/// 处理一个连接直到断开，TCP、TLS 和 Unix socket 的连接都走这里。
+///
+/// `socket` 可以是任意的双向字节流，测试中使用 `tokio::io::duplex`，不需要监听端口，见 `testing::TestServer`
+pub async fn process<S: AsyncRead + AsyncWrite + Unpin>(socket: S, addr: String, db: Db) {
+    if let Err(err) = Handler::new(socket, addr, db).run().await {
+        eprintln!("connection error: {}", err);
+    }
+}
+
+/// 每个连接对应一个 `Handler`，负责读取命令、执行并写回结果
+struct Handler<S> {
+    db: Db,
+    connection: Connection<S>,
+    /// 连接在注册表中的句柄，`Handler` 结束时自动注销
+    client: Client,
+    /// 客户端地址，MONITOR 和慢日志中会用到
+    addr: String,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Handler<S> {
+    fn new(socket: S, addr: String, db: Db) -> Handler<S> {
+        let client = db.register_client(addr.clone());
+        // 和 Redis 一样，连接时不需要密码的话，之后设置了 requirepass 也不用重新 AUTH
+        if !db.auth_required() {
+            client.set_authenticated();
+        }
+        Handler {
+            db,
+            connection: Connection::new(socket),
+            client,
+            addr,
+        }
+    }
+
+    async fn run(&mut self) -> crate::Result<()> {
+        loop {
+            let timeout = self.db.client_timeout();
+
+            // Use `read_frame` to receive a command from the connection.
+            // 读取命令的同时等待 CLIENT KILL 和空闲超时，任意一个发生都会关闭连接
+            let maybe_frame = tokio::select! {
+                res = self.connection.read_frame() => res?,
+                _ = self.client.killed() => return Ok(()),
+                _ = idle(timeout) => return Ok(()),
+            };
+
+            let frame = match maybe_frame {
+                Some(frame) => frame,
+                None => return Ok(()),
+            };
+
+            let args = command_args(&frame);
+
+            let cmd = match Command::from_frame(frame) {
+                Ok(cmd) => cmd,
+                // 命令解析失败时回复错误，连接继续可用
+                Err(err) => {
+                    let response = Frame::Error(err.to_string());
+                    self.connection.write_frame(&response).await?;
+                    continue;
+                }
+            };
+
+            self.client.touch(cmd.get_name());
+
+            // 和 Redis 一样，AUTH 带着明文密码，不出现在 MONITOR 和慢日志中
+            let auth = matches!(cmd, Command::Auth(_));
+
+            // 设置了 requirepass 时，通过 AUTH 之前只能执行 AUTH
+            if !auth && !self.client.authenticated() && self.db.auth_required() {
+                let response = Frame::Error("NOAUTH Authentication required.".to_string());
+                self.connection.write_frame(&response).await?;
+                continue;
+            }
+
+            if let Command::Monitor(_) = cmd {
+                return self.monitor().await;
+            }
+
+            if !auth {
+                self.db.feed_monitors(self.client.db(), &self.addr, &args);
+            }
+
+            match cmd {
+                Command::Subscribe(cmd) => {
+                    // 取消所有订阅之后回到普通模式
+                    if self.subscribe(cmd.into_channels()).await? {
+                        continue;
+                    }
+                    return Ok(());
+                }
+                Command::Unsubscribe(cmd) => {
+                    // 没有订阅任何频道，每个频道回复一次，订阅数都是 0
+                    for frame in unsubscribed(cmd.into_channels(), &mut StreamMap::new()) {
+                        self.connection.write_frame(&frame).await?;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            // 只统计命令本身的执行时间
+            let start = Instant::now();
+            let response = match cmd {
+                // 脚本在整个执行期间持有锁，最长可以运行 `lua-time-limit`，放到阻塞线程池中执行，
+                // 不占用运行时的 worker 线程。其它连接的命令这期间回复 BUSY，也不会等待这把锁
+                cmd @ (Command::Eval(_) | Command::EvalSha(_)) => {
+                    let db = self.db.clone();
+                    let index = self.client.db();
+                    tokio::task::spawn_blocking(move || cmd.apply_script(&db, index)).await?
+                }
+                cmd => cmd.apply(&self.db, &self.client),
+            };
+            if !auth {
+                let name = self.client.name().unwrap_or_default();
+                self.db
+                    .record_slowlog(&args, start.elapsed(), &self.addr, &name);
+            }
+
+            self.connection.write_frame(&response).await?;
+        }
+    }
+
+    /// 监控模式：把所有连接执行的命令转发给当前连接，直到客户端断开
+    async fn monitor(&mut self) -> crate::Result<()> {
+        let mut lines = self.db.monitor();
+        self.connection
+            .write_frame(&Frame::Simple("OK".to_string()))
+            .await?;
+
+        loop {
+            tokio::select! {
+                line = lines.recv() => match line {
+                    Ok(line) => self.connection.write_frame(&Frame::Simple(line)).await?,
+                    // 当前连接处理得太慢，丢掉了一部分记录，继续即可
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                _ = self.client.killed() => return Ok(()),
+                // read_frame 是取消安全的：已读到的数据保存在连接的缓冲区中
+                frame = self.connection.read_frame() => match frame? {
+                    None => return Ok(()),
+                    // 监控模式下只响应 QUIT，其它命令直接忽略
+                    Some(frame) => {
+                        if matches!(command_args(&frame).first(), Some(name) if name.eq_ignore_ascii_case(b"quit")) {
+                            self.connection.write_frame(&Frame::Simple("OK".to_string())).await?;
+                            return Ok(());
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// 订阅模式：把订阅的频道收到的消息推送给客户端。
+    ///
+    /// 订阅模式下只能执行 SUBSCRIBE / UNSUBSCRIBE / PING / QUIT，取消所有订阅后返回 `Ok(true)`，
+    /// 连接回到普通模式；连接关闭时返回 `Ok(false)`
+    async fn subscribe(&mut self, channels: Vec<String>) -> crate::Result<bool> {
+        // 和 mini-redis 一样用 StreamMap 同时监听所有订阅的频道
+        let mut subscriptions = StreamMap::new();
+        self.subscribe_to(channels, &mut subscriptions).await?;
+
+        loop {
+            tokio::select! {
+                Some((channel, message)) = subscriptions.next() => match message {
+                    Ok(message) => {
+                        self.connection.write_frame(&message_frame(&channel, message)).await?;
+                    }
+                    // 当前连接处理得太慢，丢掉了一部分消息，继续即可
+                    Err(_) => continue,
+                },
+                _ = self.client.killed() => return Ok(false),
+                frame = self.connection.read_frame() => {
+                    let frame = match frame? {
+                        Some(frame) => frame,
+                        None => return Ok(false),
+                    };
+
+                    if matches!(command_args(&frame).first(), Some(name) if name.eq_ignore_ascii_case(b"quit")) {
+                        self.connection.write_frame(&Frame::Simple("OK".to_string())).await?;
+                        return Ok(false);
+                    }
+
+                    match Command::from_frame(frame) {
+                        Ok(Command::Subscribe(cmd)) => {
+                            self.subscribe_to(cmd.into_channels(), &mut subscriptions).await?;
+                        }
+                        Ok(Command::Unsubscribe(cmd)) => {
+                            for frame in unsubscribed(cmd.into_channels(), &mut subscriptions) {
+                                self.connection.write_frame(&frame).await?;
+                            }
+                            if subscriptions.is_empty() {
+                                return Ok(true);
+                            }
+                        }
+                        Ok(Command::Ping(_)) => {
+                            let pong = Frame::Array(vec![
+                                Frame::Bulk(Bytes::from_static(b"pong")),
+                                Frame::Bulk(Bytes::new()),
+                            ]);
+                            self.connection.write_frame(&pong).await?;
+                        }
+                        Ok(cmd) => {
+                            let err = Frame::Error(format!(
+                                "ERR Can't execute '{}': only SUBSCRIBE / UNSUBSCRIBE / PING / QUIT are allowed in this context",
+                                cmd.get_name()
+                            ));
+                            self.connection.write_frame(&err).await?;
+                        }
+                        Err(err) => {
+                            self.connection.write_frame(&Frame::Error(err.to_string())).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 订阅新的频道，每个频道回复一次
+    async fn subscribe_to(
+        &mut self,
+        channels: Vec<String>,
+        subscriptions: &mut StreamMap<String, BroadcastStream<Bytes>>,
+    ) -> crate::Result<()> {
+        for channel in channels {
+            // 重复订阅同一个频道不会收到两份消息
+            if !subscriptions.contains_key(&channel) {
+                let rx = self.db.subscribe(channel.clone());
+                subscriptions.insert(channel.clone(), BroadcastStream::new(rx));
+            }
+
+            let frame = subscription_frame("subscribe", Some(&channel), subscriptions.len());
+            self.connection.write_frame(&frame).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 取消订阅并返回每个频道的回复，`channels` 为空时取消所有订阅
+fn unsubscribed(
+    mut channels: Vec<String>,
+    subscriptions: &mut StreamMap<String, BroadcastStream<Bytes>>,
+) -> Vec<Frame> {
+    if channels.is_empty() {
+        channels = subscriptions.keys().cloned().collect();
+        // 没有订阅任何频道时也要回复一次
+        if channels.is_empty() {
+            return vec![subscription_frame("unsubscribe", None, 0)];
+        }
+    }
+
+    channels
+        .into_iter()
+        .map(|channel| {
+            subscriptions.remove(&channel);
+            subscription_frame("unsubscribe", Some(&channel), subscriptions.len())
+        })
+        .collect()
+}
+
+/// 空闲超时，`timeout` 为 0 时永远不会完成
+async fn idle(timeout: Duration) {
+    if timeout.is_zero() {
+        std::future::pending().await
+    } else {
+        tokio::time::sleep(timeout).await
+    }
+}
+
+/// 将命令帧中的参数取出来，用于 MONITOR 和慢日志
+fn command_args(frame: &Frame) -> Vec<Bytes> {
+    match frame {
+        Frame::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                Frame::Bulk(data) => Some(data.clone()),
+                Frame::Simple(s) => Some(Bytes::from(s.clone())),
+                Frame::Integer(i) => Some(Bytes::from(i.to_string())),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
@@ -0,0 +1,118 @@
+//! 慢日志：执行时间超过 `slowlog-log-slower-than` 的命令会被记录到一个有界的环形缓冲区中
+//!
+//! 和 Redis 一样，只统计命令本身的执行时间，不包括读写 socket 的时间。
+
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Frame;
+
+/// 最多记录命令的多少个参数
+const MAX_ARGC: usize = 32;
+/// 每个参数最多记录多少字节
+const MAX_STRING: usize = 128;
+
+#[derive(Debug, Default)]
+pub struct SlowLog {
+    /// 最新的记录放在最前面
+    entries: VecDeque<Entry>,
+    next_id: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u64,
+    /// 命令开始执行时的 unix 时间戳，单位秒
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub args: Vec<Bytes>,
+    pub client_addr: String,
+    pub client_name: String,
+}
+
+impl SlowLog {
+    /// 追加一条记录，超过 `max_len` 时丢弃最旧的记录
+    pub fn push(
+        &mut self,
+        args: &[Bytes],
+        duration: Duration,
+        client_addr: &str,
+        client_name: &str,
+        max_len: usize,
+    ) {
+        let timestamp = (SystemTime::now() - duration)
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        self.entries.push_front(Entry {
+            id: self.next_id,
+            timestamp,
+            duration,
+            args: truncate_args(args),
+            client_addr: client_addr.to_string(),
+            client_name: client_name.to_string(),
+        });
+        self.next_id += 1;
+        self.entries.truncate(max_len);
+    }
+
+    /// 最近的 `count` 条记录，`None` 表示返回全部
+    pub fn get(&self, count: Option<usize>) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().take(count.unwrap_or(usize::MAX))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Entry {
+    /// `SLOWLOG GET` 中每条记录的格式：id、时间戳、耗时(微秒)、参数、客户端地址、客户端名称
+    pub fn to_frame(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::Integer(self.id as i64),
+            Frame::Integer(self.timestamp as i64),
+            Frame::Integer(self.duration.as_micros() as i64),
+            Frame::Array(self.args.iter().cloned().map(Frame::Bulk).collect()),
+            Frame::Bulk(Bytes::from(self.client_addr.clone())),
+            Frame::Bulk(Bytes::from(self.client_name.clone())),
+        ])
+    }
+}
+
+/// 和 Redis 一样截断过多、过长的参数，避免慢日志占用太多内存
+fn truncate_args(args: &[Bytes]) -> Vec<Bytes> {
+    let mut out = Vec::with_capacity(args.len().min(MAX_ARGC));
+
+    for (i, arg) in args.iter().enumerate() {
+        if i == MAX_ARGC - 1 && args.len() > MAX_ARGC {
+            out.push(Bytes::from(format!(
+                "... ({} more arguments)",
+                args.len() - MAX_ARGC + 1
+            )));
+            break;
+        }
+
+        if arg.len() > MAX_STRING {
+            let mut truncated = arg[..MAX_STRING].to_vec();
+            truncated.extend_from_slice(
+                format!("... ({} more bytes)", arg.len() - MAX_STRING).as_bytes(),
+            );
+            out.push(Bytes::from(truncated));
+        } else {
+            out.push(arg.clone());
+        }
+    }
+
+    out
+}
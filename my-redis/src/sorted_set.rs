@@ -0,0 +1,89 @@
+//! 有序集合，GEO 命令把经纬度编码成 52 位的 geohash 作为分数保存在这里，和 Redis 一样。
+//!
+//! 成员到分数的映射放在 `HashMap` 中，按 `(分数, 成员)` 排序的副本放在 `BTreeSet` 中，
+//! 这样既可以按成员查分数，也可以按分数范围查成员。分数相同时按成员的字节序排序
+
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+/// 按 `f64::total_cmp` 排序的分数，插入之前已经排除了 NaN
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    /// 加入成员或者更新它的分数，返回之前的分数
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let prev = self.scores.insert(member.clone(), score);
+        if let Some(prev) = prev {
+            self.ordered.remove(&(Score(prev), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        prev
+    }
+
+    /// 删除成员，成员存在时返回 true
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove_entry(member) {
+            Some((member, score)) => {
+                self.ordered.remove(&(Score(score), member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// 分数在 `[min, max)` 中的成员，按分数从小到大
+    pub fn range(&self, min: f64, max: f64) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.ordered
+            .range((
+                Bound::Included((Score(min), Bytes::new())),
+                Bound::Unbounded,
+            ))
+            .take_while(move |(score, _)| score.0 < max)
+            .map(|(score, member)| (member, score.0))
+    }
+}
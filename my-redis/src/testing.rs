@@ -0,0 +1,164 @@
+//! 在测试中启动进程内的服务端，不需要占用 6379 端口，多个测试可以并行执行。
+//!
+//! * `TestServer::bind` 监听 `127.0.0.1` 上的随机端口，用 `client` 模块的客户端访问，和真实的部署一样
+//! * `TestServer::new` 不监听任何端口，`connect` 返回的连接通过 `tokio::io::duplex` 直接交给 `server::process`。
+//!   内存中的流不涉及真实的 IO，配合 `tokio::time::pause` 可以控制时间：过期、空闲超时等不需要真的等待
+//!
+//! 只在启用 `testing` feature 时编译，集成测试通过 `[dev-dependencies]` 中对自身的依赖打开它。
+//!
+//! ```no_run
+//! # async fn example() -> my_redis::Result<()> {
+//! use my_redis::testing::TestServer;
+//! use my_redis::Config;
+//! use std::time::Duration;
+//!
+//! tokio::time::pause();
+//! let server = TestServer::new(Config::default());
+//! let mut conn = server.connect();
+//! conn.command(&["SET", "key", "value", "PX", "100"]).await?;
+//! tokio::time::advance(Duration::from_millis(100)).await;
+//! assert_eq!(conn.command(&["GET", "key"]).await?, my_redis::Frame::Null);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{self, Client};
+use crate::server::{self, Listener};
+use crate::{Config, Connection, Db, Frame};
+
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::io::DuplexStream;
+use tokio::net::TcpListener;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// duplex 流的缓冲区大小，超过之后写入方会等待对端读取
+const DUPLEX_BUFFER: usize = 64 * 1024;
+
+/// 进程内的服务端，drop 时停止接受连接和清理过期 key，`connect` 和 TCP 建立的连接也随之关闭
+#[derive(Debug)]
+pub struct TestServer {
+    db: Db,
+    addr: Option<SocketAddr>,
+    task: JoinHandle<()>,
+    /// `connect` 建立的连接的任务，`JoinSet` 被 drop 时取消其中所有的任务
+    connections: Mutex<JoinSet<()>>,
+    /// 给 duplex 连接编号，作为 `CLIENT LIST` 中的地址
+    next_conn: AtomicU64,
+}
+
+impl TestServer {
+    /// 只通过 `connect` 访问的服务端，不监听端口
+    pub fn new(config: Config) -> TestServer {
+        let db = Db::with_config(config);
+        let task = tokio::spawn(server::purge_expired(db.clone()));
+        TestServer {
+            db,
+            addr: None,
+            task,
+            connections: Mutex::new(JoinSet::new()),
+            next_conn: AtomicU64::new(0),
+        }
+    }
+
+    /// 监听随机端口的服务端，`addr` 返回实际的地址。同样可以使用 `connect`
+    pub async fn bind(config: Config) -> crate::Result<TestServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let db = Db::with_config(config);
+        let task = tokio::spawn({
+            let db = db.clone();
+            async move {
+                if let Err(err) = server::serve(vec![Listener::Tcp(listener)], db).await {
+                    eprintln!("test server error: {}", err);
+                }
+            }
+        });
+        Ok(TestServer {
+            db,
+            addr: Some(addr),
+            task,
+            connections: Mutex::new(JoinSet::new()),
+            next_conn: AtomicU64::new(0),
+        })
+    }
+
+    /// 服务端的状态，测试可以直接检查或修改键空间
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    /// `bind` 得到的地址，`new` 创建的服务端返回 None
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    /// 通过 TCP 连接到服务端
+    ///
+    /// # Panics
+    ///
+    /// 服务端不是通过 `bind` 创建的时候 panic
+    pub async fn client(&self) -> crate::Result<Client> {
+        let addr = self
+            .addr
+            .expect("TestServer::client requires TestServer::bind");
+        client::connect(addr).await
+    }
+
+    /// 新建一个内存中的连接，服务端在单独的任务中处理它，和 TCP 连接完全一样
+    pub fn connect(&self) -> TestConnection {
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER);
+        let id = self.next_conn.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut connections = self.connections.lock().unwrap();
+        // 顺便回收已经结束的连接，测试中反复建立连接时 JoinSet 不会一直增长
+        while connections.try_join_next().is_some() {}
+        connections.spawn(server::process(
+            server,
+            format!("duplex:{}", id),
+            self.db.clone(),
+        ));
+        TestConnection {
+            connection: Connection::new(client),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// `TestServer::connect` 返回的连接，按帧收发，可以检查原始的回复和推送的消息
+#[derive(Debug)]
+pub struct TestConnection {
+    connection: Connection<DuplexStream>,
+}
+
+impl TestConnection {
+    /// 发送命令并等待一个回复，错误回复也原样返回
+    pub async fn command(&mut self, args: &[&str]) -> crate::Result<Frame> {
+        self.send(args).await?;
+        self.read_frame()
+            .await?
+            .ok_or_else(|| "connection closed by server".into())
+    }
+
+    /// 只发送命令，不等待回复
+    pub async fn send(&mut self, args: &[&str]) -> crate::Result<()> {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        self.connection.write_frame(&frame).await?;
+        Ok(())
+    }
+
+    /// 读取下一个帧，例如订阅模式下推送的消息。服务端关闭连接时返回 None
+    pub async fn read_frame(&mut self) -> crate::Result<Option<Frame>> {
+        self.connection.read_frame().await
+    }
+}
@@ -0,0 +1,152 @@
+//! TLS 支持，基于 tokio-rustls。
+//!
+//! 证书和私钥都从 PEM 文件中读取。服务端通过 `tls-port`、`tls-cert-file` 等配置项开启(名称和 redis.conf 一样)，
+//! 客户端通过 `ClientTls` 指定信任的 CA，需要双向认证时再加上客户端证书。
+//! 不使用系统的根证书，服务端和客户端的证书都应该由自己的 CA 签发。
+
+use crate::Config;
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// 服务端是否要求客户端提供证书，对应 redis.conf 中的 `tls-auth-clients`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthClients {
+    /// 不要求客户端证书
+    No,
+    /// 必须提供由 `tls-ca-cert-file` 签发的证书
+    Yes,
+    /// 可以不提供，但提供了就必须是有效的
+    Optional,
+}
+
+/// 根据配置创建服务端的 TLS acceptor
+pub fn acceptor(config: &Config) -> crate::Result<TlsAcceptor> {
+    let (cert_file, key_file) = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        _ => return Err("TLS requires both tls-cert-file and tls-key-file".into()),
+    };
+
+    let builder =
+        ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+
+    let builder = match config.tls_auth_clients {
+        AuthClients::No => builder.with_no_client_auth(),
+        auth => {
+            let ca_file = config
+                .tls_ca_cert_file
+                .as_ref()
+                .ok_or("tls-auth-clients requires tls-ca-cert-file")?;
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots(ca_file)?), provider());
+            let verifier = if auth == AuthClients::Optional {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+
+    let config = builder.with_single_cert(certs(cert_file)?, private_key(key_file)?)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// 客户端的 TLS 配置，`Clone` 只是增加引用计数
+#[derive(Clone)]
+pub struct ClientTls {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl ClientTls {
+    /// 只信任 `ca_cert_file` 签发的服务端证书，`server_name` 需要和服务端证书中的域名或 IP 一致
+    pub fn new(ca_cert_file: impl AsRef<Path>, server_name: &str) -> crate::Result<ClientTls> {
+        let config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots(ca_cert_file.as_ref())?)
+            .with_no_client_auth();
+        ClientTls::build(config, server_name)
+    }
+
+    /// 和 `new` 一样，同时向服务端出示客户端证书(双向认证)
+    pub fn with_client_cert(
+        ca_cert_file: impl AsRef<Path>,
+        server_name: &str,
+        cert_file: impl AsRef<Path>,
+        key_file: impl AsRef<Path>,
+    ) -> crate::Result<ClientTls> {
+        let config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots(ca_cert_file.as_ref())?)
+            .with_client_auth_cert(certs(cert_file.as_ref())?, private_key(key_file.as_ref())?)?;
+        ClientTls::build(config, server_name)
+    }
+
+    fn build(config: ClientConfig, server_name: &str) -> crate::Result<ClientTls> {
+        Ok(ClientTls {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name: ServerName::try_from(server_name.to_string())?,
+        })
+    }
+
+    /// 在已经建立的 TCP 连接上完成握手
+    pub(crate) async fn connect(&self, socket: TcpStream) -> std::io::Result<TlsStream<TcpStream>> {
+        self.connector
+            .connect(self.server_name.clone(), socket)
+            .await
+    }
+}
+
+impl fmt::Debug for ClientTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientTls")
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 只编译了 ring 作为加密库，显式指定可以避免依赖进程级别的默认设置
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+fn roots(ca_file: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs(ca_file)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+fn certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()).into());
+    }
+    Ok(certs)
+}
+
+fn private_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(open(path)?);
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?
+        .ok_or_else(|| format!("no private key found in {}", path.display()).into())
+}
+
+fn open(path: &Path) -> crate::Result<File> {
+    File::open(path).map_err(|err| format!("failed to open {}: {}", path.display(), err).into())
+}
@@ -0,0 +1,117 @@
+//! AUTH 和配置项 requirepass 的测试
+
+mod common;
+
+use common::{assert_error, bulk, ok};
+
+use my_redis::testing::TestServer;
+use my_redis::{Config, Frame};
+
+fn with_password(password: &str) -> Config {
+    Config {
+        requirepass: Some(password.to_string()),
+        ..Config::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn commands_require_auth() {
+    let server = TestServer::new(with_password("secret"));
+    let mut conn = server.connect();
+
+    for args in [&["GET", "k"][..], &["PING"], &["SUBSCRIBE", "news"]] {
+        assert_error(conn.command(args).await.unwrap(), "NOAUTH");
+    }
+
+    assert_error(conn.command(&["AUTH", "wrong"]).await.unwrap(), "WRONGPASS");
+    assert_error(
+        conn.command(&["AUTH", "someone", "secret"]).await.unwrap(),
+        "WRONGPASS",
+    );
+    assert_error(conn.command(&["GET", "k"]).await.unwrap(), "NOAUTH");
+
+    assert_eq!(conn.command(&["AUTH", "secret"]).await.unwrap(), ok());
+    assert_eq!(conn.command(&["SET", "k", "v"]).await.unwrap(), ok());
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+
+    // 带用户名的写法只支持 default
+    let mut other = server.connect();
+    assert_eq!(
+        other.command(&["AUTH", "default", "secret"]).await.unwrap(),
+        ok()
+    );
+    assert_eq!(other.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn auth_without_password_configured() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_error(
+        conn.command(&["AUTH", "secret"]).await.unwrap(),
+        "without any password configured",
+    );
+    assert_eq!(
+        conn.command(&["PING"]).await.unwrap(),
+        Frame::Simple("PONG".to_string())
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn requirepass_set_at_runtime() {
+    let server = TestServer::new(Config::default());
+    let mut admin = server.connect();
+    assert_eq!(
+        admin
+            .command(&["CONFIG", "SET", "requirepass", "secret"])
+            .await
+            .unwrap(),
+        ok()
+    );
+
+    // 已经建立的连接不受影响，新的连接需要 AUTH
+    assert_eq!(admin.command(&["SET", "k", "v"]).await.unwrap(), ok());
+    let mut conn = server.connect();
+    assert_error(conn.command(&["GET", "k"]).await.unwrap(), "NOAUTH");
+    assert_eq!(conn.command(&["AUTH", "secret"]).await.unwrap(), ok());
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+
+    // 清空密码之后不需要 AUTH
+    assert_eq!(
+        admin
+            .command(&["CONFIG", "SET", "requirepass", ""])
+            .await
+            .unwrap(),
+        ok()
+    );
+    let mut conn = server.connect();
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn auth_is_hidden_from_monitor_and_slowlog() {
+    let server = TestServer::new(Config {
+        slowlog_log_slower_than: 0,
+        ..with_password("secret")
+    });
+    let mut monitor = server.connect();
+    assert_eq!(monitor.command(&["AUTH", "secret"]).await.unwrap(), ok());
+    assert_eq!(monitor.command(&["MONITOR"]).await.unwrap(), ok());
+
+    let mut conn = server.connect();
+    assert_eq!(conn.command(&["AUTH", "secret"]).await.unwrap(), ok());
+    assert_eq!(conn.command(&["SET", "k", "v"]).await.unwrap(), ok());
+
+    // MONITOR 收到的第一条就是 SET
+    match monitor.read_frame().await.unwrap() {
+        Some(Frame::Simple(line)) => assert!(line.ends_with(r#""SET" "k" "v""#), "{}", line),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+
+    // 慢日志中只有 SET，没有两次 AUTH
+    match conn.command(&["SLOWLOG", "LEN"]).await.unwrap() {
+        Frame::Integer(len) => assert_eq!(len, 1),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
@@ -0,0 +1,108 @@
+//! `blocking_client` 模块的测试。测试本身是同步的 `#[test]`，和使用者一样不在任何运行时中调用；
+//! `TestServer::bind` 启动的服务端运行在测试单独创建的多线程运行时中
+
+use my_redis::blocking_client::{self, BlockingClient};
+use my_redis::testing::TestServer;
+use my_redis::Config;
+
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// 服务端和它所在的运行时。字段按声明的顺序 drop，先停掉服务端再关闭运行时
+struct Server {
+    server: TestServer,
+    _rt: Runtime,
+}
+
+impl Server {
+    fn start() -> Server {
+        let rt = Runtime::new().unwrap();
+        let server = rt.block_on(TestServer::bind(Config::default())).unwrap();
+        Server { server, _rt: rt }
+    }
+
+    fn addr(&self) -> SocketAddr {
+        self.server.addr().unwrap()
+    }
+
+    fn client(&self) -> BlockingClient {
+        blocking_client::connect(self.addr()).unwrap()
+    }
+}
+
+#[test]
+fn get_and_set() {
+    let server = Server::start();
+    let mut client = server.client();
+
+    assert_eq!(client.get("k").unwrap(), None);
+    client.set("k", Bytes::from("v")).unwrap();
+    assert_eq!(client.get("k").unwrap(), Some(Bytes::from("v")));
+
+    client
+        .set_expires("t", Bytes::from("v"), Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(client.get("t").unwrap(), Some(Bytes::from("v")));
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(client.get("t").unwrap(), None);
+}
+
+#[test]
+fn publish_and_subscribe() {
+    let server = Server::start();
+    let mut publisher = server.client();
+
+    // 没有订阅者时没有人收到消息
+    assert_eq!(publisher.publish("news", Bytes::from("nobody")).unwrap(), 0);
+
+    let mut subscriber = server.client().subscribe(vec!["news".to_string()]).unwrap();
+    assert_eq!(subscriber.get_subscribed(), ["news".to_string()]);
+    assert!(subscriber
+        .next_message_timeout(Duration::from_millis(50))
+        .unwrap()
+        .is_none());
+
+    assert_eq!(publisher.publish("news", Bytes::from("hello")).unwrap(), 1);
+    let message = subscriber.next_message().unwrap().unwrap();
+    assert_eq!(message.channel, "news");
+    assert_eq!(message.content, Bytes::from("hello"));
+
+    subscriber.subscribe(&["sports".to_string()]).unwrap();
+    subscriber.unsubscribe(&["news".to_string()]).unwrap();
+    assert_eq!(subscriber.get_subscribed(), ["sports".to_string()]);
+    assert_eq!(publisher.publish("news", Bytes::from("gone")).unwrap(), 0);
+}
+
+#[test]
+fn iterate_subscription() {
+    let server = Server::start();
+    let mut publisher = server.client();
+    let subscriber = server
+        .client()
+        .subscribe(vec!["a".to_string(), "b".to_string()])
+        .unwrap();
+
+    // 订阅已经确认，消息在迭代之前发出也不会丢
+    for (channel, content) in [("a", "1"), ("b", "2"), ("a", "3")] {
+        assert_eq!(publisher.publish(channel, Bytes::from(content)).unwrap(), 1);
+    }
+
+    let received: Vec<_> = subscriber
+        .into_iter()
+        .take(3)
+        .map(|message| {
+            let message = message.unwrap();
+            (message.channel, message.content)
+        })
+        .collect();
+    assert_eq!(
+        received,
+        [
+            ("a".to_string(), Bytes::from("1")),
+            ("b".to_string(), Bytes::from("2")),
+            ("a".to_string(), Bytes::from("3")),
+        ]
+    );
+}
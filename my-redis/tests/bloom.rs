@@ -0,0 +1,209 @@
+//! Bloom 过滤器的测试：`BF.RESERVE` / `BF.ADD` / `BF.EXISTS` 命令，以及 `BloomFilter` 的扩展和误判率
+
+mod common;
+
+use common::assert_error;
+
+use my_redis::bloom::{BloomFilter, Full};
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+async fn bf_add(conn: &mut TestConnection, key: &str, item: &str) -> Frame {
+    conn.command(&["BF.ADD", key, item]).await.unwrap()
+}
+
+/// 没有加入过的元素被误判为存在的比例
+fn false_positive_rate(filter: &BloomFilter, probes: u64) -> f64 {
+    let hits = (0..probes)
+        .filter(|i| filter.contains(format!("absent:{}", i).as_bytes()))
+        .count();
+    hits as f64 / probes as f64
+}
+
+#[tokio::test(start_paused = true)]
+async fn add_and_exists() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_eq!(
+        conn.command(&["BF.EXISTS", "bf", "a"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    // BF.ADD 自动创建过滤器
+    assert_eq!(bf_add(&mut conn, "bf", "a").await, Frame::Integer(1));
+    assert_eq!(bf_add(&mut conn, "bf", "a").await, Frame::Integer(0));
+    assert_eq!(
+        conn.command(&["BF.EXISTS", "bf", "a"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["BF.EXISTS", "bf", "b"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    // 已经存在的 key 不能再 BF.RESERVE
+    assert_error(
+        conn.command(&["BF.RESERVE", "bf", "0.01", "100"])
+            .await
+            .unwrap(),
+        "item exists",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn reserve_arguments() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    for (args, expected) in [
+        (&["BF.RESERVE", "bf", "abc", "100"][..], "bad error rate"),
+        (
+            &["BF.RESERVE", "bf", "0", "100"],
+            "(0 < error rate range < 1)",
+        ),
+        (
+            &["BF.RESERVE", "bf", "1", "100"],
+            "(0 < error rate range < 1)",
+        ),
+        (
+            &["BF.RESERVE", "bf", "-0.5", "100"],
+            "(0 < error rate range < 1)",
+        ),
+        (&["BF.RESERVE", "bf", "0.01", "abc"], "bad capacity"),
+        (
+            &["BF.RESERVE", "bf", "0.01", "0"],
+            "capacity should be larger than 0",
+        ),
+        (
+            &["BF.RESERVE", "bf", "0.01", "-1"],
+            "capacity should be larger than 0",
+        ),
+        (
+            &["BF.RESERVE", "bf", "0.01", "100", "EXPANSION", "0"],
+            "expansion should be greater or equal to 1",
+        ),
+        (
+            &["BF.RESERVE", "bf", "0.01", "100", "EXPANSION", "32769"],
+            "expansion should be greater or equal to 1",
+        ),
+        (&["BF.RESERVE", "bf", "0.01", "100", "EXPANSION"], "ERR"),
+        (&["BF.RESERVE", "bf", "0.01", "100", "FOO"], "syntax error"),
+        (&["BF.RESERVE", "bf", "0.01"], "ERR"),
+    ] {
+        assert_error(conn.command(args).await.unwrap(), expected);
+    }
+    // 参数错误时不会创建 key
+    assert_eq!(
+        conn.command(&["EXISTS", "bf"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    for args in [
+        &["BF.RESERVE", "a", "0.001", "1000"][..],
+        &["BF.RESERVE", "b", "0.1", "10", "expansion", "4"],
+        &["BF.RESERVE", "c", "0.1", "10", "NONSCALING"],
+        &[
+            "BF.RESERVE",
+            "d",
+            "0.1",
+            "10",
+            "EXPANSION",
+            "1",
+            "NONSCALING",
+        ],
+    ] {
+        assert_eq!(
+            conn.command(args).await.unwrap(),
+            Frame::Simple("OK".to_string()),
+            "{:?}",
+            args
+        );
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn nonscaling_filter_is_full() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["BF.RESERVE", "bf", "0.001", "10", "NONSCALING"])
+        .await
+        .unwrap();
+
+    // 误判率很低，前 10 个元素都是新加入的
+    for i in 0..10 {
+        assert_eq!(
+            bf_add(&mut conn, "bf", &format!("item:{}", i)).await,
+            Frame::Integer(1)
+        );
+    }
+    assert_error(
+        bf_add(&mut conn, "bf", "item:10").await,
+        "non scaling filter is full",
+    );
+    // 已经存在的元素仍然可以 BF.ADD，回复 0
+    assert_eq!(bf_add(&mut conn, "bf", "item:3").await, Frame::Integer(0));
+    assert_eq!(
+        conn.command(&["BF.EXISTS", "bf", "item:10"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn wrong_type() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["SET", "s", "v"]).await.unwrap();
+    conn.command(&["PFADD", "h", "a"]).await.unwrap();
+
+    for key in ["s", "h"] {
+        assert_error(bf_add(&mut conn, key, "a").await, "WRONGTYPE");
+        assert_error(
+            conn.command(&["BF.EXISTS", key, "a"]).await.unwrap(),
+            "WRONGTYPE",
+        );
+    }
+    conn.command(&["BF.ADD", "bf", "a"]).await.unwrap();
+    assert_error(conn.command(&["GET", "bf"]).await.unwrap(), "WRONGTYPE");
+}
+
+#[test]
+fn expansion_layers() {
+    let mut filter = BloomFilter::new(0.01, 100, 2);
+    assert_eq!((filter.layers(), filter.capacity()), (1, 100));
+    assert!(filter.is_empty());
+
+    // 每装满一层新建一层，容量是上一层的 2 倍：100、200、400、800
+    let mut added = 0;
+    for i in 0..1_500 {
+        if filter.add(format!("item:{}", i).as_bytes()).unwrap() {
+            added += 1;
+        }
+    }
+    assert_eq!(filter.len(), added);
+    assert_eq!((filter.layers(), filter.capacity()), (4, 1_500));
+    assert!((0..1_500).all(|i| filter.contains(format!("item:{}", i).as_bytes())));
+
+    // 每一层的误判率减半，总的误判率不超过 2 倍的初始误判率
+    let rate = false_positive_rate(&filter, 100_000);
+    assert!(rate < 0.02, "{}", rate);
+
+    let mut wide = BloomFilter::new(0.01, 100, 4);
+    for i in 0..600 {
+        wide.add(format!("item:{}", i).as_bytes()).unwrap();
+    }
+    assert_eq!((wide.layers(), wide.capacity()), (3, 2_100));
+}
+
+#[test]
+fn nonscaling_filter_does_not_grow() {
+    let mut filter = BloomFilter::new(0.001, 50, 0);
+    for i in 0..50 {
+        assert_eq!(filter.add(format!("item:{}", i).as_bytes()), Ok(true));
+    }
+    assert_eq!(filter.add(b"one more"), Err(Full));
+    assert_eq!(filter.add(b"item:0"), Ok(false));
+    assert_eq!((filter.layers(), filter.len()), (1, 50));
+
+    let rate = false_positive_rate(&filter, 100_000);
+    assert!(rate < 0.002, "{}", rate);
+}
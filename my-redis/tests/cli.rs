@@ -0,0 +1,128 @@
+//! `my-redis-cli` 拆分输入和显示回复的测试，期望的结果和 redis-cli 一致
+
+mod common;
+
+use common::bulk;
+
+use my_redis::cli::{format_reply, split_args};
+use my_redis::Frame;
+
+use bytes::Bytes;
+
+fn split(line: &str) -> Option<Vec<String>> {
+    split_args(line).map(|args| {
+        args.iter()
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect()
+    })
+}
+
+#[test]
+fn split_plain_arguments() {
+    assert_eq!(split("SET a b").unwrap(), ["SET", "a", "b"]);
+    assert_eq!(split("  get \t key  ").unwrap(), ["get", "key"]);
+    assert_eq!(split("").unwrap(), Vec::<String>::new());
+    assert_eq!(split("   ").unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn split_quoted_arguments() {
+    assert_eq!(
+        split(r#"SET "hello world" 'it''"#),
+        None,
+        "right quote must be followed by a space"
+    );
+    assert_eq!(
+        split(r#"SET "hello world" 'single quoted'"#).unwrap(),
+        ["SET", "hello world", "single quoted"]
+    );
+    assert_eq!(split(r#"SET k """#).unwrap(), ["SET", "k", ""]);
+    // 引号可以从参数中间开始
+    assert_eq!(split(r#"a"b c" d"#).unwrap(), ["ab c", "d"]);
+
+    // 引号不匹配，或者右引号后面紧跟着其它字符
+    for line in [
+        r#"SET "a"#,
+        "SET 'a",
+        r#"SET "a"b"#,
+        "SET 'a'b",
+        r#"SET "a\"#,
+    ] {
+        assert_eq!(split(line), None, "{}", line);
+    }
+}
+
+#[test]
+fn split_escapes() {
+    let args = split_args(r#""a\nb\r\t\b\a" "\x41\x7a\xff" "\"q\"" "\\" "\q""#).unwrap();
+    assert_eq!(
+        args,
+        [
+            Bytes::from_static(b"a\nb\r\t\x08\x07"),
+            Bytes::from_static(b"Az\xff"),
+            Bytes::from_static(b"\"q\""),
+            Bytes::from_static(b"\\"),
+            Bytes::from_static(b"q"),
+        ]
+    );
+    // `\x` 后面不是两个十六进制数字时只是普通的 x
+    assert_eq!(split(r#""\xZZ" "\x4""#).unwrap(), ["xZZ", "x4"]);
+
+    // 单引号中只转义单引号，其它原样保留
+    assert_eq!(split(r"'it\'s' '\n\x41'").unwrap(), ["it's", r"\n\x41"]);
+    // 引号之外的反斜杠没有特殊含义
+    assert_eq!(split(r"a\nb").unwrap(), [r"a\nb"]);
+}
+
+#[test]
+fn format_scalars() {
+    assert_eq!(format_reply(&Frame::Simple("OK".to_string())), ["OK"]);
+    assert_eq!(
+        format_reply(&Frame::Error("ERR wrong".to_string())),
+        ["(error) ERR wrong"]
+    );
+    assert_eq!(format_reply(&Frame::Integer(-3)), ["(integer) -3"]);
+    assert_eq!(format_reply(&Frame::Null), ["(nil)"]);
+    // 字符串加上引号，不可打印的字符被转义
+    assert_eq!(format_reply(&bulk("hi")), [r#""hi""#]);
+    assert_eq!(
+        format_reply(&Frame::Bulk(Bytes::from_static(b"a\n\x01\"b"))),
+        [r#""a\n\x01\"b""#]
+    );
+    assert_eq!(format_reply(&Frame::Array(vec![])), ["(empty array)"]);
+}
+
+#[test]
+fn format_arrays() {
+    assert_eq!(
+        format_reply(&Frame::Array(vec![
+            bulk("a"),
+            Frame::Null,
+            Frame::Integer(1)
+        ])),
+        [r#"1) "a""#, "2) (nil)", "3) (integer) 1"]
+    );
+
+    // 超过 9 个元素时序号右对齐
+    let items: Vec<Frame> = (1..=10).map(Frame::Integer).collect();
+    let lines = format_reply(&Frame::Array(items));
+    assert_eq!(lines[0], " 1) (integer) 1");
+    assert_eq!(lines[9], "10) (integer) 10");
+
+    // 嵌套的数组缩进到序号之后，例如 SSCAN 的回复
+    let nested = Frame::Array(vec![
+        bulk("0"),
+        Frame::Array(vec![bulk("m1"), bulk("m2")]),
+        Frame::Array(vec![Frame::Array(vec![]), Frame::Array(vec![Frame::Null])]),
+    ]);
+    assert_eq!(
+        format_reply(&nested),
+        [
+            r#"1) "0""#,
+            r#"2) 1) "m1""#,
+            r#"   2) "m2""#,
+            "3) 1) (empty array)",
+            "   2) 1) (nil)",
+        ]
+    );
+}
@@ -0,0 +1,401 @@
+//! `client` 模块的测试。类型化的方法连接 `TestServer::bind` 启动的真实服务端；
+//! 超时、断线和重连使用测试中手写的假服务端，精确控制什么时候回复、什么时候断开。
+//!
+//! 连接走的是真实的 TCP，不能暂停时钟，超时时间都设置得比较短
+
+use my_redis::client::{self, Condition, Expiration, SetOptions};
+use my_redis::testing::TestServer;
+use my_redis::{Config, Connection, Frame};
+
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{self, Instant};
+
+fn client_error(err: &my_redis::Error) -> &client::Error {
+    err.downcast_ref::<client::Error>()
+        .unwrap_or_else(|| panic!("expected client::Error, got {:?}", err))
+}
+
+async fn accept(listener: &TcpListener) -> Connection<TcpStream> {
+    let (socket, _) = listener.accept().await.unwrap();
+    Connection::new(socket)
+}
+
+#[tokio::test]
+async fn set_options() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let client = server.client().await.unwrap();
+    let nx = SetOptions {
+        condition: Some(Condition::Nx),
+        ..SetOptions::default()
+    };
+    let xx = SetOptions {
+        condition: Some(Condition::Xx),
+        ..SetOptions::default()
+    };
+
+    assert!(!client.set_with("k", Bytes::from("v"), xx).await.unwrap());
+    assert!(client.set_with("k", Bytes::from("v1"), nx).await.unwrap());
+    assert!(!client.set_with("k", Bytes::from("v2"), nx).await.unwrap());
+    assert_eq!(client.get("k").await.unwrap(), Some(Bytes::from("v1")));
+
+    // GET 返回原来的值，即使因为 NX 没有写入
+    assert_eq!(
+        client.set_get("k", Bytes::from("v3"), nx).await.unwrap(),
+        Some(Bytes::from("v1"))
+    );
+    assert_eq!(
+        client
+            .set_get("k", Bytes::from("v3"), SetOptions::default())
+            .await
+            .unwrap(),
+        Some(Bytes::from("v1"))
+    );
+    assert_eq!(
+        client
+            .set_get("new", Bytes::from("v"), SetOptions::default())
+            .await
+            .unwrap(),
+        None
+    );
+
+    // KEEPTTL 保留原来的过期时间，普通的 SET 会清除它
+    let expires = SetOptions {
+        expiration: Some(Expiration::After(Duration::from_secs(100))),
+        ..SetOptions::default()
+    };
+    let keep = SetOptions {
+        expiration: Some(Expiration::KeepTtl),
+        condition: Some(Condition::Xx),
+    };
+    client
+        .set_with("k", Bytes::from("v"), expires)
+        .await
+        .unwrap();
+    assert!(client.set_with("k", Bytes::from("v4"), keep).await.unwrap());
+    assert!(client.ttl("k").await.unwrap().unwrap() > Duration::from_secs(90));
+    client.set("k", Bytes::from("v5")).await.unwrap();
+    assert_eq!(client.ttl("k").await.unwrap(), None);
+
+    let at = SetOptions {
+        expiration: Some(Expiration::At(SystemTime::now() + Duration::from_secs(50))),
+        ..SetOptions::default()
+    };
+    client.set_with("k", Bytes::from("v"), at).await.unwrap();
+    let ttl = client.ttl("k").await.unwrap().unwrap();
+    assert!(ttl > Duration::from_secs(40) && ttl <= Duration::from_secs(50));
+    // 过去的时间点立即过期
+    let past = SetOptions {
+        expiration: Some(Expiration::At(SystemTime::now() - Duration::from_secs(1))),
+        ..SetOptions::default()
+    };
+    client.set_with("k", Bytes::from("v"), past).await.unwrap();
+    assert_eq!(client.get("k").await.unwrap(), None);
+
+    assert!(client.setnx("a", Bytes::from("1")).await.unwrap());
+    assert!(!client.setnx("a", Bytes::from("2")).await.unwrap());
+    assert!(!client
+        .msetnx(&[("a", Bytes::from("x")), ("b", Bytes::from("y"))])
+        .await
+        .unwrap());
+    assert!(client
+        .msetnx(&[("b", Bytes::from("y")), ("c", Bytes::from("z"))])
+        .await
+        .unwrap());
+    assert_eq!(
+        client.mget(&["a", "b", "c"]).await.unwrap(),
+        vec![
+            Some(Bytes::from("1")),
+            Some(Bytes::from("y")),
+            Some(Bytes::from("z"))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn set_commands() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let client = server.client().await.unwrap();
+    let members: Vec<Bytes> = (0..30).map(|i| Bytes::from(format!("m{}", i))).collect();
+    client.sadd("a", &members).await.unwrap();
+    client.sadd("b", &members[..10]).await.unwrap();
+
+    assert_eq!(
+        client
+            .smismember("b", &[Bytes::from("m1"), Bytes::from("m20")])
+            .await
+            .unwrap(),
+        vec![true, false]
+    );
+
+    assert_eq!(client.sinterstore("inter", &["a", "b"]).await.unwrap(), 10);
+    assert_eq!(client.sdiffstore("diff", &["a", "b"]).await.unwrap(), 20);
+    assert_eq!(
+        client
+            .sunionstore("union", &["diff", "inter"])
+            .await
+            .unwrap(),
+        30
+    );
+    // 结果为空时删除目标 key
+    assert_eq!(client.sdiffstore("union", &["b", "a"]).await.unwrap(), 0);
+    assert_eq!(client.exists(&["union"]).await.unwrap(), 0);
+
+    let mut cursor = 0;
+    let mut seen = HashSet::new();
+    loop {
+        let (next, batch) = client.sscan("a", cursor, None, Some(4)).await.unwrap();
+        assert!(batch.len() >= 4 || next == 0);
+        seen.extend(batch);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    assert_eq!(seen, members.iter().cloned().collect());
+
+    let (next, batch) = client.sscan("a", 0, Some("m2*"), Some(100)).await.unwrap();
+    assert_eq!(next, 0);
+    assert_eq!(batch.len(), 11);
+}
+
+#[tokio::test]
+async fn scripts_and_databases() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let client = server.client().await.unwrap();
+
+    let sha1 = client.script_load("return ARGV[1]").await.unwrap();
+    assert_eq!(
+        client
+            .evalsha(&sha1, &[], &[Bytes::from("hi")])
+            .await
+            .unwrap(),
+        Frame::Bulk(Bytes::from("hi"))
+    );
+    assert_eq!(
+        client.script_exists(&[&sha1, "0000"]).await.unwrap(),
+        vec![true, false]
+    );
+    client.script_flush().await.unwrap();
+    let err = client.evalsha(&sha1, &[], &[]).await.unwrap_err();
+    match client_error(&err) {
+        client::Error::Server(msg) => assert!(msg.starts_with("NOSCRIPT"), "{}", msg),
+        err => panic!("unexpected error {:?}", err),
+    }
+
+    client.set("k", Bytes::from("db0")).await.unwrap();
+    client.swapdb(0, 1).await.unwrap();
+    assert_eq!(client.get("k").await.unwrap(), None);
+    client.swapdb(0, 1).await.unwrap();
+    assert!(client.move_key("k", 2).await.unwrap());
+    assert!(!client.move_key("k", 2).await.unwrap());
+    assert_eq!(client.dbsize().await.unwrap(), 0);
+
+    client.set("other", Bytes::from("v")).await.unwrap();
+    client.flushall().await.unwrap();
+    let mut conn = server.connect();
+    conn.command(&["SELECT", "2"]).await.unwrap();
+    assert_eq!(conn.command(&["DBSIZE"]).await.unwrap(), Frame::Integer(0));
+    assert_eq!(client.dbsize().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn request_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = client::connect(addr).await.unwrap();
+
+    // 假服务端读取请求但是从不回复
+    let mut conn = accept(&listener).await;
+    let server = tokio::spawn(async move { while let Ok(Some(_)) = conn.read_frame().await {} });
+
+    let timeout = Duration::from_millis(50);
+    let start = Instant::now();
+    let err = client.with_timeout(timeout).get("k").await.unwrap_err();
+    assert_eq!(client_error(&err), &client::Error::Timeout(timeout));
+    assert!(start.elapsed() >= timeout);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    server.abort();
+}
+
+#[tokio::test]
+async fn in_flight_requests_fail_on_disconnect() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = client::connect(addr).await.unwrap();
+
+    // 收到三个请求之后不回复直接断开
+    let mut conn = accept(&listener).await;
+    let server = tokio::spawn(async move {
+        for _ in 0..3 {
+            conn.read_frame().await.unwrap().unwrap();
+        }
+    });
+
+    let requests: Vec<_> = (0..3)
+        .map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move { client.get(&format!("k{}", i)).await })
+        })
+        .collect();
+    server.await.unwrap();
+
+    for request in requests {
+        let err = request.await.unwrap().unwrap_err();
+        assert!(
+            matches!(client_error(&err), client::Error::Disconnected(_)),
+            "{:?}",
+            err
+        );
+    }
+
+    // 监听还在，管理任务立即重连成功，之后的请求正常执行
+    let mut conn = accept(&listener).await;
+    tokio::spawn(async move {
+        while let Ok(Some(_)) = conn.read_frame().await {
+            let pong = Frame::Simple("PONG".to_string());
+            conn.write_frame(&pong).await.unwrap();
+        }
+    });
+    assert_eq!(client.ping(None).await.unwrap(), Bytes::from("PONG"));
+}
+
+#[tokio::test]
+async fn reconnects_with_backoff() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = client::connect(addr).await.unwrap();
+
+    // 关闭监听和连接，之后的重连都会被拒绝
+    drop(accept(&listener).await);
+    drop(listener);
+
+    // 重连期间的请求立即失败，错误中带有下一次重连前等待的时间，每次失败翻倍
+    let mut backoffs = vec![];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while backoffs.len() < 3 {
+        assert!(Instant::now() < deadline, "saw backoffs {:?}", backoffs);
+        let start = Instant::now();
+        let err = client.ping(None).await.unwrap_err();
+        assert!(
+            start.elapsed() < Duration::from_millis(50),
+            "request waited"
+        );
+        if let client::Error::Disconnected(reason) = client_error(&err) {
+            if let Some((_, backoff)) = reason.rsplit_once("retry in ") {
+                if backoffs.last().map(String::as_str) != Some(backoff) {
+                    backoffs.push(backoff.to_string());
+                }
+            }
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(backoffs, ["100ms", "200ms", "400ms"]);
+
+    // 服务端恢复之后，下一次重连成功
+    let listener = TcpListener::bind(addr).await.unwrap();
+    tokio::spawn(async move {
+        let mut conn = accept(&listener).await;
+        while let Ok(Some(_)) = conn.read_frame().await {
+            let pong = Frame::Simple("PONG".to_string());
+            conn.write_frame(&pong).await.unwrap();
+        }
+    });
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match client.ping(None).await {
+            Ok(pong) => break assert_eq!(pong, Bytes::from("PONG")),
+            Err(err) => assert!(Instant::now() < deadline, "still failing: {}", err),
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn pipelined_replies_match_concurrent_callers() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let client = server.client().await.unwrap();
+
+    // 请求和回复都是 1MB，一批请求远远超过 socket 的缓冲区：
+    // 如果写请求的时候不同时读回复，服务端写回复时会阻塞，不再读取请求，两边都卡住
+    let value = |key: usize, round: usize| Bytes::from(vec![(key * 2 + round) as u8; 1 << 20]);
+    let tasks: Vec<_> = (0..64)
+        .map(|key| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let name = format!("k{}", key);
+                for round in 0..2 {
+                    let old = client.get_set(&name, value(key, round)).await.unwrap();
+                    // 每个调用方收到的都是自己的 key 上一轮的值
+                    assert_eq!(old, round.checked_sub(1).map(|prev| value(key, prev)));
+                }
+            })
+        })
+        .collect();
+
+    time::timeout(Duration::from_secs(30), futures::future::join_all(tasks))
+        .await
+        .expect("client and server deadlocked")
+        .into_iter()
+        .for_each(|result| result.unwrap());
+}
+
+#[tokio::test]
+async fn reconnect_restores_selected_db() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let client = server.client().await.unwrap();
+    let admin = server.client().await.unwrap();
+
+    let args = |args: &[&str]| {
+        args.iter()
+            .map(|arg| Bytes::from(arg.to_string()))
+            .collect()
+    };
+    client.command(args(&["SELECT", "2"])).await.unwrap();
+    client.set("k", Bytes::from("v")).await.unwrap();
+    let id = client.command(args(&["CLIENT", "ID"])).await.unwrap();
+    let id = match id {
+        Frame::Integer(id) => id.to_string(),
+        frame => panic!("unexpected reply {}", frame),
+    };
+    let killed = admin
+        .command(args(&["CLIENT", "KILL", "ID", &id]))
+        .await
+        .unwrap();
+    assert_eq!(killed, Frame::Integer(1));
+
+    // 重连之后仍然在 2 号数据库上
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match client.get("k").await {
+            Ok(value) => break assert_eq!(value, Some(Bytes::from("v"))),
+            Err(err) => assert!(Instant::now() < deadline, "still failing: {}", err),
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(admin.get("k").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn pending_replies_outlive_client_handles() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = client::connect(addr).await.unwrap();
+
+    let mut conn = accept(&listener).await;
+    let pending = client.send(vec![Bytes::from("PING")]).await.unwrap();
+    // 所有的句柄都被 drop 了，已经发出的请求仍然要等到回复
+    drop(client);
+
+    conn.read_frame().await.unwrap().unwrap();
+    time::sleep(Duration::from_millis(50)).await;
+    let pong = Frame::Simple("PONG".to_string());
+    conn.write_frame(&pong).await.unwrap();
+    assert_eq!(pending.recv().await.unwrap(), pong);
+
+    // 没有等待的回复之后管理任务关闭连接
+    let closed = time::timeout(Duration::from_secs(1), conn.read_frame()).await;
+    assert!(matches!(closed, Ok(Ok(None))), "{:?}", closed);
+}
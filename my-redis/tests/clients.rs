@@ -0,0 +1,234 @@
+//! CLIENT 子命令和 MONITOR 的测试
+
+mod common;
+
+use common::{assert_error, bulk, ok};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+use std::time::Duration;
+
+/// CLIENT LIST 的每一行，按 id 排序
+async fn client_list(conn: &mut TestConnection) -> Vec<String> {
+    match conn.command(&["CLIENT", "LIST"]).await.unwrap() {
+        Frame::Bulk(list) => std::str::from_utf8(&list)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+/// 连接被关闭之前，服务端可能还没有处理完 KILL 的通知，读到 None 就说明连接已经关闭
+async fn assert_closed(conn: &mut TestConnection) {
+    assert_eq!(conn.read_frame().await.unwrap(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_id_name_and_list() {
+    let server = TestServer::new(Config::default());
+    let mut first = server.connect();
+    let mut second = server.connect();
+
+    // id 从 1 开始按连接的顺序递增
+    assert_eq!(
+        first.command(&["CLIENT", "ID"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        second.command(&["CLIENT", "ID"]).await.unwrap(),
+        Frame::Integer(2)
+    );
+
+    assert_eq!(
+        first.command(&["CLIENT", "GETNAME"]).await.unwrap(),
+        Frame::Null
+    );
+    assert_eq!(
+        first
+            .command(&["CLIENT", "SETNAME", "worker"])
+            .await
+            .unwrap(),
+        ok()
+    );
+    assert_eq!(
+        first.command(&["CLIENT", "GETNAME"]).await.unwrap(),
+        bulk("worker")
+    );
+    assert_error(
+        first
+            .command(&["CLIENT", "SETNAME", "bad name"])
+            .await
+            .unwrap(),
+        "cannot contain spaces",
+    );
+
+    second.command(&["SELECT", "3"]).await.unwrap();
+    tokio::time::advance(Duration::from_secs(5)).await;
+
+    let list = client_list(&mut first).await;
+    assert_eq!(
+        list,
+        vec![
+            "id=1 addr=duplex:1 name=worker age=5 idle=0 db=0 cmd=client".to_string(),
+            "id=2 addr=duplex:2 name= age=5 idle=5 db=3 cmd=select".to_string(),
+        ]
+    );
+
+    // 空字符串清除名称
+    first.command(&["CLIENT", "SETNAME", ""]).await.unwrap();
+    assert_eq!(
+        first.command(&["CLIENT", "GETNAME"]).await.unwrap(),
+        Frame::Null
+    );
+
+    // 断开的连接从列表中消失
+    drop(second);
+    tokio::task::yield_now().await;
+    assert_eq!(client_list(&mut first).await.len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_kill_filters() {
+    let server = TestServer::new(Config::default());
+    let mut admin = server.connect();
+    let mut by_id = server.connect();
+    let mut by_addr = server.connect();
+
+    // 不存在的 id 和地址
+    assert_eq!(
+        admin
+            .command(&["CLIENT", "KILL", "ID", "100"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_error(
+        admin
+            .command(&["CLIENT", "KILL", "duplex:100"])
+            .await
+            .unwrap(),
+        "No such client",
+    );
+
+    assert_eq!(
+        admin.command(&["CLIENT", "KILL", "ID", "2"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_closed(&mut by_id).await;
+
+    // 旧的写法只有一个地址参数，回复 OK
+    assert_eq!(
+        admin
+            .command(&["CLIENT", "KILL", "duplex:3"])
+            .await
+            .unwrap(),
+        ok()
+    );
+    assert_closed(&mut by_addr).await;
+
+    // 条件之间是"且"的关系
+    let mut other = server.connect();
+    assert_eq!(
+        admin
+            .command(&["CLIENT", "KILL", "ID", "4", "ADDR", "duplex:1"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+
+    // 默认 SKIPME yes，不会关闭自己
+    assert_eq!(
+        admin
+            .command(&["CLIENT", "KILL", "ADDR", "duplex:1"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        admin
+            .command(&["CLIENT", "KILL", "ADDR", "duplex:1", "SKIPME", "no"])
+            .await
+            .unwrap(),
+        Frame::Integer(1)
+    );
+    assert_closed(&mut admin).await;
+
+    assert_eq!(
+        other.command(&["PING"]).await.unwrap(),
+        Frame::Simple("PONG".to_string())
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_kill_rejects_invalid_ids() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    for id in ["-1", "0"] {
+        assert_error(
+            conn.command(&["CLIENT", "KILL", "ID", id]).await.unwrap(),
+            "client-id should be greater than 0",
+        );
+    }
+    assert_error(
+        conn.command(&["CLIENT", "KILL", "ID", "abc"])
+            .await
+            .unwrap(),
+        "ERR",
+    );
+    assert_error(
+        conn.command(&["CLIENT", "KILL", "ID", "1", "SKIPME", "maybe"])
+            .await
+            .unwrap(),
+        "syntax error",
+    );
+    assert_error(
+        conn.command(&["CLIENT", "KILL", "SKIPME", "no"])
+            .await
+            .unwrap(),
+        "wrong number of arguments",
+    );
+    // 出错之后连接仍然可用
+    assert_eq!(
+        conn.command(&["CLIENT", "ID"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn monitor_shows_database_and_script_commands() {
+    let server = TestServer::new(Config::default());
+    let mut monitor = server.connect();
+    let mut conn = server.connect();
+
+    assert_eq!(monitor.command(&["MONITOR"]).await.unwrap(), ok());
+
+    conn.command(&["SELECT", "2"]).await.unwrap();
+    conn.command(&["EVAL", "return redis.call('GET', 'k\\n')", "0"])
+        .await
+        .unwrap();
+
+    let mut lines = vec![];
+    for _ in 0..3 {
+        match monitor.read_frame().await.unwrap() {
+            Some(Frame::Simple(line)) => lines.push(line),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+    assert!(
+        lines[0].ends_with(r#"[0 duplex:2] "SELECT" "2""#),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[1].contains("[2 duplex:2] \"EVAL\""), "{}", lines[1]);
+    // 脚本中执行的命令显示为来自 lua，不可打印的字符被转义
+    assert!(lines[2].ends_with(r#"[2 lua] "GET" "k\n""#), "{}", lines[2]);
+
+    // 监控模式下只响应 QUIT
+    monitor.send(&["GET", "k"]).await.unwrap();
+    assert_eq!(monitor.command(&["QUIT"]).await.unwrap(), ok());
+    assert_closed(&mut monitor).await;
+}
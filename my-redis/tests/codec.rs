@@ -0,0 +1,148 @@
+//! `codec` 模块的测试：各个协议的 `decode` 对不完整的帧、多个帧和超过最大帧长度的处理
+
+use my_redis::codec::{self, Codec, FrameReader, LengthPrefixed, Lines, Resp};
+
+use bytes::Bytes;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+const MAX: usize = codec::DEFAULT_MAX_FRAME_SIZE;
+
+fn encode(codec: &dyn Codec, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    codec.encode(payload, &mut buf);
+    buf
+}
+
+/// 一个完整的帧的任何前缀都是不完整的帧
+fn assert_prefixes_incomplete(codec: &dyn Codec, frame: &[u8]) {
+    for end in 0..frame.len() {
+        assert_eq!(
+            codec.decode(&frame[..end], MAX).unwrap(),
+            None,
+            "{} decoded partial frame {:?}",
+            codec.name(),
+            &frame[..end]
+        );
+    }
+    assert_eq!(codec.decode(frame, MAX).unwrap(), Some(frame.len()));
+}
+
+fn assert_too_large(result: my_redis::Result<Option<usize>>) {
+    match result {
+        Err(err) => assert!(
+            err.to_string().contains("exceeds max frame size"),
+            "{}",
+            err
+        ),
+        Ok(len) => panic!("expected max frame size error, got {:?}", len),
+    }
+}
+
+#[test]
+fn length_prefixed() {
+    let frame = encode(&LengthPrefixed, b"hello");
+    assert_eq!(frame, b"\x00\x00\x00\x05hello");
+    assert_prefixes_incomplete(&LengthPrefixed, &frame);
+
+    // 后面跟着下一个帧时只返回第一个帧的长度
+    let mut two = frame.clone();
+    two.extend(encode(&LengthPrefixed, b""));
+    assert_eq!(LengthPrefixed.decode(&two, MAX).unwrap(), Some(9));
+    assert_eq!(LengthPrefixed.decode(&two[9..], MAX).unwrap(), Some(4));
+
+    // 长度前缀本身也计入帧的长度；只读到前缀就能发现帧太大，不用等数据
+    assert_eq!(LengthPrefixed.decode(&frame, 9).unwrap(), Some(9));
+    assert_too_large(LengthPrefixed.decode(&frame, 8));
+    assert_too_large(LengthPrefixed.decode(b"\xff\xff\xff\xff", MAX));
+}
+
+#[test]
+fn lines() {
+    let frame = encode(&Lines, b"hello");
+    assert_eq!(frame, b"hello\n");
+    assert_prefixes_incomplete(&Lines, &frame);
+
+    assert_eq!(Lines.decode(b"\n", MAX).unwrap(), Some(1));
+    assert_eq!(Lines.decode(b"a\r\nb\n", MAX).unwrap(), Some(3));
+
+    // 换行符计入帧的长度
+    assert_eq!(Lines.decode(b"1234\n", 5).unwrap(), Some(5));
+    assert!(Lines.decode(b"12345\n", 5).is_err());
+    // 还没有读到换行符，但已经超过了最大帧长度
+    assert_eq!(Lines.decode(b"1234", 5).unwrap(), None);
+    let err = Lines.decode(b"12345", 5).unwrap_err();
+    assert!(
+        err.to_string().contains("exceeds max frame size"),
+        "{}",
+        err
+    );
+    // 超过最大长度之后才出现的换行符不会被接受
+    assert!(Lines.decode(b"123456789\n", 5).is_err());
+}
+
+#[test]
+fn resp() {
+    let frame = encode(&Resp, b"hello");
+    assert_eq!(frame, b"$5\r\nhello\r\n");
+    assert_prefixes_incomplete(&Resp, &frame);
+
+    for frame in [
+        &b"+OK\r\n"[..],
+        b"-ERR bad\r\n",
+        b":42\r\n",
+        b"$-1\r\n",
+        b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n",
+        b"*2\r\n*1\r\n:1\r\n$0\r\n\r\n",
+    ] {
+        assert_prefixes_incomplete(&Resp, frame);
+    }
+
+    let two = b"+OK\r\n:1\r\n";
+    assert_eq!(Resp.decode(two, MAX).unwrap(), Some(5));
+
+    // 不合法的帧直接返回错误
+    assert!(Resp.decode(b"?\r\n", MAX).is_err());
+
+    assert_eq!(Resp.decode(&frame, frame.len()).unwrap(), Some(frame.len()));
+    assert_too_large(Resp.decode(&frame, frame.len() - 1));
+    // 不完整的帧已经占满了最大帧长度，不会再等待
+    assert_eq!(Resp.decode(&frame[..5], 6).unwrap(), None);
+    assert_too_large(Resp.decode(&frame[..6], 6));
+}
+
+#[test]
+fn from_name() {
+    for name in ["length", "line", "resp"] {
+        assert_eq!(codec::from_name(name).unwrap().name(), name);
+    }
+    assert!(codec::from_name("json").is_none());
+}
+
+#[tokio::test]
+async fn frame_reader_reassembles_frames() {
+    let (mut client, server) = tokio::io::duplex(64);
+    let mut reader = FrameReader::new(server, Arc::new(Lines), 1024);
+
+    // 一个帧分多次写入，一次写入包含多个帧
+    tokio::spawn(async move {
+        for chunk in [&b"hel"[..], b"lo\nwor", b"ld\n\nlast"] {
+            client.write_all(chunk).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+    });
+
+    assert_eq!(
+        reader.read_frame().await.unwrap(),
+        Some(Bytes::from("hello\n"))
+    );
+    assert_eq!(
+        reader.read_frame().await.unwrap(),
+        Some(Bytes::from("world\n"))
+    );
+    assert_eq!(reader.read_frame().await.unwrap(), Some(Bytes::from("\n")));
+    // 关闭时剩下不完整的帧
+    let err = reader.read_frame().await.unwrap_err();
+    assert!(err.to_string().contains("partial frame"), "{}", err);
+    assert_eq!(reader.bytes_read(), 17);
+}
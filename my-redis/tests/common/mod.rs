@@ -0,0 +1,26 @@
+//! 集成测试共用的辅助函数，每个测试文件通过 `mod common;` 引入
+
+// 每个测试文件是一个单独的 crate，只用到其中一部分函数
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use my_redis::Frame;
+
+pub fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
+
+pub fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| bulk(item)).collect())
+}
+
+pub fn ok() -> Frame {
+    Frame::Simple("OK".to_string())
+}
+
+pub fn assert_error(frame: Frame, expected: &str) {
+    match frame {
+        Frame::Error(msg) => assert!(msg.contains(expected), "{}", msg),
+        frame => panic!("expected error containing {:?}, got {:?}", expected, frame),
+    }
+}
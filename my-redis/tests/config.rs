@@ -0,0 +1,200 @@
+//! CONFIG GET/SET 和 SLOWLOG 的测试
+
+mod common;
+
+use common::{assert_error, bulk, ok};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+/// SLOWLOG GET 的记录，只取 id 和参数
+async fn slowlog(conn: &mut TestConnection, count: &str) -> Vec<(i64, Vec<Frame>)> {
+    match conn.command(&["SLOWLOG", "GET", count]).await.unwrap() {
+        Frame::Array(entries) => entries
+            .into_iter()
+            .map(|entry| match entry {
+                Frame::Array(fields) => match (&fields[0], &fields[3]) {
+                    (Frame::Integer(id), Frame::Array(args)) => (*id, args.clone()),
+                    _ => panic!("unexpected entry {:?}", fields),
+                },
+                entry => panic!("unexpected entry {:?}", entry),
+            })
+            .collect(),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn config_get_and_set() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_eq!(
+        conn.command(&["CONFIG", "GET", "slowlog-*"]).await.unwrap(),
+        Frame::Array(vec![
+            bulk("slowlog-log-slower-than"),
+            bulk("10000"),
+            bulk("slowlog-max-len"),
+            bulk("128"),
+        ])
+    );
+    // 名称不区分大小写
+    assert_eq!(
+        conn.command(&["CONFIG", "SET", "LUA-TIME-LIMIT", "100"])
+            .await
+            .unwrap(),
+        ok()
+    );
+    assert_eq!(
+        conn.command(&["CONFIG", "GET", "lua-time-limit"])
+            .await
+            .unwrap(),
+        Frame::Array(vec![bulk("lua-time-limit"), bulk("100")])
+    );
+    assert_eq!(
+        conn.command(&["CONFIG", "GET", "no-such-option"])
+            .await
+            .unwrap(),
+        Frame::Array(vec![])
+    );
+
+    assert_error(
+        conn.command(&["CONFIG", "SET", "timeout", "-1"])
+            .await
+            .unwrap(),
+        "Invalid argument '-1' for CONFIG SET 'timeout'",
+    );
+    // 数据库的个数、端口、TLS 和 Unix socket 相关的配置只能在启动时指定
+    for (name, value) in [
+        ("databases", "4"),
+        ("port", "6380"),
+        ("tls-port", "6390"),
+        ("tls-cert-file", "/tmp/cert.pem"),
+        ("tls-key-file", "/tmp/key.pem"),
+        ("tls-ca-cert-file", "/tmp/ca.pem"),
+        ("TLS-AUTH-CLIENTS", "yes"),
+        ("unixsocket", "/tmp/my-redis.sock"),
+        ("unixsocketperm", "700"),
+    ] {
+        assert_error(
+            conn.command(&["CONFIG", "SET", name, value]).await.unwrap(),
+            "can't set immutable config",
+        );
+    }
+    assert_eq!(
+        conn.command(&["CONFIG", "GET", "port"]).await.unwrap(),
+        Frame::Array(vec![bulk("port"), bulk("6379")])
+    );
+    assert_error(
+        conn.command(&["CONFIG", "SET", "no-such-option", "1"])
+            .await
+            .unwrap(),
+        "Unknown option",
+    );
+    assert_error(
+        conn.command(&["CONFIG", "RESETSTAT"]).await.unwrap(),
+        "unknown subcommand",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn slowlog_records_commands_over_threshold() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    // 默认阈值是 10ms，普通命令不会被记录
+    conn.command(&["SET", "k", "v"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["SLOWLOG", "LEN"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    // 阈值为 0 时记录所有命令，包括修改配置的这一条
+    conn.command(&["CONFIG", "SET", "slowlog-log-slower-than", "0"])
+        .await
+        .unwrap();
+    conn.command(&["CLIENT", "SETNAME", "tester"])
+        .await
+        .unwrap();
+    conn.command(&["GET", "k"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["SLOWLOG", "LEN"]).await.unwrap(),
+        Frame::Integer(3)
+    );
+
+    // 最新的记录在最前面
+    let entries = slowlog(&mut conn, "2").await;
+    assert_eq!(
+        entries,
+        vec![
+            (3, vec![bulk("SLOWLOG"), bulk("LEN")]),
+            (2, vec![bulk("GET"), bulk("k")]),
+        ]
+    );
+    match conn.command(&["SLOWLOG", "GET", "1"]).await.unwrap() {
+        Frame::Array(entries) => match &entries[0] {
+            Frame::Array(fields) => {
+                assert_eq!(fields[4], bulk("duplex:1"));
+                assert_eq!(fields[5], bulk("tester"));
+            }
+            entry => panic!("unexpected entry {:?}", entry),
+        },
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+
+    assert_eq!(conn.command(&["SLOWLOG", "RESET"]).await.unwrap(), ok());
+    // RESET 本身在清空之后被记录
+    assert_eq!(
+        slowlog(&mut conn, "-1").await,
+        vec![(6, vec![bulk("SLOWLOG"), bulk("RESET")])]
+    );
+
+    // 负数关闭慢日志
+    conn.command(&["CONFIG", "SET", "slowlog-log-slower-than", "-1"])
+        .await
+        .unwrap();
+    conn.command(&["SLOWLOG", "RESET"]).await.unwrap();
+    conn.command(&["GET", "k"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["SLOWLOG", "LEN"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    assert_error(
+        conn.command(&["SLOWLOG", "GET", "-2"]).await.unwrap(),
+        "greater than or equal to -1",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn slowlog_max_len_and_truncation() {
+    let server = TestServer::new(Config {
+        slowlog_log_slower_than: 0,
+        slowlog_max_len: 3,
+        ..Config::default()
+    });
+    let mut conn = server.connect();
+
+    for i in 0..5 {
+        conn.command(&["GET", &i.to_string()]).await.unwrap();
+    }
+    assert_eq!(
+        conn.command(&["SLOWLOG", "LEN"]).await.unwrap(),
+        Frame::Integer(3)
+    );
+
+    // 超过 32 个参数时，最后一个位置记录剩下的参数个数；超过 128 字节的参数被截断
+    let long = "x".repeat(200);
+    let mut args = vec!["DEL", long.as_str()];
+    let keys: Vec<String> = (0..40).map(|i| format!("key{}", i)).collect();
+    args.extend(keys.iter().map(String::as_str));
+    conn.command(&args).await.unwrap();
+
+    let (_, logged) = slowlog(&mut conn, "1").await.remove(0);
+    assert_eq!(logged.len(), 32);
+    assert_eq!(
+        logged[1],
+        bulk(&format!("{}... (72 more bytes)", "x".repeat(128)))
+    );
+    assert_eq!(logged[31], bulk("... (11 more arguments)"));
+}
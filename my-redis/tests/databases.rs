@@ -0,0 +1,178 @@
+//! 编号数据库相关命令的测试：SELECT、SWAPDB、MOVE、DBSIZE、FLUSHDB 和 FLUSHALL
+
+mod common;
+
+use common::{assert_error, bulk, ok};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+use std::time::Duration;
+
+async fn dbsize(conn: &mut TestConnection) -> Frame {
+    conn.command(&["DBSIZE"]).await.unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn select_isolates_keyspaces() {
+    let server = TestServer::new(Config {
+        databases: 4,
+        ..Config::default()
+    });
+    let mut conn = server.connect();
+    let mut other = server.connect();
+
+    conn.command(&["SET", "k", "db0"]).await.unwrap();
+    assert_eq!(conn.command(&["SELECT", "3"]).await.unwrap(), ok());
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), Frame::Null);
+    conn.command(&["SET", "k", "db3"]).await.unwrap();
+
+    // 每个连接有自己选择的数据库
+    assert_eq!(other.command(&["GET", "k"]).await.unwrap(), bulk("db0"));
+
+    for index in ["4", "-1"] {
+        assert_error(
+            conn.command(&["SELECT", index]).await.unwrap(),
+            "DB index is out of range",
+        );
+    }
+    assert_error(conn.command(&["SELECT", "one"]).await.unwrap(), "ERR");
+    // 出错时仍然在原来的数据库
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("db3"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn swapdb_is_seen_by_other_connections() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    let mut in_db1 = server.connect();
+    in_db1.command(&["SELECT", "1"]).await.unwrap();
+
+    conn.command(&["SET", "k", "from db0"]).await.unwrap();
+    in_db1.command(&["SET", "k", "from db1"]).await.unwrap();
+    in_db1.command(&["SET", "only1", "x"]).await.unwrap();
+
+    assert_eq!(conn.command(&["SWAPDB", "0", "1"]).await.unwrap(), ok());
+    // 连接记录的只是编号，交换之后看到的是对方的数据
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("from db1"));
+    assert_eq!(dbsize(&mut conn).await, Frame::Integer(2));
+    assert_eq!(
+        in_db1.command(&["GET", "k"]).await.unwrap(),
+        bulk("from db0")
+    );
+
+    // 和自己交换没有效果
+    assert_eq!(conn.command(&["SWAPDB", "0", "0"]).await.unwrap(), ok());
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("from db1"));
+
+    assert_error(
+        conn.command(&["SWAPDB", "0", "16"]).await.unwrap(),
+        "DB index is out of range",
+    );
+    assert_error(
+        conn.command(&["SWAPDB", "-1", "0"]).await.unwrap(),
+        "DB index is out of range",
+    );
+    assert_error(
+        conn.command(&["SWAPDB", "0"]).await.unwrap(),
+        "wrong number of arguments",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn move_keeps_expiry() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    conn.command(&["SET", "k", "v", "EX", "10"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["MOVE", "k", "2"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["EXISTS", "k"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    conn.command(&["SELECT", "2"]).await.unwrap();
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+    assert_eq!(
+        conn.command(&["TTL", "k"]).await.unwrap(),
+        Frame::Integer(10)
+    );
+
+    tokio::time::advance(Duration::from_secs(10)).await;
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), Frame::Null);
+}
+
+#[tokio::test(start_paused = true)]
+async fn move_does_not_overwrite() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    conn.command(&["SET", "k", "db0"]).await.unwrap();
+    conn.command(&["SELECT", "1"]).await.unwrap();
+    conn.command(&["SET", "k", "db1"]).await.unwrap();
+    conn.command(&["SELECT", "0"]).await.unwrap();
+
+    // 目标数据库中已经存在，或者当前数据库中不存在时都返回 0
+    assert_eq!(
+        conn.command(&["MOVE", "k", "1"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("db0"));
+    assert_eq!(
+        conn.command(&["MOVE", "missing", "1"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    assert_error(
+        conn.command(&["MOVE", "k", "0"]).await.unwrap(),
+        "source and destination objects are the same",
+    );
+    assert_error(
+        conn.command(&["MOVE", "k", "16"]).await.unwrap(),
+        "DB index is out of range",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn flushdb_and_flushall() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    for db in ["0", "1", "2"] {
+        conn.command(&["SELECT", db]).await.unwrap();
+        conn.command(&["SET", "a", db]).await.unwrap();
+        conn.command(&["SADD", "b", db]).await.unwrap();
+    }
+
+    // FLUSHDB 只清空当前选择的数据库，同步和异步的结果一样
+    assert_eq!(conn.command(&["FLUSHDB"]).await.unwrap(), ok());
+    assert_eq!(dbsize(&mut conn).await, Frame::Integer(0));
+    conn.command(&["SELECT", "1"]).await.unwrap();
+    assert_eq!(dbsize(&mut conn).await, Frame::Integer(2));
+    assert_eq!(conn.command(&["FLUSHDB", "ASYNC"]).await.unwrap(), ok());
+    assert_eq!(dbsize(&mut conn).await, Frame::Integer(0));
+    conn.command(&["SELECT", "0"]).await.unwrap();
+    assert_eq!(dbsize(&mut conn).await, Frame::Integer(2));
+
+    assert_eq!(conn.command(&["FLUSHALL", "sync"]).await.unwrap(), ok());
+    assert_eq!(dbsize(&mut conn).await, Frame::Integer(0));
+
+    // 带过期时间的 key 也被清空，之后重新设置的同名 key 不会被旧的过期时间删除
+    conn.command(&["SET", "a", "v", "PX", "100"]).await.unwrap();
+    assert_eq!(conn.command(&["FLUSHALL", "ASYNC"]).await.unwrap(), ok());
+    conn.command(&["SET", "a", "v"]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(conn.command(&["GET", "a"]).await.unwrap(), bulk("v"));
+
+    assert_error(
+        conn.command(&["FLUSHDB", "LATER"]).await.unwrap(),
+        "syntax error",
+    );
+    assert_error(
+        conn.command(&["FLUSHALL", "ASYNC", "SYNC"]).await.unwrap(),
+        "wrong number of arguments",
+    );
+}
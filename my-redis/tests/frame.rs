@@ -0,0 +1,277 @@
+//! RESP 帧解析的属性测试。所有的数据都通过内存中的 duplex 流按任意的切分写入，
+//! 再由 `Connection::read_frame` 读出，检查：
+//!
+//! * 每种帧编码之后都能原样解析回来
+//! * 任意字节都不会导致 panic，并且不管怎样切分，读到的结果都一样
+//! * 恶意的长度前缀和深层嵌套只会得到错误，不会按声明的长度分配内存
+
+use my_redis::{frame, Connection, Frame};
+
+use bytes::Bytes;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use tokio::io::AsyncWriteExt;
+
+/// 记录当前线程中最大的一次内存分配
+struct Tracking;
+
+thread_local! {
+    static LARGEST: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.with(|largest| largest.set(largest.get().max(layout.size())));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.with(|largest| largest.set(largest.get().max(new_size)));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+/// 把 `chunks` 依次写入 duplex 流，返回 `read_frame` 读到的所有帧，出错时以错误结束
+fn read_all(chunks: Vec<Vec<u8>>) -> Vec<Result<Frame, String>> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    rt.block_on(async move {
+        let (mut client, server) = tokio::io::duplex(64);
+
+        let writer = async move {
+            for chunk in chunks {
+                // 读端出错之后会关闭连接，剩下的数据写不进去
+                if client.write_all(&chunk).await.is_err() {
+                    return;
+                }
+                // 让读端先处理这一块，保证切分的位置真的出现在一次读取的末尾
+                tokio::task::yield_now().await;
+            }
+        };
+
+        let reader = async move {
+            let mut connection = Connection::new(server);
+            let mut frames = vec![];
+            loop {
+                match connection.read_frame().await {
+                    Ok(Some(frame)) => frames.push(Ok(frame)),
+                    Ok(None) => break,
+                    Err(err) => {
+                        frames.push(Err(err.to_string()));
+                        break;
+                    }
+                }
+            }
+            frames
+        };
+
+        tokio::join!(writer, reader).1
+    })
+}
+
+/// 在 `splits` 指定的位置切开 `bytes`
+fn split(bytes: &[u8], splits: &[Index]) -> Vec<Vec<u8>> {
+    let mut points: Vec<usize> = splits.iter().map(|i| i.index(bytes.len() + 1)).collect();
+    points.push(0);
+    points.push(bytes.len());
+    points.sort_unstable();
+    points
+        .windows(2)
+        .map(|w| bytes[w[0]..w[1]].to_vec())
+        .collect()
+}
+
+fn encode(frames: &[Frame]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for frame in frames {
+        frame.encode(&mut bytes);
+    }
+    bytes
+}
+
+fn frame() -> impl Strategy<Value = Frame> {
+    let leaf = prop_oneof![
+        "[^\r\n]*".prop_map(Frame::Simple),
+        "[^\r\n]*".prop_map(Frame::Error),
+        any::<i64>().prop_map(Frame::Integer),
+        vec(any::<u8>(), 0..64).prop_map(|bytes| Frame::Bulk(Bytes::from(bytes))),
+        Just(Frame::Null),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| vec(inner, 0..8).prop_map(Frame::Array))
+}
+
+/// 任意字节，以及在合法编码上随机改写、截断得到的字节，后者更容易走到解析的深处
+fn hostile_bytes() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        vec(any::<u8>(), 0..256),
+        (
+            vec(frame(), 1..4),
+            vec((any::<Index>(), any::<u8>()), 0..4),
+            any::<Index>()
+        )
+            .prop_map(|(frames, edits, truncate)| {
+                let mut bytes = encode(&frames);
+                for (index, byte) in edits {
+                    let i = index.index(bytes.len());
+                    bytes[i] = byte;
+                }
+                bytes.truncate(truncate.index(bytes.len() + 1));
+                bytes
+            }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn round_trip(frames in vec(frame(), 0..8), splits in vec(any::<Index>(), 0..16)) {
+        let bytes = encode(&frames);
+        let expected: Vec<_> = frames.into_iter().map(Ok).collect();
+        prop_assert_eq!(read_all(split(&bytes, &splits)), expected);
+    }
+
+    #[test]
+    fn chunking_does_not_matter(bytes in hostile_bytes(), splits in vec(any::<Index>(), 0..16)) {
+        let whole = read_all(vec![bytes.clone()]);
+        prop_assert_eq!(read_all(split(&bytes, &splits)), whole);
+    }
+
+    #[test]
+    fn parse_never_panics(bytes in hostile_bytes()) {
+        // 直接调用 parse，不经过 check
+        let _ = Frame::parse(&mut std::io::Cursor::new(&bytes[..]));
+    }
+}
+
+#[test]
+fn hostile_length_prefixes() {
+    let inputs: Vec<Vec<u8>> = vec![
+        b"$9223372036854775807\r\n".to_vec(),
+        b"$536870913\r\nabc".to_vec(),
+        b"$-2\r\n".to_vec(),
+        b"$18446744073709551616\r\n".to_vec(),
+        b"*9223372036854775807\r\n".to_vec(),
+        b"*2147483647\r\n:1\r\n".to_vec(),
+        b"*-5\r\n".to_vec(),
+        b"*1\r\n".repeat(100_000),
+    ];
+
+    for input in inputs {
+        LARGEST.with(|largest| largest.set(0));
+        let result = read_all(vec![input.clone()]);
+        let largest = LARGEST.with(|largest| largest.get());
+
+        assert!(
+            matches!(result.last(), Some(Err(_))),
+            "{:?} => {:?}",
+            String::from_utf8_lossy(&input[..input.len().min(32)]),
+            result
+        );
+        // 最大的分配来自保存输入本身，和声明的长度无关
+        assert!(
+            largest <= 4 * input.len() + 64 * 1024,
+            "allocated {}",
+            largest
+        );
+    }
+}
+
+#[test]
+fn null_array() {
+    assert_eq!(
+        read_all(vec![b"*-1\r\n$-1\r\n".to_vec()]),
+        vec![Ok(Frame::Null), Ok(Frame::Null)]
+    );
+}
+
+#[test]
+fn line_length_limit() {
+    let max = 64 * 1024;
+    let simple = |len: usize| format!("+{}\r\n", "a".repeat(len)).into_bytes();
+
+    assert_eq!(
+        read_all(vec![simple(max)]),
+        vec![Ok(Frame::Simple("a".repeat(max)))]
+    );
+    for input in [
+        simple(max + 1),
+        format!("-{}\r\n", "e".repeat(max + 1)).into_bytes(),
+        format!(":{}1\r\n", "0".repeat(max)).into_bytes(),
+        format!("*1\r\n${}1\r\nx\r\n", "0".repeat(max)).into_bytes(),
+    ] {
+        let result = read_all(vec![input]);
+        assert!(
+            matches!(&result[..], [Err(err)] if err.contains("line too long")),
+            "{:?}",
+            result
+        );
+    }
+
+    // 对端一直不发送 \r\n 时，超过上限就报错，不会一直缓存下去
+    let mut checker = frame::Checker::default();
+    let line = simple(max + 2);
+    let unterminated = &line[..line.len() - 2];
+    assert!(matches!(
+        checker.check(&unterminated[..max + 1]),
+        Err(frame::Error::Incomplete)
+    ));
+    assert!(matches!(
+        checker.check(unterminated),
+        Err(frame::Error::Other(_))
+    ));
+}
+
+/// 逐块追加数据，每追加一块检查一次，返回完整时缓冲区的长度
+fn check_in_chunks(bytes: &[u8], chunk: usize) -> usize {
+    let mut checker = frame::Checker::default();
+    for end in (chunk..bytes.len()).step_by(chunk) {
+        match checker.check(&bytes[..end]) {
+            Err(frame::Error::Incomplete) => {}
+            result => panic!("complete too early at {}: {:?}", end, result),
+        }
+    }
+    checker.check(bytes).unwrap()
+}
+
+#[test]
+fn checker_resumes_where_it_stopped() {
+    // 每次都从头检查时，下面两种输入的开销和长度的平方成正比，测试会慢得无法完成
+    let mut array = b"*100000\r\n".to_vec();
+    for i in 0..100_000 {
+        array.extend_from_slice(format!(":{}\r\n", i).as_bytes());
+    }
+    array.extend_from_slice(b"+next\r\n");
+    let len = array.len() - b"+next\r\n".len();
+    assert_eq!(check_in_chunks(&array[..len], 7), len);
+
+    let long = format!("+{}\r\n", "a".repeat(60_000)).into_bytes();
+    assert_eq!(check_in_chunks(&long, 1), long.len());
+
+    // 嵌套的数组和 bulk，每种切分方式的结果都和一次检查完全一样
+    let nested = encode(&[Frame::Array(vec![
+        Frame::Array(vec![Frame::Integer(1), Frame::Null, Frame::array()]),
+        Frame::Bulk(Bytes::from_static(b"bulk\r\ndata")),
+        Frame::Array(vec![Frame::Array(vec![Frame::Simple("x".into())])]),
+    ])]);
+    let mut whole = std::io::Cursor::new(&nested[..]);
+    Frame::check(&mut whole).unwrap();
+    for chunk in 1..nested.len() {
+        assert_eq!(check_in_chunks(&nested, chunk), nested.len());
+    }
+
+    // 完整的帧返回之后状态被重置，可以接着检查下一个帧
+    let mut checker = frame::Checker::default();
+    assert_eq!(checker.check(b"*2\r\n:1\r\n:2\r\n").unwrap(), 12);
+    assert_eq!(checker.check(b"$3\r\nabc\r\n").unwrap(), 9);
+}
@@ -0,0 +1,617 @@
+//! GEO 命令的测试。期望值来自 Redis 文档中的例子(Sicily)，极点和 ±180 经线附近的查询和逐个计算距离的结果比较
+
+mod common;
+
+use common::{assert_error, bulk, bulks};
+
+use my_redis::geohash::{self, Shape};
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+use bytes::Bytes;
+use std::collections::BTreeSet;
+
+fn to_f64(frame: &Frame) -> f64 {
+    match frame {
+        Frame::Bulk(data) => std::str::from_utf8(data).unwrap().parse().unwrap(),
+        frame => panic!("expected a number, got {:?}", frame),
+    }
+}
+
+async fn sicily() -> (TestServer, TestConnection) {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    let added = conn
+        .command(&[
+            "GEOADD",
+            "Sicily",
+            "13.361389",
+            "38.115556",
+            "Palermo",
+            "15.087269",
+            "37.502669",
+            "Catania",
+        ])
+        .await
+        .unwrap();
+    assert_eq!(added, Frame::Integer(2));
+    (server, conn)
+}
+
+#[tokio::test(start_paused = true)]
+async fn geodist_and_geohash() {
+    let (_server, mut conn) = sicily().await;
+
+    for (unit, expected) in [
+        (None, "166274.1516"),
+        (Some("m"), "166274.1516"),
+        (Some("km"), "166.2742"),
+        (Some("KM"), "166.2742"),
+        (Some("mi"), "103.3182"),
+        (Some("ft"), "545518.8700"),
+    ] {
+        let mut args = vec!["GEODIST", "Sicily", "Palermo", "Catania"];
+        args.extend(unit);
+        assert_eq!(
+            conn.command(&args).await.unwrap(),
+            bulk(expected),
+            "{:?}",
+            unit
+        );
+    }
+    assert_eq!(
+        conn.command(&["GEODIST", "Sicily", "Palermo", "Palermo"])
+            .await
+            .unwrap(),
+        bulk("0.0000")
+    );
+    assert_eq!(
+        conn.command(&["GEODIST", "Sicily", "Palermo", "Rome"])
+            .await
+            .unwrap(),
+        Frame::Null
+    );
+    assert_error(
+        conn.command(&["GEODIST", "Sicily", "Palermo", "Catania", "yd"])
+            .await
+            .unwrap(),
+        "unsupported unit",
+    );
+
+    assert_eq!(
+        conn.command(&["GEOHASH", "Sicily", "Palermo", "Catania", "Rome"])
+            .await
+            .unwrap(),
+        Frame::Array(vec![bulk("sqc8b49rny0"), bulk("sqdtr74hyu0"), Frame::Null])
+    );
+
+    // 坐标是 geohash 格子的中心，和 Redis 一样与输入有很小的误差
+    match conn
+        .command(&["GEOPOS", "Sicily", "Palermo", "Rome"])
+        .await
+        .unwrap()
+    {
+        Frame::Array(items) => {
+            assert_eq!(items.len(), 2);
+            match &items[0] {
+                Frame::Array(pos) => {
+                    assert!((to_f64(&pos[0]) - 13.361389338970184).abs() < 1e-9);
+                    assert!((to_f64(&pos[1]) - 38.1155563954963).abs() < 1e-9);
+                }
+                frame => panic!("unexpected position {:?}", frame),
+            }
+            assert_eq!(items[1], Frame::Null);
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn geoadd_options_and_validation() {
+    let (_server, mut conn) = sicily().await;
+
+    // NX 不更新已有的成员，XX 不新增成员，CH 同时计算位置变化的成员
+    assert_eq!(
+        conn.command(&["GEOADD", "Sicily", "NX", "13", "38", "Palermo"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["GEOADD", "Sicily", "XX", "CH", "13", "38", "Palermo", "14", "37", "Enna"])
+            .await
+            .unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["GEOHASH", "Sicily", "Enna"]).await.unwrap(),
+        Frame::Array(vec![Frame::Null])
+    );
+    assert_error(
+        conn.command(&["GEOADD", "Sicily", "NX", "XX", "13", "38", "Palermo"])
+            .await
+            .unwrap(),
+        "ERR",
+    );
+
+    for (lon, lat) in [("180.1", "0"), ("-181", "0"), ("0", "85.06"), ("0", "-86")] {
+        assert_error(
+            conn.command(&["GEOADD", "Sicily", lon, lat, "bad"])
+                .await
+                .unwrap(),
+            "invalid longitude,latitude pair",
+        );
+    }
+    // 边界上的坐标是合法的
+    assert_eq!(
+        conn.command(&[
+            "GEOADD",
+            "edges",
+            "180",
+            "85.05112878",
+            "ne",
+            "-180",
+            "-85.05112878",
+            "sw",
+        ])
+        .await
+        .unwrap(),
+        Frame::Integer(2)
+    );
+    assert_eq!(
+        conn.command(&["TYPE", "Sicily"]).await.unwrap(),
+        Frame::Simple("zset".to_string())
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn geosearch_radius_and_box() {
+    let (_server, mut conn) = sicily().await;
+    conn.command(&[
+        "GEOADD",
+        "Sicily",
+        "12.758489",
+        "38.788135",
+        "edge1",
+        "17.241510",
+        "38.788135",
+        "edge2",
+    ])
+    .await
+    .unwrap();
+
+    // Redis 文档中的例子
+    assert_eq!(
+        conn.command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYRADIUS",
+            "200",
+            "km",
+            "ASC",
+        ])
+        .await
+        .unwrap(),
+        bulks(&["Catania", "Palermo"])
+    );
+    assert_eq!(
+        conn.command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYBOX",
+            "400",
+            "400",
+            "km",
+            "ASC",
+            "WITHDIST",
+        ])
+        .await
+        .unwrap(),
+        Frame::Array(vec![
+            Frame::Array(vec![bulk("Catania"), bulk("56.4413")]),
+            Frame::Array(vec![bulk("Palermo"), bulk("190.4424")]),
+            Frame::Array(vec![bulk("edge2"), bulk("279.7403")]),
+            Frame::Array(vec![bulk("edge1"), bulk("279.7405")]),
+        ])
+    );
+    assert_eq!(
+        conn.command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYBOX",
+            "400",
+            "400",
+            "km",
+            "DESC",
+        ])
+        .await
+        .unwrap(),
+        bulks(&["edge1", "edge2", "Palermo", "Catania"])
+    );
+
+    // 只有 COUNT 时返回最近的几个；DESC 先排序再截断
+    assert_eq!(
+        conn.command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMMEMBER",
+            "Palermo",
+            "BYRADIUS",
+            "500",
+            "km",
+            "COUNT",
+            "2",
+        ])
+        .await
+        .unwrap(),
+        bulks(&["Palermo", "edge1"])
+    );
+    assert_eq!(
+        conn.command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYBOX",
+            "400",
+            "400",
+            "km",
+            "DESC",
+            "COUNT",
+            "1",
+        ])
+        .await
+        .unwrap(),
+        bulks(&["edge1"])
+    );
+    // ANY 找到足够的成员就返回，结果是区域中的任意成员
+    match conn
+        .command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYBOX",
+            "400",
+            "400",
+            "km",
+            "COUNT",
+            "3",
+            "ANY",
+        ])
+        .await
+        .unwrap()
+    {
+        Frame::Array(items) => {
+            assert_eq!(items.len(), 3);
+            let all = ["Catania", "Palermo", "edge1", "edge2"];
+            assert!(items
+                .iter()
+                .all(|item| all.iter().any(|name| *item == bulk(name))));
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+
+    // WITHDIST 使用查询的单位，WITHHASH 是 52 位分数，WITHCOORD 是格子的中心
+    match conn
+        .command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMMEMBER",
+            "Catania",
+            "BYRADIUS",
+            "100",
+            "mi",
+            "WITHCOORD",
+            "WITHDIST",
+            "WITHHASH",
+        ])
+        .await
+        .unwrap()
+    {
+        Frame::Array(items) => {
+            assert_eq!(items.len(), 1);
+            let expected_hash = geohash::encode(15.087269, 37.502669) as i64;
+            match &items[0] {
+                Frame::Array(fields) => {
+                    assert_eq!(fields[0], bulk("Catania"));
+                    assert_eq!(fields[1], bulk("0.0000"));
+                    assert_eq!(fields[2], Frame::Integer(expected_hash));
+                    assert!(matches!(&fields[3], Frame::Array(pos) if pos.len() == 2));
+                }
+                frame => panic!("unexpected item {:?}", frame),
+            }
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+
+    assert_eq!(
+        conn.command(&[
+            "GEOSEARCH",
+            "missing",
+            "FROMLONLAT",
+            "0",
+            "0",
+            "BYRADIUS",
+            "1",
+            "m"
+        ])
+        .await
+        .unwrap(),
+        bulks(&[])
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn geosearch_arguments() {
+    let (_server, mut conn) = sicily().await;
+
+    for (args, expected) in [
+        (
+            &["GEOSEARCH", "Sicily", "BYRADIUS", "1", "km"][..],
+            "exactly one of FROMMEMBER or FROMLONLAT",
+        ),
+        (
+            &["GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37"],
+            "exactly one of BYRADIUS and BYBOX",
+        ),
+        (
+            &[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "1",
+                "km",
+                "BYBOX",
+                "1",
+                "1",
+                "km",
+            ],
+            "exactly one of BYRADIUS and BYBOX",
+        ),
+        (
+            &[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "1",
+                "km",
+                "ANY",
+            ],
+            "the ANY argument requires COUNT argument",
+        ),
+        (
+            &[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "1",
+                "km",
+                "COUNT",
+                "0",
+            ],
+            "COUNT must be > 0",
+        ),
+        (
+            &[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "-1",
+                "km",
+            ],
+            "radius cannot be negative",
+        ),
+        (
+            &[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMLONLAT",
+                "190",
+                "37",
+                "BYRADIUS",
+                "1",
+                "km",
+            ],
+            "invalid longitude,latitude pair",
+        ),
+        (
+            &[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMMEMBER",
+                "Rome",
+                "BYRADIUS",
+                "1",
+                "km",
+            ],
+            "could not decode requested zset member",
+        ),
+    ] {
+        assert_error(conn.command(args).await.unwrap(), expected);
+    }
+
+    conn.command(&["SET", "s", "v"]).await.unwrap();
+    for args in [
+        &["GEOADD", "s", "13", "38", "m"][..],
+        &["GEOPOS", "s", "m"],
+        &["GEODIST", "s", "a", "b"],
+        &["GEOHASH", "s", "m"],
+        &[
+            "GEOSEARCH",
+            "s",
+            "FROMLONLAT",
+            "0",
+            "0",
+            "BYRADIUS",
+            "1",
+            "km",
+        ],
+    ] {
+        assert_error(conn.command(args).await.unwrap(), "WRONGTYPE");
+    }
+}
+
+/// 网格上的点，加上极点和 ±180 经线附近更密的点
+fn grid() -> Vec<(f64, f64)> {
+    let mut points = vec![];
+    for lat in (-34..=34).map(|i| i as f64 * 2.5) {
+        for lon in (-24..24).map(|i| i as f64 * 7.5) {
+            points.push((lon, lat));
+        }
+    }
+    for lat in (0..=20).map(|i| 80.0 + i as f64 * 0.25) {
+        for lon in (-36..36).map(|i| i as f64 * 5.0) {
+            points.push((lon, lat.min(geohash::LAT_MAX)));
+            points.push((lon, -lat.min(geohash::LAT_MAX)));
+        }
+    }
+    for lon in [179.0, 179.5, 179.9, 180.0, -180.0, -179.9, -179.5, -179.0] {
+        for lat in (-8..=8).map(|i| i as f64 * 10.0) {
+            points.push((lon, lat));
+        }
+    }
+    points
+}
+
+#[tokio::test(start_paused = true)]
+async fn geosearch_near_poles_and_antimeridian() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    let points = grid();
+    let names: Vec<String> = (0..points.len()).map(|i| format!("p{}", i)).collect();
+    let values: Vec<(String, String)> = points
+        .iter()
+        .map(|(lon, lat)| (lon.to_string(), lat.to_string()))
+        .collect();
+    for chunk in (0..points.len()).collect::<Vec<_>>().chunks(500) {
+        let mut args = vec!["GEOADD", "world"];
+        for &i in chunk {
+            args.extend([
+                values[i].0.as_str(),
+                values[i].1.as_str(),
+                names[i].as_str(),
+            ]);
+        }
+        conn.command(&args).await.unwrap();
+    }
+    // 位置都按 geohash 格子的中心计算距离，和服务端保存的一样
+    let stored: Vec<(f64, f64)> = points
+        .iter()
+        .map(|&(lon, lat)| geohash::decode(geohash::encode(lon, lat)))
+        .collect();
+
+    let centers = [
+        (0.0, 85.0),
+        (179.9, 84.0),
+        (-135.0, -85.05112878),
+        (180.0, 0.0),
+        (-180.0, -60.0),
+        (179.95, 30.0),
+    ];
+    let shapes = [
+        (Shape::Radius(300_000.0), vec!["BYRADIUS", "300", "km"]),
+        (Shape::Radius(1_500_000.0), vec!["BYRADIUS", "1500", "km"]),
+        (Shape::Radius(5_000_000.0), vec!["BYRADIUS", "5000", "km"]),
+        (
+            Shape::Box {
+                width: 1_000_000.0,
+                height: 600_000.0,
+            },
+            vec!["BYBOX", "1000", "600", "km"],
+        ),
+        (
+            Shape::Box {
+                width: 4_000_000.0,
+                height: 2_000_000.0,
+            },
+            vec!["BYBOX", "4000", "2000", "km"],
+        ),
+    ];
+
+    for (lon, lat) in centers {
+        let (lon_arg, lat_arg) = (lon.to_string(), lat.to_string());
+        for (shape, by) in &shapes {
+            let mut args = vec!["GEOSEARCH", "world", "FROMLONLAT", &lon_arg, &lat_arg];
+            args.extend(by.iter().copied());
+            let found: BTreeSet<Bytes> = match conn.command(&args).await.unwrap() {
+                Frame::Array(items) => items
+                    .into_iter()
+                    .map(|item| match item {
+                        Frame::Bulk(name) => name,
+                        frame => panic!("unexpected item {:?}", frame),
+                    })
+                    .collect(),
+                frame => panic!("unexpected reply {:?}", frame),
+            };
+
+            // 逐个检查所有的点，范围查询不能漏掉任何一个
+            let expected: BTreeSet<Bytes> = stored
+                .iter()
+                .enumerate()
+                .filter(|(_, &point)| shape.distance((lon, lat), point).is_some())
+                .map(|(i, _)| Bytes::from(names[i].clone()))
+                .collect();
+            assert!(!expected.is_empty(), "{:?}", args);
+            assert_eq!(found, expected, "{:?}", args);
+        }
+    }
+
+    // ±180 经线两侧的点相距很近
+    let near: BTreeSet<Bytes> = points
+        .iter()
+        .enumerate()
+        .filter(|(_, &(lon, lat))| lat == 0.0 && [179.9, 180.0, -180.0, -179.9].contains(&lon))
+        .map(|(i, _)| Bytes::from(names[i].clone()))
+        .collect();
+    match conn
+        .command(&[
+            "GEOSEARCH",
+            "world",
+            "FROMLONLAT",
+            "179.95",
+            "0",
+            "BYRADIUS",
+            "20",
+            "km",
+        ])
+        .await
+        .unwrap()
+    {
+        Frame::Array(items) => {
+            let found: BTreeSet<Bytes> = items
+                .into_iter()
+                .map(|item| match item {
+                    Frame::Bulk(name) => name,
+                    frame => panic!("unexpected item {:?}", frame),
+                })
+                .collect();
+            assert_eq!(found, near);
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
@@ -0,0 +1,205 @@
+//! HyperLogLog 命令的测试：PFADD / PFCOUNT / PFMERGE，用 `PFDEBUG ENCODING` 检查稀疏和密集编码
+
+mod common;
+
+use common::assert_error;
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+async fn encoding(conn: &mut TestConnection, key: &str) -> String {
+    match conn.command(&["PFDEBUG", "ENCODING", key]).await.unwrap() {
+        Frame::Simple(encoding) => encoding,
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+async fn pfcount(conn: &mut TestConnection, keys: &[&str]) -> i64 {
+    let mut args = vec!["PFCOUNT"];
+    args.extend_from_slice(keys);
+    match conn.command(&args).await.unwrap() {
+        Frame::Integer(n) => n,
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+/// 把 `prefix:start` 到 `prefix:end - 1` 分批加入
+async fn pfadd_range(conn: &mut TestConnection, key: &str, prefix: &str, start: u64, end: u64) {
+    let elements: Vec<String> = (start..end).map(|i| format!("{}:{}", prefix, i)).collect();
+    for chunk in elements.chunks(1000) {
+        let mut args = vec!["PFADD", key];
+        args.extend(chunk.iter().map(String::as_str));
+        conn.command(&args).await.unwrap();
+    }
+}
+
+/// 标准误差是 1.04 / sqrt(2^14) ≈ 0.81%，允许 3 倍标准误差
+fn assert_close(count: i64, expected: u64) {
+    let error = (count - expected as i64).abs() as f64 / expected as f64;
+    assert!(
+        error < 0.025,
+        "count {} expected {} error {}",
+        count,
+        expected,
+        error
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn pfadd_reports_updates() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    // 没有元素时也会创建 key
+    assert_eq!(
+        conn.command(&["PFADD", "h"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["PFADD", "h"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(pfcount(&mut conn, &["h"]).await, 0);
+
+    assert_eq!(
+        conn.command(&["PFADD", "h", "a", "b", "c"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    // 重复的元素不会更新任何寄存器
+    assert_eq!(
+        conn.command(&["PFADD", "h", "a", "b"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(pfcount(&mut conn, &["h"]).await, 3);
+    assert_eq!(pfcount(&mut conn, &["missing"]).await, 0);
+    assert_eq!(
+        conn.command(&["TYPE", "h"]).await.unwrap(),
+        Frame::Simple("string".to_string())
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn pfcount_error_bounds() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    let mut added = 0;
+    for expected in [10, 100, 1_000, 10_000, 100_000] {
+        pfadd_range(&mut conn, "h", "element", added, expected).await;
+        added = expected;
+
+        let count = pfcount(&mut conn, &["h"]).await;
+        if expected <= 10 {
+            // 基数很小时寄存器几乎没有冲突，估计值是精确的
+            assert_eq!(count, expected as i64);
+        } else {
+            assert_close(count, expected);
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn sparse_to_dense() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    pfadd_range(&mut conn, "h", "element", 0, 500).await;
+    assert_eq!(encoding(&mut conn, "h").await, "sparse");
+    let sparse_count = pfcount(&mut conn, &["h"]).await;
+
+    // 非 0 寄存器超过 1024 个之后转换成密集编码，之前加入的元素不受影响
+    pfadd_range(&mut conn, "h", "element", 0, 500).await;
+    assert_eq!(pfcount(&mut conn, &["h"]).await, sparse_count);
+    pfadd_range(&mut conn, "h", "element", 500, 3_000).await;
+    assert_eq!(encoding(&mut conn, "h").await, "dense");
+    assert_close(pfcount(&mut conn, &["h"]).await, 3_000);
+
+    // 转换之后不会再回到稀疏编码，合并到新 key 也得到同样的基数
+    pfadd_range(&mut conn, "h", "element", 0, 3_000).await;
+    assert_eq!(encoding(&mut conn, "h").await, "dense");
+    let count = pfcount(&mut conn, &["h"]).await;
+    conn.command(&["PFMERGE", "copy", "h"]).await.unwrap();
+    assert_eq!(pfcount(&mut conn, &["copy"]).await, count);
+
+    assert_error(
+        conn.command(&["PFDEBUG", "ENCODING", "missing"])
+            .await
+            .unwrap(),
+        "does not exist",
+    );
+    assert_error(
+        conn.command(&["PFDEBUG", "GETREG", "h"]).await.unwrap(),
+        "Unknown PFDEBUG subcommand",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn pfmerge_includes_destination() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["PFADD", "dest", "a", "b"]).await.unwrap();
+    conn.command(&["PFADD", "src1", "b", "c"]).await.unwrap();
+    conn.command(&["PFADD", "src2", "c", "d", "e"])
+        .await
+        .unwrap();
+
+    // 多个 key 的 PFCOUNT 是并集的基数
+    assert_eq!(pfcount(&mut conn, &["dest", "src1", "src2"]).await, 5);
+    assert_eq!(pfcount(&mut conn, &["src1", "missing"]).await, 2);
+
+    // destkey 已有的元素保留下来，源 key 不变
+    assert_eq!(
+        conn.command(&["PFMERGE", "dest", "src1", "src2"])
+            .await
+            .unwrap(),
+        Frame::Simple("OK".to_string())
+    );
+    assert_eq!(pfcount(&mut conn, &["dest"]).await, 5);
+    assert_eq!(pfcount(&mut conn, &["src1"]).await, 2);
+
+    // destkey 也可以出现在源 key 中，没有源 key 时只是确保 destkey 存在
+    conn.command(&["PFMERGE", "dest", "dest"]).await.unwrap();
+    assert_eq!(pfcount(&mut conn, &["dest"]).await, 5);
+    conn.command(&["PFMERGE", "empty"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["EXISTS", "empty"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(pfcount(&mut conn, &["empty"]).await, 0);
+
+    // 稀疏和密集编码的 HyperLogLog 可以互相合并
+    pfadd_range(&mut conn, "big", "element", 0, 5_000).await;
+    assert_eq!(encoding(&mut conn, "big").await, "dense");
+    conn.command(&["PFMERGE", "dest", "big"]).await.unwrap();
+    assert_close(pfcount(&mut conn, &["dest"]).await, 5_005);
+}
+
+#[tokio::test(start_paused = true)]
+async fn wrong_type() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["SET", "s", "v"]).await.unwrap();
+    conn.command(&["SADD", "set", "m"]).await.unwrap();
+    conn.command(&["BF.ADD", "bf", "x"]).await.unwrap();
+    conn.command(&["PFADD", "h", "a"]).await.unwrap();
+
+    for key in ["s", "set", "bf"] {
+        for args in [
+            &["PFADD", key, "a"][..],
+            &["PFCOUNT", key],
+            &["PFCOUNT", "h", key],
+            &["PFMERGE", key, "h"],
+            &["PFMERGE", "h", key],
+            &["PFDEBUG", "ENCODING", key],
+        ] {
+            assert_error(conn.command(args).await.unwrap(), "WRONGTYPE");
+        }
+    }
+    // 出错的 PFMERGE 不会修改 destkey
+    assert_eq!(pfcount(&mut conn, &["h"]).await, 1);
+    assert_error(conn.command(&["GET", "h"]).await.unwrap(), "WRONGTYPE");
+    assert_error(
+        conn.command(&["PFCOUNT"]).await.unwrap(),
+        "wrong number of arguments",
+    );
+}
@@ -0,0 +1,163 @@
+//! 列表命令的测试：LPUSH / RPUSH / LPOP / RPOP / LLEN / LRANGE
+
+mod common;
+
+use common::{assert_error, bulk, bulks};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+async fn lrange(conn: &mut TestConnection, start: &str, stop: &str) -> Frame {
+    conn.command(&["LRANGE", "l", start, stop]).await.unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn push_order() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    // LPUSH 逐个插入到队首，所以顺序是反的
+    assert_eq!(
+        conn.command(&["LPUSH", "l", "a", "b", "c"]).await.unwrap(),
+        Frame::Integer(3)
+    );
+    assert_eq!(
+        conn.command(&["RPUSH", "l", "x", "y"]).await.unwrap(),
+        Frame::Integer(5)
+    );
+    assert_eq!(
+        lrange(&mut conn, "0", "-1").await,
+        bulks(&["c", "b", "a", "x", "y"])
+    );
+    assert_eq!(
+        conn.command(&["LLEN", "l"]).await.unwrap(),
+        Frame::Integer(5)
+    );
+    assert_eq!(
+        conn.command(&["LLEN", "missing"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["TYPE", "l"]).await.unwrap(),
+        Frame::Simple("list".to_string())
+    );
+
+    assert_error(
+        conn.command(&["LPUSH", "l"]).await.unwrap(),
+        "wrong number of arguments",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn lrange_bounds() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["RPUSH", "l", "a", "b", "c", "d", "e"])
+        .await
+        .unwrap();
+
+    assert_eq!(lrange(&mut conn, "1", "3").await, bulks(&["b", "c", "d"]));
+    assert_eq!(lrange(&mut conn, "-2", "-1").await, bulks(&["d", "e"]));
+    assert_eq!(lrange(&mut conn, "-100", "1").await, bulks(&["a", "b"]));
+    assert_eq!(lrange(&mut conn, "2", "100").await, bulks(&["c", "d", "e"]));
+    assert_eq!(lrange(&mut conn, "3", "1").await, bulks(&[]));
+    assert_eq!(lrange(&mut conn, "5", "10").await, bulks(&[]));
+    assert_eq!(lrange(&mut conn, "0", "-6").await, bulks(&[]));
+    assert_eq!(
+        lrange(&mut conn, "-9223372036854775808", "9223372036854775807").await,
+        bulks(&["a", "b", "c", "d", "e"])
+    );
+    assert_eq!(
+        conn.command(&["LRANGE", "missing", "0", "-1"])
+            .await
+            .unwrap(),
+        bulks(&[])
+    );
+
+    assert_error(lrange(&mut conn, "a", "1").await, "ERR");
+}
+
+#[tokio::test(start_paused = true)]
+async fn pop_from_both_ends() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["RPUSH", "l", "a", "b", "c", "d", "e"])
+        .await
+        .unwrap();
+
+    assert_eq!(conn.command(&["LPOP", "l"]).await.unwrap(), bulk("a"));
+    assert_eq!(conn.command(&["RPOP", "l"]).await.unwrap(), bulk("e"));
+    assert_eq!(
+        conn.command(&["LPOP", "l", "2"]).await.unwrap(),
+        bulks(&["b", "c"])
+    );
+    // count 超过长度时返回所有剩下的元素，列表空了之后 key 被删除
+    assert_eq!(
+        conn.command(&["RPOP", "l", "10"]).await.unwrap(),
+        bulks(&["d"])
+    );
+    assert_eq!(
+        conn.command(&["EXISTS", "l"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    // key 不存在时不管有没有 count 都回复 Null
+    assert_eq!(conn.command(&["LPOP", "l"]).await.unwrap(), Frame::Null);
+    assert_eq!(
+        conn.command(&["RPOP", "l", "3"]).await.unwrap(),
+        Frame::Null
+    );
+
+    assert_error(
+        conn.command(&["LPOP", "l", "-1"]).await.unwrap(),
+        "value is out of range, must be positive",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn list_commands_check_type() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["SET", "s", "v"]).await.unwrap();
+    conn.command(&["RPUSH", "l", "a"]).await.unwrap();
+
+    for args in [
+        &["LPUSH", "s", "a"][..],
+        &["RPUSH", "s", "a"],
+        &["LPOP", "s"],
+        &["RPOP", "s", "1"],
+        &["LLEN", "s"],
+        &["LRANGE", "s", "0", "-1"],
+    ] {
+        assert_error(conn.command(args).await.unwrap(), "WRONGTYPE");
+    }
+    // 字符串命令也不能操作列表
+    assert_error(conn.command(&["GET", "l"]).await.unwrap(), "WRONGTYPE");
+    assert_eq!(conn.command(&["GET", "s"]).await.unwrap(), bulk("v"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn list_expires_with_key() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["RPUSH", "l", "a", "b"]).await.unwrap();
+    conn.command(&["PEXPIRE", "l", "100"]).await.unwrap();
+
+    // 修改列表不会改变过期时间
+    conn.command(&["RPUSH", "l", "c"]).await.unwrap();
+    conn.command(&["LPOP", "l"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["PTTL", "l"]).await.unwrap(),
+        Frame::Integer(100)
+    );
+
+    tokio::time::advance(std::time::Duration::from_millis(100)).await;
+    assert_eq!(
+        conn.command(&["LLEN", "l"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["LRANGE", "l", "0", "-1"]).await.unwrap(),
+        bulks(&[])
+    );
+}
@@ -0,0 +1,260 @@
+//! `pool` 模块的测试。服务端是测试中手写的假服务端，对任何命令都回复 `PONG`，
+//! 并记录建立了多少个连接，这样可以检查连接池什么时候复用、什么时候新建连接。
+//! 需要真实命令(SELECT)的测试连接 `TestServer::bind` 启动的服务端。
+//!
+//! 连接走的是真实的 TCP，不能暂停时钟，超时时间都设置得比较短
+
+use my_redis::pool::{self, Pool, PoolConfig};
+use my_redis::testing::TestServer;
+use my_redis::{Config, Connection, Frame};
+
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::{self, Instant};
+
+/// 假服务端的状态
+#[derive(Default)]
+struct FakeServer {
+    /// 接受的连接数
+    accepted: AtomicUsize,
+    /// 设置之后第一个连接不再回复，模拟卡住的连接
+    mute_first: AtomicBool,
+}
+
+impl FakeServer {
+    async fn start() -> (SocketAddr, Arc<FakeServer>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(FakeServer::default());
+
+        tokio::spawn({
+            let state = state.clone();
+            async move {
+                loop {
+                    let (socket, _) = listener.accept().await.unwrap();
+                    let id = state.accepted.fetch_add(1, Ordering::SeqCst);
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        let mut conn = Connection::new(socket);
+                        while let Ok(Some(_)) = conn.read_frame().await {
+                            if id == 0 && state.mute_first.load(Ordering::SeqCst) {
+                                continue;
+                            }
+                            let pong = Frame::Simple("PONG".to_string());
+                            if conn.write_frame(&pong).await.is_err() {
+                                return;
+                            }
+                        }
+                    });
+                }
+            }
+        });
+
+        (addr, state)
+    }
+
+    /// 客户端的 connect 在握手完成时就返回了，先等假服务端的 accept 循环处理完已经建立的连接
+    async fn accepted(&self) -> usize {
+        time::sleep(Duration::from_millis(20)).await;
+        self.accepted.load(Ordering::SeqCst)
+    }
+}
+
+async fn connect(addr: SocketAddr, config: PoolConfig) -> Pool {
+    pool::connect(addr, config).await.unwrap()
+}
+
+#[tokio::test]
+async fn min_and_max_size() {
+    let (addr, server) = FakeServer::start().await;
+    let pool = connect(
+        addr,
+        PoolConfig {
+            min_size: 2,
+            max_size: 3,
+            checkout_timeout: Duration::from_millis(100),
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    // 预先建立 min_size 个连接
+    assert_eq!(pool.idle(), 2);
+    assert_eq!(server.accepted().await, 2);
+
+    let a = pool.get().await.unwrap();
+    let b = pool.get().await.unwrap();
+    let c = pool.get().await.unwrap();
+    assert_eq!((pool.idle(), pool.in_use()), (0, 3));
+    assert_eq!(server.accepted().await, 3);
+    assert_eq!(a.ping(None).await.unwrap(), Bytes::from("PONG"));
+
+    // 耗尽之后等待，超过 checkout_timeout 返回错误
+    let start = Instant::now();
+    let err = pool.get().await.unwrap_err();
+    assert!(err.to_string().contains("timed out waiting"), "{}", err);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // 放回的连接被复用，不会新建
+    drop(b);
+    assert_eq!((pool.idle(), pool.in_use()), (1, 2));
+    let b = pool.get().await.unwrap();
+    assert_eq!(server.accepted().await, 3);
+    drop((a, b, c));
+    assert_eq!((pool.idle(), pool.in_use()), (3, 0));
+
+    for (min_size, max_size) in [(0, 0), (3, 2)] {
+        let config = PoolConfig {
+            min_size,
+            max_size,
+            ..PoolConfig::default()
+        };
+        assert!(pool::connect(addr, config).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn checkout_waits_for_returned_connection() {
+    let (addr, server) = FakeServer::start().await;
+    let pool = connect(
+        addr,
+        PoolConfig {
+            max_size: 1,
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    let held = pool.get().await.unwrap();
+    let waiter = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.get().await.map(|conn| conn.timeout()) }
+    });
+
+    time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiter.is_finished());
+    drop(held);
+
+    waiter.await.unwrap().unwrap();
+    assert_eq!(server.accepted().await, 1);
+}
+
+#[tokio::test]
+async fn timeout_is_reset_when_returned() {
+    let (addr, _server) = FakeServer::start().await;
+    let pool = connect(addr, PoolConfig::default()).await;
+
+    let mut conn = pool.get().await.unwrap();
+    conn.set_timeout(Duration::from_millis(10));
+    assert_eq!(conn.timeout(), Duration::from_millis(10));
+    drop(conn);
+
+    let conn = pool.get().await.unwrap();
+    assert_eq!(conn.timeout(), my_redis::client::DEFAULT_TIMEOUT);
+}
+
+#[tokio::test]
+async fn idle_connections_are_evicted() {
+    let (addr, _server) = FakeServer::start().await;
+    let pool = connect(
+        addr,
+        PoolConfig {
+            min_size: 1,
+            max_size: 4,
+            idle_timeout: Duration::from_millis(100),
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    let conns: Vec<_> = futures::future::join_all((0..3).map(|_| pool.get()))
+        .await
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    drop(conns);
+    assert_eq!(pool.idle(), 3);
+
+    // 空闲超时之后只保留 min_size 个
+    time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(pool.idle(), 1);
+
+    // 正在使用的连接也计入 min_size，空闲的可以全部关闭
+    let held = pool.get().await.unwrap();
+    let extra = pool.get().await.unwrap();
+    drop(extra);
+    assert_eq!(pool.idle(), 1);
+    time::sleep(Duration::from_millis(300)).await;
+    assert_eq!((pool.idle(), pool.in_use()), (0, 1));
+    drop(held);
+}
+
+#[tokio::test]
+async fn unhealthy_connection_is_replaced() {
+    let (addr, server) = FakeServer::start().await;
+    let pool = connect(addr, PoolConfig::default()).await;
+    assert_eq!(server.accepted().await, 1);
+
+    // 唯一的空闲连接不再回复，取出时 PING 超时，连接池丢弃它并新建一个
+    server.mute_first.store(true, Ordering::SeqCst);
+    let conn = pool.get().await.unwrap();
+    assert_eq!(server.accepted().await, 2);
+    assert_eq!(conn.ping(None).await.unwrap(), Bytes::from("PONG"));
+    drop(conn);
+
+    // 丢弃的连接不会再回到池中
+    assert_eq!(pool.idle(), 1);
+    let conn = pool.get().await.unwrap();
+    assert_eq!(conn.ping(None).await.unwrap(), Bytes::from("PONG"));
+    assert_eq!(server.accepted().await, 2);
+}
+
+#[tokio::test]
+async fn checkout_timeout_covers_health_check() {
+    let (addr, server) = FakeServer::start().await;
+    let pool = connect(
+        addr,
+        PoolConfig {
+            checkout_timeout: Duration::from_millis(100),
+            ..PoolConfig::default()
+        },
+    )
+    .await;
+
+    // 健康检查的 PING 要等 500ms 才超时，checkout_timeout 先到
+    server.mute_first.store(true, Ordering::SeqCst);
+    let start = Instant::now();
+    let err = pool.get().await.unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{}", err);
+    assert!(
+        start.elapsed() < Duration::from_millis(400),
+        "waited for PING"
+    );
+    assert_eq!(pool.in_use(), 0);
+}
+
+#[tokio::test]
+async fn selected_db_is_not_returned_to_pool() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let pool = pool::connect(server.addr().unwrap(), PoolConfig::default())
+        .await
+        .unwrap();
+
+    let conn = pool.get().await.unwrap();
+    conn.command(vec![Bytes::from("SELECT"), Bytes::from("3")])
+        .await
+        .unwrap();
+    conn.set("k", Bytes::from("v")).await.unwrap();
+    drop(conn);
+    assert_eq!(pool.idle(), 0);
+
+    // 新建的连接在 0 号数据库上
+    let conn = pool.get().await.unwrap();
+    assert_eq!(conn.get("k").await.unwrap(), None);
+    drop(conn);
+    assert_eq!(pool.idle(), 1);
+}
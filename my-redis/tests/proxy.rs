@@ -0,0 +1,515 @@
+//! `my-redis-proxy` 的测试：后端是 `TestServer::bind` 启动的三个服务端，测试通过代理执行命令，
+//! 再直接连接后端检查 key 实际落在哪里。key 的分布用同样参数的 `Ketama` 预先算出来
+
+mod common;
+
+use common::bulk;
+
+use my_redis::client::{self, Client};
+use my_redis::ketama::Ketama;
+use my_redis::proxy::{self, Backend, ProxyConfig};
+use my_redis::testing::TestServer;
+use my_redis::{Config, Connection, Frame};
+
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+const BACKENDS: usize = 3;
+
+struct Cluster {
+    backends: Vec<TestServer>,
+    ring: Ketama,
+    proxy: Client,
+    addr: String,
+}
+
+impl Cluster {
+    async fn start() -> Cluster {
+        let mut backends = vec![];
+        for _ in 0..BACKENDS {
+            backends.push(TestServer::bind(Config::default()).await.unwrap());
+        }
+        let addrs: Vec<_> = backends
+            .iter()
+            .map(|backend| backend.addr().unwrap().to_string())
+            .collect();
+
+        let proxy = proxy::connect(ProxyConfig {
+            backends: addrs
+                .iter()
+                .map(|addr| Backend {
+                    addr: addr.clone(),
+                    weight: 1,
+                })
+                .collect(),
+            ..ProxyConfig::default()
+        })
+        .await
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { proxy.run(listener).await });
+
+        let servers: Vec<_> = addrs.iter().map(|addr| (addr.as_str(), 1)).collect();
+        Cluster {
+            ring: Ketama::new(&servers),
+            backends,
+            proxy: client::connect(&addr).await.unwrap(),
+            addr,
+        }
+    }
+
+    fn backend_of(&self, key: &str) -> usize {
+        self.ring.get(key.as_bytes()).unwrap()
+    }
+
+    async fn backend(&self, index: usize) -> Client {
+        self.backends[index].client().await.unwrap()
+    }
+
+    /// 每个后端各取 `per_backend` 个 key，按后端轮流排列，这样相邻的 key 总是在不同的后端
+    fn spread_keys(&self, per_backend: usize) -> Vec<String> {
+        let mut by_backend: Vec<Vec<String>> = vec![vec![]; BACKENDS];
+        for i in 0.. {
+            let key = format!("key:{}", i);
+            let backend = self.backend_of(&key);
+            if by_backend[backend].len() < per_backend {
+                by_backend[backend].push(key);
+            }
+            if by_backend.iter().all(|keys| keys.len() == per_backend) {
+                break;
+            }
+        }
+        (0..per_backend)
+            .flat_map(|i| by_backend.iter().map(move |keys| keys[i].clone()))
+            .collect()
+    }
+}
+
+fn command(args: &[&str]) -> Frame {
+    Frame::Array(args.iter().map(|arg| bulk(arg)).collect())
+}
+
+#[tokio::test]
+async fn single_key_commands_go_to_one_backend() {
+    let cluster = Cluster::start().await;
+    let keys = cluster.spread_keys(2);
+
+    for key in &keys {
+        cluster
+            .proxy
+            .set(key, Bytes::from(key.clone()))
+            .await
+            .unwrap();
+    }
+    for key in &keys {
+        let owner = cluster.backend_of(key);
+        for index in 0..BACKENDS {
+            let value = cluster.backend(index).await.get(key).await.unwrap();
+            assert_eq!(
+                value.is_some(),
+                index == owner,
+                "{} on backend {}",
+                key,
+                index
+            );
+        }
+        assert_eq!(
+            cluster.proxy.get(key).await.unwrap(),
+            Some(Bytes::from(key.clone()))
+        );
+    }
+}
+
+#[tokio::test]
+async fn geo_members_are_removed_with_zrem() {
+    let cluster = Cluster::start().await;
+    let args = |args: &[&str]| {
+        args.iter()
+            .map(|arg| Bytes::from(arg.to_string()))
+            .collect()
+    };
+
+    let added = cluster
+        .proxy
+        .command(args(&[
+            "GEOADD",
+            "places",
+            "13.361389",
+            "38.115556",
+            "Palermo",
+            "15.087269",
+            "37.502669",
+            "Catania",
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(added, Frame::Integer(2));
+
+    // GEOADD 写入的有序集合和 ZREM / ZCARD 落在同一个后端
+    let zrem = args(&["ZREM", "places", "Palermo"]);
+    assert_eq!(
+        cluster.proxy.command(zrem).await.unwrap(),
+        Frame::Integer(1)
+    );
+    let zcard = args(&["ZCARD", "places"]);
+    assert_eq!(
+        cluster.proxy.command(zcard).await.unwrap(),
+        Frame::Integer(1)
+    );
+
+    let owner = cluster.backend(cluster.backend_of("places")).await;
+    let zcard = args(&["ZCARD", "places"]);
+    assert_eq!(owner.command(zcard).await.unwrap(), Frame::Integer(1));
+}
+
+#[tokio::test]
+async fn mget_keeps_key_order() {
+    let cluster = Cluster::start().await;
+    let keys = cluster.spread_keys(3);
+
+    // 只设置一部分 key，缺少的位置应该是 nil
+    for key in keys.iter().step_by(2) {
+        cluster
+            .proxy
+            .set(key, Bytes::from(key.clone()))
+            .await
+            .unwrap();
+    }
+
+    // 倒序并重复一个 key，回复仍然和请求的顺序一一对应
+    let mut request: Vec<&str> = keys.iter().rev().map(String::as_str).collect();
+    request.push(&keys[0]);
+    let values = cluster.proxy.mget(&request).await.unwrap();
+    assert_eq!(values.len(), request.len());
+    for (key, value) in request.iter().zip(values) {
+        let index = keys.iter().position(|k| k == key).unwrap();
+        let expected = (index % 2 == 0).then(|| Bytes::from(key.to_string()));
+        assert_eq!(value, expected, "{}", key);
+    }
+}
+
+#[tokio::test]
+async fn del_and_exists_sum_backend_replies() {
+    let cluster = Cluster::start().await;
+    let keys = cluster.spread_keys(2);
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    for key in &keys[..4] {
+        cluster.proxy.set(key, Bytes::from("v")).await.unwrap();
+    }
+
+    assert_eq!(cluster.proxy.exists(&keys).await.unwrap(), 4);
+    // 和 Redis 一样，重复的 key 计算多次
+    assert_eq!(
+        cluster
+            .proxy
+            .exists(&[keys[0], keys[0], keys[5]])
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        cluster
+            .proxy
+            .del(&[keys[1], keys[2], keys[5]])
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(cluster.proxy.exists(&keys).await.unwrap(), 2);
+    assert_eq!(cluster.proxy.del(&keys).await.unwrap(), 2);
+
+    for index in 0..BACKENDS {
+        assert_eq!(cluster.backend(index).await.dbsize().await.unwrap(), 0);
+    }
+}
+
+#[tokio::test]
+async fn mset_is_split_by_backend() {
+    let cluster = Cluster::start().await;
+    let keys = cluster.spread_keys(2);
+    let pairs: Vec<(&str, Bytes)> = keys
+        .iter()
+        .map(|key| (key.as_str(), Bytes::from(format!("{}-value", key))))
+        .collect();
+    cluster.proxy.mset(&pairs).await.unwrap();
+
+    // 每个后端只收到属于自己的 key
+    for index in 0..BACKENDS {
+        let backend = cluster.backend(index).await;
+        assert_eq!(backend.dbsize().await.unwrap(), 2);
+        for (key, value) in &pairs {
+            let expected = (cluster.backend_of(key) == index).then(|| value.clone());
+            assert_eq!(backend.get(key).await.unwrap(), expected);
+        }
+    }
+
+    let err = cluster
+        .proxy
+        .command(vec![Bytes::from("MSET"), Bytes::from("k")])
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("wrong number of arguments"),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn multi_key_commands_require_same_backend() {
+    let cluster = Cluster::start().await;
+    let keys = cluster.spread_keys(1);
+    for key in &keys {
+        cluster.proxy.sadd(key, &[Bytes::from("m")]).await.unwrap();
+    }
+
+    let err = cluster
+        .proxy
+        .sinter(&[&keys[0], &keys[1]])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("CROSSSLOT"), "{}", err);
+    let err = cluster
+        .proxy
+        .eval("return 1", &[&keys[0], &keys[1]], &[])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("CROSSSLOT"), "{}", err);
+
+    // 相同的 hash tag 总是落在同一个后端，不管 tag 之外的部分是什么
+    let tagged = ["{user:1}.a", "{user:1}.b", "x{user:1}"];
+    let owner = cluster.backend_of("user:1");
+    for key in tagged {
+        assert_eq!(cluster.backend_of(key), owner, "{}", key);
+        cluster
+            .proxy
+            .sadd(key, &[Bytes::from("m"), Bytes::from(key)])
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        cluster.proxy.sinter(&tagged).await.unwrap(),
+        vec![Bytes::from("m")]
+    );
+    assert_eq!(
+        cluster
+            .proxy
+            .sunionstore("{user:1}.all", &tagged)
+            .await
+            .unwrap(),
+        4
+    );
+    assert_eq!(
+        cluster
+            .backend(owner)
+            .await
+            .scard("{user:1}.all")
+            .await
+            .unwrap(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn unsupported_commands_are_rejected() {
+    let cluster = Cluster::start().await;
+    for args in [
+        &["SELECT", "1"][..],
+        &["FLUSHALL"],
+        &["EVAL", "return 1", "0"],
+    ] {
+        let err = cluster
+            .proxy
+            .command(
+                args.iter()
+                    .map(|arg| Bytes::from(arg.to_string()))
+                    .collect(),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("my-redis-proxy"),
+            "{:?} -> {}",
+            args,
+            err
+        );
+    }
+}
+
+#[tokio::test]
+async fn pipelined_replies_keep_request_order() {
+    let cluster = Cluster::start().await;
+    let keys = cluster.spread_keys(20);
+
+    // 一次写入所有命令再读取回复。中间夹着代理直接回复的错误和需要合并多个后端的 MGET，
+    // 它们比前面转发到后端的命令先就绪，仍然要按顺序写回
+    let mut conn = Connection::new(TcpStream::connect(&cluster.addr).await.unwrap());
+    let mut expected = vec![];
+    for (i, key) in keys.iter().enumerate() {
+        let value = i.to_string();
+        conn.buffer_frame(&command(&["SET", key, &value]))
+            .await
+            .unwrap();
+        expected.push(Frame::Simple("OK".to_string()));
+        conn.buffer_frame(&command(&["GET", key])).await.unwrap();
+        expected.push(bulk(&value));
+        if i % 5 == 4 {
+            conn.buffer_frame(&command(&["SELECT", "1"])).await.unwrap();
+            expected.push(Frame::Error(
+                "ERR command 'select' is not supported by my-redis-proxy".to_string(),
+            ));
+            conn.buffer_frame(&command(&["MGET", &keys[i - 2], &keys[i - 1], key]))
+                .await
+                .unwrap();
+            expected.push(Frame::Array(
+                (i - 2..=i).map(|j| bulk(&j.to_string())).collect(),
+            ));
+        }
+    }
+    conn.flush().await.unwrap();
+
+    for (i, frame) in expected.iter().enumerate() {
+        let reply = conn.read_frame().await.unwrap().unwrap();
+        assert_eq!(&reply, frame, "reply {}", i);
+    }
+}
+
+#[test]
+fn ketama_distribution() {
+    let keys: Vec<String> = (0..30_000).map(|i| format!("key:{}", i)).collect();
+    let count = |ring: &Ketama, servers: usize| {
+        let mut counts = vec![0usize; servers];
+        for key in &keys {
+            counts[ring.get(key.as_bytes()).unwrap()] += 1;
+        }
+        counts
+    };
+
+    // 权重相同时每个服务器分到的 key 接近平均值
+    let equal = Ketama::new(&[("a:1", 1), ("b:1", 1), ("c:1", 1)]);
+    for n in count(&equal, 3) {
+        assert!((8_000..12_000).contains(&n), "{}", n);
+    }
+
+    // 权重为 2 的服务器大约分到一半
+    let weighted = Ketama::new(&[("a:1", 2), ("b:1", 1), ("c:1", 1)]);
+    let counts = count(&weighted, 3);
+    assert!((13_000..17_000).contains(&counts[0]), "{:?}", counts);
+
+    // 增加一个服务器时，改变位置的 key 都移到了新的服务器上
+    let grown = Ketama::new(&[("a:1", 1), ("b:1", 1), ("c:1", 1), ("d:1", 1)]);
+    let mut moved = 0;
+    for key in &keys {
+        let before = equal.get(key.as_bytes()).unwrap();
+        let after = grown.get(key.as_bytes()).unwrap();
+        if before != after {
+            assert_eq!(after, 3, "{}", key);
+            moved += 1;
+        }
+    }
+    assert!((6_000..9_000).contains(&moved), "{}", moved);
+
+    // 权重为 0 的服务器不会分到 key，没有可用的服务器时返回 None
+    let zero = Ketama::new(&[("a:1", 1), ("b:1", 0)]);
+    assert!(keys.iter().all(|key| zero.get(key.as_bytes()) == Some(0)));
+    assert_eq!(Ketama::new::<&str>(&[]).get(b"key"), None);
+    assert_eq!(Ketama::new(&[("a:1", 0)]).get(b"key"), None);
+}
+
+#[test]
+fn ketama_hash_tag() {
+    let servers: Vec<_> = (0..16).map(|i| (format!("server:{}", i), 1)).collect();
+    let ring = Ketama::new(&servers);
+    let get = |key: &str| ring.get(key.as_bytes()).unwrap();
+
+    // 只对第一个 `{` 和之后第一个 `}` 之间的内容求哈希
+    for tag in ["user:1", "user:2", "a", "{nested"] {
+        for key in [
+            format!("{{{}}}", tag),
+            format!("{{{}}}.name", tag),
+            format!("prefix:{{{}}}:suffix", tag),
+            format!("{{{}}}{{other}}", tag),
+        ] {
+            assert_eq!(get(&key), get(tag), "{}", key);
+        }
+    }
+
+    // tag 为空或者括号不完整时使用整个 key，这些 key 不会全部落在同一个服务器上
+    for pattern in ["{}", "{}{tag}", "{", "}{", "tag}"] {
+        let spread: HashSet<_> = (0..64).map(|i| get(&format!("{}{}", pattern, i))).collect();
+        assert!(spread.len() > 1, "{}", pattern);
+    }
+}
+
+#[tokio::test]
+async fn busy_backend_does_not_block_ready_replies() {
+    // 后端 A 等一会儿再回复；后端 B 接受连接之后从不读取，发给它的命令最终会把代理到 B 的请求队列塞满
+    let slow = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stuck = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addrs = [
+        slow.local_addr().unwrap().to_string(),
+        stuck.local_addr().unwrap().to_string(),
+    ];
+    tokio::spawn(async move {
+        let (socket, _) = slow.accept().await.unwrap();
+        let mut conn = Connection::new(socket);
+        while let Ok(Some(_)) = conn.read_frame().await {
+            time::sleep(Duration::from_millis(200)).await;
+            conn.write_frame(&bulk("a")).await.unwrap();
+        }
+    });
+    let held = tokio::spawn(async move {
+        let (socket, _) = stuck.accept().await.unwrap();
+        std::future::pending::<()>().await;
+        drop(socket);
+    });
+
+    let proxy = proxy::connect(ProxyConfig {
+        backends: addrs
+            .iter()
+            .map(|addr| Backend {
+                addr: addr.clone(),
+                weight: 1,
+            })
+            .collect(),
+        ..ProxyConfig::default()
+    })
+    .await
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { proxy.run(listener).await });
+
+    let ring = Ketama::new(&[(addrs[0].as_str(), 1), (addrs[1].as_str(), 1)]);
+    let key_on = |backend: usize| {
+        (0..)
+            .map(|i| format!("key:{}", i))
+            .find(|key| ring.get(key.as_bytes()) == Some(backend))
+            .unwrap()
+    };
+    let (slow_key, stuck_key) = (key_on(0), key_on(1));
+
+    // 先 GET 慢的后端，后面跟着一大批发给卡住的后端的 SET。代理不再读取之后写也会卡住，放到单独的任务中
+    let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+    tokio::spawn(async move {
+        let value = "x".repeat(1 << 20);
+        let mut requests = vec![];
+        command(&["GET", &slow_key]).encode(&mut requests);
+        for _ in 0..64 {
+            command(&["SET", &stuck_key, &value]).encode(&mut requests);
+        }
+        write.write_all(&requests).await
+    });
+
+    let mut conn = Connection::new(tokio::io::join(read, tokio::io::sink()));
+    let reply = time::timeout(Duration::from_secs(5), conn.read_frame())
+        .await
+        .expect("reply blocked behind the stuck backend");
+    assert_eq!(reply.unwrap(), Some(bulk("a")));
+    held.abort();
+}
@@ -0,0 +1,377 @@
+//! EVAL / EVALSHA / SCRIPT 的测试，包括 `redis.call` 的错误处理、沙箱和脚本超时
+
+mod common;
+
+use common::{assert_error, bulk, ok};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+use std::time::{Duration, Instant};
+
+async fn load(conn: &mut TestConnection, script: &str) -> String {
+    match conn.command(&["SCRIPT", "LOAD", script]).await.unwrap() {
+        Frame::Bulk(sha) => String::from_utf8(sha.to_vec()).unwrap(),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+/// 固定窗口的限流脚本：窗口内的请求数不超过 ARGV[1] 时返回 1
+const RATE_LIMITER: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+if count > tonumber(ARGV[1]) then
+    return 0
+end
+return 1
+"#;
+
+#[tokio::test(start_paused = true)]
+async fn rate_limiter_script() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    let sha = load(&mut conn, RATE_LIMITER).await;
+
+    for expected in [1, 1, 1, 0, 0] {
+        assert_eq!(
+            conn.command(&["EVALSHA", &sha, "1", "rate:user1", "3", "1000"])
+                .await
+                .unwrap(),
+            Frame::Integer(expected)
+        );
+    }
+    // 另一个 key 有自己的计数
+    assert_eq!(
+        conn.command(&["EVALSHA", &sha, "1", "rate:user2", "3", "1000"])
+            .await
+            .unwrap(),
+        Frame::Integer(1)
+    );
+
+    // 窗口结束后计数重新开始
+    tokio::time::advance(Duration::from_millis(1000)).await;
+    assert_eq!(
+        conn.command(&["EVALSHA", &sha, "1", "rate:user1", "3", "1000"])
+            .await
+            .unwrap(),
+        Frame::Integer(1)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn eval_converts_return_values() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_eq!(
+        conn.command(&[
+            "EVAL",
+            "return {KEYS[1], ARGV[1], ARGV[2]}",
+            "1",
+            "k",
+            "a",
+            "b"
+        ])
+        .await
+        .unwrap(),
+        Frame::Array(vec![bulk("k"), bulk("a"), bulk("b")])
+    );
+    // 浮点数被截断，true 是 1，false 和 nil 是 Null，数组遇到 nil 就结束
+    assert_eq!(
+        conn.command(&["EVAL", "return 3.99", "0"]).await.unwrap(),
+        Frame::Integer(3)
+    );
+    assert_eq!(
+        conn.command(&["EVAL", "return true", "0"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["EVAL", "return false", "0"]).await.unwrap(),
+        Frame::Null
+    );
+    assert_eq!(
+        conn.command(&["EVAL", "return {1, 2, nil, 4}", "0"])
+            .await
+            .unwrap(),
+        Frame::Array(vec![Frame::Integer(1), Frame::Integer(2)])
+    );
+    assert_eq!(
+        conn.command(&["EVAL", "return redis.status_reply('FINE')", "0"])
+            .await
+            .unwrap(),
+        Frame::Simple("FINE".to_string())
+    );
+    assert_error(
+        conn.command(&["EVAL", "return redis.error_reply('MY failure')", "0"])
+            .await
+            .unwrap(),
+        "MY failure",
+    );
+
+    // redis.call 的回复按 Redis 的规则转换：GET 不存在的 key 得到 false
+    conn.command(&["SET", "k", "v"]).await.unwrap();
+    assert_eq!(
+        conn.command(&[
+            "EVAL",
+            "return {redis.call('GET', KEYS[1]), tostring(redis.call('GET', 'missing'))}",
+            "1",
+            "k"
+        ])
+        .await
+        .unwrap(),
+        Frame::Array(vec![bulk("v"), bulk("false")])
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn eval_argument_errors() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_error(
+        conn.command(&["EVAL", "return 1", "-1"]).await.unwrap(),
+        "can't be negative",
+    );
+    assert_error(
+        conn.command(&["EVAL", "return 1", "2", "k"]).await.unwrap(),
+        "can't be greater than number of args",
+    );
+    assert_error(
+        conn.command(&["EVAL", "return +", "0"]).await.unwrap(),
+        "Error compiling script",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn script_cache() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    // 和 Redis 一样，sha1 是脚本内容的 sha1
+    let sha = load(&mut conn, "return 'hello'").await;
+    assert_eq!(sha, "1b936e3fe509bcbc9cd0664897bbe8fd0cac101b");
+    assert_eq!(
+        conn.command(&["EVALSHA", &sha, "0"]).await.unwrap(),
+        bulk("hello")
+    );
+    // sha1 不区分大小写
+    assert_eq!(
+        conn.command(&["EVALSHA", &sha.to_uppercase(), "0"])
+            .await
+            .unwrap(),
+        bulk("hello")
+    );
+
+    // EVAL 执行过的脚本也会被缓存
+    conn.command(&["EVAL", "return 2", "0"]).await.unwrap();
+    let eval_sha = load(&mut conn, "return 2").await;
+    assert_eq!(
+        conn.command(&["SCRIPT", "EXISTS", &sha, &eval_sha, "0000"])
+            .await
+            .unwrap(),
+        Frame::Array(vec![
+            Frame::Integer(1),
+            Frame::Integer(1),
+            Frame::Integer(0)
+        ])
+    );
+
+    assert_eq!(conn.command(&["SCRIPT", "FLUSH"]).await.unwrap(), ok());
+    assert_eq!(
+        conn.command(&["SCRIPT", "EXISTS", &sha]).await.unwrap(),
+        Frame::Array(vec![Frame::Integer(0)])
+    );
+    assert_error(
+        conn.command(&["EVALSHA", &sha, "0"]).await.unwrap(),
+        "NOSCRIPT",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn redis_call_errors() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    conn.command(&["SET", "k", "not a number"]).await.unwrap();
+
+    // redis.call 的错误中断脚本，之前的写入不会回滚
+    assert_error(
+        conn.command(&[
+            "EVAL",
+            "redis.call('SET', 'written', '1'); return redis.call('INCR', KEYS[1])",
+            "1",
+            "k",
+        ])
+        .await
+        .unwrap(),
+        "value is not an integer",
+    );
+    assert_eq!(conn.command(&["GET", "written"]).await.unwrap(), bulk("1"));
+
+    // redis.pcall 把错误作为返回值交给脚本
+    assert_eq!(
+        conn.command(&[
+            "EVAL",
+            "local reply = redis.pcall('INCR', KEYS[1]); return type(reply.err)",
+            "1",
+            "k",
+        ])
+        .await
+        .unwrap(),
+        bulk("string")
+    );
+
+    assert_error(
+        conn.command(&["EVAL", "return redis.call()", "0"])
+            .await
+            .unwrap(),
+        "at least one argument",
+    );
+    assert_error(
+        conn.command(&["EVAL", "return redis.call('GET', {})", "0"])
+            .await
+            .unwrap(),
+        "must be strings or integers",
+    );
+    assert_error(
+        conn.command(&["EVAL", "return redis.call('CONFIG', 'GET', '*')", "0"])
+            .await
+            .unwrap(),
+        "not allowed from script",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn select_in_script_does_not_leak() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_eq!(
+        conn.command(&[
+            "EVAL",
+            "redis.call('SELECT', 1); redis.call('SET', 'k', 'db1'); return 1",
+            "0"
+        ])
+        .await
+        .unwrap(),
+        Frame::Integer(1)
+    );
+    // 连接仍然在 0 号数据库
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), Frame::Null);
+    conn.command(&["SELECT", "1"]).await.unwrap();
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("db1"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn sandbox_has_no_os_access() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    for global in ["os", "io", "require", "dofile", "loadfile", "package"] {
+        let script = format!("return type({})", global);
+        assert_eq!(
+            conn.command(&["EVAL", &script, "0"]).await.unwrap(),
+            bulk("nil"),
+            "{} should not be available",
+            global
+        );
+    }
+    // 允许的标准库
+    assert_eq!(
+        conn.command(&["EVAL", "return string.rep('a', 3) .. math.floor(2.5)", "0"])
+            .await
+            .unwrap(),
+        bulk("aaa2")
+    );
+    assert_eq!(
+        conn.command(&["EVAL", "return redis.sha1hex('')", "0"])
+            .await
+            .unwrap(),
+        bulk("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+    );
+}
+
+/// 脚本超时使用真实的时间，不能暂停时钟
+#[tokio::test]
+async fn script_is_killed_after_time_limit() {
+    let config = Config {
+        lua_time_limit: Duration::from_millis(300),
+        ..Config::default()
+    };
+    let server = TestServer::new(config);
+    let mut conn = server.connect();
+
+    let start = Instant::now();
+    conn.send(&["EVAL", "while true do end", "0"])
+        .await
+        .unwrap();
+
+    // 脚本在阻塞线程池中运行，单线程的运行时仍然可以处理计时器和其它任务
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(
+        start.elapsed() < Duration::from_millis(300),
+        "runtime was blocked by the script for {:?}",
+        start.elapsed()
+    );
+
+    assert_error(
+        conn.read_frame().await.unwrap().unwrap(),
+        "exceeded the time limit",
+    );
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // 超时的脚本释放了锁，连接继续可用
+    assert_eq!(
+        conn.command(&["EVAL", "return 1", "0"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+}
+
+/// 脚本执行期间其它连接访问键空间的命令回复 BUSY，不等待锁
+#[tokio::test]
+async fn other_connections_get_busy_while_script_runs() {
+    let config = Config {
+        lua_time_limit: Duration::from_millis(300),
+        ..Config::default()
+    };
+    let server = TestServer::new(config);
+    let mut script = server.connect();
+    let mut other = server.connect();
+
+    script
+        .send(&[
+            "EVAL",
+            "redis.call('SET', 'k', 'partial') while true do end",
+            "0",
+        ])
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let start = Instant::now();
+    assert_error(other.command(&["GET", "k"]).await.unwrap(), "BUSY");
+    assert_error(other.command(&["SET", "x", "1"]).await.unwrap(), "BUSY");
+    assert_error(
+        other.command(&["EVAL", "return 1", "0"]).await.unwrap(),
+        "BUSY",
+    );
+    // 不访问键空间的命令照常执行
+    assert!(matches!(
+        other.command(&["CLIENT", "ID"]).await.unwrap(),
+        Frame::Integer(_)
+    ));
+    assert!(start.elapsed() < Duration::from_millis(200));
+
+    assert_error(
+        script.read_frame().await.unwrap().unwrap(),
+        "exceeded the time limit",
+    );
+
+    // 被中断的脚本已经执行的写命令不会回滚
+    assert_eq!(other.command(&["GET", "k"]).await.unwrap(), bulk("partial"));
+    assert_eq!(
+        other.command(&["EXISTS", "x"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+}
@@ -0,0 +1,247 @@
+//! 通过 `testing::TestServer` 测试服务端。除了最后两个 TCP 的测试之外，连接都是内存中的 duplex 流，
+//! 时间由 `tokio::time::pause` 控制，过期和超时不需要真的等待
+
+mod common;
+
+use common::{bulk, ok};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Connection, Frame};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{self, Instant};
+
+async fn client_id(conn: &mut TestConnection) -> String {
+    match conn.command(&["CLIENT", "ID"]).await.unwrap() {
+        Frame::Integer(id) => id.to_string(),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn key_expires_without_sleeping() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_eq!(
+        conn.command(&["SET", "k", "v", "PX", "100"]).await.unwrap(),
+        ok()
+    );
+
+    time::advance(Duration::from_millis(99)).await;
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+    assert_eq!(
+        conn.command(&["PTTL", "k"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+
+    time::advance(Duration::from_millis(1)).await;
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), Frame::Null);
+    assert_eq!(
+        conn.command(&["TTL", "k"]).await.unwrap(),
+        Frame::Integer(-2)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn expired_keys_are_purged_in_background() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    for i in 0..10 {
+        let key = format!("key{}", i);
+        conn.command(&["SET", &key, "v", "EX", "60"]).await.unwrap();
+    }
+    conn.command(&["SET", "forever", "v"]).await.unwrap();
+    assert_eq!(conn.command(&["DBSIZE"]).await.unwrap(), Frame::Integer(11));
+
+    // 没有访问过期的 key，只能靠后台的定期清理
+    time::sleep(Duration::from_secs(61)).await;
+    assert_eq!(conn.command(&["DBSIZE"]).await.unwrap(), Frame::Integer(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_connection_is_closed_after_timeout() {
+    let config = Config {
+        timeout: Duration::from_secs(300),
+        ..Config::default()
+    };
+    let server = TestServer::new(config);
+    let mut conn = server.connect();
+    conn.command(&["PING"]).await.unwrap();
+
+    // 读取会一直阻塞到服务端关闭连接，暂停的时钟会直接跳到超时的时刻
+    let start = Instant::now();
+    assert_eq!(conn.read_frame().await.unwrap(), None);
+    assert_eq!(start.elapsed(), Duration::from_secs(300));
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_kill_wakes_blocked_connection() {
+    let server = TestServer::new(Config::default());
+    let mut victim = server.connect();
+    let mut admin = server.connect();
+    let id = client_id(&mut victim).await;
+
+    let blocked = tokio::spawn(async move { victim.read_frame().await.unwrap() });
+
+    assert_eq!(
+        admin.command(&["CLIENT", "KILL", "ID", &id]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(blocked.await.unwrap(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropping_server_closes_connections() {
+    let server = TestServer::new(Config::default());
+    let mut idle = server.connect();
+    let mut subscriber = server.connect();
+    idle.command(&["SET", "k", "v"]).await.unwrap();
+    subscriber.command(&["SUBSCRIBE", "news"]).await.unwrap();
+
+    // 连接的任务随服务端一起被取消，不会在测试结束之后还占着 Db
+    drop(server);
+    assert_eq!(idle.read_frame().await.unwrap(), None);
+    assert_eq!(subscriber.read_frame().await.unwrap(), None);
+    assert!(idle.command(&["GET", "k"]).await.is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn publish_reaches_subscribers() {
+    let server = TestServer::new(Config::default());
+    let mut subscriber = server.connect();
+    let mut publisher = server.connect();
+
+    assert_eq!(
+        subscriber.command(&["SUBSCRIBE", "news"]).await.unwrap(),
+        Frame::Array(vec![bulk("subscribe"), bulk("news"), Frame::Integer(1)])
+    );
+
+    assert_eq!(
+        publisher
+            .command(&["PUBLISH", "news", "hello"])
+            .await
+            .unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        publisher
+            .command(&["PUBLISH", "other", "ignored"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        subscriber.read_frame().await.unwrap(),
+        Some(Frame::Array(vec![
+            bulk("message"),
+            bulk("news"),
+            bulk("hello")
+        ]))
+    );
+
+    // 订阅模式下只允许少数几个命令
+    assert!(matches!(
+        subscriber.command(&["GET", "k"]).await.unwrap(),
+        Frame::Error(msg) if msg.contains("only SUBSCRIBE")
+    ));
+
+    // 取消订阅之后回到普通模式，不再收到消息
+    assert_eq!(
+        subscriber.command(&["UNSUBSCRIBE"]).await.unwrap(),
+        Frame::Array(vec![bulk("unsubscribe"), bulk("news"), Frame::Integer(0)])
+    );
+    assert_eq!(
+        publisher
+            .command(&["PUBLISH", "news", "again"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        subscriber.command(&["GET", "k"]).await.unwrap(),
+        Frame::Null
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn subscriber_waits_for_messages() {
+    let server = TestServer::new(Config::default());
+    let mut subscriber = server.connect();
+    subscriber.command(&["SUBSCRIBE", "news"]).await.unwrap();
+
+    let received = tokio::spawn(async move { subscriber.read_frame().await.unwrap() });
+
+    // 订阅者一直阻塞到一个小时之后才有消息
+    let mut publisher = server.connect();
+    time::sleep(Duration::from_secs(3600)).await;
+    publisher
+        .command(&["PUBLISH", "news", "late"])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        received.await.unwrap(),
+        Some(Frame::Array(vec![
+            bulk("message"),
+            bulk("news"),
+            bulk("late")
+        ]))
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn monitor_streams_commands() {
+    let server = TestServer::new(Config::default());
+    let mut monitor = server.connect();
+    let mut conn = server.connect();
+
+    assert_eq!(monitor.command(&["MONITOR"]).await.unwrap(), ok());
+    conn.command(&["SET", "k", "v"]).await.unwrap();
+
+    match monitor.read_frame().await.unwrap() {
+        Some(Frame::Simple(line)) => {
+            assert!(line.contains("duplex:"), "{}", line);
+            assert!(line.ends_with(r#""SET" "k" "v""#), "{}", line);
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn tcp_server_on_ephemeral_port() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    assert_ne!(server.addr().unwrap().port(), 0);
+
+    let client = server.client().await.unwrap();
+    client.set("k", Bytes::from("v")).await.unwrap();
+    assert_eq!(client.get("k").await.unwrap(), Some(Bytes::from("v")));
+
+    // TCP 和内存中的连接访问的是同一个服务端
+    let mut conn = server.connect();
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v"));
+}
+
+#[tokio::test]
+async fn dropping_server_closes_tcp_connections() {
+    let server = TestServer::bind(Config::default()).await.unwrap();
+    let socket = TcpStream::connect(server.addr().unwrap()).await.unwrap();
+    let mut conn = Connection::new(socket);
+    conn.write_frame(&Frame::Array(vec![bulk("PING")]))
+        .await
+        .unwrap();
+    assert_eq!(
+        conn.read_frame().await.unwrap(),
+        Some(Frame::Simple("PONG".to_string()))
+    );
+
+    // 连接的任务随着服务端一起取消，客户端读到 EOF
+    drop(server);
+    let closed = time::timeout(Duration::from_secs(1), conn.read_frame())
+        .await
+        .expect("connection outlived the server");
+    assert!(matches!(closed, Ok(None) | Err(_)), "{:?}", closed);
+}
@@ -0,0 +1,366 @@
+//! 集合命令的测试：SADD / SREM / SINTER* / SPOP / SRANDMEMBER / SSCAN
+
+mod common;
+
+use common::{assert_error, bulk};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+use std::collections::HashSet;
+
+/// 把数组回复转换成字符串，集合的回复没有顺序
+fn strings(frame: Frame) -> Vec<String> {
+    match frame {
+        Frame::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Frame::Bulk(data) => String::from_utf8(data.to_vec()).unwrap(),
+                item => panic!("unexpected item {:?}", item),
+            })
+            .collect(),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+async fn members(conn: &mut TestConnection, args: &[&str]) -> HashSet<String> {
+    strings(conn.command(args).await.unwrap())
+        .into_iter()
+        .collect()
+}
+
+fn set_of(members: &[&str]) -> HashSet<String> {
+    members.iter().map(|member| member.to_string()).collect()
+}
+
+async fn sadd(conn: &mut TestConnection, key: &str, members: &[&str]) {
+    let mut args = vec!["SADD", key];
+    args.extend_from_slice(members);
+    conn.command(&args).await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn add_remove_and_membership() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    assert_eq!(
+        conn.command(&["SADD", "s", "a", "b", "a"]).await.unwrap(),
+        Frame::Integer(2)
+    );
+    assert_eq!(
+        conn.command(&["SADD", "s", "b", "c"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["SCARD", "s"]).await.unwrap(),
+        Frame::Integer(3)
+    );
+    assert_eq!(
+        members(&mut conn, &["SMEMBERS", "s"]).await,
+        set_of(&["a", "b", "c"])
+    );
+    assert_eq!(
+        conn.command(&["SMISMEMBER", "s", "a", "x", "c"])
+            .await
+            .unwrap(),
+        Frame::Array(vec![
+            Frame::Integer(1),
+            Frame::Integer(0),
+            Frame::Integer(1)
+        ])
+    );
+    assert_eq!(
+        conn.command(&["SISMEMBER", "missing", "a"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    // 删除最后一个元素后 key 也被删除
+    assert_eq!(
+        conn.command(&["SREM", "s", "a", "b", "x"]).await.unwrap(),
+        Frame::Integer(2)
+    );
+    assert_eq!(
+        conn.command(&["SREM", "s", "c"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["EXISTS", "s"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    conn.command(&["SET", "str", "v"]).await.unwrap();
+    assert_error(
+        conn.command(&["SADD", "str", "a"]).await.unwrap(),
+        "WRONGTYPE",
+    );
+    assert_error(
+        conn.command(&["SMEMBERS", "str"]).await.unwrap(),
+        "WRONGTYPE",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn set_algebra() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    sadd(&mut conn, "a", &["1", "2", "3", "4"]).await;
+    sadd(&mut conn, "b", &["3", "4", "5"]).await;
+    sadd(&mut conn, "c", &["4", "6"]).await;
+
+    assert_eq!(
+        members(&mut conn, &["SINTER", "a", "b", "c"]).await,
+        set_of(&["4"])
+    );
+    assert_eq!(
+        members(&mut conn, &["SUNION", "a", "b", "c"]).await,
+        set_of(&["1", "2", "3", "4", "5", "6"])
+    );
+    assert_eq!(
+        members(&mut conn, &["SDIFF", "a", "b", "c"]).await,
+        set_of(&["1", "2"])
+    );
+    // 不存在的 key 当作空集合
+    assert_eq!(
+        members(&mut conn, &["SINTER", "a", "missing"]).await,
+        set_of(&[])
+    );
+    assert_eq!(
+        members(&mut conn, &["SUNION", "missing", "c"]).await,
+        set_of(&["4", "6"])
+    );
+
+    // *STORE 覆盖目标 key，不管原来是什么类型
+    conn.command(&["SET", "dest", "string"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["SINTERSTORE", "dest", "a", "b"])
+            .await
+            .unwrap(),
+        Frame::Integer(2)
+    );
+    assert_eq!(
+        members(&mut conn, &["SMEMBERS", "dest"]).await,
+        set_of(&["3", "4"])
+    );
+    // 目标 key 也可以是参与运算的 key
+    assert_eq!(
+        conn.command(&["SUNIONSTORE", "dest", "dest", "c"])
+            .await
+            .unwrap(),
+        Frame::Integer(3)
+    );
+    assert_eq!(
+        conn.command(&["SDIFFSTORE", "diff", "a", "b"])
+            .await
+            .unwrap(),
+        Frame::Integer(2)
+    );
+    // 存入的结果是普通的集合
+    assert_eq!(
+        conn.command(&["SISMEMBER", "diff", "1"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+
+    // 结果为空时删除目标 key
+    assert_eq!(
+        conn.command(&["SINTERSTORE", "dest", "a", "missing"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["EXISTS", "dest"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    conn.command(&["SET", "str", "v"]).await.unwrap();
+    assert_error(
+        conn.command(&["SUNION", "a", "str"]).await.unwrap(),
+        "WRONGTYPE",
+    );
+    assert_error(
+        conn.command(&["SINTERSTORE", "dest", "str"]).await.unwrap(),
+        "WRONGTYPE",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn spop_and_srandmember() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+    sadd(&mut conn, "s", &["a", "b", "c", "d", "e"]).await;
+    let all = set_of(&["a", "b", "c", "d", "e"]);
+
+    match conn.command(&["SRANDMEMBER", "s"]).await.unwrap() {
+        Frame::Bulk(member) => assert!(all.contains(std::str::from_utf8(&member).unwrap())),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+    let chosen = strings(conn.command(&["SRANDMEMBER", "s", "3"]).await.unwrap());
+    assert_eq!(chosen.len(), 3);
+    assert_eq!(chosen.iter().collect::<HashSet<_>>().len(), 3);
+
+    // 正数超过集合大小时返回整个集合，不会按 count 分配内存
+    assert_eq!(
+        members(&mut conn, &["SRANDMEMBER", "s", "1000000000000"]).await,
+        all
+    );
+    // 负数允许重复
+    let repeated = strings(conn.command(&["SRANDMEMBER", "s", "-20"]).await.unwrap());
+    assert_eq!(repeated.len(), 20);
+    assert!(repeated.iter().all(|member| all.contains(member)));
+    assert_eq!(
+        conn.command(&["SRANDMEMBER", "s", "0"]).await.unwrap(),
+        Frame::Array(vec![])
+    );
+
+    for count in ["-1000000000000", "-9223372036854775808"] {
+        assert_error(
+            conn.command(&["SRANDMEMBER", "s", count]).await.unwrap(),
+            "value is out of range",
+        );
+    }
+    assert_error(
+        conn.command(&["SPOP", "s", "-1"]).await.unwrap(),
+        "value is out of range, must be positive",
+    );
+
+    // SPOP 删除返回的元素
+    let popped = strings(conn.command(&["SPOP", "s", "2"]).await.unwrap());
+    assert_eq!(popped.len(), 2);
+    for member in &popped {
+        assert_eq!(
+            conn.command(&["SISMEMBER", "s", member]).await.unwrap(),
+            Frame::Integer(0)
+        );
+    }
+    assert_eq!(
+        conn.command(&["SCARD", "s"]).await.unwrap(),
+        Frame::Integer(3)
+    );
+    assert_eq!(
+        strings(conn.command(&["SPOP", "s", "1000000000000"]).await.unwrap()).len(),
+        3
+    );
+    assert_eq!(
+        conn.command(&["EXISTS", "s"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    assert_eq!(conn.command(&["SPOP", "s"]).await.unwrap(), Frame::Null);
+    assert_eq!(
+        conn.command(&["SRANDMEMBER", "s", "-3"]).await.unwrap(),
+        Frame::Array(vec![])
+    );
+}
+
+/// 用 SSCAN 遍历整个集合，返回 (所有元素, 调用次数)
+async fn scan_all(conn: &mut TestConnection, key: &str, extra: &[&str]) -> (Vec<String>, usize) {
+    let mut cursor = "0".to_string();
+    let mut all = vec![];
+    let mut calls = 0;
+    loop {
+        let mut args = vec!["SSCAN", key, cursor.as_str()];
+        args.extend_from_slice(extra);
+        let reply = conn.command(&args).await.unwrap();
+        calls += 1;
+        match reply {
+            Frame::Array(mut parts) => {
+                let batch = parts.pop().unwrap();
+                cursor = match parts.pop().unwrap() {
+                    Frame::Bulk(next) => String::from_utf8(next.to_vec()).unwrap(),
+                    frame => panic!("unexpected cursor {:?}", frame),
+                };
+                all.extend(strings(batch));
+            }
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+        if cursor == "0" {
+            return (all, calls);
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn sscan_iterates_every_member_once() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    let names: Vec<String> = (0..100).map(|i| format!("m{}", i)).collect();
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    sadd(&mut conn, "s", &refs).await;
+
+    let (all, calls) = scan_all(&mut conn, "s", &["COUNT", "7"]).await;
+    assert_eq!(all.len(), 100);
+    assert_eq!(
+        all.into_iter().collect::<HashSet<_>>(),
+        names.iter().cloned().collect()
+    );
+    // 每次至少返回 COUNT 个元素
+    assert_eq!(calls, 15);
+
+    // MATCH 在取出元素之后过滤
+    let (matched, _) = scan_all(&mut conn, "s", &["MATCH", "m1*", "COUNT", "1000"]).await;
+    assert_eq!(matched.len(), 11);
+    assert!(matched.iter().all(|member| member.starts_with("m1")));
+
+    assert_eq!(
+        conn.command(&["SSCAN", "missing", "0"]).await.unwrap(),
+        Frame::Array(vec![bulk("0"), Frame::Array(vec![])])
+    );
+    assert_error(
+        conn.command(&["SSCAN", "s", "abc"]).await.unwrap(),
+        "invalid cursor",
+    );
+    assert_error(
+        conn.command(&["SSCAN", "s", "0", "COUNT", "0"])
+            .await
+            .unwrap(),
+        "syntax error",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn sscan_while_set_is_modified() {
+    let server = TestServer::new(Config::default());
+    let mut conn = server.connect();
+
+    let names: Vec<String> = (0..50).map(|i| format!("keep{}", i)).collect();
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    sadd(&mut conn, "s", &refs).await;
+    sadd(&mut conn, "s", &["gone1", "gone2", "gone3"]).await;
+
+    let mut cursor = "0".to_string();
+    let mut seen = HashSet::new();
+    let mut round = 0;
+    loop {
+        let reply = conn
+            .command(&["SSCAN", "s", &cursor, "COUNT", "5"])
+            .await
+            .unwrap();
+        let Frame::Array(mut parts) = reply else {
+            panic!("unexpected reply");
+        };
+        seen.extend(strings(parts.pop().unwrap()));
+        cursor = match parts.pop().unwrap() {
+            Frame::Bulk(next) => String::from_utf8(next.to_vec()).unwrap(),
+            frame => panic!("unexpected cursor {:?}", frame),
+        };
+
+        // 扫描过程中增删其它元素
+        round += 1;
+        if round == 2 {
+            conn.command(&["SREM", "s", "gone1", "gone2", "gone3"])
+                .await
+                .unwrap();
+            sadd(&mut conn, "s", &["new1", "new2"]).await;
+        }
+        if cursor == "0" {
+            break;
+        }
+    }
+
+    // 从开始到结束一直存在的元素都被返回了
+    for name in &names {
+        assert!(seen.contains(name), "{} was not returned", name);
+    }
+}
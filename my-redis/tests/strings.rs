@@ -0,0 +1,401 @@
+//! 字符串命令的测试，结果和真实的 Redis 对照过
+
+mod common;
+
+use common::{assert_error, bulk, ok};
+
+use my_redis::testing::{TestConnection, TestServer};
+use my_redis::{Config, Frame};
+
+use bytes::Bytes;
+use std::time::Duration;
+
+async fn connect() -> (TestServer, TestConnection) {
+    let server = TestServer::new(Config::default());
+    let conn = server.connect();
+    (server, conn)
+}
+
+#[tokio::test(start_paused = true)]
+async fn incrbyfloat_formatting() {
+    let (_server, mut conn) = connect().await;
+
+    conn.command(&["SET", "k", "10.50"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["INCRBYFLOAT", "k", "0.1"]).await.unwrap(),
+        bulk("10.6")
+    );
+    // 没有多余的 0 和小数点
+    assert_eq!(
+        conn.command(&["INCRBYFLOAT", "k", "-5.6"]).await.unwrap(),
+        bulk("5")
+    );
+    conn.command(&["SET", "k", "5.0e3"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["INCRBYFLOAT", "k", "2.0e2"]).await.unwrap(),
+        bulk("5200")
+    );
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("5200"));
+    // 不存在的 key 从 0 开始，结果可以继续用 INCR
+    assert_eq!(
+        conn.command(&["INCRBYFLOAT", "new", "3"]).await.unwrap(),
+        bulk("3")
+    );
+    assert_eq!(
+        conn.command(&["INCR", "new"]).await.unwrap(),
+        Frame::Integer(4)
+    );
+
+    assert_error(
+        conn.command(&["INCRBYFLOAT", "k", "abc"]).await.unwrap(),
+        "not a valid float",
+    );
+    assert_error(
+        conn.command(&["INCRBYFLOAT", "k", "nan"]).await.unwrap(),
+        "not a valid float",
+    );
+    assert_error(
+        conn.command(&["INCRBYFLOAT", "k", "inf"]).await.unwrap(),
+        "NaN or Infinity",
+    );
+    conn.command(&["SET", "text", "hello"]).await.unwrap();
+    assert_error(
+        conn.command(&["INCRBYFLOAT", "text", "1"]).await.unwrap(),
+        "not a valid float",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn incr_keeps_ttl_and_detects_overflow() {
+    let (_server, mut conn) = connect().await;
+
+    conn.command(&["SET", "n", "9223372036854775806", "EX", "10"])
+        .await
+        .unwrap();
+    assert_eq!(
+        conn.command(&["INCR", "n"]).await.unwrap(),
+        Frame::Integer(i64::MAX)
+    );
+    assert_error(
+        conn.command(&["INCR", "n"]).await.unwrap(),
+        "would overflow",
+    );
+    assert_eq!(
+        conn.command(&["TTL", "n"]).await.unwrap(),
+        Frame::Integer(10)
+    );
+    assert_error(
+        conn.command(&["DECRBY", "n", "-9223372036854775808"])
+            .await
+            .unwrap(),
+        "would overflow",
+    );
+    assert_eq!(
+        conn.command(&["DECRBY", "missing", "5"]).await.unwrap(),
+        Frame::Integer(-5)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn integers_reject_leading_plus() {
+    let (_server, mut conn) = connect().await;
+
+    // 和 Redis 的 string2ll 一样，参数和保存的值都不能带 `+`
+    assert_error(
+        conn.command(&["INCRBY", "k", "+5"]).await.unwrap(),
+        "not an integer",
+    );
+    assert_error(
+        conn.command(&["DECRBY", "k", "+5"]).await.unwrap(),
+        "not an integer",
+    );
+    conn.command(&["SET", "k", "+5"]).await.unwrap();
+    assert_error(
+        conn.command(&["INCR", "k"]).await.unwrap(),
+        "not an integer",
+    );
+
+    conn.command(&["SET", "k", "-5"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["INCRBY", "k", "-5"]).await.unwrap(),
+        Frame::Integer(-10)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn getrange_bounds() {
+    let (_server, mut conn) = connect().await;
+    conn.command(&["SET", "k", "This is a string"])
+        .await
+        .unwrap();
+
+    for (start, end, expected) in [
+        ("0", "3", "This"),
+        ("-3", "-1", "ing"),
+        ("0", "-1", "This is a string"),
+        ("10", "100", "string"),
+        // 超出范围的负数被截断到开头
+        ("-100", "3", "This"),
+        ("5", "3", ""),
+        ("100", "200", ""),
+        ("-1", "-2", ""),
+    ] {
+        assert_eq!(
+            conn.command(&["GETRANGE", "k", start, end]).await.unwrap(),
+            bulk(expected),
+            "GETRANGE k {} {}",
+            start,
+            end
+        );
+    }
+
+    assert_eq!(
+        conn.command(&["GETRANGE", "missing", "0", "-1"])
+            .await
+            .unwrap(),
+        bulk("")
+    );
+    conn.command(&["SET", "empty", ""]).await.unwrap();
+    assert_eq!(
+        conn.command(&["GETRANGE", "empty", "0", "-1"])
+            .await
+            .unwrap(),
+        bulk("")
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn setrange_bounds() {
+    let (_server, mut conn) = connect().await;
+
+    conn.command(&["SET", "k", "Hello World"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["SETRANGE", "k", "6", "Redis"])
+            .await
+            .unwrap(),
+        Frame::Integer(11)
+    );
+    assert_eq!(
+        conn.command(&["GET", "k"]).await.unwrap(),
+        bulk("Hello Redis")
+    );
+
+    // 超出长度的部分用 0 填充
+    assert_eq!(
+        conn.command(&["SETRANGE", "pad", "3", "x"]).await.unwrap(),
+        Frame::Integer(4)
+    );
+    assert_eq!(
+        conn.command(&["GET", "pad"]).await.unwrap(),
+        Frame::Bulk(Bytes::from_static(b"\0\0\0x"))
+    );
+
+    // 写入空字符串不会创建 key
+    assert_eq!(
+        conn.command(&["SETRANGE", "missing", "100", ""])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["EXISTS", "missing"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    assert_error(
+        conn.command(&["SETRANGE", "k", "-1", "x"]).await.unwrap(),
+        "offset is out of range",
+    );
+    // 超过 512MB 的上限，包括相加会溢出的 offset
+    assert_error(
+        conn.command(&["SETRANGE", "k", "536870912", "x"])
+            .await
+            .unwrap(),
+        "offset is out of range",
+    );
+    assert_error(
+        conn.command(&["SETRANGE", "k", "9223372036854775807", "x"])
+            .await
+            .unwrap(),
+        "offset is out of range",
+    );
+    assert_eq!(
+        conn.command(&["GET", "k"]).await.unwrap(),
+        bulk("Hello Redis")
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn mset_and_msetnx() {
+    let (_server, mut conn) = connect().await;
+
+    assert_eq!(
+        conn.command(&["MSET", "a", "1", "b", "2"]).await.unwrap(),
+        ok()
+    );
+    assert_eq!(
+        conn.command(&["MGET", "a", "b", "c"]).await.unwrap(),
+        Frame::Array(vec![bulk("1"), bulk("2"), Frame::Null])
+    );
+
+    // 只要有一个 key 已经存在就一个都不写
+    assert_eq!(
+        conn.command(&["MSETNX", "c", "3", "a", "x"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["MGET", "a", "c"]).await.unwrap(),
+        Frame::Array(vec![bulk("1"), Frame::Null])
+    );
+    assert_eq!(
+        conn.command(&["MSETNX", "c", "3", "d", "4"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        conn.command(&["MGET", "c", "d"]).await.unwrap(),
+        Frame::Array(vec![bulk("3"), bulk("4")])
+    );
+
+    // 其它类型的 key 也算存在，MGET 对它返回 Null
+    conn.command(&["SADD", "set", "m"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["MSETNX", "set", "x", "e", "5"])
+            .await
+            .unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["MGET", "set"]).await.unwrap(),
+        Frame::Array(vec![Frame::Null])
+    );
+
+    assert_error(
+        conn.command(&["MSET", "a", "1", "b"]).await.unwrap(),
+        "wrong number of arguments",
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn set_options() {
+    let (_server, mut conn) = connect().await;
+
+    assert_eq!(
+        conn.command(&["SET", "k", "v1", "XX"]).await.unwrap(),
+        Frame::Null
+    );
+    assert_eq!(
+        conn.command(&["SET", "k", "v1", "NX", "EX", "10"])
+            .await
+            .unwrap(),
+        ok()
+    );
+    assert_eq!(
+        conn.command(&["SET", "k", "v2", "NX"]).await.unwrap(),
+        Frame::Null
+    );
+    // GET 返回旧的值，KEEPTTL 保留过期时间
+    assert_eq!(
+        conn.command(&["SET", "k", "v2", "XX", "GET", "KEEPTTL"])
+            .await
+            .unwrap(),
+        bulk("v1")
+    );
+    assert_eq!(
+        conn.command(&["TTL", "k"]).await.unwrap(),
+        Frame::Integer(10)
+    );
+    // 不带过期选项的 SET 清除过期时间
+    conn.command(&["SET", "k", "v3"]).await.unwrap();
+    assert_eq!(
+        conn.command(&["TTL", "k"]).await.unwrap(),
+        Frame::Integer(-1)
+    );
+
+    // NX 条件不满足时 GET 仍然返回旧的值
+    assert_eq!(
+        conn.command(&["SET", "k", "v4", "NX", "GET"])
+            .await
+            .unwrap(),
+        bulk("v3")
+    );
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("v3"));
+
+    conn.command(&["SADD", "set", "m"]).await.unwrap();
+    assert_error(
+        conn.command(&["SET", "set", "v", "GET"]).await.unwrap(),
+        "WRONGTYPE",
+    );
+    // 不带 GET 时覆盖任意类型
+    assert_eq!(conn.command(&["SET", "set", "v"]).await.unwrap(), ok());
+
+    for args in [
+        &["SET", "k", "v", "NX", "XX"][..],
+        &["SET", "k", "v", "EX", "1", "PX", "1"],
+        &["SET", "k", "v", "KEEPTTL", "EX", "1"],
+        &["SET", "k", "v", "FOREVER"],
+    ] {
+        assert_error(conn.command(args).await.unwrap(), "syntax error");
+    }
+    assert_error(
+        conn.command(&["SET", "k", "v", "EX", "0"]).await.unwrap(),
+        "invalid expire time",
+    );
+
+    conn.command(&["SET", "short", "v", "PX", "1500"])
+        .await
+        .unwrap();
+    tokio::time::advance(Duration::from_millis(1500)).await;
+    assert_eq!(conn.command(&["GET", "short"]).await.unwrap(), Frame::Null);
+}
+
+#[tokio::test(start_paused = true)]
+async fn append_strlen_getset_setnx() {
+    let (_server, mut conn) = connect().await;
+
+    assert_eq!(
+        conn.command(&["APPEND", "k", "Hello"]).await.unwrap(),
+        Frame::Integer(5)
+    );
+    assert_eq!(
+        conn.command(&["APPEND", "k", " World"]).await.unwrap(),
+        Frame::Integer(11)
+    );
+    assert_eq!(
+        conn.command(&["STRLEN", "k"]).await.unwrap(),
+        Frame::Integer(11)
+    );
+    assert_eq!(
+        conn.command(&["STRLEN", "missing"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+
+    assert_eq!(
+        conn.command(&["GETSET", "k", "new"]).await.unwrap(),
+        bulk("Hello World")
+    );
+    assert_eq!(
+        conn.command(&["GETSET", "missing", "v"]).await.unwrap(),
+        Frame::Null
+    );
+
+    assert_eq!(
+        conn.command(&["SETNX", "k", "ignored"]).await.unwrap(),
+        Frame::Integer(0)
+    );
+    assert_eq!(
+        conn.command(&["SETNX", "fresh", "v"]).await.unwrap(),
+        Frame::Integer(1)
+    );
+    assert_eq!(conn.command(&["GET", "k"]).await.unwrap(), bulk("new"));
+
+    conn.command(&["SADD", "set", "m"]).await.unwrap();
+    for args in [
+        &["APPEND", "set", "x"][..],
+        &["STRLEN", "set"],
+        &["GETRANGE", "set", "0", "1"],
+        &["SETRANGE", "set", "0", "x"],
+        &["INCR", "set"],
+    ] {
+        assert_error(conn.command(args).await.unwrap(), "WRONGTYPE");
+    }
+}
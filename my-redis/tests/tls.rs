@@ -0,0 +1,230 @@
+//! TLS 的集成测试。证书在测试运行时用 rcgen 生成：一个自签名的 CA，由它签发服务端和客户端的证书
+
+use my_redis::client::{self, Client};
+use my_redis::server::{self, Listener};
+use my_redis::tls::{self, AuthClients, ClientTls};
+use my_redis::{Config, Db};
+
+use bytes::Bytes;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, KeyUsagePurpose,
+};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// 一个测试用到的所有证书，写在临时目录中
+struct Certs {
+    dir: PathBuf,
+}
+
+impl Certs {
+    fn generate(name: &str) -> Certs {
+        let dir =
+            std::env::temp_dir().join(format!("my-redis-tls-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (ca, ca_key) = new_ca("my-redis test CA");
+        write(&dir, "ca", &ca, &ca_key);
+        let (server, server_key) = new_leaf(&ca, &ca_key, ExtendedKeyUsagePurpose::ServerAuth);
+        write(&dir, "server", &server, &server_key);
+        let (client, client_key) = new_leaf(&ca, &ca_key, ExtendedKeyUsagePurpose::ClientAuth);
+        write(&dir, "client", &client, &client_key);
+
+        // 另一个 CA 签发的客户端证书，服务端不应该信任它
+        let (other_ca, other_key) = new_ca("untrusted CA");
+        write(&dir, "other-ca", &other_ca, &other_key);
+        let (rogue, rogue_key) =
+            new_leaf(&other_ca, &other_key, ExtendedKeyUsagePurpose::ClientAuth);
+        write(&dir, "rogue", &rogue, &rogue_key);
+
+        Certs { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn client_tls(&self) -> ClientTls {
+        ClientTls::new(self.path("ca.crt"), "localhost").unwrap()
+    }
+
+    fn client_tls_with_cert(&self, name: &str) -> ClientTls {
+        ClientTls::with_client_cert(
+            self.path("ca.crt"),
+            "localhost",
+            self.path(&format!("{}.crt", name)),
+            self.path(&format!("{}.key", name)),
+        )
+        .unwrap()
+    }
+}
+
+impl Drop for Certs {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn new_ca(name: &str) -> (Certificate, KeyPair) {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    (params.self_signed(&key).unwrap(), key)
+}
+
+fn new_leaf(
+    ca: &Certificate,
+    ca_key: &KeyPair,
+    usage: ExtendedKeyUsagePurpose,
+) -> (Certificate, KeyPair) {
+    let key = KeyPair::generate().unwrap();
+    let mut params =
+        CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+    params.extended_key_usages = vec![usage];
+    (params.signed_by(&key, ca, ca_key).unwrap(), key)
+}
+
+fn write(dir: &std::path::Path, name: &str, cert: &Certificate, key: &KeyPair) {
+    std::fs::write(dir.join(format!("{}.crt", name)), cert.pem()).unwrap();
+    std::fs::write(dir.join(format!("{}.key", name)), key.serialize_pem()).unwrap();
+}
+
+/// 启动一个只监听 TLS 端口的服务端
+async fn start_server(certs: &Certs, auth: AuthClients) -> SocketAddr {
+    let config = Config {
+        tls_cert_file: Some(certs.path("server.crt")),
+        tls_key_file: Some(certs.path("server.key")),
+        tls_ca_cert_file: Some(certs.path("ca.crt")),
+        tls_auth_clients: auth,
+        ..Config::default()
+    };
+    let acceptor = tls::acceptor(&config).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(
+        vec![Listener::Tls(listener, acceptor)],
+        Db::with_config(config),
+    ));
+    addr
+}
+
+/// 连接并执行一次 PING。TLS 1.3 中服务端在握手之后才验证客户端证书，失败可能出现在第一个请求上
+async fn connect_and_ping(addr: SocketAddr, tls: ClientTls) -> my_redis::Result<Client> {
+    let client = client::connect_tls(addr, tls)
+        .await?
+        .with_timeout(Duration::from_secs(2));
+    client.ping(None).await?;
+    Ok(client)
+}
+
+#[tokio::test]
+async fn set_and_get_over_tls() {
+    let certs = Certs::generate("set-get");
+    let addr = start_server(&certs, AuthClients::No).await;
+
+    let client = connect_and_ping(addr, certs.client_tls()).await.unwrap();
+    client.set("hello", Bytes::from("world")).await.unwrap();
+    assert_eq!(
+        client.get("hello").await.unwrap(),
+        Some(Bytes::from("world"))
+    );
+}
+
+#[tokio::test]
+async fn plain_tcp_client_is_rejected() {
+    let certs = Certs::generate("plain");
+    let addr = start_server(&certs, AuthClients::No).await;
+
+    let client = client::connect(addr)
+        .await
+        .unwrap()
+        .with_timeout(Duration::from_secs(2));
+    assert!(client.ping(None).await.is_err());
+}
+
+#[tokio::test]
+async fn server_signed_by_unknown_ca_is_rejected() {
+    let certs = Certs::generate("unknown-ca");
+    let addr = start_server(&certs, AuthClients::No).await;
+
+    let tls = ClientTls::new(certs.path("other-ca.crt"), "localhost").unwrap();
+    assert!(client::connect_tls(addr, tls).await.is_err());
+}
+
+#[tokio::test]
+async fn server_name_must_match_certificate() {
+    let certs = Certs::generate("server-name");
+    let addr = start_server(&certs, AuthClients::No).await;
+
+    let tls = ClientTls::new(certs.path("ca.crt"), "redis.example.com").unwrap();
+    assert!(client::connect_tls(addr, tls).await.is_err());
+}
+
+#[tokio::test]
+async fn mutual_tls_requires_client_certificate() {
+    let certs = Certs::generate("mtls-required");
+    let addr = start_server(&certs, AuthClients::Yes).await;
+
+    assert!(connect_and_ping(addr, certs.client_tls()).await.is_err());
+    assert!(connect_and_ping(addr, certs.client_tls_with_cert("rogue"))
+        .await
+        .is_err());
+
+    let client = connect_and_ping(addr, certs.client_tls_with_cert("client"))
+        .await
+        .unwrap();
+    assert_eq!(client.incr("counter").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn mutual_tls_optional_accepts_both() {
+    let certs = Certs::generate("mtls-optional");
+    let addr = start_server(&certs, AuthClients::Optional).await;
+
+    connect_and_ping(addr, certs.client_tls()).await.unwrap();
+    connect_and_ping(addr, certs.client_tls_with_cert("client"))
+        .await
+        .unwrap();
+    // 提供了证书就必须是有效的
+    assert!(connect_and_ping(addr, certs.client_tls_with_cert("rogue"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn subscribe_over_tls() {
+    let certs = Certs::generate("subscribe");
+    let addr = start_server(&certs, AuthClients::Yes).await;
+
+    let client = connect_and_ping(addr, certs.client_tls_with_cert("client"))
+        .await
+        .unwrap();
+    // SUBSCRIBE 会单独建立连接，同样需要使用 TLS 和客户端证书
+    let mut subscriber = client.subscribe(&["news".to_string()]).await.unwrap();
+
+    assert_eq!(client.publish("news", Bytes::from("hi")).await.unwrap(), 1);
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(message.channel, "news");
+    assert_eq!(message.content, Bytes::from("hi"));
+}
+
+#[test]
+fn acceptor_requires_cert_and_key() {
+    assert!(tls::acceptor(&Config::default()).is_err());
+
+    let certs = Certs::generate("config");
+    let config = Config {
+        tls_cert_file: Some(certs.path("server.crt")),
+        tls_key_file: Some(certs.path("server.key")),
+        tls_auth_clients: AuthClients::Yes,
+        ..Config::default()
+    };
+    // 双向认证需要 CA
+    assert!(tls::acceptor(&config).is_err());
+}
@@ -0,0 +1,119 @@
+//! 通过 Unix domain socket 访问服务端的测试，包括 `unixsocketperm` 设置的文件权限
+
+use my_redis::server::{self, Listener};
+use my_redis::{client, Config, Connection, Db, Frame};
+
+use bytes::Bytes;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use tokio::net::UnixStream;
+
+/// 每个测试使用自己的临时目录，结束时删除
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir =
+            std::env::temp_dir().join(format!("my-redis-unix-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// 和 `src/bin/server.rs` 一样从命令行参数读取配置并监听 Unix socket
+fn start(args: &[&str]) -> Config {
+    let config = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+    let path = config.unixsocket.clone().unwrap();
+    let listener = server::bind_unix(&path, config.unixsocketperm).unwrap();
+    let db = Db::with_config(config.clone());
+    tokio::spawn(server::serve(vec![Listener::Unix(listener)], db));
+    config
+}
+
+fn mode(path: &PathBuf) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[tokio::test]
+async fn serve_over_unix_socket() {
+    let dir = TempDir::new("serve");
+    let path = dir.0.join("redis.sock");
+    let path_arg = path.to_str().unwrap();
+    start(&["--unixsocket", path_arg, "--unixsocketperm", "700"]);
+
+    assert!(fs::metadata(&path).unwrap().file_type().is_socket());
+    assert_eq!(mode(&path), 0o700);
+
+    let client = client::connect_unix(&path).await.unwrap();
+    client.set("k", Bytes::from("v")).await.unwrap();
+    assert_eq!(client.get("k").await.unwrap(), Some(Bytes::from("v")));
+
+    // 和 Redis 一样，Unix socket 的客户端地址显示为 `路径:0`
+    let mut conn = Connection::new(UnixStream::connect(&path).await.unwrap());
+    let list = Frame::Array(vec![
+        Frame::Bulk(Bytes::from("CLIENT")),
+        Frame::Bulk(Bytes::from("LIST")),
+    ]);
+    conn.write_frame(&list).await.unwrap();
+    match conn.read_frame().await.unwrap().unwrap() {
+        Frame::Bulk(list) => {
+            let list = String::from_utf8(list.to_vec()).unwrap();
+            let expected = format!("addr={}:0 ", path.display());
+            assert_eq!(list.lines().count(), 2, "{}", list);
+            assert!(
+                list.lines().all(|line| line.contains(&expected)),
+                "{}",
+                list
+            );
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn unixsocketperm_is_applied() {
+    let dir = TempDir::new("perm");
+
+    for (perm, expected) in [("700", 0o700), ("0770", 0o770), ("755", 0o755)] {
+        let path = dir.0.join(format!("{}.sock", perm));
+        let config = start(&[
+            "--unixsocket",
+            path.to_str().unwrap(),
+            "--unixsocketperm",
+            perm,
+        ]);
+        assert_eq!(config.unixsocketperm, expected);
+        assert_eq!(mode(&path), expected, "unixsocketperm {}", perm);
+
+        let client = client::connect_unix(&path).await.unwrap();
+        assert_eq!(client.ping(None).await.unwrap(), Bytes::from("PONG"));
+    }
+
+    for perm in ["800", "1777", "rwx"] {
+        assert!(
+            Config::from_args(["--unixsocketperm".to_string(), perm.to_string()]).is_err(),
+            "{} should be rejected",
+            perm
+        );
+    }
+}
+
+#[tokio::test]
+async fn stale_socket_file_is_replaced() {
+    let dir = TempDir::new("stale");
+    let path = dir.0.join("redis.sock");
+
+    // 上次没有正常退出留下的文件
+    fs::write(&path, b"stale").unwrap();
+    server::bind_unix(&path, 0o600).unwrap();
+    let meta = fs::metadata(&path).unwrap();
+    assert!(meta.file_type().is_socket());
+    assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+}